@@ -295,6 +295,7 @@ class InstructionType(StrEnum):
     New = "New"
     Edit = "Edit"
     Update = "Update"
+    Chat = "Chat"
 
 
 class LabelType(StrEnum):
@@ -640,6 +641,18 @@ class Suggestion(Entity):
     feedback: str | None = None
     """Feedback on the suggestion"""
 
+    prompt_tokens: UnsignedInteger | None = None
+    """The number of tokens in the prompts used to generate the suggestion."""
+
+    completion_tokens: UnsignedInteger | None = None
+    """The number of tokens generated for the suggestion."""
+
+    cost: float | None = None
+    """The estimated cost, in US dollars, of generating the suggestion."""
+
+    messages: list[InstructionMessage] | None = None
+    """The messages, other than those of the instruction, exchanged with the model while generating the suggestion."""
+
 
 @dataclass(kw_only=True, repr=False)
 class CodeExecutable(Executable):
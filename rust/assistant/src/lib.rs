@@ -5,7 +5,13 @@
 //! assistants build on top of lower level, more generalized assistants
 //! in other crates and prompts defined in the top level `prompts` module.
 
-use std::{fs::read_to_string, sync::Arc};
+use std::{
+    collections::HashMap,
+    fs::{metadata, read_to_string},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
 
 #[cfg(not(debug_assertions))]
 use cached::proc_macro::once;
@@ -20,6 +26,7 @@ use model::{
         glob::glob,
         inflector::Inflector,
         itertools::Itertools,
+        once_cell::sync::Lazy,
         regex::Regex,
         serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer},
         serde_yaml, tracing,
@@ -27,9 +34,11 @@ use model::{
     deserialize_option_regex,
     format::Format,
     merge::Merge,
-    schema::{AuthorRoleName, InstructionMessage, MessagePart, NodeType},
-    Embeddings, GenerateOutput, GenerateTask, Instruction, InstructionType, Model, ModelIO,
-    ModelType,
+    schema::{
+        AuthorRoleName, Block, InstructionMessage, MessageLevel, MessagePart, MessageRole, NodeType,
+    },
+    Embeddings, GenerateOutput, GenerateSender, GenerateTask, Instruction, InstructionType, Model,
+    ModelIO, ModelType, Nodes, Partials,
 };
 
 /// Default preference rank
@@ -91,6 +100,15 @@ impl ExpectedNodes {
         Ok(Regex::new(&pattern)?)
     }
 
+    /// Whether generated nodes are of the expected type and number
+    pub fn matches(&self, nodes: &Nodes) -> Result<bool> {
+        let list = match nodes {
+            Nodes::Blocks(nodes) => nodes.iter().map(|node| node.to_string()).join(","),
+            Nodes::Inlines(nodes) => nodes.iter().map(|node| node.to_string()).join(","),
+        };
+        Ok(self.as_regex(true)?.is_match(&list))
+    }
+
     /// Update the options based on the expected nodes.
     fn apply(&self, options: &mut GenerateOptions) -> Result<()> {
         if options.transform_nodes.is_none() {
@@ -297,13 +315,48 @@ where
     }
 }
 
+/// Get the code, and language, of the code chunks in generated nodes
+fn code_chunks(nodes: &Nodes) -> Vec<(String, Option<String>)> {
+    let Nodes::Blocks(blocks) = nodes else {
+        return Vec::new();
+    };
+
+    blocks
+        .iter()
+        .filter_map(|block| match block {
+            Block::CodeChunk(chunk) => {
+                Some((chunk.code.to_string(), chunk.programming_language.clone()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
 /// Get the first model in the list of models capable to performing task
+///
+/// Model names may be prefixed with `record/` or `replay/` to record, or replay,
+/// the responses of the model (see `models_replay::ReplayModel`).
 #[tracing::instrument(skip_all)]
 pub async fn choose_model(model: &[String], task: &GenerateTask) -> Result<Arc<dyn Model>> {
     for id in model {
+        // Models prefixed with `replay/` do not need the underlying model to be available,
+        // whereas those prefixed with `record/` wrap it
+        let (record, id) = match id.split_once('/') {
+            Some(("replay", id)) => {
+                let replay: Arc<dyn Model> = Arc::new(models_replay::ReplayModel::replay(id));
+                if replay.supports_task(task) {
+                    return Ok(replay);
+                }
+                continue;
+            }
+            Some(("record", id)) => (true, id),
+            _ => (false, id.as_str()),
+        };
+
+        // Split on the first slash only because models on OpenAI-compatible
+        // endpoints may include an organization e.g. `vllm/meta-llama/Llama-3-8b`
         let (provider, _model) = id
-            .split('/')
-            .collect_tuple()
+            .split_once('/')
             .ok_or_else(|| eyre!("Expected model name to have a forward slash"))?;
 
         let list = match provider {
@@ -312,16 +365,27 @@ pub async fn choose_model(model: &[String], task: &GenerateTask) -> Result<Arc<d
             "mistral" => models_mistral::list().await?,
             "ollama" => models_ollama::list().await?,
             "openai" => models_openai::list().await?,
-            _ => bail!("Unknown assistant provider: {provider}"),
+            _ => {
+                let list = models_openai_compatible::list().await?;
+                let prefix = [provider, "/"].concat();
+                if !list.iter().any(|model| model.name().starts_with(&prefix)) {
+                    bail!("Unknown assistant provider: {provider}")
+                }
+                list
+            }
         };
 
         if let Some(assistant) = list
             .into_iter()
-            .find(|assistant| &assistant.name() == id)
+            .find(|assistant| assistant.name() == id)
             .take()
         {
             if assistant.supports_task(task) {
-                return Ok(assistant);
+                return Ok(if record {
+                    Arc::new(models_replay::ReplayModel::record(assistant)) as Arc<dyn Model>
+                } else {
+                    assistant
+                });
             }
         }
     }
@@ -373,7 +437,10 @@ impl SpecializedAssistant {
         };
 
         // Parse header into an assistant
-        let mut assistant: SpecializedAssistant = serde_yaml::from_str(&header)?;
+        // Field types (e.g. of `instruction-type`, `expected-nodes` and `preference-rank`)
+        // are validated when deserializing
+        let mut assistant: SpecializedAssistant =
+            serde_yaml::from_str(&header).map_err(|error| eyre!("Invalid YAML header: {error}"))?;
         assistant.id = id.to_string();
         assistant.description = parts.next().unwrap_or_else(|| "No description".to_string());
 
@@ -385,11 +452,45 @@ impl SpecializedAssistant {
             }
         }
 
+        assistant.validate()?;
         assistant.init()?;
 
         Ok(assistant)
     }
 
+    /// Validate the front matter of the assistant
+    ///
+    /// Checks for combinations of fields that can be deserialized but which
+    /// would mean that the assistant is never used, or fails, at runtime.
+    fn validate(&self) -> Result<()> {
+        if self.expected_nodes.is_some() && self.instruction_type.is_none() {
+            bail!("`expected-nodes` requires `instruction-type` to be specified");
+        }
+
+        if self.content_nodes.is_some() || self.content_regexes.is_some() {
+            if let Some(
+                instruction_type @ (InstructionType::InsertBlocks | InstructionType::InsertInlines),
+            ) = &self.instruction_type
+            {
+                bail!("`content-nodes` and `content-regexes` can not be used with `instruction-type` {instruction_type:?} because instructions of that type have no content");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check that the prompt template of the assistant can be rendered
+    ///
+    /// Renders the system prompt for an empty task, with the `partials` that
+    /// it may include, so that syntax errors, and missing partials, are found
+    /// before the assistant is used.
+    pub async fn check(&self, partials: Arc<Partials>) -> Result<()> {
+        let mut task = self
+            .merge_task(&GenerateTask::default())
+            .with_partials(partials);
+        task.prepare(None, None, self.system_prompt.as_ref()).await
+    }
+
     /// Initialize the assistant
     pub fn init(&mut self) -> Result<()> {
         // Calculate embeddings if necessary
@@ -435,6 +536,259 @@ impl SpecializedAssistant {
         merged_options.merge(options.clone());
         merged_options
     }
+
+    /// Perform a generation task, optionally streaming the generated text
+    async fn perform(
+        &self,
+        task: &GenerateTask,
+        options: &GenerateOptions,
+        sender: Option<GenerateSender>,
+    ) -> Result<GenerateOutput> {
+        let mut options = self.merge_options(options);
+
+        // Use the partials of the workspace of the task's document, if any
+        let home = task.context().and_then(|context| context.home.clone());
+        let mut task = self
+            .merge_task(task)
+            .with_passages(options.passages.unwrap_or_default())
+            .with_partials(Arc::new(partials(home.as_deref())?));
+
+        // Take the model name from the options so that it is not passed on to the model
+        let model_name = options.model.take();
+
+        let content_format = self
+            .content_format
+            .clone()
+            .or(self.format.clone())
+            .or(Some(FORMAT));
+
+        // Create the prompter role now so it has a timestamp before the generator timestamp
+        let prompter_role = self.to_author_role(AuthorRoleName::Prompter);
+
+        let mut output = if options.dry_run {
+            // Dry run so just prepare the task but return an empty output
+            task.prepare(None, content_format.as_ref(), self.system_prompt.as_ref())
+                .await?;
+
+            GenerateOutput::empty(self)?
+        } else if self.models.is_empty() {
+            // No modelss, so simply render the template into output.
+            // This differs from `options.dry_run` in that the prompt is decoded into nodes
+            // (including transformations associated with `expected_nodes`) in the call to `from_text`.
+            task.prepare(None, content_format.as_ref(), self.system_prompt.as_ref())
+                .await?;
+            let prompt = task.system_prompt().clone().unwrap_or_default();
+
+            if let Some(sender) = &sender {
+                sender.send(prompt.clone()).ok();
+            }
+
+            GenerateOutput::from_text(self, task.format(), task.instruction(), &options, prompt)
+                .await?
+        } else {
+            // Get the first available model, using the model named in the options
+            // (if any) instead of the assistant's models
+            let model = match model_name {
+                Some(name) => choose_model(&[name], &task).await?,
+                None => choose_model(&self.models, &task).await?,
+            };
+
+            // Update the task, to render template etc based on the model, before performing it
+            task.prepare(
+                Some(model.as_ref()),
+                content_format.as_ref(),
+                self.system_prompt.as_ref(),
+            )
+            .await?;
+
+            let output = self
+                .perform_with_retries(model.as_ref(), &mut task, &options, &sender)
+                .await?;
+
+            // Check any generated code chunks and, if they have errors, have the model repair them
+            self.repair(model.as_ref(), &mut task, &options, &sender, output)
+                .await?
+        };
+
+        // Add the prompter role. Intentionally appended, not prepended, so that
+        // the generator is the primary author
+        output.authors.push(prompter_role);
+
+        Ok(output)
+    }
+
+    /// Repair any code chunks in generated output which have errors
+    ///
+    /// Checks the code chunks by executing them with the task's tools and, if there are
+    /// errors, sends those back to the model to correct, for up to `repair_rounds`.
+    /// Each failed attempt, and the errors it had, are added to the messages of the task
+    /// so that the model can use them, and to the transcript of the returned output so that
+    /// they are recorded on the suggestion made from it. If the code still has errors after
+    /// the last round, the output is returned with those errors at the end of its transcript.
+    async fn repair(
+        &self,
+        model: &dyn Model,
+        task: &mut GenerateTask,
+        options: &GenerateOptions,
+        sender: &Option<GenerateSender>,
+        mut output: GenerateOutput,
+    ) -> Result<GenerateOutput> {
+        let rounds = options.repair_rounds.unwrap_or_default();
+        let mut round = 0;
+        let mut transcript = std::mem::take(&mut output.transcript);
+        while rounds > 0 {
+            let code = code_chunks(&output.nodes);
+            if code.is_empty() {
+                break;
+            }
+
+            let Some(tools) = task.tools() else {
+                tracing::debug!("Unable to check code chunks because task has no tools");
+                break;
+            };
+            let errors = match tools.check_code(&code).await {
+                Ok(messages) => messages
+                    .into_iter()
+                    .filter(|message| {
+                        matches!(message.level, MessageLevel::Error | MessageLevel::Exception)
+                    })
+                    .map(|message| match message.error_type {
+                        Some(error_type) => format!("{error_type}: {}", message.message),
+                        None => message.message,
+                    })
+                    .collect_vec(),
+                Err(error) => {
+                    tracing::warn!("Unable to check code chunks: {error}");
+                    break;
+                }
+            };
+            if errors.is_empty() {
+                break;
+            }
+
+            let errors = errors.join("\n");
+            if round >= rounds {
+                tracing::warn!(
+                    "Generated code still had errors after {rounds} rounds of repair:\n\n{errors}"
+                );
+                transcript.push(InstructionMessage {
+                    role: Some(MessageRole::User),
+                    parts: vec![MessagePart::Text(
+                        format!("The code still failed with the following errors:\n\n{errors}")
+                            .into(),
+                    )],
+                    ..Default::default()
+                });
+                break;
+            }
+            round += 1;
+
+            tracing::debug!("Repair round {round} for code with errors");
+
+            let attempt = InstructionMessage {
+                role: Some(MessageRole::Assistant),
+                ..output.to_message()
+            };
+            let feedback = InstructionMessage {
+                role: Some(MessageRole::User),
+                parts: vec![MessagePart::Text(
+                    format!(
+                        "The code failed with the following errors:\n\n{errors}\n\nPlease correct the code."
+                    )
+                    .into(),
+                )],
+                ..Default::default()
+            };
+            transcript.push(attempt.clone());
+            transcript.push(feedback.clone());
+            match task.instruction_mut() {
+                Instruction::Block(instr) => {
+                    instr.messages.push(attempt);
+                    instr.messages.push(feedback);
+                }
+                Instruction::Inline(instr) => {
+                    instr.messages.push(attempt);
+                    instr.messages.push(feedback);
+                }
+            }
+
+            let usage = output.usage.take();
+            output = self
+                .perform_with_retries(model, task, options, sender)
+                .await?;
+            transcript.append(&mut output.transcript);
+
+            // Usage includes that of all attempts
+            output.usage = match (usage, output.usage.take()) {
+                (Some(mut usage), Some(other)) => {
+                    usage += other;
+                    Some(usage)
+                }
+                (usage, other) => usage.or(other),
+            };
+        }
+        output.transcript = transcript;
+
+        Ok(output)
+    }
+
+    /// Perform a task with a model, retrying up to `max_retries` if there is an error
+    ///
+    /// Errors are added to the messages of the task's instruction so that the model
+    /// can use them to try to correct.
+    async fn perform_with_retries(
+        &self,
+        model: &dyn Model,
+        task: &mut GenerateTask,
+        options: &GenerateOptions,
+        sender: &Option<GenerateSender>,
+    ) -> Result<GenerateOutput> {
+        // Try once, and then up to `max_retries`, breaking early if successful
+        let mut output = None;
+        let max_retries = self.max_retries.unwrap_or(MAX_RETRIES);
+        for retry in 0..=max_retries {
+            // When streaming, each attempt sends all of its text so far, so any partial
+            // text from a failed attempt is replaced by that of the next
+            let result: Result<GenerateOutput> = match sender {
+                Some(sender) => {
+                    model
+                        .perform_task_stream(task, options, sender.clone())
+                        .await
+                }
+                None => model.perform_task(task, options).await,
+            };
+            match result {
+                Ok(out) => {
+                    output = Some(out);
+                    break;
+                }
+                Err(error) => {
+                    if retry >= max_retries {
+                        return Err(error);
+                    }
+
+                    tracing::debug!("Error on retry {retry}: {error}");
+
+                    // Add the error to the instruction messages so that the assistant
+                    // can use it to try to correct
+                    let message = InstructionMessage {
+                        parts: vec![MessagePart::Text(format!("Error: {error}").into())],
+                        ..Default::default()
+                    };
+                    match task.instruction_mut() {
+                        Instruction::Block(instr) => instr.messages.push(message),
+                        Instruction::Inline(instr) => instr.messages.push(message),
+                    }
+                }
+            }
+        }
+
+        match output {
+            Some(output) => Ok(output),
+            // Should not be reached but in case it is...
+            None => bail!("Maximum number of retries reached"),
+        }
+    }
 }
 
 #[async_trait]
@@ -502,89 +856,17 @@ impl Model for SpecializedAssistant {
         task: &GenerateTask,
         options: &GenerateOptions,
     ) -> Result<GenerateOutput> {
-        let mut task = self.merge_task(task);
-        let options = self.merge_options(options);
-
-        let content_format = self
-            .content_format
-            .clone()
-            .or(self.format.clone())
-            .or(Some(FORMAT));
-
-        // Create the prompter role now so it has a timestamp before the generator timestamp
-        let prompter_role = self.to_author_role(AuthorRoleName::Prompter);
-
-        let mut output = if options.dry_run {
-            // Dry run so just prepare the task but return an empty output
-            task.prepare(None, content_format.as_ref(), self.system_prompt.as_ref())
-                .await?;
-
-            GenerateOutput::empty(self)?
-        } else if self.models.is_empty() {
-            // No modelss, so simply render the template into output.
-            // This differs from `options.dry_run` in that the prompt is decoded into nodes
-            // (including transformations associated with `expected_nodes`) in the call to `from_text`.
-            task.prepare(None, content_format.as_ref(), self.system_prompt.as_ref())
-                .await?;
-            let prompt = task.system_prompt().clone().unwrap_or_default();
-
-            GenerateOutput::from_text(self, task.format(), task.instruction(), &options, prompt)
-                .await?
-        } else {
-            // Get the first available model
-            let model = choose_model(&self.models, &task).await?;
-
-            // Update the task, to render template etc based on the model, before performing it
-            task.prepare(
-                Some(model.as_ref()),
-                content_format.as_ref(),
-                self.system_prompt.as_ref(),
-            )
-            .await?;
-
-            // Try once, and then up to `max_retries`, breaking early if successful
-            let mut output = None;
-            let max_retries = self.max_retries.unwrap_or(MAX_RETRIES);
-            for retry in 0..=max_retries {
-                let result: Result<GenerateOutput> = model.perform_task(&task, &options).await;
-                match result {
-                    Ok(out) => {
-                        output = Some(out);
-                        break;
-                    }
-                    Err(error) => {
-                        if retry >= max_retries {
-                            return Err(error);
-                        }
-
-                        tracing::debug!("Error on retry {retry}: {error}");
-
-                        // Add the error to the instruction messages so that the assistant
-                        // can use it to try to correct
-                        let message = InstructionMessage {
-                            parts: vec![MessagePart::Text(format!("Error: {error}").into())],
-                            ..Default::default()
-                        };
-                        match task.instruction_mut() {
-                            Instruction::Block(instr) => instr.messages.push(message),
-                            Instruction::Inline(instr) => instr.messages.push(message),
-                        }
-                    }
-                }
-            }
-
-            match output {
-                Some(output) => output,
-                // Should not be reached but in case it is...
-                None => bail!("Maximum number of retries reached"),
-            }
-        };
-
-        // Add the prompter role. Intentionally appended, not prepended, so that
-        // the generator is the primary author
-        output.authors.push(prompter_role);
+        self.perform(task, options, None).await
+    }
 
-        Ok(output)
+    #[tracing::instrument(skip_all)]
+    async fn perform_task_stream(
+        &self,
+        task: &GenerateTask,
+        options: &GenerateOptions,
+        sender: GenerateSender,
+    ) -> Result<GenerateOutput> {
+        self.perform(task, options, Some(sender)).await
     }
 }
 
@@ -598,12 +880,29 @@ struct Builtin;
 
 /// Get a list of all available specialized assistants
 ///
-/// Memoized in production for performance (i.e not parsing files or creating
-/// embeddings), but not in debug (so that custom assistants can be reloaded from disk).
-#[cfg_attr(not(debug_assertions), once(result = true))]
-pub fn list() -> Result<Vec<Arc<dyn Model>>> {
-    let mut list = list_builtin()?;
-    list.append(&mut list_local()?);
+/// Workspace assistants are those in the workspace containing `home` (usually the
+/// directory of the document that the instruction is in). These, and local assistants,
+/// are read from disk on each call so that they can be edited without restarting
+/// (but are only parsed again if their file has been modified).
+pub fn list(home: Option<&Path>) -> Result<Vec<Arc<dyn Model>>> {
+    let builtin = list_builtin()?;
+    let local = list_local()?;
+    let workspace = list_workspace(home)?;
+
+    // Workspace assistants take precedence over local assistants, which take
+    // precedence over builtin assistants, having the same name
+    let list = workspace
+        .into_iter()
+        .chain(local)
+        .chain(builtin)
+        .unique_by(|assistant| {
+            let name = assistant.name();
+            name.split_once('/')
+                .map(|(.., name)| name.to_string())
+                .unwrap_or(name)
+        })
+        .collect();
+
     Ok(list)
 }
 
@@ -615,6 +914,10 @@ pub fn list_builtin_as_specialized() -> Result<Vec<SpecializedAssistant>> {
     for (name, content) in
         Builtin::iter().filter_map(|name| Builtin::get(&name).map(|file| (name, file.data)))
     {
+        if name.starts_with("partials/") {
+            continue;
+        }
+
         let id = format!("stencila/{}", name.strip_suffix(".md").unwrap_or(&name));
         let content = String::from_utf8_lossy(&content);
         let assistant = SpecializedAssistant::parse(&id, &content)
@@ -625,6 +928,10 @@ pub fn list_builtin_as_specialized() -> Result<Vec<SpecializedAssistant>> {
 }
 
 /// Get a list of all builtin specialized assistants as Assistant trait objects
+///
+/// Memoized in production for performance (i.e not parsing files or creating
+/// embeddings), but not in debug (so that builtin assistants can be reloaded from disk).
+#[cfg_attr(not(debug_assertions), once(result = true))]
 fn list_builtin() -> Result<Vec<Arc<dyn Model>>> {
     list_builtin_as_specialized().map(|assistants| {
         assistants
@@ -636,9 +943,72 @@ fn list_builtin() -> Result<Vec<Arc<dyn Model>>> {
 
 /// Get a list of all local specialized assistants
 fn list_local() -> Result<Vec<Arc<dyn Model>>> {
+    let dir = get_app_dir(DirType::Assistants, false)?;
+    parse_dir(&dir, "local")
+}
+
+/// Get a list of all specialized assistants in the workspace containing `home`
+fn list_workspace(home: Option<&Path>) -> Result<Vec<Arc<dyn Model>>> {
+    match home.and_then(workspace_dir) {
+        Some(dir) => parse_dir(&dir, "workspace"),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Get the assistants directory of the workspace containing `home` (if any)
+///
+/// Searches for a `.stencila/assistants` directory in `home` and its ancestors.
+fn workspace_dir(home: &Path) -> Option<PathBuf> {
+    home.ancestors()
+        .map(|dir| dir.join(".stencila").join("assistants"))
+        .find(|dir| dir.is_dir())
+}
+
+/// Local and workspace assistants, and the modification time of the file each was parsed from
+///
+/// Used so that an assistant is only parsed (and its embeddings calculated) again
+/// if its file has been modified.
+static PARSED: Lazy<Mutex<HashMap<PathBuf, (SystemTime, Arc<dyn Model>)>>> =
+    Lazy::new(Mutex::default);
+
+/// Parse the specialized assistants in a directory
+fn parse_dir(dir: &Path, prefix: &str) -> Result<Vec<Arc<dyn Model>>> {
     let mut assistants = vec![];
 
-    let dir = get_app_dir(DirType::Assistants, false)?;
+    for (id, path) in read_dir(dir, prefix)? {
+        let modified = metadata(&path)?.modified()?;
+
+        let cached = PARSED
+            .lock()
+            .map_err(|error| eyre!("Unable to lock parsed assistants: {error}"))?
+            .get(&path)
+            .filter(|(parsed, ..)| *parsed == modified)
+            .map(|(.., assistant)| assistant.clone());
+        if let Some(assistant) = cached {
+            assistants.push(assistant);
+            continue;
+        }
+
+        // Parse without holding the lock because calculating embeddings can be slow
+        let content = read_to_string(&path)?;
+        let assistant = SpecializedAssistant::parse(&id, &content)
+            .map_err(|error| eyre!("While parsing `{}`: {error}", path.display()))?;
+        let assistant = Arc::new(assistant) as Arc<dyn Model>;
+
+        PARSED
+            .lock()
+            .map_err(|error| eyre!("Unable to lock parsed assistants: {error}"))?
+            .insert(path, (modified, assistant.clone()));
+
+        assistants.push(assistant)
+    }
+
+    Ok(assistants)
+}
+
+/// Read the id and path of each of the assistants in a directory
+fn read_dir(dir: &Path, prefix: &str) -> Result<Vec<(String, PathBuf)>> {
+    let mut files = vec![];
 
     tracing::debug!(
         "Attempting to read assistants from `{}` (if it exists)",
@@ -646,30 +1016,117 @@ fn list_local() -> Result<Vec<Arc<dyn Model>>> {
     );
 
     if !dir.exists() {
-        return Ok(assistants);
+        return Ok(files);
     }
 
     for path in glob(&dir.join("*.md").to_string_lossy())?.flatten() {
         let Some(name) = path.file_name().map(|name| name.to_string_lossy()) else {
             continue;
         };
-        let id = format!("local/{}", name.strip_suffix(".md").unwrap_or(&name));
+        let id = format!("{prefix}/{}", name.strip_suffix(".md").unwrap_or(&name));
+
+        files.push((id, path));
+    }
+
+    Ok(files)
+}
+
+/// Get the partial templates which the prompts of assistants may include
+///
+/// Prompts can include partials using their name e.g. `{% include "partials/style.md" %}`.
+/// Builtin partials are replaced by local partials, and both by those in the workspace
+/// containing `home`, having the same name. Partials are read for each task, rather than
+/// shared by all tasks, so that the partials of one workspace are not used in another.
+pub fn partials(home: Option<&Path>) -> Result<Partials> {
+    let mut partials = Partials::new();
+
+    for name in Builtin::iter().filter(|name| name.starts_with("partials/")) {
+        if let Some(file) = Builtin::get(&name) {
+            partials.insert(
+                name.to_string(),
+                String::from_utf8_lossy(&file.data).to_string(),
+            );
+        }
+    }
+
+    read_partials(&get_app_dir(DirType::Assistants, false)?, &mut partials)?;
+
+    if let Some(dir) = home.and_then(workspace_dir) {
+        read_partials(&dir, &mut partials)?;
+    }
+
+    Ok(partials)
+}
 
+/// Read the partial templates in the `partials` subdirectory of a directory of assistants
+fn read_partials(dir: &Path, partials: &mut Partials) -> Result<()> {
+    for path in glob(&dir.join("partials").join("*").to_string_lossy())?.flatten() {
+        let Some(name) = path.file_name().map(|name| name.to_string_lossy()) else {
+            continue;
+        };
         let content = read_to_string(&path)?;
+        partials.insert(["partials/", &name].concat(), content);
+    }
 
-        let assistant = SpecializedAssistant::parse(&id, &content)
-            .map_err(|error| eyre!("While parsing `{}`: {error}", path.display()))?;
-        assistants.push(Arc::new(assistant) as Arc<dyn Model>)
+    Ok(())
+}
+
+/// Check all specialized assistants, including their prompt templates
+///
+/// Returns the id and path of each assistant, and any error in parsing
+/// its front matter or rendering its prompt template. Workspace assistants
+/// are those in the workspace containing `home`.
+pub async fn check(home: Option<&Path>) -> Result<Vec<(String, PathBuf, Option<String>)>> {
+    let mut files = Builtin::iter()
+        .filter(|name| !name.starts_with("partials/"))
+        .filter_map(|name| {
+            let content = Builtin::get(&name)?.data;
+            let id = format!("stencila/{}", name.strip_suffix(".md").unwrap_or(&name));
+            let path = PathBuf::from("assistants/builtin").join(name.as_ref());
+            Some((id, path, String::from_utf8_lossy(&content).to_string()))
+        })
+        .collect_vec();
+
+    let mut dirs = vec![(get_app_dir(DirType::Assistants, false)?, "local")];
+    if let Some(dir) = home.and_then(workspace_dir) {
+        dirs.push((dir, "workspace"));
+    }
+    for (dir, prefix) in dirs {
+        for (id, path) in read_dir(&dir, prefix)? {
+            let content = read_to_string(&path)?;
+            files.push((id, path, content));
+        }
     }
 
-    Ok(assistants)
+    let partials = Arc::new(partials(home)?);
+
+    let mut results = Vec::new();
+    for (id, path, content) in files {
+        let result = match SpecializedAssistant::parse(&id, &content) {
+            Ok(assistant) => assistant.check(partials.clone()).await,
+            Err(error) => Err(error),
+        };
+        results.push((id, path, result.err().map(|error| error.to_string())));
+    }
+
+    Ok(results)
 }
 
 #[cfg(test)]
 mod tests {
+    use std::{
+        fs::{create_dir_all, write, File},
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
     use model::{
-        schema::shortcuts::{p, t},
-        Instruction,
+        common::{serde_json, tempfile::tempdir, tokio},
+        schema::{
+            shortcuts::{p, t},
+            ExecutionMessage,
+        },
+        Instruction, Tool, Tools,
     };
 
     use super::*;
@@ -681,6 +1138,203 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn workspace_assistants_from_home() -> Result<()> {
+        let workspace = tempdir()?;
+        let dir = workspace.path().join(".stencila").join("assistants");
+        create_dir_all(dir.join("partials"))?;
+        write(dir.join("partials").join("greeting.md"), "Hello")?;
+        write(
+            dir.join("greeter.md"),
+            "---\nversion: \"0.1.0\"\ninstruction-type: insert-blocks\n---\n\n{% include \"partials/greeting.md\" %}",
+        )?;
+
+        let home = workspace.path().join("docs");
+        create_dir_all(&home)?;
+
+        let is_greeter = |assistant: &Arc<dyn Model>| assistant.name() == "workspace/greeter";
+        assert!(list(Some(&home))?.iter().any(is_greeter));
+        assert!(!list(None)?.iter().any(is_greeter));
+
+        let results = check(Some(&home)).await?;
+        let (.., error) = results
+            .iter()
+            .find(|(id, ..)| id == "workspace/greeter")
+            .expect("workspace assistant should be checked");
+        assert_eq!(error, &None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn partials_scoped_to_workspace() -> Result<()> {
+        let (one, two) = (tempdir()?, tempdir()?);
+        for (workspace, greeting) in [(&one, "Hello"), (&two, "Kia ora")] {
+            let dir = workspace.path().join(".stencila").join("assistants");
+            create_dir_all(dir.join("partials"))?;
+            write(dir.join("partials").join("greeting.md"), greeting)?;
+        }
+
+        let render = |home: Option<&Path>| {
+            let partials = partials(home).map(Arc::new);
+            async move {
+                let mut task = GenerateTask::default().with_partials(partials?);
+                let prompt = "{% include \"partials/greeting.md\" %}".to_string();
+                task.prepare(None, None, Some(&prompt)).await?;
+                eyre::Ok(task.system_prompt().clone().unwrap_or_default())
+            }
+        };
+
+        assert_eq!(render(Some(one.path())).await?, "Hello");
+        assert_eq!(render(Some(two.path())).await?, "Kia ora");
+        assert!(render(None).await.is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn workspace_assistants_parsed_once() -> Result<()> {
+        let workspace = tempdir()?;
+        let dir = workspace.path().join(".stencila").join("assistants");
+        create_dir_all(&dir)?;
+        let path = dir.join("cached.md");
+        write(&path, "---\nversion: \"0.1.0\"\n---\n")?;
+
+        let get = || -> Result<Arc<dyn Model>> {
+            list(Some(workspace.path()))?
+                .into_iter()
+                .find(|assistant| assistant.name() == "workspace/cached")
+                .ok_or_else(|| eyre!("Workspace assistant should be listed"))
+        };
+
+        let first = get()?;
+        assert!(Arc::ptr_eq(&first, &get()?));
+
+        // Parsed again if the file is modified
+        write(&path, "---\nversion: \"0.2.0\"\n---\n")?;
+        File::options()
+            .write(true)
+            .open(&path)?
+            .set_modified(SystemTime::now() + Duration::from_secs(60))?;
+        let second = get()?;
+        assert!(!Arc::ptr_eq(&first, &second));
+        assert_eq!(second.version(), "0.2.0");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn repair_code_with_errors() -> Result<()> {
+        /// A model which generates a code chunk with an error, `failures` times, and then one without
+        struct TestModel {
+            calls: AtomicUsize,
+            failures: usize,
+        }
+
+        #[async_trait]
+        impl Model for TestModel {
+            fn name(&self) -> String {
+                "test/coder".to_string()
+            }
+
+            async fn perform_task(
+                &self,
+                task: &GenerateTask,
+                options: &GenerateOptions,
+            ) -> Result<GenerateOutput> {
+                let code = if self.calls.fetch_add(1, Ordering::SeqCst) < self.failures {
+                    "1 / 0"
+                } else {
+                    "1 / 1"
+                };
+                GenerateOutput::from_text(
+                    self,
+                    task.format(),
+                    task.instruction(),
+                    options,
+                    format!("```python exec\n{code}\n```\n"),
+                )
+                .await
+            }
+        }
+
+        /// Tools which report an error for code dividing by zero
+        #[derive(Debug)]
+        struct TestTools;
+
+        #[async_trait]
+        impl Tools for TestTools {
+            fn list(&self) -> Vec<Tool> {
+                Vec::new()
+            }
+
+            async fn call(&self, _name: &str, _arguments: &serde_json::Value) -> Result<String> {
+                bail!("No tools")
+            }
+
+            async fn check_code(
+                &self,
+                code: &[(String, Option<String>)],
+            ) -> Result<Vec<ExecutionMessage>> {
+                Ok(code
+                    .iter()
+                    .filter(|(code, ..)| code.contains("/ 0"))
+                    .map(|_| ExecutionMessage {
+                        error_type: Some("ZeroDivisionError".to_string()),
+                        ..ExecutionMessage::new(
+                            MessageLevel::Exception,
+                            "division by zero".to_string(),
+                        )
+                    })
+                    .collect())
+            }
+        }
+
+        let assistant = SpecializedAssistant {
+            id: "test/repairer".to_string(),
+            ..Default::default()
+        };
+        let options = GenerateOptions {
+            repair_rounds: Some(1),
+            ..Default::default()
+        };
+        let (assistant, options) = (&assistant, &options);
+        let perform = |failures: usize| async move {
+            let model = TestModel {
+                calls: AtomicUsize::new(0),
+                failures,
+            };
+            let mut task = GenerateTask::new(Instruction::block_text("divide one"), None)
+                .with_tools(Some(Arc::new(TestTools)));
+            let output = assistant
+                .perform_with_retries(&model, &mut task, options, &None)
+                .await?;
+            let output = assistant
+                .repair(&model, &mut task, options, &None, output)
+                .await?;
+            eyre::Ok((model.calls.into_inner(), task, output))
+        };
+
+        // The code is repaired in one round and the attempt, and its errors, are
+        // in the messages of the task and the transcript of the output
+        let (calls, task, output) = perform(1).await?;
+        assert_eq!(calls, 2);
+        assert_eq!(code_chunks(&output.nodes)[0].0, "1 / 1");
+        assert_eq!(task.instruction().messages().len(), 3);
+        assert_eq!(output.transcript.len(), 2);
+        assert_eq!(output.transcript[0].role, Some(MessageRole::Assistant));
+        assert_eq!(output.transcript[1].role, Some(MessageRole::User));
+
+        // If the code still has errors after the last round the output, and the
+        // attempts and remaining errors, are still returned
+        let (calls, .., output) = perform(2).await?;
+        assert_eq!(calls, 2);
+        assert_eq!(code_chunks(&output.nodes)[0].0, "1 / 0");
+        assert_eq!(output.transcript.len(), 3);
+
+        Ok(())
+    }
+
     #[test]
     fn test_expected_nodes_conversion() {
         let test_cases = [
@@ -742,6 +1396,42 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn front_matter_is_validated() {
+        let parse =
+            |header: &str| SpecializedAssistant::parse("test", &format!("---\n{header}\n---\n"));
+
+        assert!(parse(
+            "version: 0.1.0\ninstruction-type: insert-blocks\nexpected-nodes: CodeChunk"
+        )
+        .is_ok());
+        assert!(parse("version: 0.1.0\nexpected-nodes: CodeChunk").is_err());
+        assert!(parse(
+            "version: 0.1.0\ninstruction-type: insert-blocks\ncontent-nodes: ^CodeChunk$"
+        )
+        .is_err());
+        assert!(parse("version: 0.1.0\ninstruction-type: insert-block").is_err());
+        assert!(parse("version: 0.1.0\nexpected-nodes: NotANode").is_err());
+    }
+
+    #[test]
+    fn expected_nodes_matches() -> Result<()> {
+        let one = ExpectedNodes::try_from("Paragraph")?;
+        let many = ExpectedNodes::try_from("Paragraph+")?;
+
+        let nodes = Nodes::Blocks(vec![p([t("A")])]);
+        assert!(one.matches(&nodes)?);
+        assert!(many.matches(&nodes)?);
+
+        let nodes = Nodes::Blocks(vec![p([t("A")]), p([t("B")])]);
+        assert!(!one.matches(&nodes)?);
+        assert!(many.matches(&nodes)?);
+
+        assert!(!one.matches(&Nodes::Blocks(Vec::new()))?);
+
+        Ok(())
+    }
+
     #[test]
     fn supports_task_works_as_expected() -> Result<()> {
         let tasks = [
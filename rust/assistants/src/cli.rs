@@ -1,11 +1,14 @@
+use std::{env, path::PathBuf};
+
 use cli_utils::{
-    table::{self, Attribute, Cell, Color},
+    table::{self, Attribute, Cell, CellAlignment, Color},
     Code, ToStdout,
 };
 use model::{
     common::{
         clap::{self, Args, Parser, Subcommand},
-        eyre::Result,
+        eyre::{bail, Result},
+        itertools::Itertools,
         serde_yaml,
     },
     context::Context,
@@ -14,7 +17,7 @@ use model::{
     GenerateOptions, ModelAvailability, ModelType,
 };
 
-use crate::execute_instruction;
+use crate::{eval::Suite, execute_instruction};
 
 /// Manage assistants
 #[derive(Debug, Parser)]
@@ -27,6 +30,8 @@ pub struct Cli {
 enum Command {
     List(List),
     Execute(Execute),
+    Eval(Eval),
+    Check(Check),
 }
 
 impl Cli {
@@ -39,6 +44,8 @@ impl Cli {
         match command {
             Command::List(list) => list.run().await?,
             Command::Execute(execute) => execute.run().await?,
+            Command::Eval(eval) => eval.run().await?,
+            Command::Check(check) => check.run().await?,
         }
 
         Ok(())
@@ -54,7 +61,8 @@ impl List {
         let mut table = table::new();
         table.set_header(["Name", "Provider", "Version", "Description"]);
 
-        for assistant in super::list(true).await {
+        let home = env::current_dir().ok();
+        for assistant in super::list(true, home.as_deref()).await {
             use ModelAvailability::*;
             let availability = assistant.availability();
 
@@ -112,7 +120,12 @@ impl Execute {
         );
         instruction.assignee = Some(self.name);
 
-        let context = Context::default();
+        // Use the current directory as home so that workspace assistants are available
+        let context = Context {
+            home: env::current_dir().ok(),
+            skip_passages: true,
+            ..Default::default()
+        };
         let options = GenerateOptions::default();
         let output = execute_instruction(instruction.clone(), context, options).await?;
 
@@ -125,3 +138,153 @@ impl Execute {
         Ok(())
     }
 }
+
+/// Evaluate assistants using a suite of instructions
+///
+/// Runs each case in a YAML suite with each assistant and model, scores the
+/// output against the checks of the case, and reports a comparison table.
+#[derive(Debug, Args)]
+struct Eval {
+    /// The path of the YAML file of the suite
+    suite: PathBuf,
+
+    /// The assistants to evaluate, instead of those in the suite
+    #[arg(long = "assistant", short)]
+    assistants: Vec<String>,
+
+    /// The models to evaluate each assistant with, instead of those in the suite
+    ///
+    /// Overrides the models of each assistant. If neither this option nor the suite
+    /// specify models, each assistant uses its own models.
+    ///
+    /// Prefix with `replay/` to use recorded responses (see `record/`).
+    #[arg(long = "model", short)]
+    models: Vec<String>,
+}
+
+impl Eval {
+    async fn run(self) -> Result<()> {
+        let suite = Suite::read(&self.suite).await?;
+        let runs = suite.run(&self.assistants, &self.models).await?;
+
+        // A column for each combination of assistant and model, in the order run
+        let columns = runs
+            .iter()
+            .map(|run| (run.assistant.as_str(), run.model.as_deref()))
+            .unique()
+            .collect_vec();
+
+        let mut table = table::new();
+        table.set_header(["Case".to_string()].into_iter().chain(columns.iter().map(
+            |(assistant, model)| match model {
+                Some(model) => format!("{assistant}\n{model}"),
+                None => assistant.to_string(),
+            },
+        )));
+
+        let score = |passed: usize, total: usize| {
+            let cell = Cell::new(format!("{passed}/{total}")).set_alignment(CellAlignment::Right);
+            if passed == total {
+                cell.fg(Color::Green)
+            } else if passed == 0 {
+                cell.fg(Color::Red)
+            } else {
+                cell.fg(Color::Yellow)
+            }
+        };
+
+        for case in &suite.cases {
+            let cells = columns.iter().map(|column| {
+                runs.iter()
+                    .find(|run| {
+                        run.case == case.name
+                            && (run.assistant.as_str(), run.model.as_deref()) == *column
+                    })
+                    .map(|run| score(run.passed, run.total))
+                    .unwrap_or_else(|| Cell::new(""))
+            });
+            table.add_row(
+                [Cell::new(&case.name).add_attribute(Attribute::Bold)]
+                    .into_iter()
+                    .chain(cells),
+            );
+        }
+
+        let totals = columns.iter().map(|column| {
+            let (passed, total) = runs
+                .iter()
+                .filter(|run| (run.assistant.as_str(), run.model.as_deref()) == *column)
+                .fold((0, 0), |(passed, total), run| {
+                    (passed + run.passed, total + run.total)
+                });
+            let percent = if total > 0 {
+                passed as f64 / total as f64 * 100.
+            } else {
+                0.
+            };
+            Cell::new(format!("{percent:.0}%"))
+                .set_alignment(CellAlignment::Right)
+                .add_attribute(Attribute::Bold)
+        });
+        table.add_row(
+            [Cell::new("Score").add_attribute(Attribute::Bold)]
+                .into_iter()
+                .chain(totals),
+        );
+
+        println!("{table}");
+
+        for run in &runs {
+            for failure in &run.failures {
+                let model = run
+                    .model
+                    .as_ref()
+                    .map(|model| format!(" ({model})"))
+                    .unwrap_or_default();
+                println!("{} / {}{model}: {failure}", run.case, run.assistant);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Check specialized assistants for errors
+///
+/// Parses the YAML header, and renders the prompt template, of each builtin,
+/// local, and workspace assistant so that errors are found before any
+/// instructions are executed.
+#[derive(Debug, Args)]
+struct Check;
+
+impl Check {
+    async fn run(self) -> Result<()> {
+        let mut table = table::new();
+        table.set_header(["Name", "Path", "Errors"]);
+
+        let mut errors = 0;
+        let home = env::current_dir().ok();
+        for (id, path, error) in assistant::check(home.as_deref()).await? {
+            let cell = match error {
+                Some(error) => {
+                    errors += 1;
+                    Cell::new(error).fg(Color::Red)
+                }
+                None => Cell::new("none").fg(Color::Green),
+            };
+            table.add_row([
+                Cell::new(id).add_attribute(Attribute::Bold),
+                Cell::new(path.display()),
+                cell,
+            ]);
+        }
+
+        println!("{table}");
+
+        if errors > 0 {
+            bail!("{errors} assistant(s) have errors");
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,521 @@
+//! Evaluation of assistants using suites of instructions
+//!
+//! Allows changes to the prompts or instruction examples of assistants to be
+//! assessed by running the same instructions, with different assistants and
+//! models, and scoring the outputs against a set of checks.
+
+use std::path::{Path, PathBuf};
+
+use codec_text_trait::TextCodec;
+use kernels::Kernels;
+use model::{
+    codecs::{self, DecodeOptions},
+    common::{
+        eyre::{bail, Result},
+        regex::Regex,
+        serde::Deserialize,
+        serde_json::{self, Value},
+        serde_yaml,
+        tokio::fs,
+    },
+    context::Context,
+    format::Format,
+    schema::{
+        Block, Inline, InstructionBlock, InstructionMessage, InstructionType, MessageLevel, Node,
+        Visitor, WalkControl,
+    },
+    GenerateOptions, GenerateOutput, Nodes,
+};
+
+use assistant::ExpectedNodes;
+
+use crate::execute_instruction;
+
+/// A suite of cases for evaluating assistants
+#[derive(Debug, Deserialize)]
+#[serde(
+    rename_all = "kebab-case",
+    deny_unknown_fields,
+    crate = "model::common::serde"
+)]
+pub struct Suite {
+    /// The names of the assistants to evaluate
+    #[serde(default)]
+    pub assistants: Vec<String>,
+
+    /// The names of the models to evaluate each assistant with
+    ///
+    /// If empty, each assistant uses its own models.
+    #[serde(default)]
+    pub models: Vec<String>,
+
+    /// The cases in the suite
+    pub cases: Vec<Case>,
+
+    /// The directory of the suite, which paths of documents are relative to
+    #[serde(skip)]
+    dir: PathBuf,
+}
+
+/// A case within an evaluation suite
+#[derive(Debug, Deserialize)]
+#[serde(
+    rename_all = "kebab-case",
+    deny_unknown_fields,
+    crate = "model::common::serde"
+)]
+pub struct Case {
+    /// The name of the case
+    pub name: String,
+
+    /// The text of the instruction
+    pub instruction: String,
+
+    /// The type of the instruction
+    #[serde(rename = "type", default)]
+    pub instruction_type: InstructionType,
+
+    /// The content of the instruction, as Markdown (e.g. for edit instructions)
+    pub content: Option<String>,
+
+    /// A document which provides the context for the instruction
+    ///
+    /// The code chunks in the document are executed before the instruction
+    /// so that the kernels have the same state as when the instruction is
+    /// within the document.
+    pub document: Option<PathBuf>,
+
+    /// The type and number of nodes expected e.g. `CodeChunk` or `Paragraph+`
+    pub expected_nodes: Option<ExpectedNodes>,
+
+    /// Whether the generated code should execute without errors
+    #[serde(default)]
+    pub executes: bool,
+
+    /// Regular expressions that the generated content should match
+    #[serde(default)]
+    pub regexes: Vec<String>,
+
+    /// Assertions on the JSON representation of the generated nodes
+    #[serde(default)]
+    pub json: Vec<JsonAssertion>,
+}
+
+/// An assertion on the JSON representation of generated nodes
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, crate = "model::common::serde")]
+pub struct JsonAssertion {
+    /// A JSON Pointer to a value e.g. `/0/programmingLanguage`
+    pub pointer: String,
+
+    /// The value expected at the pointer
+    ///
+    /// If neither this, or `matches` are specified then the
+    /// assertion is that a value exists at the pointer.
+    pub equals: Option<Value>,
+
+    /// A regular expression that the string value at the pointer should match
+    pub matches: Option<String>,
+}
+
+/// The result of running a case with an assistant and model
+#[derive(Debug)]
+pub struct Run {
+    /// The name of the case
+    pub case: String,
+
+    /// The name of the assistant
+    pub assistant: String,
+
+    /// The name of the model, if specified
+    pub model: Option<String>,
+
+    /// The number of checks that passed
+    pub passed: usize,
+
+    /// The total number of checks
+    pub total: usize,
+
+    /// A description of each failed check, or of the error generating output
+    pub failures: Vec<String>,
+}
+
+impl Suite {
+    /// Read a suite from a YAML file
+    pub async fn read(path: &Path) -> Result<Self> {
+        let yaml = fs::read_to_string(path).await?;
+
+        let mut suite: Suite = serde_yaml::from_str(&yaml)?;
+        suite.dir = path
+            .parent()
+            .map(|dir| dir.to_path_buf())
+            .unwrap_or_default();
+
+        Ok(suite)
+    }
+
+    /// Run each case in the suite with each of the assistants and models
+    ///
+    /// Assistants and models override those specified in the suite, if not empty.
+    pub async fn run(&self, assistants: &[String], models: &[String]) -> Result<Vec<Run>> {
+        let assistants = if assistants.is_empty() {
+            &self.assistants
+        } else {
+            assistants
+        };
+        if assistants.is_empty() {
+            bail!("No assistants specified in suite or as arguments")
+        }
+
+        let models = if models.is_empty() {
+            &self.models
+        } else {
+            models
+        };
+        let models = if models.is_empty() {
+            vec![None]
+        } else {
+            models.iter().cloned().map(Some).collect()
+        };
+
+        let mut runs = Vec::new();
+        for case in &self.cases {
+            for assistant in assistants {
+                for model in &models {
+                    runs.push(self.run_case(case, assistant, model.clone()).await);
+                }
+            }
+        }
+
+        Ok(runs)
+    }
+
+    /// Run a case with an assistant and model
+    async fn run_case(&self, case: &Case, assistant: &str, model: Option<String>) -> Run {
+        let mut run = Run {
+            case: case.name.clone(),
+            assistant: assistant.to_string(),
+            model: model.clone(),
+            passed: 0,
+            total: case.checks(),
+            failures: Vec::new(),
+        };
+
+        // Kernels are used for the instruction's context, and to check generated code,
+        // and are stopped at the end of the case whether or not generation succeeded
+        let mut kernels = Kernels::new(&self.home(case));
+
+        match self.generate(case, assistant, model, &mut kernels).await {
+            Ok(output) => {
+                for failure in case.check(&output, &mut kernels).await {
+                    match failure {
+                        None => run.passed += 1,
+                        Some(failure) => run.failures.push(failure),
+                    }
+                }
+            }
+            Err(error) => run.failures.push(format!("Error: {error}")),
+        }
+
+        kernels.stop().await;
+
+        run
+    }
+
+    /// Get the home directory for a case
+    ///
+    /// The directory of the case's document, if any, otherwise that of the suite
+    /// so that workspace assistants are available.
+    fn home(&self, case: &Case) -> PathBuf {
+        case.document
+            .as_ref()
+            .and_then(|document| self.dir.join(document).parent().map(Path::to_path_buf))
+            .unwrap_or_else(|| self.dir.clone())
+    }
+
+    /// Generate output for a case
+    ///
+    /// The code chunks in the case's document, if any, are executed in `kernels`
+    /// before the instruction so that they provide its context.
+    async fn generate(
+        &self,
+        case: &Case,
+        assistant: &str,
+        model: Option<String>,
+        kernels: &mut Kernels,
+    ) -> Result<GenerateOutput> {
+        let mut instruction = InstructionBlock::new(
+            case.instruction_type.clone(),
+            vec![InstructionMessage::user(&case.instruction)],
+        );
+        instruction.assignee = Some(assistant.to_string());
+        if let Some(content) = &case.content {
+            if let Node::Article(article) = codecs::from_str(
+                content,
+                Some(DecodeOptions {
+                    format: Some(Format::Markdown),
+                    ..Default::default()
+                }),
+            )
+            .await?
+            {
+                instruction.content = Some(article.content);
+            }
+        }
+
+        let context = match &case.document {
+            Some(document) => {
+                let node = codecs::from_path(&self.dir.join(document), None).await?;
+                let mut collector = Collector::default();
+                collector.visit(&node);
+
+                for (code, language) in &collector.code {
+                    kernels.execute(code, language.as_deref()).await?;
+                }
+
+                let mut context = collector.context;
+                context.kernels = kernels.kernel_contexts().await;
+                context.home = Some(self.home(case));
+
+                context
+            }
+            None => Context {
+                home: Some(self.home(case)),
+                skip_passages: true,
+                ..Default::default()
+            },
+        };
+
+        let options = GenerateOptions {
+            model,
+            ..Default::default()
+        };
+        execute_instruction(instruction, context, options).await
+    }
+}
+
+impl Case {
+    /// The number of checks for the case
+    fn checks(&self) -> usize {
+        self.expected_nodes.iter().count()
+            + self.executes as usize
+            + self.regexes.len()
+            + self.json.len()
+    }
+
+    /// Check the output generated for the case
+    ///
+    /// Returns `None` for each check that passed and a description of
+    /// the failure for each that did not.
+    async fn check(&self, output: &GenerateOutput, kernels: &mut Kernels) -> Vec<Option<String>> {
+        let mut results = Vec::new();
+
+        if let Some(expected) = &self.expected_nodes {
+            results.push(match expected.matches(&output.nodes) {
+                Ok(true) => None,
+                Ok(false) => Some(format!("Expected nodes `{}`", String::from(*expected))),
+                Err(error) => Some(error.to_string()),
+            });
+        }
+
+        if self.executes {
+            results.push(execute_code(&output.nodes, kernels).await);
+        }
+
+        for regex in &self.regexes {
+            results.push(match Regex::new(regex) {
+                Ok(re) if re.is_match(&output.content) => None,
+                Ok(..) => Some(format!("Expected content to match `{regex}`")),
+                Err(error) => Some(format!("Invalid regex `{regex}`: {error}")),
+            });
+        }
+
+        let json = serde_json::to_value(&output.nodes).unwrap_or_default();
+        for assertion in &self.json {
+            results.push(assertion.check(&json));
+        }
+
+        results
+    }
+}
+
+impl JsonAssertion {
+    /// Check the assertion against a JSON value
+    fn check(&self, json: &Value) -> Option<String> {
+        let pointer = &self.pointer;
+        let Some(value) = json.pointer(pointer) else {
+            return Some(format!("Expected a value at `{pointer}`"));
+        };
+
+        if let Some(expected) = &self.equals {
+            if value != expected {
+                return Some(format!(
+                    "Expected `{expected}` at `{pointer}`, got `{value}`"
+                ));
+            }
+        }
+
+        if let Some(regex) = &self.matches {
+            let string = value.as_str().unwrap_or_default();
+            match Regex::new(regex) {
+                Ok(re) if re.is_match(string) => {}
+                Ok(..) => return Some(format!("Expected value at `{pointer}` to match `{regex}`")),
+                Err(error) => return Some(format!("Invalid regex `{regex}`: {error}")),
+            }
+        }
+
+        None
+    }
+}
+
+/// Execute the code chunks in generated nodes and check that there are no errors
+async fn execute_code(nodes: &Nodes, kernels: &mut Kernels) -> Option<String> {
+    let Nodes::Blocks(blocks) = nodes else {
+        return Some("Expected code chunks, got inline nodes".to_string());
+    };
+
+    let chunks = blocks
+        .iter()
+        .filter_map(|block| match block {
+            Block::CodeChunk(chunk) => Some(chunk),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    if chunks.is_empty() {
+        return Some("Expected code chunks to execute".to_string());
+    }
+
+    for chunk in chunks {
+        let language = chunk.programming_language.as_deref();
+        let code = chunk.code.to_string();
+        let messages = match kernels.execute(&code, language).await {
+            Ok((.., messages)) => messages,
+            Err(error) => return Some(format!("Unable to execute code: {error}")),
+        };
+
+        if let Some(message) = messages
+            .iter()
+            .find(|message| matches!(message.level, MessageLevel::Error | MessageLevel::Exception))
+        {
+            return Some(format!("Code failed: {}", message.message));
+        }
+    }
+
+    None
+}
+
+/// A visitor which collects the context for an instruction, and the
+/// code to execute, from a document
+#[derive(Default)]
+struct Collector {
+    /// The context of the document
+    context: Context,
+
+    /// The code, and language, of each code chunk in the document
+    code: Vec<(String, Option<String>)>,
+}
+
+impl Visitor for Collector {
+    fn visit_node(&mut self, node: &Node) -> WalkControl {
+        if let Node::Article(article) = node {
+            if let Some(title) = &article.title {
+                self.context.set_title(&title.to_text().0);
+            }
+            if let Some(genre) = &article.genre {
+                self.context.set_genre(&genre.to_text().0);
+            }
+            if let Some(keywords) = &article.keywords {
+                self.context.set_keywords(keywords);
+            }
+        }
+
+        WalkControl::Continue
+    }
+
+    fn visit_block(&mut self, block: &Block) -> WalkControl {
+        match block {
+            Block::CodeChunk(node) => {
+                self.context.push_code_chunk(node);
+                self.code
+                    .push((node.code.to_string(), node.programming_language.clone()));
+            }
+            Block::InstructionBlock(node) => self.context.push_instruction_block(node),
+            Block::MathBlock(node) => self.context.push_math_block(node),
+            Block::Heading(node) => self.context.push_heading(node),
+            Block::Paragraph(node) => self.context.push_paragraph(node),
+            _ => {}
+        }
+
+        WalkControl::Continue
+    }
+
+    fn visit_inline(&mut self, inline: &Inline) -> WalkControl {
+        match inline {
+            Inline::CodeExpression(node) => self.context.push_code_expression(node),
+            Inline::InstructionInline(node) => self.context.push_instruction_inline(node),
+            Inline::MathInline(node) => self.context.push_math_inline(node),
+            Inline::Text(node) => self.context.push_text(node),
+            _ => {}
+        }
+
+        WalkControl::Continue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_assertions() {
+        let json = serde_json::json!([{"type": "CodeChunk", "programmingLanguage": "python"}]);
+
+        let assertion = |pointer: &str, equals: Option<Value>, matches: Option<&str>| {
+            JsonAssertion {
+                pointer: pointer.to_string(),
+                equals,
+                matches: matches.map(String::from),
+            }
+            .check(&json)
+        };
+
+        assert!(assertion("/0/type", None, None).is_none());
+        assert!(assertion("/1/type", None, None).is_some());
+        assert!(assertion("/0/type", Some(Value::from("CodeChunk")), None).is_none());
+        assert!(assertion("/0/type", Some(Value::from("Paragraph")), None).is_some());
+        assert!(assertion("/0/programmingLanguage", None, Some("^py")).is_none());
+        assert!(assertion("/0/programmingLanguage", None, Some("^r$")).is_some());
+    }
+
+    #[test]
+    fn suite_can_be_parsed() -> Result<()> {
+        let suite: Suite = serde_yaml::from_str(
+            r#"
+assistants: [insert-code-chunk]
+models: [openai/gpt-4o]
+cases:
+  - name: histogram
+    instruction: Plot a histogram of the heights
+    document: data.smd
+    expected-nodes: CodeChunk
+    executes: true
+    regexes: [hist]
+    json:
+      - pointer: /0/programmingLanguage
+        equals: python
+  - name: summary
+    type: Edit
+    instruction: Make this more concise
+    content: A very long paragraph.
+    expected-nodes: Paragraph+
+"#,
+        )?;
+
+        assert_eq!(suite.cases.len(), 2);
+        assert_eq!(suite.cases[0].checks(), 4);
+        assert_eq!(suite.cases[1].instruction_type, InstructionType::Edit);
+        assert_eq!(suite.cases[1].checks(), 1);
+
+        Ok(())
+    }
+}
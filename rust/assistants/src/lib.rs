@@ -1,6 +1,6 @@
 #![recursion_limit = "256"]
 
-use std::{cmp::Ordering, sync::Arc};
+use std::{cmp::Ordering, path::Path, sync::Arc};
 
 use model::{
     common::{
@@ -10,20 +10,24 @@ use model::{
         tracing,
     },
     context::Context,
-    GenerateOptions, GenerateOutput, GenerateTask, Instruction, Model,
+    GenerateOptions, GenerateOutput, GenerateSender, GenerateTask, Instruction, Model, Tools,
 };
 
 pub use assistant;
 
 pub mod cli;
+pub mod eval;
 
 /// Get a list of available assistants in descending preference rank
-pub async fn list(all: bool) -> Vec<Arc<dyn Model>> {
+///
+/// Specialized assistants in the workspace containing `home` (usually the directory
+/// of the document that the instruction is in) are included.
+pub async fn list(all: bool, home: Option<&Path>) -> Vec<Arc<dyn Model>> {
     let range = if all { 0..=6 } else { 5..=6 };
 
     let futures = range.map(|provider| async move {
         let (provider, result) = match provider {
-            5 => ("specialized", assistant::list()),
+            5 => ("specialized", assistant::list(home)),
             6 => ("plugin", plugins::assistants::list().await),
             _ => return vec![],
         };
@@ -66,29 +70,62 @@ where
     assistant.perform_task(&task, &options).await
 }
 
+/// Execute an instruction, streaming the generated text as it arrives
+///
+/// The text generated so far is sent on `sender` as the assistant generates it.
+/// If `tools` are supplied then models that support tool calls may call them
+/// while performing the instruction.
+pub async fn execute_instruction_stream<T>(
+    instruction: T,
+    context: Context,
+    options: GenerateOptions,
+    tools: Option<Arc<dyn Tools>>,
+    sender: GenerateSender,
+) -> Result<GenerateOutput>
+where
+    Instruction: From<T>,
+{
+    let instruction = Instruction::from(instruction);
+
+    let mut task = GenerateTask::new(instruction, Some(context)).with_tools(tools);
+    let assistant = get_assistant(&mut task).await?;
+    assistant.perform_task_stream(&task, &options, sender).await
+}
+
 /// Get the assistant for a task
 ///
 /// If the task's instruction has an `assignee` (and assignee exists and supports the
 /// task) then returns that assistant. Otherwise returns the assignee with the highest
 /// suitability score for the task.
 pub async fn get_assistant(task: &mut GenerateTask) -> Result<Arc<dyn Model>> {
-    let assistants = list(true).await;
+    let home = task.context().and_then(|context| context.home.clone());
+    let assistants = list(true, home.as_deref()).await;
 
     let assistant = if let Some(assignee) = task.instruction().assignee() {
-        let name = if assignee.contains('/') {
-            assignee.to_string()
-        } else {
-            ["stencila/", assignee].concat()
-        };
-
+        // Assignees without a prefix (or with the prefix of a specialized assistant that
+        // has been overridden) match the specialized assistant with the same name, in
+        // order of precedence
         let assistant = assistants
             .iter()
-            .find(|assistant| assistant.name() == name)
-            .ok_or_else(|| eyre!("No assistant with name `{name}`"))?;
+            .find(|assistant| assistant.name() == assignee)
+            .or_else(|| {
+                let name = match assignee.split_once('/') {
+                    Some(("stencila" | "local" | "workspace", name)) => name,
+                    Some(..) => return None,
+                    None => assignee,
+                };
+                ["workspace/", "local/", "stencila/"]
+                    .iter()
+                    .find_map(|prefix| {
+                        let name = [prefix, name].concat();
+                        assistants.iter().find(|assistant| assistant.name() == name)
+                    })
+            })
+            .ok_or_else(|| eyre!("No assistant with name `{assignee}`"))?;
 
         // Check that the assignee supports the task
         if !assistant.supports_task(task) {
-            bail!("The assigned assistant `{name}` does not support this task")
+            bail!("The assigned assistant `{assignee}` does not support this task")
         }
 
         tracing::debug!("Using assistant with name: {}", assistant.name());
@@ -29,6 +29,7 @@ async fn local_get_assistant(itype: InstructionType, text: String) -> Result<(St
         InstructionType::ModifyInlines => {
             GenerateTask::new(Instruction::inline_text_with(text, vec![]), None)
         }
+        InstructionType::Chat => GenerateTask::new(Instruction::chat_text(text), None),
     };
     let assistant = get_assistant(&mut task).await?;
     let score = assistant.suitability_score(&mut task)?;
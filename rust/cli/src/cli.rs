@@ -321,6 +321,12 @@ pub enum Command {
     /// Run the Stencila Language Server
     Lsp,
 
+    /// Run the Stencila Debug Adapter
+    ///
+    /// Implements the Debug Adapter Protocol over stdio so that editors can
+    /// step through the execution of a document and inspect kernel variables.
+    Dap,
+
     Assistants(assistants::cli::Cli),
     Models(models::cli::Cli),
     Kernels(kernels::cli::Cli),
@@ -689,6 +695,8 @@ impl Cli {
 
             Command::Lsp => lsp::run().await,
 
+            Command::Dap => dap::run().await,
+
             Command::Assistants(assistants) => assistants.run().await?,
             Command::Models(models) => models.run().await?,
             Command::Kernels(kernels) => kernels.run().await?,
@@ -8,7 +8,7 @@ async fn main() -> Result<()> {
     let cli = Cli::parse();
 
     // For some commands avoid the normal logging & error reporting setup
-    let run_only = matches!(cli.command, Command::Lsp);
+    let run_only = matches!(cli.command, Command::Lsp | Command::Dap);
 
     if !run_only {
         errors::setup(&cli.error_details, cli.error_link)?;
@@ -60,6 +60,13 @@ where
         self.mapping.node_id_at(generated_index)
     }
 
+    /// Get the authorship of the `Cord` run, if any, at a UTF16-based line/column position
+    pub fn position16_to_authorship(&self, position16: Position16) -> Option<&(u8, u64, u8)> {
+        let source_index = self.positions.index_at_position16(position16)?;
+        let generated_index = self.shifter.source_to_generated(source_index);
+        self.mapping.authorship_at(generated_index)
+    }
+
     /// Get the [`Range16`] for a [`NodeId`]
     pub fn node_id_to_range16(&self, node_id: &NodeId) -> Option<Range16> {
         let generated_range = self.mapping.range_of_node(node_id)?;
@@ -25,7 +25,7 @@ use super::{
     inlines::{mds_to_inlines, mds_to_string},
     shared::{
         assignee, attrs, execution_mode, instruction_type, model, name, node_to_string,
-        primitive_node, take_until_unbalanced,
+        primitive_node, suggestion_usage, take_until_unbalanced,
     },
     Context,
 };
@@ -671,11 +671,28 @@ fn instruction_block(input: &mut Located<&str>) -> PResult<Block> {
 fn suggestion_block(input: &mut Located<&str>) -> PResult<Block> {
     preceded(
         (alt(("suggestion", "suggest")), multispace0),
-        opt(take_while(1.., |_| true)),
+        (
+            opt(terminated(attrs, multispace0)),
+            opt(take_while(1.., |_| true)),
+        ),
     )
-    .map(|feedback| {
+    .map(|(usage, feedback)| {
+        let options: IndexMap<&str, _> = usage.unwrap_or_default().into_iter().collect();
+
+        let usage = |name: &str| options.get(name).cloned().flatten().map(node_to_string);
+        let (prompt_tokens, completion_tokens, cost, execution_duration) = suggestion_usage(
+            usage("prompt_tokens"),
+            usage("completion_tokens"),
+            usage("cost"),
+            usage("duration"),
+        );
+
         Block::SuggestionBlock(SuggestionBlock {
             feedback: feedback.map(String::from),
+            prompt_tokens,
+            completion_tokens,
+            cost,
+            execution_duration,
             ..Default::default()
         })
     })
@@ -1212,7 +1229,7 @@ fn myst_to_block(code: &mdast::Code) -> Option<Block> {
             select: options.get("select").map(|select| select.to_string()),
             ..Default::default()
         }),
-        "new" | "edit" | "update" => {
+        "new" | "edit" | "update" | "chat" => {
             let message = InstructionMessage::from(args.unwrap_or_default().trim());
 
             Block::InstructionBlock(InstructionBlock {
@@ -1228,11 +1245,25 @@ fn myst_to_block(code: &mdast::Code) -> Option<Block> {
                 ..Default::default()
             })
         }
-        "suggest" => Block::SuggestionBlock(SuggestionBlock {
-            feedback: args.map(|value| value.to_string()),
-            content: decode_blocks(&value),
-            ..Default::default()
-        }),
+        "suggest" => {
+            let usage = |name: &str| options.get(name).map(|value| value.to_string());
+            let (prompt_tokens, completion_tokens, cost, execution_duration) = suggestion_usage(
+                usage("prompt-tokens"),
+                usage("completion-tokens"),
+                usage("cost"),
+                usage("duration"),
+            );
+
+            Block::SuggestionBlock(SuggestionBlock {
+                feedback: args.map(|value| value.to_string()),
+                prompt_tokens,
+                completion_tokens,
+                cost,
+                execution_duration,
+                content: decode_blocks(&value),
+                ..Default::default()
+            })
+        }
         "style" => Block::StyledBlock(StyledBlock {
             code: args.unwrap_or_default().into(),
             content: decode_blocks(&value),
@@ -1446,7 +1477,7 @@ fn mds_to_table_cells(mds: Vec<mdast::Node>, context: &mut Context) -> Vec<Table
 
 #[cfg(test)]
 mod tests {
-    use codec::schema::{ClaimType, ExecutionMode, Node};
+    use codec::schema::{ClaimType, Duration, ExecutionMode, InstructionType, Node, TimeUnit};
     use common_dev::pretty_assertions::assert_eq;
 
     use super::*;
@@ -1718,6 +1749,49 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_instruction_block_chat() {
+        assert_eq!(
+            block(&mut Located::new("::: chat @chat load the data :::")).unwrap(),
+            Block::InstructionBlock(InstructionBlock {
+                instruction_type: InstructionType::Chat,
+                messages: vec![InstructionMessage::from("load the data")],
+                assignee: Some("chat".to_string()),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_suggestion_block() {
+        assert_eq!(
+            suggestion_block(&mut Located::new("suggest looks good")).unwrap(),
+            Block::SuggestionBlock(SuggestionBlock {
+                feedback: Some("looks good".to_string()),
+                ..Default::default()
+            })
+        );
+
+        assert_eq!(
+            suggestion_block(&mut Located::new(
+                "suggest {prompt_tokens=120 completion_tokens=45 cost=0.0012 duration=2345} looks good"
+            ))
+            .unwrap(),
+            Block::SuggestionBlock(SuggestionBlock {
+                feedback: Some("looks good".to_string()),
+                prompt_tokens: Some(120),
+                completion_tokens: Some(45),
+                cost: Some(0.0012),
+                execution_duration: Some(Duration {
+                    value: 2345,
+                    time_unit: TimeUnit::Millisecond,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+        );
+    }
+
     #[test]
     fn test_styled_block() {
         assert_eq!(
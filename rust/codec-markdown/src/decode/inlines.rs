@@ -3,7 +3,7 @@ use std::{collections::HashMap, ops::Range};
 use markdown::{mdast, unist::Position};
 use winnow::{
     ascii::{multispace0, multispace1, space0},
-    combinator::{alt, delimited, not, opt, peek, preceded, repeat, separated},
+    combinator::{alt, delimited, not, opt, peek, preceded, repeat, separated, terminated},
     stream::{Located, Stream},
     token::{take, take_until, take_while},
     PResult, Parser,
@@ -28,7 +28,7 @@ use super::{
         assignee, attrs, instruction_type, name, node_to_from_str, node_to_option_date,
         node_to_option_datetime, node_to_option_duration, node_to_option_i64,
         node_to_option_number, node_to_option_time, node_to_option_timestamp, node_to_string,
-        take_until_unbalanced,
+        suggestion_usage, take_until_unbalanced,
     },
     Context,
 };
@@ -831,9 +831,30 @@ fn take_until_edit<'s>(input: &mut Located<&'s str>) -> PResult<(&'s str, &'stat
 
 /// Parse a string into a `SuggestionInline` node
 fn suggestion_inline(input: &mut Located<&str>) -> PResult<Inline> {
-    (EDIT_START, "suggest", ' ')
-        .map(|_| Inline::SuggestionInline(SuggestionInline::default()))
-        .parse_next(input)
+    preceded(
+        (EDIT_START, "suggest", ' '),
+        opt(terminated(attrs, multispace0)),
+    )
+    .map(|usage| {
+        let options: IndexMap<&str, _> = usage.unwrap_or_default().into_iter().collect();
+
+        let usage = |name: &str| options.get(name).cloned().flatten().map(node_to_string);
+        let (prompt_tokens, completion_tokens, cost, execution_duration) = suggestion_usage(
+            usage("prompt_tokens"),
+            usage("completion_tokens"),
+            usage("cost"),
+            usage("duration"),
+        );
+
+        Inline::SuggestionInline(SuggestionInline {
+            prompt_tokens,
+            completion_tokens,
+            cost,
+            execution_duration,
+            ..Default::default()
+        })
+    })
+    .parse_next(input)
 }
 
 /// Parse a string into a `InsertInline` node
@@ -12,7 +12,8 @@ use winnow::{
 };
 
 use codec::schema::{
-    Date, DateTime, Duration, ExecutionMode, InstructionType, Node, Time, Timestamp,
+    Date, DateTime, Duration, ExecutionMode, InstructionType, Node, Number, Time, TimeUnit,
+    Timestamp, UnsignedInteger,
 };
 use codec_json5_trait::Json5Codec;
 use codec_text_trait::TextCodec;
@@ -43,11 +44,12 @@ pub(super) fn execution_mode(input: &mut &str) -> PResult<ExecutionMode> {
 
 /// Parse an instruction type
 pub(super) fn instruction_type(input: &mut Located<&str>) -> PResult<InstructionType> {
-    alt(("new", "edit", "update"))
+    alt(("new", "edit", "update", "chat"))
         .map(|typ| match typ {
             "new" => InstructionType::New,
             "edit" => InstructionType::Edit,
             "update" => InstructionType::Update,
+            "chat" => InstructionType::Chat,
             _ => unreachable!(),
         })
         .parse_next(input)
@@ -497,3 +499,33 @@ mod tests {
         );
     }
 }
+
+/// Parse the usage of a model to generate a suggestion from the values of options
+///
+/// The reverse of the encoding of the tokens, cost and duration (in milliseconds)
+/// of suggestions when they are encoded to Markdown.
+#[allow(clippy::type_complexity)]
+pub(super) fn suggestion_usage(
+    prompt_tokens: Option<String>,
+    completion_tokens: Option<String>,
+    cost: Option<String>,
+    duration: Option<String>,
+) -> (
+    Option<UnsignedInteger>,
+    Option<UnsignedInteger>,
+    Option<Number>,
+    Option<Duration>,
+) {
+    (
+        prompt_tokens.and_then(|value| value.parse().ok()),
+        completion_tokens.and_then(|value| value.parse().ok()),
+        cost.and_then(|value| value.parse().ok()),
+        duration
+            .and_then(|value| value.parse().ok())
+            .map(|value| Duration {
+                value,
+                time_unit: TimeUnit::Millisecond,
+                ..Default::default()
+            }),
+    )
+}
@@ -1,4 +1,4 @@
-use std::collections::VecDeque;
+use std::{collections::VecDeque, path::PathBuf};
 
 use common::{serde::Serialize, smart_default::SmartDefault};
 use schema::{
@@ -23,6 +23,10 @@ use schema::{
 ///   the document's execution kernels. Intended for use in specialized
 ///   assistants for `CodeChunk` and `CodeExpression` nodes to help improve
 ///   the accuracy of generated code.
+///
+/// - The `passages` property which provides passages from other files in the
+///   document's project which are relevant to the instruction. This is populated
+///   when a generation task is prepared, rather than when walking the document.
 #[derive(Debug, SmartDefault, Clone, Serialize)]
 #[serde(crate = "common::serde")]
 pub struct Context {
@@ -74,9 +78,43 @@ pub struct Context {
 
     /// Information about the document's execution kernels
     pub kernels: Vec<KernelContext>,
+
+    /// The directory of the document
+    ///
+    /// Used to find the workspace that the document is in so that
+    /// relevant `passages` can be retrieved from the workspace's files,
+    /// and workspace assistants and fixtures can be found.
+    #[serde(skip)]
+    pub home: Option<PathBuf>,
+
+    /// The path of the document
+    ///
+    /// Used to exclude the document itself from the retrieved `passages`.
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+
+    /// Whether to skip retrieving `passages` from the project's files
+    #[serde(skip)]
+    pub skip_passages: bool,
+
+    /// Passages from the project's files which are relevant to the instruction
+    pub passages: Vec<Passage>,
 }
 
 impl Context {
+    /// Get the workspace directory of the document
+    ///
+    /// The closest ancestor of the document's `home` which contains a `.stencila`
+    /// directory, falling back to `home` itself. Returns `None` if `home` is not set.
+    pub fn workspace(&self) -> Option<PathBuf> {
+        let home = self.home.as_ref()?;
+        let workspace = home
+            .ancestors()
+            .find(|dir| dir.join(".stencila").is_dir())
+            .unwrap_or(home);
+        Some(workspace.to_path_buf())
+    }
+
     pub fn set_title(&mut self, title: &str) {
         self.title = Some(title.to_string());
     }
@@ -137,6 +175,20 @@ impl Context {
     }
 }
 
+/// A passage from a file in the project which is relevant to an instruction
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "common::serde")]
+pub struct Passage {
+    /// The path of the file, relative to the root of the project
+    pub path: String,
+
+    /// The text of the passage
+    pub text: String,
+
+    /// The similarity of the passage to the instruction
+    pub score: f32,
+}
+
 /// Contextual information from a kernel
 ///
 /// This encapsulates the information that can be obtained from
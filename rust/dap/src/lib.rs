@@ -0,0 +1,11 @@
+//! A Debug Adapter Protocol (DAP) server for stepping through the execution of documents
+//!
+//! Breakpoints can be set on code chunks, "stepping" executes the next executable node
+//! in the document, and the variables view shows the variables in the document's kernels.
+
+mod protocol;
+mod run;
+mod session;
+mod steps;
+
+pub use run::run;
@@ -0,0 +1,245 @@
+//! Messages of the Debug Adapter Protocol and their transport
+//!
+//! Only the parts of the protocol used by the debug adapter are implemented.
+//!
+//! See https://microsoft.github.io/debug-adapter-protocol/specification
+
+use common::{
+    eyre::{bail, Result},
+    serde::{Deserialize, Serialize},
+    serde_json::{self, Value},
+    tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+};
+
+/// A request from the client
+#[derive(Debug, Deserialize)]
+#[serde(crate = "common::serde")]
+pub(crate) struct Request {
+    /// The sequence number of the request
+    pub seq: i64,
+
+    /// The command requested
+    pub command: String,
+
+    /// The arguments of the command
+    #[serde(default)]
+    pub arguments: Value,
+}
+
+/// A message sent to the client
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase", crate = "common::serde")]
+pub(crate) enum Message {
+    /// A response to a request
+    Response {
+        request_seq: i64,
+        success: bool,
+        command: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        message: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        body: Option<Value>,
+    },
+
+    /// An event e.g. `stopped` or `output`
+    Event {
+        event: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        body: Option<Value>,
+    },
+}
+
+impl Message {
+    /// Create a successful response to a request
+    pub fn success(request: &Request, body: Option<Value>) -> Self {
+        Self::Response {
+            request_seq: request.seq,
+            success: true,
+            command: request.command.clone(),
+            message: None,
+            body,
+        }
+    }
+
+    /// Create an error response to a request
+    pub fn error(request: &Request, message: String) -> Self {
+        Self::Response {
+            request_seq: request.seq,
+            success: false,
+            command: request.command.clone(),
+            message: Some(message),
+            body: None,
+        }
+    }
+
+    /// Create an event
+    pub fn event(event: &str, body: Option<Value>) -> Self {
+        Self::Event {
+            event: event.to_string(),
+            body,
+        }
+    }
+}
+
+/// Read a request from the client
+///
+/// Returns `None` if the input has ended. Messages other than requests
+/// (e.g. responses to reverse requests) are skipped.
+pub(crate) async fn read<R>(reader: &mut R) -> Result<Option<Request>>
+where
+    R: AsyncBufRead + Unpin,
+{
+    loop {
+        // Read the header, until an empty line, to get the length of the content
+        let mut length = None;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).await? == 0 {
+                return Ok(None);
+            }
+
+            let line = line.trim();
+            if line.is_empty() {
+                break;
+            }
+
+            if let Some(value) = line.strip_prefix("Content-Length:") {
+                length = Some(value.trim().parse::<usize>()?);
+            }
+        }
+        let Some(length) = length else {
+            bail!("Message header has no `Content-Length`")
+        };
+
+        let mut content = vec![0; length];
+        reader.read_exact(&mut content).await?;
+
+        let value: Value = serde_json::from_slice(&content)?;
+        if value.get("type").and_then(|value| value.as_str()) != Some("request") {
+            continue;
+        }
+
+        return Ok(Some(serde_json::from_value(value)?));
+    }
+}
+
+/// Write a message to the client
+///
+/// The `seq` is the sequence number of the message which is added to it.
+pub(crate) async fn write<W>(writer: &mut W, seq: i64, message: &Message) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut value = serde_json::to_value(message)?;
+    if let Some(object) = value.as_object_mut() {
+        object.insert("seq".to_string(), Value::from(seq));
+    }
+
+    let content = serde_json::to_string(&value)?;
+    writer
+        .write_all(format!("Content-Length: {}\r\n\r\n{content}", content.len()).as_bytes())
+        .await?;
+    writer.flush().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use common::tokio::{self, io::BufReader};
+
+    use super::*;
+
+    /// Frame some content with a header as it is by clients
+    fn frame(content: &str) -> String {
+        format!("Content-Length: {}\r\n\r\n{content}", content.len())
+    }
+
+    #[tokio::test]
+    async fn read_requests() -> Result<()> {
+        // Requests with non-ASCII content (so that the length in bytes differs from
+        // the length in characters) and other messages between them
+        let input = [
+            frame(r#"{"seq":1,"type":"request","command":"evaluate","arguments":{"expression":"π"}}"#),
+            frame(r#"{"seq":2,"type":"response","request_seq":1,"success":true,"command":"runInTerminal"}"#),
+            frame(r#"{"seq":3,"type":"request","command":"threads"}"#),
+        ]
+        .concat();
+        let mut reader = BufReader::new(input.as_bytes());
+
+        let request = read(&mut reader).await?.expect("should be a request");
+        assert_eq!(request.seq, 1);
+        assert_eq!(request.command, "evaluate");
+        assert_eq!(request.arguments["expression"], "π");
+
+        let request = read(&mut reader).await?.expect("should be a request");
+        assert_eq!(request.seq, 3);
+        assert_eq!(request.command, "threads");
+        assert_eq!(request.arguments, Value::Null);
+
+        assert!(read(&mut reader).await?.is_none());
+
+        // Missing content length
+        let mut reader = BufReader::new("Content-Type: json\r\n\r\n{}".as_bytes());
+        assert!(read(&mut reader).await.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn write_messages() -> Result<()> {
+        let request = Request {
+            seq: 5,
+            command: "evaluate".to_string(),
+            arguments: Value::Null,
+        };
+
+        let mut output = Vec::new();
+        write(
+            &mut output,
+            1,
+            &Message::success(&request, Some(serde_json::json!({ "result": "π" }))),
+        )
+        .await?;
+        write(&mut output, 2, &Message::event("terminated", None)).await?;
+
+        // Read the messages back using the header to split them
+        let mut output = output.as_slice();
+        let mut messages = Vec::new();
+        while !output.is_empty() {
+            let header_end = output
+                .windows(4)
+                .position(|window| window == b"\r\n\r\n")
+                .expect("should have header");
+            let header = std::str::from_utf8(&output[..header_end])?;
+            let length: usize = header
+                .strip_prefix("Content-Length: ")
+                .expect("should have content length")
+                .parse()?;
+            let content = &output[header_end + 4..header_end + 4 + length];
+            messages.push(serde_json::from_slice::<Value>(content)?);
+            output = &output[header_end + 4 + length..];
+        }
+
+        assert_eq!(
+            messages,
+            vec![
+                serde_json::json!({
+                    "seq": 1,
+                    "type": "response",
+                    "request_seq": 5,
+                    "success": true,
+                    "command": "evaluate",
+                    "body": { "result": "π" }
+                }),
+                serde_json::json!({
+                    "seq": 2,
+                    "type": "event",
+                    "event": "terminated"
+                })
+            ]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,67 @@
+use common::{
+    tokio::{self, io::BufReader, sync::mpsc},
+    tracing,
+};
+
+use crate::{protocol, session::Session};
+
+/// Run the debug adapter
+///
+/// Reads requests from stdin and writes responses and events to stdout.
+pub async fn run() {
+    // Write messages to stdout in a separate task so that events can be
+    // sent while steps are running
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let writer = tokio::spawn(async move {
+        let mut stdout = tokio::io::stdout();
+        let mut seq = 1;
+        while let Some(message) = receiver.recv().await {
+            if let Err(error) = protocol::write(&mut stdout, seq, &message).await {
+                tracing::error!("While writing message: {error}");
+                break;
+            }
+            seq += 1;
+        }
+    });
+
+    // Read requests from stdin in a separate task since reading is not cancellation safe
+    let (request_sender, mut requests) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        let mut stdin = BufReader::new(tokio::io::stdin());
+        loop {
+            match protocol::read(&mut stdin).await {
+                Ok(Some(request)) => {
+                    if request_sender.send(request).is_err() {
+                        break;
+                    }
+                }
+                Ok(None) => break,
+                Err(error) => {
+                    tracing::error!("While reading request: {error}");
+                    break;
+                }
+            }
+        }
+    });
+
+    let (stop_sender, mut stops) = mpsc::unbounded_channel();
+    let mut session = Session::new(sender, stop_sender);
+    loop {
+        tokio::select! {
+            request = requests.recv() => {
+                let Some(request) = request else {
+                    break;
+                };
+                if !session.handle(request).await {
+                    break;
+                }
+            }
+            Some(stop) = stops.recv() => session.stopped(stop),
+        }
+    }
+
+    // Drop the session (and its sender) so that the writer finishes
+    // once all messages have been written
+    drop(session);
+    writer.await.ok();
+}
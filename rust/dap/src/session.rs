@@ -0,0 +1,711 @@
+//! A debugging session for a document
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use codec_text_trait::to_text;
+use codecs::{DecodeInfo, DecodeOptions, Format};
+use common::{
+    eyre::{bail, eyre, Result},
+    itertools::Itertools,
+    serde_json::{json, Value},
+    tokio::{
+        self,
+        sync::{
+            mpsc::{self, UnboundedReceiver, UnboundedSender},
+            Mutex, RwLock,
+        },
+    },
+    tracing,
+};
+use kernels::Kernels;
+use schema::{Node, NodeId, Patch};
+
+use crate::{
+    protocol::{Message, Request},
+    steps::{self, Step},
+};
+
+/// The id of the single thread of execution of a document
+const THREAD_ID: i64 = 1;
+
+/// The reference for the variables in the kernels of the document
+const VARIABLES_REFERENCE: i64 = 1;
+
+/// The maximum number of characters in the value of a variable
+const MAX_VALUE_LENGTH: usize = 500;
+
+/// A debugging session
+pub(crate) struct Session {
+    /// A sender of messages to the client
+    sender: UnboundedSender<Message>,
+
+    /// A sender of stops (to clone for each run of steps)
+    stop_sender: UnboundedSender<Stop>,
+
+    /// Whether lines sent to, and received from, the client start at 1
+    lines_start_at1: bool,
+
+    /// The document being debugged (once launched)
+    program: Option<Program>,
+
+    /// Whether to stop before executing the first step
+    stop_on_entry: bool,
+
+    /// The indices of the steps that have breakpoints
+    breakpoints: HashSet<usize>,
+
+    /// The index of the next step to be executed
+    position: usize,
+
+    /// Whether steps are currently being run
+    running: bool,
+
+    /// Whether the client has requested that running steps be paused
+    pause: Arc<AtomicBool>,
+}
+
+/// A document that has been launched for debugging
+struct Program {
+    /// The path of the document
+    path: PathBuf,
+
+    /// The executable steps in the document
+    steps: Arc<Vec<Step>>,
+
+    /// The execution state of the document
+    execution: Arc<Execution>,
+}
+
+/// The state needed to execute the steps of a document
+struct Execution {
+    /// The home directory of the document
+    home: PathBuf,
+
+    /// The root node of the document
+    root: Arc<RwLock<Node>>,
+
+    /// The kernels used to execute the document
+    kernels: Arc<RwLock<Kernels>>,
+
+    /// A sender for patches to the root node during execution
+    patch_sender: UnboundedSender<Patch>,
+
+    /// A receiver for patches to the root node during execution
+    patch_receiver: Mutex<UnboundedReceiver<Patch>>,
+}
+
+impl Execution {
+    /// Create the execution state for a root node
+    fn new(home: &Path, root: Node) -> Self {
+        let (patch_sender, patch_receiver) = mpsc::unbounded_channel();
+
+        Self {
+            home: home.to_path_buf(),
+            root: Arc::new(RwLock::new(root)),
+            kernels: Arc::new(RwLock::new(Kernels::new(home))),
+            patch_sender,
+            patch_receiver: Mutex::new(patch_receiver),
+        }
+    }
+
+    /// Compile the document
+    async fn compile(&self) -> Result<()> {
+        node_execute::compile(
+            self.home.clone(),
+            self.root.clone(),
+            self.kernels.clone(),
+            self.patch_sender.clone(),
+            None,
+            None,
+        )
+        .await?;
+        self.apply_patches().await;
+
+        Ok(())
+    }
+
+    /// Execute a single node in the document
+    async fn execute(&self, node_id: &NodeId) -> Result<()> {
+        node_execute::execute(
+            self.home.clone(),
+            self.root.clone(),
+            self.kernels.clone(),
+            self.patch_sender.clone(),
+            Some(vec![node_id.clone()]),
+            None,
+        )
+        .await?;
+        self.apply_patches().await;
+
+        Ok(())
+    }
+
+    /// Apply patches sent during compilation or execution to the root node
+    ///
+    /// Patches are sent synchronously by the executor so all patches will have
+    /// been received once it has finished.
+    async fn apply_patches(&self) {
+        let mut receiver = self.patch_receiver.lock().await;
+        let mut root = self.root.write().await;
+        while let Ok(patch) = receiver.try_recv() {
+            if let Err(error) = schema::patch(&mut *root, patch) {
+                tracing::error!("While applying patch: {error}");
+            }
+        }
+    }
+}
+
+/// A stop in the running of steps
+pub(crate) struct Stop {
+    /// The index of the next step to be executed
+    position: usize,
+
+    /// The reason for the stop
+    reason: &'static str,
+}
+
+impl Session {
+    /// Create a new session
+    pub fn new(sender: UnboundedSender<Message>, stop_sender: UnboundedSender<Stop>) -> Self {
+        Self {
+            sender,
+            stop_sender,
+            lines_start_at1: true,
+            program: None,
+            stop_on_entry: false,
+            breakpoints: HashSet::new(),
+            position: 0,
+            running: false,
+            pause: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Send a message to the client
+    fn send(&self, message: Message) {
+        if let Err(error) = self.sender.send(message) {
+            tracing::error!("While sending message: {error}");
+        }
+    }
+
+    /// Handle a request from the client
+    ///
+    /// Returns `false` if the session should end.
+    pub async fn handle(&mut self, request: Request) -> bool {
+        let result = match request.command.as_str() {
+            "initialize" => self.initialize(&request.arguments),
+            "launch" => self.launch(&request.arguments).await,
+            "setBreakpoints" => self.set_breakpoints(&request.arguments),
+            "setExceptionBreakpoints" => Ok(Some(json!({ "breakpoints": [] }))),
+            "configurationDone" => Ok(None),
+            "threads" => Ok(Some(json!({
+                "threads": [{ "id": THREAD_ID, "name": "Document" }]
+            }))),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => Ok(Some(json!({
+                "scopes": [{
+                    "name": "Kernels",
+                    "variablesReference": VARIABLES_REFERENCE,
+                    "expensive": false
+                }]
+            }))),
+            "variables" => self.variables(&request.arguments).await,
+            "evaluate" => self.evaluate(&request.arguments).await,
+            "next" | "stepIn" | "stepOut" => self.run(true).map(|_| None),
+            "continue" => self
+                .run(false)
+                .map(|_| Some(json!({ "allThreadsContinued": true }))),
+            "pause" => {
+                self.pause.store(true, Ordering::SeqCst);
+                Ok(None)
+            }
+            "disconnect" | "terminate" => {
+                self.send(Message::success(&request, None));
+                return false;
+            }
+            command => Err(eyre!("Unsupported command `{command}`")),
+        };
+
+        match result {
+            Ok(body) => self.send(Message::success(&request, body)),
+            Err(error) => self.send(Message::error(&request, error.to_string())),
+        }
+
+        // Events which must be sent after the response to the request
+        match request.command.as_str() {
+            "launch" if self.program.is_some() => {
+                self.send(Message::event("initialized", None));
+            }
+            "configurationDone" => self.start(),
+            _ => {}
+        }
+
+        true
+    }
+
+    /// Handle an `initialize` request
+    fn initialize(&mut self, arguments: &Value) -> Result<Option<Value>> {
+        self.lines_start_at1 = arguments
+            .get("linesStartAt1")
+            .and_then(|value| value.as_bool())
+            .unwrap_or(true);
+
+        Ok(Some(json!({
+            "supportsConfigurationDoneRequest": true,
+            "supportsTerminateRequest": true,
+            "supportsEvaluateForHovers": false,
+        })))
+    }
+
+    /// Handle a `launch` request
+    ///
+    /// Decodes and compiles the document and collects the steps within it.
+    async fn launch(&mut self, arguments: &Value) -> Result<Option<Value>> {
+        let Some(program) = arguments.get("program").and_then(|value| value.as_str()) else {
+            bail!("The `program` argument, the path of the document, is required")
+        };
+        self.stop_on_entry = arguments
+            .get("stopOnEntry")
+            .and_then(|value| value.as_bool())
+            .unwrap_or_default();
+
+        let path = tokio::fs::canonicalize(program).await?;
+        let Some(home) = path.parent() else {
+            bail!("Document does not have a parent dir")
+        };
+
+        let source = tokio::fs::read_to_string(&path).await?;
+        let (root, DecodeInfo { mapping, .. }) = codecs::from_str_with_info(
+            &source,
+            Some(DecodeOptions {
+                format: Some(Format::from_path(&path)),
+                ..Default::default()
+            }),
+        )
+        .await?;
+
+        let steps = steps::collect(&root, &source, &mapping);
+
+        let execution = Execution::new(home, root);
+        execution.compile().await?;
+
+        self.program = Some(Program {
+            path,
+            steps: Arc::new(steps),
+            execution: Arc::new(execution),
+        });
+
+        Ok(None)
+    }
+
+    /// Handle a `setBreakpoints` request
+    ///
+    /// Breakpoints can only be set on code chunks. A breakpoint on any line
+    /// of a code chunk is moved to the first line of the chunk.
+    fn set_breakpoints(&mut self, arguments: &Value) -> Result<Option<Value>> {
+        let Some(program) = &self.program else {
+            bail!("Document has not been launched")
+        };
+
+        let same_path = arguments
+            .pointer("/source/path")
+            .and_then(|value| value.as_str())
+            .and_then(|path| PathBuf::from(path).canonicalize().ok())
+            .map_or(false, |path| path == program.path);
+
+        let lines = arguments
+            .get("breakpoints")
+            .and_then(|value| value.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|breakpoint| breakpoint.get("line").and_then(|line| line.as_u64()))
+            .map(|line| self.line_from_client(line))
+            .collect_vec();
+
+        self.breakpoints.clear();
+
+        let mut breakpoints = Vec::new();
+        for line in lines {
+            let index = same_path
+                .then(|| {
+                    program.steps.iter().position(|step| {
+                        step.node_type == schema::NodeType::CodeChunk
+                            && line >= step.start_line
+                            && line <= step.end_line
+                    })
+                })
+                .flatten();
+
+            breakpoints.push(match index {
+                Some(index) => {
+                    self.breakpoints.insert(index);
+                    json!({
+                        "verified": true,
+                        "line": self.line_to_client(program.steps[index].start_line)
+                    })
+                }
+                None => json!({
+                    "verified": false,
+                    "line": self.line_to_client(line),
+                    "message": "Breakpoints can only be set on code chunks"
+                }),
+            });
+        }
+
+        Ok(Some(json!({ "breakpoints": breakpoints })))
+    }
+
+    /// Handle a `stackTrace` request
+    ///
+    /// Returns a single frame for the next step to be executed.
+    fn stack_trace(&self) -> Result<Option<Value>> {
+        let Some(program) = &self.program else {
+            bail!("Document has not been launched")
+        };
+
+        let frames = match program.steps.get(self.position) {
+            Some(step) => vec![json!({
+                "id": self.position,
+                "name": step.name(),
+                "source": {
+                    "name": program.path.file_name().map(|name| name.to_string_lossy()),
+                    "path": program.path,
+                },
+                "line": self.line_to_client(step.start_line),
+                "column": 1,
+            })],
+            None => Vec::new(),
+        };
+
+        Ok(Some(json!({
+            "stackFrames": frames,
+            "totalFrames": frames.len(),
+        })))
+    }
+
+    /// Handle a `variables` request
+    ///
+    /// Lists the variables in the kernels of the document and gets the value of each.
+    async fn variables(&self, arguments: &Value) -> Result<Option<Value>> {
+        let Some(program) = &self.program else {
+            bail!("Document has not been launched")
+        };
+
+        if arguments
+            .get("variablesReference")
+            .and_then(|value| value.as_i64())
+            != Some(VARIABLES_REFERENCE)
+        {
+            return Ok(Some(json!({ "variables": [] })));
+        }
+
+        let mut kernels = program.execution.kernels.write().await;
+        let mut variables = Vec::new();
+        for variable in kernels.variables().await {
+            let value = match kernels.get(&variable.name).await {
+                Ok(Some(value)) => to_text(&value),
+                _ => variable.native_hint.clone().unwrap_or_default(),
+            };
+            let value = match value.char_indices().nth(MAX_VALUE_LENGTH) {
+                Some((index, ..)) => [&value[..index], "…"].concat(),
+                None => value,
+            };
+
+            variables.push(json!({
+                "name": variable.name,
+                "value": value,
+                "type": variable.native_type.or(variable.node_type),
+                "variablesReference": 0,
+            }));
+        }
+
+        Ok(Some(json!({ "variables": variables })))
+    }
+
+    /// Handle an `evaluate` request
+    ///
+    /// Evaluates the expression in the default kernel of the document.
+    async fn evaluate(&self, arguments: &Value) -> Result<Option<Value>> {
+        let Some(program) = &self.program else {
+            bail!("Document has not been launched")
+        };
+
+        let Some(expression) = arguments.get("expression").and_then(|value| value.as_str()) else {
+            bail!("The `expression` argument is required")
+        };
+
+        let (value, messages) = program
+            .execution
+            .kernels
+            .write()
+            .await
+            .evaluate(expression, None)
+            .await?;
+
+        if !messages.is_empty() {
+            bail!(messages
+                .into_iter()
+                .map(|message| message.message)
+                .join("\n"))
+        }
+
+        Ok(Some(json!({
+            "result": to_text(&value),
+            "variablesReference": 0,
+        })))
+    }
+
+    /// Start running steps after configuration of the session is done
+    fn start(&mut self) {
+        let Some(program) = &self.program else {
+            return;
+        };
+
+        // If there are no steps, then stopping will terminate the session
+        if program.steps.is_empty() || self.stop_on_entry {
+            self.stopped(Stop {
+                position: 0,
+                reason: "entry",
+            })
+        } else if self.breakpoints.contains(&0) {
+            self.stopped(Stop {
+                position: 0,
+                reason: "breakpoint",
+            })
+        } else if let Err(error) = self.run(false) {
+            tracing::error!("While running steps: {error}");
+        }
+    }
+
+    /// Run steps from the current position
+    ///
+    /// If `single` is true, only the next step is executed. Otherwise, steps
+    /// are executed until a breakpoint is reached, the client requests a pause,
+    /// or there are no more steps.
+    fn run(&mut self, single: bool) -> Result<()> {
+        let Some(program) = &self.program else {
+            bail!("Document has not been launched")
+        };
+        if self.running {
+            bail!("Document is already running")
+        }
+        self.running = true;
+        self.pause.store(false, Ordering::SeqCst);
+
+        let start = self.position;
+        let steps = program.steps.clone();
+        let execution = program.execution.clone();
+        let breakpoints = self.breakpoints.clone();
+        let pause = self.pause.clone();
+        let sender = self.sender.clone();
+        let stop_sender = self.stop_sender.clone();
+        tokio::spawn(async move {
+            let mut position = start;
+            let reason = loop {
+                let Some(step) = steps.get(position) else {
+                    break "end";
+                };
+
+                if position != start {
+                    if breakpoints.contains(&position) {
+                        break "breakpoint";
+                    }
+                    if pause.swap(false, Ordering::SeqCst) {
+                        break "pause";
+                    }
+                }
+
+                let output = |category: &str, output: String| {
+                    sender.send(Message::event(
+                        "output",
+                        Some(json!({ "category": category, "output": output + "\n" })),
+                    ))
+                };
+
+                output("console", format!("Executing {}", step.name())).ok();
+                if let Err(error) = execution.execute(&step.node_id).await {
+                    output("stderr", error.to_string()).ok();
+                }
+
+                let (outputs, messages) =
+                    steps::results(&*execution.root.read().await, &step.node_id);
+                for text in outputs {
+                    output("stdout", text).ok();
+                }
+                for text in messages {
+                    output("stderr", text).ok();
+                }
+
+                position += 1;
+                if single {
+                    break "step";
+                }
+            };
+
+            if let Err(error) = stop_sender.send(Stop { position, reason }) {
+                tracing::error!("While sending stop: {error}");
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Handle a stop in the running of steps
+    ///
+    /// Sends a `stopped` event to the client, or, if there are no more steps,
+    /// `terminated` and `exited` events.
+    pub fn stopped(&mut self, stop: Stop) {
+        self.running = false;
+        self.position = stop.position;
+
+        let finished = self
+            .program
+            .as_ref()
+            .map_or(true, |program| stop.position >= program.steps.len());
+        if finished {
+            self.send(Message::event("exited", Some(json!({ "exitCode": 0 }))));
+            self.send(Message::event("terminated", None));
+        } else {
+            self.send(Message::event(
+                "stopped",
+                Some(json!({
+                    "reason": stop.reason,
+                    "threadId": THREAD_ID,
+                    "allThreadsStopped": true
+                })),
+            ));
+        }
+    }
+
+    /// Convert a line from the client to a zero-based line
+    fn line_from_client(&self, line: u64) -> usize {
+        let line = line as usize;
+        if self.lines_start_at1 {
+            line.saturating_sub(1)
+        } else {
+            line
+        }
+    }
+
+    /// Convert a zero-based line to a line for the client
+    fn line_to_client(&self, line: usize) -> usize {
+        if self.lines_start_at1 {
+            line + 1
+        } else {
+            line
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::{serde_json, tempfile::tempdir};
+
+    use super::*;
+
+    /// Take the messages sent to the client so far, as JSON
+    fn messages(receiver: &mut UnboundedReceiver<Message>) -> Vec<Value> {
+        let mut messages = Vec::new();
+        while let Ok(message) = receiver.try_recv() {
+            messages.push(serde_json::to_value(message).expect("should serialize"));
+        }
+        messages
+    }
+
+    /// Get the names of the events, and the outputs, in messages
+    fn events(messages: &[Value]) -> Vec<String> {
+        messages
+            .iter()
+            .filter_map(|message| {
+                let event = message.get("event")?.as_str()?;
+                Some(match message.pointer("/body/output") {
+                    Some(output) => format!("{event}: {}", output.as_str()?.trim()),
+                    None => event.to_string(),
+                })
+            })
+            .collect()
+    }
+
+    /// Launch a document and step through its code chunks
+    #[tokio::test]
+    async fn stepping() -> Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("doc.md");
+        tokio::fs::write(
+            &path,
+            "# Title\n\n```rhai exec\nlet a = 1;\n```\n\n```rhai exec\na + 1\n```\n",
+        )
+        .await?;
+
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let (stop_sender, mut stops) = mpsc::unbounded_channel();
+        let mut session = Session::new(sender, stop_sender);
+
+        let request = |seq: i64, command: &str, arguments: Value| Request {
+            seq,
+            command: command.to_string(),
+            arguments,
+        };
+
+        session.handle(request(1, "initialize", json!({}))).await;
+        session
+            .handle(request(
+                2,
+                "launch",
+                json!({ "program": path, "stopOnEntry": true }),
+            ))
+            .await;
+        session
+            .handle(request(3, "configurationDone", Value::Null))
+            .await;
+        let sent = messages(&mut receiver);
+        assert!(sent[..4]
+            .iter()
+            .all(|message| message["type"] == "response" || message["event"] == "initialized"));
+        assert_eq!(sent[1]["request_seq"], 2);
+        assert_eq!(sent[1]["success"], true);
+        assert_eq!(sent[4]["event"], "stopped");
+        assert_eq!(sent[4]["body"]["reason"], "entry");
+
+        // The first step is the first code chunk
+        session.handle(request(4, "stackTrace", json!({}))).await;
+        let sent = messages(&mut receiver);
+        assert_eq!(sent[0]["body"]["stackFrames"][0]["line"], 3);
+
+        // Step over the first code chunk
+        session.handle(request(5, "next", json!({}))).await;
+        let stop = stops.recv().await.expect("should stop");
+        session.stopped(stop);
+        assert_eq!(
+            events(&messages(&mut receiver)),
+            vec!["output: Executing CodeChunk (line 3)", "stopped"]
+        );
+
+        session.handle(request(6, "stackTrace", json!({}))).await;
+        let sent = messages(&mut receiver);
+        assert_eq!(sent[0]["body"]["stackFrames"][0]["line"], 7);
+
+        // Step over the second code chunk, which uses the variable
+        // assigned in the first, to the end of the document
+        session.handle(request(7, "next", json!({}))).await;
+        let stop = stops.recv().await.expect("should stop");
+        session.stopped(stop);
+        assert_eq!(
+            events(&messages(&mut receiver)),
+            vec![
+                "output: Executing CodeChunk (line 7)",
+                "output: 2",
+                "exited",
+                "terminated"
+            ]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,192 @@
+//! Collection of the executable nodes in a document that can be stepped through
+
+use codec_text_trait::to_text;
+use codecs::Mapping;
+use schema::{Block, ExecutionMessage, Inline, Node, NodeId, NodeType, Visitor, WalkControl};
+
+/// An executable node in a document
+///
+/// Steps are in the order that nodes appear in the document and are
+/// executed one at a time by the debug adapter.
+pub(crate) struct Step {
+    /// The id of the node
+    pub node_id: NodeId,
+
+    /// The type of the node
+    pub node_type: NodeType,
+
+    /// The label or id of the node, if any
+    pub label: Option<String>,
+
+    /// The zero-based line in the source on which the node starts
+    pub start_line: usize,
+
+    /// The zero-based line in the source on which the node ends
+    pub end_line: usize,
+}
+
+impl Step {
+    /// Get the name of the step for display in the client (e.g. in the call stack)
+    pub fn name(&self) -> String {
+        match &self.label {
+            Some(label) => format!("{} {label}", self.node_type),
+            None => format!("{} (line {})", self.node_type, self.start_line + 1),
+        }
+    }
+}
+
+/// Collect the steps in a document
+///
+/// Nodes that are not in the `mapping` (e.g. because they were not
+/// decoded from the source) are ignored.
+pub(crate) fn collect(root: &Node, source: &str, mapping: &Mapping) -> Vec<Step> {
+    let mut collector = Collector::default();
+    collector.visit(root);
+
+    // The character indices of the start of each line
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(
+            source
+                .chars()
+                .enumerate()
+                .filter_map(|(index, char)| (char == '\n').then_some(index + 1)),
+        )
+        .collect();
+    let line_of = |index: usize| {
+        line_starts
+            .partition_point(|start| *start <= index)
+            .saturating_sub(1)
+    };
+
+    collector
+        .nodes
+        .into_iter()
+        .filter_map(|(node_id, node_type, label)| {
+            let range = mapping.range_of_node(&node_id)?;
+            Some(Step {
+                start_line: line_of(range.start),
+                end_line: line_of(range.end.saturating_sub(1).max(range.start)),
+                node_id,
+                node_type,
+                label,
+            })
+        })
+        .collect()
+}
+
+/// A visitor which collects executable nodes
+///
+/// Does not walk into the content of nodes such as `ForBlock`s and `IfBlock`s
+/// because that content is executed as part of those nodes.
+#[derive(Default)]
+struct Collector {
+    nodes: Vec<(NodeId, NodeType, Option<String>)>,
+}
+
+impl Visitor for Collector {
+    fn visit_block(&mut self, block: &Block) -> WalkControl {
+        match block {
+            Block::CodeChunk(chunk) => {
+                self.nodes.push((
+                    chunk.node_id(),
+                    NodeType::CodeChunk,
+                    chunk.label.clone().or_else(|| chunk.id.clone()),
+                ));
+            }
+            Block::ForBlock(block) => {
+                self.nodes
+                    .push((block.node_id(), NodeType::ForBlock, block.id.clone()));
+                return WalkControl::Break;
+            }
+            Block::IfBlock(block) => {
+                self.nodes
+                    .push((block.node_id(), NodeType::IfBlock, block.id.clone()));
+                return WalkControl::Break;
+            }
+            Block::CallBlock(block) => {
+                self.nodes
+                    .push((block.node_id(), NodeType::CallBlock, block.id.clone()));
+                return WalkControl::Break;
+            }
+            _ => {}
+        }
+
+        WalkControl::Continue
+    }
+
+    fn visit_inline(&mut self, inline: &Inline) -> WalkControl {
+        if let Inline::CodeExpression(expr) = inline {
+            self.nodes
+                .push((expr.node_id(), NodeType::CodeExpression, expr.id.clone()));
+        }
+
+        WalkControl::Continue
+    }
+}
+
+/// Get the outputs, and execution messages, of a node as text
+///
+/// Used to send the results of executing a step to the client.
+pub(crate) fn results(root: &Node, node_id: &NodeId) -> (Vec<String>, Vec<String>) {
+    let mut finder = Finder {
+        node_id: node_id.clone(),
+        results: None,
+    };
+    finder.visit(root);
+
+    let (outputs, messages) = finder.results.unwrap_or_default();
+    let outputs = outputs.iter().map(to_text).collect();
+    let messages = messages
+        .iter()
+        .map(|message| match &message.error_type {
+            Some(error_type) => format!("{error_type}: {}", message.message),
+            None => message.message.clone(),
+        })
+        .collect();
+
+    (outputs, messages)
+}
+
+/// A visitor which finds the outputs and messages of a code chunk or expression
+struct Finder {
+    node_id: NodeId,
+    results: Option<(Vec<Node>, Vec<ExecutionMessage>)>,
+}
+
+impl Visitor for Finder {
+    fn visit_block(&mut self, block: &Block) -> WalkControl {
+        if self.results.is_some() {
+            return WalkControl::Break;
+        }
+
+        if let Block::CodeChunk(chunk) = block {
+            if chunk.node_id() == self.node_id {
+                self.results = Some((
+                    chunk.outputs.clone().unwrap_or_default(),
+                    chunk.options.execution_messages.clone().unwrap_or_default(),
+                ));
+                return WalkControl::Break;
+            }
+        }
+
+        WalkControl::Continue
+    }
+
+    fn visit_inline(&mut self, inline: &Inline) -> WalkControl {
+        if self.results.is_some() {
+            return WalkControl::Break;
+        }
+
+        if let Inline::CodeExpression(expr) = inline {
+            if expr.node_id() == self.node_id {
+                self.results = Some((
+                    expr.output.iter().map(|output| *output.clone()).collect(),
+                    expr.options.execution_messages.clone().unwrap_or_default(),
+                ));
+                return WalkControl::Break;
+            }
+        }
+
+        WalkControl::Continue
+    }
+}
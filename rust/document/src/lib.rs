@@ -14,7 +14,7 @@ use common::{
     strum::{Display, EnumString},
     tokio::{
         self,
-        sync::{broadcast, mpsc, watch, RwLock},
+        sync::{broadcast, mpsc, oneshot, watch, RwLock},
         time::sleep,
     },
     tracing,
@@ -188,6 +188,9 @@ type DocumentUpdateReceiver = mpsc::Receiver<Update>;
 type DocumentPatchSender = mpsc::UnboundedSender<Patch>;
 type DocumentPatchReceiver = mpsc::UnboundedReceiver<Patch>;
 
+type DocumentSyncSender = mpsc::UnboundedSender<oneshot::Sender<()>>;
+type DocumentSyncReceiver = mpsc::UnboundedReceiver<oneshot::Sender<()>>;
+
 type DocumentCommandCounter = AtomicU64;
 
 type DocumentCommandSender = mpsc::Sender<(Command, u64)>;
@@ -218,6 +221,12 @@ pub struct Document {
     /// The filesystem path to the document's Automerge store
     path: Option<PathBuf>,
 
+    /// The filesystem path of the source file of the document (if any)
+    ///
+    /// Used to exclude the document itself when retrieving passages from
+    /// other files in its project while executing instructions.
+    source: Option<PathBuf>,
+
     root: DocumentRoot,
 
     /// The document's execution kernels
@@ -232,6 +241,9 @@ pub struct Document {
     /// A channel sender for sending patches to the root [`Node`]
     patch_sender: DocumentPatchSender,
 
+    /// A channel sender for waiting until updates and patches have been applied
+    sync_sender: DocumentSyncSender,
+
     /// A counter of commands used for creating unique command ids
     command_counter: DocumentCommandCounter,
 
@@ -248,7 +260,7 @@ impl Document {
     /// This initializes the document's "watch", "update", and "command" channels, and
     /// starts its background tasks.
     #[tracing::instrument]
-    pub fn init(home: PathBuf, path: Option<PathBuf>) -> Result<Self> {
+    pub fn init(home: PathBuf, path: Option<PathBuf>, source: Option<PathBuf>) -> Result<Self> {
         let id = DocumentId::new();
 
         // Create the document's kernels with the same home directory
@@ -261,6 +273,7 @@ impl Document {
 
         let (update_sender, update_receiver) = mpsc::channel(8);
         let (patch_sender, patch_receiver) = mpsc::unbounded_channel();
+        let (sync_sender, sync_receiver) = mpsc::unbounded_channel();
         let (command_sender, command_receiver) = mpsc::channel(256);
         let (command_status_sender, command_status_receiver) = broadcast::channel(256);
 
@@ -272,6 +285,7 @@ impl Document {
                 Self::update_task(
                     update_receiver,
                     patch_receiver,
+                    sync_receiver,
                     root,
                     watch_sender,
                     command_sender,
@@ -286,6 +300,7 @@ impl Document {
         // Start the command task
         {
             let home = home.clone();
+            let source = source.clone();
             let root = root.clone();
             let kernels = kernels.clone();
             let patch_sender = patch_sender.clone();
@@ -294,6 +309,7 @@ impl Document {
                     command_receiver,
                     command_status_sender,
                     home,
+                    source,
                     root,
                     kernels,
                     patch_sender,
@@ -306,11 +322,13 @@ impl Document {
             id,
             home,
             path,
+            source,
             root,
             kernels,
             watch_receiver,
             update_sender,
             patch_sender,
+            sync_sender,
             command_counter,
             command_sender,
             command_status_receiver,
@@ -320,7 +338,7 @@ impl Document {
     /// Create a new in-memory document
     pub fn new() -> Result<Self> {
         let home = std::env::current_dir()?;
-        Self::init(home, None)
+        Self::init(home, None, None)
     }
 
     /// Create a new document
@@ -348,7 +366,7 @@ impl Document {
             .ok_or_else(|| eyre!("path has no parent; is it a file?"))?
             .to_path_buf();
 
-        Self::init(home, None)
+        Self::init(home, None, source.map(Path::to_path_buf))
     }
 
     /// Open an existing document
@@ -362,7 +380,7 @@ impl Document {
             .ok_or_else(|| eyre!("path has no parent; is it a file?"))?
             .to_path_buf();
 
-        let me = Self::init(home, None)?;
+        let me = Self::init(home, None, Some(path.to_path_buf()))?;
         me.import(path, None).await?;
 
         Ok(me)
@@ -373,6 +391,11 @@ impl Document {
         &self.id
     }
 
+    /// Get the execution kernels of the document
+    pub fn kernels(&self) -> Arc<RwLock<Kernels>> {
+        self.kernels.clone()
+    }
+
     /// Import a file into a new, or existing, document
     ///
     /// By default the format of the `source` file is inferred from its extension but
@@ -429,6 +452,24 @@ impl Document {
             .await?)
     }
 
+    /// Patch the root node of the document
+    ///
+    /// Unlike sending a `Command::PatchNode`, the patch is sent directly to the
+    /// document's update task so that a following call to `sync` will wait for it.
+    pub fn patch(&self, patch: Patch) -> Result<()> {
+        Ok(self.patch_sender.send(patch)?)
+    }
+
+    /// Wait for all updates and patches sent to the document to be applied
+    ///
+    /// Returns once the update task has applied all the updates and patches
+    /// that it had received before this call, and has notified watchers of them.
+    pub async fn sync(&self) -> Result<()> {
+        let (sender, receiver) = oneshot::channel();
+        self.sync_sender.send(sender)?;
+        Ok(receiver.await?)
+    }
+
     /// Perform a command on the document
     #[tracing::instrument(skip(self))]
     pub async fn command(&self, command: Command) -> Result<()> {
@@ -19,6 +19,7 @@ impl Document {
         mut command_receiver: DocumentCommandReceiver,
         command_status_sender: DocumentCommandStatusSender,
         home: PathBuf,
+        source: Option<PathBuf>,
         root: DocumentRoot,
         kernels: DocumentKernels,
         patch_sender: DocumentPatchSender,
@@ -140,6 +141,10 @@ impl Document {
                     current = Some((command, command_id, task));
                 }
                 ExecuteDocument(options) => {
+                    let options = ExecuteOptions {
+                        path: options.path.or_else(|| source.clone()),
+                        ..options
+                    };
                     let task = tokio::spawn(async move {
                         let status = if let Err(error) =
                             execute(home, root, kernels, patch_sender, None, Some(options)).await
@@ -155,8 +160,11 @@ impl Document {
                     current = Some((command, command_id, task));
                 }
                 ExecuteNodes(CommandNodes { node_ids, .. }) => {
+                    let options = ExecuteOptions {
+                        path: source.clone(),
+                        ..Default::default()
+                    };
                     let task = tokio::spawn(async move {
-                        let options = ExecuteOptions::default();
                         // TODO: set other options based on scope
 
                         let status = if let Err(error) = execute(
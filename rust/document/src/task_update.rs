@@ -5,7 +5,7 @@ use common::{
 
 use crate::{
     Command, Document, DocumentCommandSender, DocumentPatchReceiver, DocumentRoot,
-    DocumentUpdateReceiver, DocumentWatchSender,
+    DocumentSyncReceiver, DocumentUpdateReceiver, DocumentWatchSender,
 };
 
 impl Document {
@@ -19,10 +19,15 @@ impl Document {
     /// but watchers need complete nodes (e.g with `executionStatus` and `output` properties).
     ///
     /// This task takes a write lock on the document's `store` for each update.
+    ///
+    /// Requests on the `sync_receiver` channel are only responded to once there are no
+    /// pending updates or patches, so that callers of `Document::sync` can know that
+    /// everything they sent before has been applied.
     #[tracing::instrument(skip_all)]
     pub(super) async fn update_task(
         mut update_receiver: DocumentUpdateReceiver,
         mut patch_receiver: DocumentPatchReceiver,
+        mut sync_receiver: DocumentSyncReceiver,
         root: DocumentRoot,
         watch_sender: DocumentWatchSender,
         command_sender: DocumentCommandSender,
@@ -31,6 +36,8 @@ impl Document {
 
         loop {
             let compile = tokio::select! {
+                biased;
+
                 Some(update) = update_receiver.recv() => {
                     tracing::trace!("Document root node update received");
 
@@ -51,8 +58,14 @@ impl Document {
 
                     false
                 },
+                Some(sender) = sync_receiver.recv() => {
+                    // Because the select is biased, this branch is only reached when
+                    // there are no pending updates or patches
+                    sender.send(()).ok();
+                    continue;
+                },
                 else => {
-                    tracing::debug!("Both update and patch channels closed");
+                    tracing::debug!("All update, patch and sync channels closed");
                     break;
                 },
             };
@@ -67,9 +80,6 @@ impl Document {
             }
 
             // Recompile the document
-            // TODO: consider recompiling on patches as well but with care taken
-            // to ignore patches that are from the compilation of execution already
-            // (will require an additional patch field to indicate this).
             // TODO: consider throttling or debouncing this (although note that if the document is already
             // compiling or executing then the command will be ignored anyway)
             if compile {
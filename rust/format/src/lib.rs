@@ -45,11 +45,14 @@ pub enum Format {
     Shell,
     JavaScript,
     Jinja,
+    Lua,
     Python,
     R,
     Rhai,
     // Diagramming languages
     Dot,
+    Mermaid,
+    VegaLite,
     // Styling languages
     Tailwind,
     Css,
@@ -117,7 +120,9 @@ impl Format {
             Json5 => "JSON5",
             JsonLd => "JSON-LD",
             Latex => "LaTeX",
+            Lua => "Lua",
             Markdown => "Markdown",
+            Mermaid => "Mermaid",
             Mkv => "Matroska",
             Mp3 => "MPEG-3",
             Mp4 => "MPEG-4",
@@ -134,6 +139,7 @@ impl Format {
             Tex => "TeX",
             Text => "Plain text",
             Toml => "TOML",
+            VegaLite => "Vega-Lite",
             Wav => "WAV",
             WebM => "WebM",
             WebP => "WebP",
@@ -212,7 +218,9 @@ impl Format {
             "json5" => Json5,
             "jsonld" | "json-ld" => JsonLd,
             "latex" => Latex,
+            "lua" => Lua,
             "markdown" | "md" => Markdown,
+            "mermaid" | "mmd" => Mermaid,
             "myst" => Myst,
             "mkv" => Mkv,
             "mp3" => Mp3,
@@ -230,6 +238,7 @@ impl Format {
             "tex" => Tex,
             "text" | "txt" => Text,
             "toml" => Toml,
+            "vegalite" | "vega-lite" | "vl" => VegaLite,
             "wav" => Wav,
             "webm" => WebM,
             "webp" => WebP,
@@ -370,7 +379,9 @@ impl Display for Format {
             Json5 => "json5",
             JsonLd => "jsonld",
             Latex => "latex",
+            Lua => "lua",
             Markdown => "markdown",
+            Mermaid => "mermaid",
             Mkv => "mkv",
             Mp3 => "mp3",
             Mp4 => "mp4",
@@ -387,6 +398,7 @@ impl Display for Format {
             Tex => "tex",
             Text => "text",
             Toml => "toml",
+            VegaLite => "vegalite",
             Wav => "wav",
             WebM => "webm",
             WebP => "webp",
@@ -153,7 +153,8 @@ do
       echo $!
       ;;
     *)
-      echo "Unrecognised flag" >&2
+      # Respond with an `UnknownFlag` error so the parent can detect unsupported tasks
+      printf '{"type":"ExecutionMessage","level":"Exception","message":"Unrecognized flag","errorType":"UnknownFlag"}\n' >&2
       ;;
   esac
   
@@ -4,15 +4,13 @@ use layout::{
 };
 
 use kernel::{
-    common::{
-        async_trait::async_trait, eyre::Result, once_cell::sync::Lazy, regex::Regex, tracing,
-    },
+    common::{async_trait::async_trait, eyre::Result, tracing},
     format::Format,
     schema::{
         ExecutionMessage, ImageObject, MessageLevel, Node, SoftwareApplication,
         SoftwareApplicationOptions,
     },
-    Kernel, KernelInstance,
+    svg_data_uri, Kernel, KernelInstance,
 };
 
 const NAME: &str = "graphviz";
@@ -30,6 +28,10 @@ impl Kernel for GraphvizKernel {
         vec![Format::Dot]
     }
 
+    fn executes_on_compile(&self) -> bool {
+        true
+    }
+
     fn create_instance(&self) -> Result<Box<dyn KernelInstance>> {
         Ok(Box::new(GraphvizKernelInstance {}))
     }
@@ -62,31 +64,11 @@ impl KernelInstance for GraphvizKernelInstance {
 
                     let mut svg_writer = SVGWriter::new();
                     visual_graph.do_it(false, false, false, &mut svg_writer);
-                    let svg = svg_writer.finalize();
-
-                    svg.replace(
-                        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\"?>",
-                        "",
-                    )
+                    svg_writer.finalize()
                 };
 
-                // Based on the recommendation for creating SVG DataURIs at
-                // https://gist.github.com/jennyknuth/222825e315d45a738ed9d6e04c7a88d0
-                let svg = svg
-                    .replace('"', "\'")
-                    .replace('%', "%25")
-                    .replace('#', "%23")
-                    .replace('{', "%7B")
-                    .replace('}', "%7D")
-                    .replace('<', "%3C")
-                    .replace('>', "%3E");
-                static SPACES_RE: Lazy<Regex> =
-                    Lazy::new(|| Regex::new(r"\s+").expect("invalid regex"));
-                let svg = SPACES_RE.replace_all(&svg, " ");
-                let data_uri = format!("data:image/svg+xml,{}", svg);
-
                 let image = Node::ImageObject(ImageObject {
-                    content_url: data_uri,
+                    content_url: svg_data_uri(&svg),
                     ..Default::default()
                 });
 
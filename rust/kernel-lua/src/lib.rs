@@ -0,0 +1,1098 @@
+use std::{
+    collections::HashSet,
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, AtomicU8, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use mlua::{Lua, LuaSerdeExt, MultiValue, Table, Value};
+
+use kernel::{
+    common::{
+        async_trait::async_trait,
+        eyre::{bail, eyre, Report, Result},
+        itertools::Itertools,
+        once_cell::sync::Lazy,
+        regex::Regex,
+        serde_json,
+        tokio::{
+            self,
+            sync::{mpsc, watch},
+        },
+        tracing,
+    },
+    format::Format,
+    schema::{
+        ArrayHint, CodeLocation, ExecutionMessage, Hint, MessageLevel, Node, NodeType, Null,
+        ObjectHint, SoftwareApplication, SoftwareApplicationOptions, SoftwareSourceCode,
+        StringHint, Unknown, Variable,
+    },
+    Kernel, KernelForks, KernelInstance, KernelSignal, KernelStatus, KernelTerminate,
+    KernelVariableRequest, KernelVariableRequester, KernelVariableResponder,
+};
+
+/// The name given to chunks of code loaded into the Lua VM
+///
+/// The leading `=` tells Lua to use the name verbatim in error messages
+/// (i.e. `code:1: ...` rather than `[string "..."]:1: ...`).
+const CHUNK_NAME: &str = "=code";
+
+/// A kernel for executing Lua.
+///
+/// Uses an embedded Lua 5.4 virtual machine (https://www.lua.org/) so that
+/// no external runtime is required.
+#[derive(Default)]
+pub struct LuaKernel {
+    /// A counter of instances of this kernel
+    instances: AtomicU64,
+}
+
+impl Kernel for LuaKernel {
+    fn name(&self) -> String {
+        "lua".to_string()
+    }
+
+    fn supports_languages(&self) -> Vec<Format> {
+        vec![Format::Lua]
+    }
+
+    fn supports_forks(&self) -> KernelForks {
+        KernelForks::Yes
+    }
+
+    fn supports_terminate(&self) -> KernelTerminate {
+        KernelTerminate::Yes
+    }
+
+    fn supports_variable_requests(&self) -> bool {
+        true
+    }
+
+    fn create_instance(&self) -> Result<Box<dyn KernelInstance>> {
+        // Assign an id for the instance using the index, if necessary, to ensure it is unique
+        let index = self.instances.fetch_add(1, Ordering::SeqCst);
+        let id = if index == 0 {
+            self.name()
+        } else {
+            format!("{}-{index}", self.name())
+        };
+
+        Ok(Box::new(LuaKernelInstance::new(id)?))
+    }
+}
+
+pub struct LuaKernelInstance {
+    /// The id of this instance
+    id: String,
+
+    /// The Lua virtual machine for this instance
+    ///
+    /// Needs to be a `Mutex` because `Lua` is `Send` but not `Sync`.
+    lua: Mutex<Lua>,
+
+    /// The names of the global variables defined by the Lua standard library
+    ///
+    /// Used to exclude these when listing, and forking, variables.
+    builtins: HashSet<String>,
+
+    /// Nodes printed during execution
+    printed: Arc<Mutex<Vec<Node>>>,
+
+    /// The status of this instance
+    status: Arc<AtomicU8>,
+
+    /// A channel sender for the status of this instance
+    status_sender: watch::Sender<KernelStatus>,
+
+    /// A channel sender for sending signals to the instance
+    signal_sender: mpsc::Sender<KernelSignal>,
+
+    /// A counter of forks of this instance
+    forks: AtomicU64,
+}
+
+#[async_trait]
+impl KernelInstance for LuaKernelInstance {
+    fn name(&self) -> String {
+        self.id.clone()
+    }
+
+    async fn status(&self) -> Result<KernelStatus> {
+        Ok(self.get_status())
+    }
+
+    fn status_watcher(&self) -> Result<watch::Receiver<KernelStatus>> {
+        Ok(self.status_sender.subscribe())
+    }
+
+    fn signal_sender(&self) -> Result<mpsc::Sender<KernelSignal>> {
+        Ok(self.signal_sender.clone())
+    }
+
+    async fn start(&mut self, _directory: &Path) -> Result<()> {
+        self.set_status(KernelStatus::Ready)
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        self.set_status(KernelStatus::Stopped)
+    }
+
+    async fn execute(&mut self, code: &str) -> Result<(Vec<Node>, Vec<ExecutionMessage>)> {
+        tracing::trace!("Executing Lua code");
+
+        let status = self.get_status();
+        if status != KernelStatus::Ready {
+            bail!(
+                "Kernel `{}` is not ready; status is `{status}`",
+                self.name()
+            )
+        }
+
+        self.set_status(KernelStatus::Busy)?;
+
+        let result = execute_code(get_lua(&mut self.lua), code);
+
+        // Printed outputs come before any returned by the last line
+        let mut outputs: Vec<Node> = self
+            .printed
+            .lock()
+            .map_err(|error| eyre!("While reading outputs: {error}"))?
+            .drain(..)
+            .collect();
+        let messages = match result {
+            Ok(mut values) => {
+                outputs.append(&mut values);
+                vec![]
+            }
+            Err(error) => vec![error_to_message(error)],
+        };
+
+        self.set_status(KernelStatus::Ready)?;
+
+        Ok((outputs, messages))
+    }
+
+    async fn evaluate(&mut self, code: &str) -> Result<(Node, Vec<ExecutionMessage>)> {
+        tracing::trace!("Evaluating Lua code");
+
+        let status = self.get_status();
+        if status != KernelStatus::Ready {
+            bail!(
+                "Kernel `{}` is not ready; status is `{status}`",
+                self.name()
+            )
+        }
+
+        if code.trim().is_empty() {
+            return Ok((Node::Null(Null), vec![]));
+        }
+
+        self.set_status(KernelStatus::Busy)?;
+
+        let lua = get_lua(&mut self.lua);
+        let result = match lua
+            .load(format!("return {code}"))
+            .set_name(CHUNK_NAME)
+            .eval::<Value>()
+        {
+            Ok(value) => (value_to_node(lua, value), vec![]),
+            Err(error) => (Node::Null(Null), vec![error_to_message(error)]),
+        };
+
+        self.set_status(KernelStatus::Ready)?;
+
+        Ok(result)
+    }
+
+    async fn info(&mut self) -> Result<SoftwareApplication> {
+        tracing::trace!("Getting Lua runtime info");
+
+        // The `_VERSION` global is of the form "Lua 5.4"
+        let version = get_lua(&mut self.lua)
+            .globals()
+            .raw_get::<_, String>("_VERSION")
+            .ok()
+            .and_then(|version| version.strip_prefix("Lua ").map(String::from));
+
+        Ok(SoftwareApplication {
+            name: "Lua".to_string(),
+            options: Box::new(SoftwareApplicationOptions {
+                software_version: version,
+                operating_system: Some(std::env::consts::OS.to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+    }
+
+    async fn packages(&mut self) -> Result<Vec<SoftwareSourceCode>> {
+        tracing::trace!("Getting Lua packages");
+
+        Ok(vec![])
+    }
+
+    async fn list(&mut self) -> Result<Vec<Variable>> {
+        tracing::trace!("Listing Lua variables");
+
+        self.set_status(KernelStatus::Busy)?;
+
+        let lua = get_lua(&mut self.lua);
+        let variables = lua
+            .globals()
+            .pairs::<String, Value>()
+            .flatten()
+            .filter(|(name, ..)| !self.builtins.contains(name))
+            .map(|(name, value)| {
+                let programming_language = Some("Lua".to_string());
+                let native_type = Some(value.type_name().to_string());
+                let (node_type, hint) = value_to_type_hint(&value);
+
+                Variable {
+                    name,
+                    programming_language,
+                    native_type,
+                    node_type,
+                    hint,
+                    ..Default::default()
+                }
+            })
+            .collect();
+
+        self.set_status(KernelStatus::Ready)?;
+
+        Ok(variables)
+    }
+
+    async fn get(&mut self, name: &str) -> Result<Option<Node>> {
+        tracing::trace!("Getting Lua variable");
+
+        self.set_status(KernelStatus::Busy)?;
+
+        // Use `raw_get` to avoid making a variable request to other kernels
+        let lua = get_lua(&mut self.lua);
+        let node = match lua.globals().raw_get::<_, Value>(name)? {
+            Value::Nil => None,
+            value => Some(value_to_node(lua, value)),
+        };
+
+        self.set_status(KernelStatus::Ready)?;
+
+        Ok(node)
+    }
+
+    async fn set(&mut self, name: &str, node: &Node) -> Result<()> {
+        tracing::trace!("Setting Lua variable");
+
+        self.set_status(KernelStatus::Busy)?;
+
+        let lua = get_lua(&mut self.lua);
+        lua.globals().raw_set(name, node_to_value(lua, node)?)?;
+
+        self.set_status(KernelStatus::Ready)
+    }
+
+    async fn remove(&mut self, name: &str) -> Result<()> {
+        tracing::trace!("Removing Lua variable");
+
+        self.set_status(KernelStatus::Busy)?;
+
+        get_lua(&mut self.lua).globals().raw_set(name, Value::Nil)?;
+
+        self.set_status(KernelStatus::Ready)
+    }
+
+    async fn fork(&mut self) -> Result<Box<dyn KernelInstance>> {
+        tracing::trace!("Forking Lua kernel instance");
+
+        // Create fork id
+        let id = format!(
+            "{}-fork-{}",
+            self.id,
+            self.forks.fetch_add(1, Ordering::SeqCst)
+        );
+
+        // Create instance
+        let mut fork = LuaKernelInstance::new(id)?;
+
+        // Clone global variables into fork. Works by converting variables to/from
+        // Stencila nodes so functions and other values that can not be represented
+        // as nodes are not copied.
+        let lua = get_lua(&mut self.lua);
+        let vars = lua
+            .globals()
+            .pairs::<String, Value>()
+            .flatten()
+            .filter(|(name, value)| {
+                !self.builtins.contains(name)
+                    && matches!(
+                        value,
+                        Value::Boolean(..)
+                            | Value::Integer(..)
+                            | Value::Number(..)
+                            | Value::String(..)
+                            | Value::Table(..)
+                    )
+            })
+            .map(|(name, value)| (name, value_to_node(lua, value)))
+            .collect_vec();
+
+        {
+            let fork_lua = get_lua(&mut fork.lua);
+            let fork_globals = fork_lua.globals();
+            for (name, node) in vars {
+                fork_globals.raw_set(name, node_to_value(fork_lua, &node)?)?;
+            }
+        }
+
+        Ok(Box::new(fork))
+    }
+
+    fn variable_channel(
+        &mut self,
+        requester: KernelVariableRequester,
+        responder: KernelVariableResponder,
+    ) {
+        if let Err(error) = self.variable_requests(requester, responder) {
+            tracing::error!("While setting up Lua variable requests: {error}")
+        }
+    }
+}
+
+impl LuaKernelInstance {
+    /// Create a new kernel instance
+    fn new(id: String) -> Result<Self> {
+        let lua = Lua::new();
+
+        let builtins = lua
+            .globals()
+            .pairs::<String, Value>()
+            .flatten()
+            .map(|(name, ..)| name)
+            .collect();
+
+        // Override the `print` function so that each argument is
+        // a separate output node
+        let printed = Arc::new(Mutex::new(Vec::new()));
+        let printed_clone = printed.clone();
+        let print = lua.create_function(move |lua, args: MultiValue| {
+            let mut nodes = args
+                .into_iter()
+                .map(|value| value_to_node(lua, value))
+                .collect_vec();
+            if let Ok(mut guard) = printed_clone.lock() {
+                guard.append(&mut nodes)
+            }
+            Ok(())
+        })?;
+        lua.globals().raw_set("print", print)?;
+
+        let status = Arc::new(AtomicU8::new(KernelStatus::Pending.into()));
+        let (status_sender, ..) = watch::channel(KernelStatus::Pending);
+
+        let (signal_sender, mut signal_receiver) = mpsc::channel(1);
+
+        // Start a task to handle signals
+        let status_clone = status.clone();
+        tokio::spawn(async move {
+            while let Some(kernel_signal) = signal_receiver.recv().await {
+                if matches!(kernel_signal, KernelSignal::Terminate | KernelSignal::Kill) {
+                    status_clone.store(KernelStatus::Stopped.into(), Ordering::SeqCst);
+                }
+            }
+        });
+
+        Ok(Self {
+            id,
+            lua: Mutex::new(lua),
+            builtins,
+            printed,
+            status,
+            status_sender,
+            signal_sender,
+            forks: Default::default(),
+        })
+    }
+
+    /// Set up requests for variables from other kernels
+    ///
+    /// Sets an `__index` metamethod on the globals table so that when code uses
+    /// a global variable which is not defined, a request for it is made to the
+    /// other kernels of the document.
+    fn variable_requests(
+        &mut self,
+        requester: KernelVariableRequester,
+        responder: KernelVariableResponder,
+    ) -> Result<()> {
+        let instance = self.name();
+        let channel = Mutex::new((requester, responder));
+
+        let lua = get_lua(&mut self.lua);
+        let index = lua.create_function(move |lua, (_table, key): (Table, Value)| {
+            let Value::String(name) = key else {
+                return Ok(Value::Nil);
+            };
+
+            match request_variable(&instance, &channel, &name.to_string_lossy()) {
+                Some(node) => node_to_value(lua, &node)
+                    .map_err(|error| mlua::Error::external(error.to_string())),
+                None => Ok(Value::Nil),
+            }
+        })?;
+
+        let metatable = lua.create_table()?;
+        metatable.raw_set("__index", index)?;
+        lua.globals().set_metatable(Some(metatable));
+
+        Ok(())
+    }
+
+    /// Get the status of the kernel
+    fn get_status(&self) -> KernelStatus {
+        self.status.load(Ordering::SeqCst).into()
+    }
+
+    /// Set the status of the kernel instance and notify watchers if there was a change
+    ///
+    /// Avoids overwriting of `Stopping` or `Stopped` status (which can happen when a
+    /// `Terminate` signal is received while a task is executing)
+    fn set_status(&mut self, status: KernelStatus) -> Result<()> {
+        let previous: KernelStatus = self.status.swap(status.into(), Ordering::SeqCst).into();
+        if previous >= KernelStatus::Stopping {
+            self.status.store(previous.into(), Ordering::SeqCst);
+            return Ok(());
+        }
+
+        self.status_sender.send_if_modified(|previous| {
+            if status != *previous {
+                tracing::trace!(
+                    "Status of `{}` kernel changed from `{previous}` to `{status}`",
+                    self.name()
+                );
+                *previous = status;
+                true
+            } else {
+                false
+            }
+        });
+
+        Ok(())
+    }
+}
+
+/// Get the Lua VM from its mutex
+///
+/// Takes the mutex, rather than the instance, so that other fields
+/// of the instance can be borrowed at the same time.
+fn get_lua(lua: &mut Mutex<Lua>) -> &mut Lua {
+    lua.get_mut()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Execute code in a Lua VM
+///
+/// If the last line of the code is an expression then its values are returned.
+/// This is done by attempting to compile the code with a `return` statement
+/// prepended to the last line and, if that fails, compiling the code as is.
+fn execute_code(lua: &Lua, code: &str) -> mlua::Result<Vec<Node>> {
+    if code.trim().is_empty() {
+        return Ok(vec![]);
+    }
+
+    let mut lines = code.trim_end().lines().collect_vec();
+    let last = lines.pop().unwrap_or_default();
+    let with_return = if lines.is_empty() {
+        format!("return {last}")
+    } else {
+        format!("{}\nreturn {last}", lines.join("\n"))
+    };
+
+    let values = match lua
+        .load(with_return.as_str())
+        .set_name(CHUNK_NAME)
+        .into_function()
+    {
+        Ok(function) => function.call::<_, MultiValue>(())?,
+        Err(..) => {
+            lua.load(code).set_name(CHUNK_NAME).exec()?;
+            MultiValue::new()
+        }
+    };
+
+    Ok(values
+        .into_iter()
+        .filter(|value| !matches!(value, Value::Nil))
+        .map(|value| value_to_node(lua, value))
+        .collect())
+}
+
+/// Request a variable from other kernels
+///
+/// Waits for the response in a separate thread because this is called
+/// from within a synchronous Lua callback.
+fn request_variable(
+    instance: &str,
+    channel: &Mutex<(KernelVariableRequester, KernelVariableResponder)>,
+    name: &str,
+) -> Option<Node> {
+    let Ok(mut guard) = channel.lock() else {
+        return None;
+    };
+
+    let (sender, receiver) = &mut *guard;
+    let mut receiver = receiver.resubscribe();
+
+    // Send the request
+    match sender.send(KernelVariableRequest {
+        variable: name.to_string(),
+        instance: instance.to_string(),
+    }) {
+        Err(error) => {
+            tracing::error!("While sending variable request: {error}");
+            return None;
+        }
+        Ok(..) => {
+            tracing::trace!("Sent request for variable `{name}`");
+        }
+    }
+
+    // This seems to be necessary to "tick over" the Tokio runtime
+    // to process the request sent above
+    tokio::spawn(async {});
+
+    // Wait for the response. Uses `blocking_recv` to must be done in a thread.
+    let name = name.to_string();
+    let receiving = thread::spawn(move || {
+        tracing::trace!("Waiting for response for variable `{name}`");
+        loop {
+            let response = receiver.blocking_recv()?;
+            if response.variable == name {
+                return Ok::<Option<Node>, Report>(response.value);
+            }
+        }
+    });
+    match receiving.join() {
+        Err(..) => {
+            tracing::error!("Error joining variable request receiving thread");
+            None
+        }
+        Ok(Err(error)) => {
+            tracing::error!("While receiving variable request: {error}");
+            None
+        }
+        Ok(Ok(node)) => node,
+    }
+}
+
+/// Create an `ExecutionMessage` from a Lua error
+///
+/// Extracts the line number and message from the first line of the error
+/// and any stack traceback that follows it.
+fn error_to_message(error: mlua::Error) -> ExecutionMessage {
+    static REGEX: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"^code:(\d+): (.*)").expect("invalid regex"));
+
+    let level = match &error {
+        mlua::Error::SyntaxError { .. } => MessageLevel::Error,
+        _ => MessageLevel::Exception,
+    };
+
+    let error = error.to_string();
+    let (first, stack_trace) = match error.split_once("\nstack traceback:") {
+        Some((first, rest)) => (first, Some(format!("stack traceback:{rest}"))),
+        None => (error.as_str(), None),
+    };
+
+    let (message, code_location) = match REGEX.captures(first) {
+        Some(captures) => (
+            captures[2].to_string(),
+            Some(CodeLocation {
+                start_line: captures[1]
+                    .parse()
+                    .ok()
+                    .map(|line: u64| line.saturating_sub(1)),
+                ..Default::default()
+            }),
+        ),
+        None => (first.to_string(), None),
+    };
+
+    ExecutionMessage {
+        level,
+        message,
+        stack_trace,
+        code_location,
+        ..Default::default()
+    }
+}
+
+/// Create a `NodeType` string and `Hint` for a Lua value
+fn value_to_type_hint(value: &Value) -> (Option<String>, Option<Hint>) {
+    match value {
+        Value::Nil => (Some(NodeType::Null.to_string()), None),
+        Value::Boolean(value) => (
+            Some(NodeType::Boolean.to_string()),
+            Some(Hint::Boolean(*value)),
+        ),
+        Value::Integer(value) => (
+            Some(NodeType::Integer.to_string()),
+            Some(Hint::Integer(*value)),
+        ),
+        Value::Number(value) => (
+            Some(NodeType::Number.to_string()),
+            Some(Hint::Number(*value)),
+        ),
+        Value::String(value) => (
+            Some(NodeType::String.to_string()),
+            Some(Hint::StringHint(StringHint::new(
+                value.to_string_lossy().chars().count() as i64,
+            ))),
+        ),
+        Value::Table(table) => {
+            if let Ok(Value::String(typ)) = table.raw_get::<_, Value>("type") {
+                (Some(typ.to_string_lossy().to_string()), None)
+            } else if table.raw_len() > 0 {
+                (
+                    Some(NodeType::Array.to_string()),
+                    Some(Hint::ArrayHint(ArrayHint::new(table.raw_len() as i64))),
+                )
+            } else {
+                // Sort by key because the order of iteration of Lua tables is undefined
+                let pairs = table
+                    .clone()
+                    .pairs::<String, Value>()
+                    .flatten()
+                    .sorted_by(|(a, ..), (b, ..)| a.cmp(b))
+                    .collect_vec();
+                let length = pairs.len() as i64;
+                let (keys, values) = pairs
+                    .into_iter()
+                    .map(|(key, value)| {
+                        (
+                            key,
+                            value_to_type_hint(&value)
+                                .1
+                                .unwrap_or(Hint::Unknown(Unknown::new())),
+                        )
+                    })
+                    .unzip();
+                (
+                    Some(NodeType::Object.to_string()),
+                    Some(Hint::ObjectHint(ObjectHint::new(length, keys, values))),
+                )
+            }
+        }
+        _ => (None, None),
+    }
+}
+
+/// Convert a Lua value to a Stencila `Node`
+///
+/// Tables are converted using serde so that sequences become `Array`s, other
+/// tables become `Object`s, and tables with a `type` field become the
+/// corresponding node type. Values which can not be converted (e.g. functions)
+/// are represented as a string of their type.
+fn value_to_node(lua: &Lua, value: Value) -> Node {
+    match value {
+        Value::Nil => Node::Null(Null),
+        Value::Boolean(value) => Node::Boolean(value),
+        Value::Integer(value) => Node::Integer(value),
+        Value::Number(value) => Node::Number(value),
+        Value::String(value) => {
+            let string = value.to_string_lossy().to_string();
+
+            // Allow nodes to be printed as JSON objects
+            if string.starts_with('{') {
+                if let Ok(node) = serde_json::from_str(&string) {
+                    return node;
+                }
+            }
+
+            Node::String(string)
+        }
+        Value::Table(..) => lua
+            .from_value::<serde_json::Value>(value)
+            .ok()
+            .and_then(|json| serde_json::from_value(json).ok())
+            .unwrap_or_else(|| Node::String("<table>".to_string())),
+        _ => Node::String(format!("<{}>", value.type_name())),
+    }
+}
+
+/// Convert a Stencila `Node` to a Lua value
+fn node_to_value<'lua>(lua: &'lua Lua, node: &Node) -> Result<Value<'lua>> {
+    Ok(match node {
+        Node::Null(..) => Value::Nil,
+        Node::Boolean(value) => Value::Boolean(*value),
+        Node::Integer(value) => Value::Integer(*value),
+        Node::UnsignedInteger(value) => Value::Integer(*value as i64),
+        Node::Number(value) => Value::Number(*value),
+        Node::String(value) => Value::String(lua.create_string(value)?),
+        _ => {
+            // Resort to conversion using serde
+            lua.to_value(node)?
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use common_dev::pretty_assertions::assert_eq;
+    use kernel::{
+        common::{indexmap::IndexMap, tokio},
+        schema::{Array, Node, Object, Primitive},
+        tests::{create_instance, start_instance},
+    };
+
+    use super::*;
+
+    // Pro-tip! Use get logs for these tests use:
+    //
+    // ```sh
+    // RUST_LOG=trace cargo test -p kernel-lua -- --nocapture
+    // ```
+
+    /// Standard kernel test for execution of code
+    #[test_log::test(tokio::test)]
+    async fn execution() -> Result<()> {
+        let Some(instance) = create_instance::<LuaKernel>().await? else {
+            return Ok(());
+        };
+
+        kernel::tests::execution(
+            instance,
+            vec![
+                // Empty code: no outputs
+                ("", vec![], vec![]),
+                (" ", vec![], vec![]),
+                ("\n\n", vec![], vec![]),
+                // Only an expression: one output
+                (
+                    "
+1 + 1",
+                    vec![Node::Integer(2)],
+                    vec![],
+                ),
+                // Prints and an expression: multiple, separate outputs
+                (
+                    "
+print(1)
+print(2)
+1 + 2",
+                    vec![Node::Integer(1), Node::Integer(2), Node::Integer(3)],
+                    vec![],
+                ),
+                // Variables set in one chunk are available in the next
+                (
+                    "
+a = 1
+b = 2",
+                    vec![],
+                    vec![],
+                ),
+                (
+                    "
+print(a)
+b",
+                    vec![Node::Integer(1), Node::Integer(2)],
+                    vec![],
+                ),
+                // Last line which is a statement: no outputs
+                (
+                    "
+for i = 1, 3 do
+  c = i
+end",
+                    vec![],
+                    vec![],
+                ),
+            ],
+        )
+        .await
+    }
+
+    /// Standard kernel test for evaluation of expressions
+    #[test_log::test(tokio::test)]
+    async fn evaluation() -> Result<()> {
+        let Some(instance) = create_instance::<LuaKernel>().await? else {
+            return Ok(());
+        };
+
+        kernel::tests::evaluation(
+            instance,
+            vec![
+                ("1 + 1", Node::Integer(2), None),
+                ("2.0 * 2.2", Node::Number(4.4), None),
+                ("16 ^ 0.5", Node::Number(4.0), None),
+                (r#"'a' .. "bc""#, Node::String("abc".to_string()), None),
+                (
+                    r#"string.lower("ABC")"#,
+                    Node::String("abc".to_string()),
+                    None,
+                ),
+                (
+                    "{1, 2, 3}",
+                    Node::Array(Array(vec![
+                        Primitive::Integer(1),
+                        Primitive::Integer(2),
+                        Primitive::Integer(3),
+                    ])),
+                    None,
+                ),
+                (
+                    "{a = 1, b = 2.3}",
+                    Node::Object(Object(IndexMap::from([
+                        (String::from("a"), Primitive::Integer(1)),
+                        (String::from("b"), Primitive::Number(2.3)),
+                    ]))),
+                    None,
+                ),
+                ("", Node::Null(Null), None),
+                ("foo", Node::Null(Null), None),
+                ("@", Node::Null(Null), Some("unexpected symbol near '@'")),
+            ],
+        )
+        .await
+    }
+
+    /// Standard kernel test for printing nodes
+    #[test_log::test(tokio::test)]
+    async fn printing() -> Result<()> {
+        let Some(instance) = create_instance::<LuaKernel>().await? else {
+            return Ok(());
+        };
+
+        kernel::tests::printing(
+            instance,
+            r#"print("str")"#,
+            r#"print("str1", "str2")"#,
+            r#"
+                print(nil)
+                print(true)
+                print(1)
+                print(2.3)
+                print("str")
+                print({1, 2.3, "str"})
+                print({a = 1, b = 2.3, c = "str"})
+            "#,
+            r#"print('{"type":"Paragraph","content":[]}')"#,
+        )
+        .await
+    }
+
+    /// Custom test for execution messages
+    #[tokio::test]
+    async fn messages() -> Result<()> {
+        let Some(mut kernel) = start_instance::<LuaKernel>().await? else {
+            return Ok(());
+        };
+
+        // Syntax error
+        let (outputs, messages) = kernel.execute("bad ^ # syntax").await?;
+        assert_eq!(messages[0].level, MessageLevel::Error);
+        assert_eq!(
+            messages[0].code_location,
+            Some(CodeLocation {
+                start_line: Some(0),
+                ..Default::default()
+            })
+        );
+        assert_eq!(outputs, vec![]);
+
+        // Runtime error
+        let (outputs, messages) = kernel
+            .execute(
+                "
+local a = 1
+foo()",
+            )
+            .await?;
+        assert_eq!(messages[0].level, MessageLevel::Exception);
+        assert_eq!(
+            messages[0].message,
+            "attempt to call a nil value (global 'foo')"
+        );
+        assert!(messages[0].stack_trace.is_some());
+        assert_eq!(
+            messages[0].code_location,
+            Some(CodeLocation {
+                start_line: Some(2),
+                ..Default::default()
+            })
+        );
+        assert_eq!(outputs, vec![]);
+
+        Ok(())
+    }
+
+    /// Standard kernel test for getting runtime information
+    #[test_log::test(tokio::test)]
+    async fn info() -> Result<()> {
+        let Some(instance) = create_instance::<LuaKernel>().await? else {
+            return Ok(());
+        };
+
+        let sw = kernel::tests::info(instance).await?;
+        assert_eq!(sw.name, "Lua");
+        assert_eq!(sw.options.software_version.as_deref(), Some("5.4"));
+        assert!(sw.options.operating_system.is_some());
+
+        Ok(())
+    }
+
+    /// Standard kernel test for listing installed packages
+    #[test_log::test(tokio::test)]
+    async fn packages() -> Result<()> {
+        let Some(instance) = start_instance::<LuaKernel>().await? else {
+            return Ok(());
+        };
+
+        let pkgs = kernel::tests::packages(instance).await?;
+        assert!(pkgs.is_empty());
+
+        Ok(())
+    }
+
+    /// Standard kernel test for variable listing
+    #[test_log::test(tokio::test)]
+    async fn var_listing() -> Result<()> {
+        let Some(instance) = create_instance::<LuaKernel>().await? else {
+            return Ok(());
+        };
+
+        kernel::tests::var_listing(
+            instance,
+            r#"
+bool = true
+int = 123
+num = 1.23
+str = "abc👍"
+arr = {1, 2, 3}
+obj = {a = 1, b = 2.3}
+para = {type = "Paragraph", content = {}}
+"#,
+            vec![
+                Variable {
+                    name: "bool".to_string(),
+                    native_type: Some("boolean".to_string()),
+                    node_type: Some("Boolean".to_string()),
+                    hint: Some(Hint::Boolean(true)),
+                    programming_language: Some("Lua".to_string()),
+                    ..Default::default()
+                },
+                Variable {
+                    name: "int".to_string(),
+                    native_type: Some("integer".to_string()),
+                    node_type: Some("Integer".to_string()),
+                    hint: Some(Hint::Integer(123)),
+                    programming_language: Some("Lua".to_string()),
+                    ..Default::default()
+                },
+                Variable {
+                    name: "num".to_string(),
+                    native_type: Some("number".to_string()),
+                    node_type: Some("Number".to_string()),
+                    hint: Some(Hint::Number(1.23)),
+                    programming_language: Some("Lua".to_string()),
+                    ..Default::default()
+                },
+                Variable {
+                    name: "str".to_string(),
+                    native_type: Some("string".to_string()),
+                    node_type: Some("String".to_string()),
+                    hint: Some(Hint::StringHint(StringHint::new(4))),
+                    programming_language: Some("Lua".to_string()),
+                    ..Default::default()
+                },
+                Variable {
+                    name: "arr".to_string(),
+                    native_type: Some("table".to_string()),
+                    node_type: Some("Array".to_string()),
+                    hint: Some(Hint::ArrayHint(ArrayHint::new(3))),
+                    programming_language: Some("Lua".to_string()),
+                    ..Default::default()
+                },
+                Variable {
+                    name: "obj".to_string(),
+                    native_type: Some("table".to_string()),
+                    node_type: Some("Object".to_string()),
+                    hint: Some(Hint::ObjectHint(ObjectHint::new(
+                        2,
+                        vec!["a".to_string(), "b".to_string()],
+                        vec![Hint::Integer(1), Hint::Number(2.3)],
+                    ))),
+                    programming_language: Some("Lua".to_string()),
+                    ..Default::default()
+                },
+                Variable {
+                    name: "para".to_string(),
+                    native_type: Some("table".to_string()),
+                    node_type: Some("Paragraph".to_string()),
+                    programming_language: Some("Lua".to_string()),
+                    ..Default::default()
+                },
+            ],
+        )
+        .await
+    }
+
+    /// Standard kernel test for variable management
+    #[test_log::test(tokio::test)]
+    async fn var_management() -> Result<()> {
+        let Some(instance) = create_instance::<LuaKernel>().await? else {
+            return Ok(());
+        };
+
+        kernel::tests::var_management(instance).await
+    }
+
+    /// Standard kernel test for forking
+    #[test_log::test(tokio::test)]
+    async fn forking() -> Result<()> {
+        let Some(instance) = create_instance::<LuaKernel>().await? else {
+            return Ok(());
+        };
+
+        kernel::tests::forking(instance).await
+    }
+
+    /// Standard kernel test for signals
+    ///
+    /// Needs to use multiple threads because no `await`s in `execute()` method
+    /// for signals and watches to run on.
+    #[test_log::test(tokio::test(flavor = "multi_thread", worker_threads = 2))]
+    async fn signals() -> Result<()> {
+        let Some(instance) = create_instance::<LuaKernel>().await? else {
+            return Ok(());
+        };
+
+        kernel::tests::signals(
+            instance,
+            "
+local start = os.clock()
+while os.clock() - start < 0.1 do end
+value = 1
+print(value)",
+            None,
+            Some(
+                "
+local start = os.clock()
+while os.clock() - start < 0.1 do end",
+            ),
+            None,
+        )
+        .await
+    }
+
+    /// Standard kernel test for stopping
+    #[test_log::test(tokio::test)]
+    async fn stop() -> Result<()> {
+        let Some(instance) = create_instance::<LuaKernel>().await? else {
+            return Ok(());
+        };
+
+        kernel::tests::stop(instance).await
+    }
+}
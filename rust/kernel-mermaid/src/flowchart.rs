@@ -0,0 +1,478 @@
+//! Rendering of Mermaid flowcharts
+//!
+//! Flowcharts are parsed into nodes and edges which are then converted to
+//! Graphviz DOT and laid out using the same library as `kernel-graphviz`.
+
+use std::fmt::Write;
+
+use layout::{
+    backends::svg::SVGWriter,
+    gv::{DotParser, GraphBuilder},
+};
+
+use kernel::common::{indexmap::IndexMap, once_cell::sync::Lazy, regex::Regex};
+
+use crate::{label_text, SyntaxError};
+
+/// Render a flowchart to SVG
+///
+/// The `header` argument is the index of the line containing the
+/// `flowchart` (or `graph`) keyword.
+pub(crate) fn render(code: &str, header: usize) -> Result<String, SyntaxError> {
+    let flowchart = Flowchart::parse(code, header)?;
+    flowchart.to_svg(header)
+}
+
+/// The shape of a node
+///
+/// Mermaid has many more shapes than those supported by the layout
+/// library so most are rendered as boxes.
+#[derive(Clone, Copy)]
+enum Shape {
+    Box,
+    Circle,
+    DoubleCircle,
+}
+
+impl Shape {
+    fn as_dot(&self) -> &'static str {
+        match self {
+            Shape::Box => "box",
+            Shape::Circle => "circle",
+            Shape::DoubleCircle => "doublecircle",
+        }
+    }
+}
+
+/// The opening and closing delimiters of node shapes
+///
+/// Ordered so that longer delimiters are matched before shorter ones.
+const SHAPES: &[(&str, &str, Shape)] = &[
+    ("(((", ")))", Shape::DoubleCircle),
+    ("((", "))", Shape::Circle),
+    ("([", "])", Shape::Box),
+    ("[[", "]]", Shape::Box),
+    ("[(", ")]", Shape::Box),
+    ("{{", "}}", Shape::Box),
+    ("[", "]", Shape::Box),
+    ("(", ")", Shape::Box),
+    ("{", "}", Shape::Box),
+    (">", "]", Shape::Box),
+];
+
+/// Statements which are valid Mermaid but which are ignored when rendering
+const IGNORED: &[&str] = &[
+    "class",
+    "classDef",
+    "click",
+    "direction",
+    "end",
+    "linkStyle",
+    "style",
+    "subgraph",
+];
+
+/// The style of a link between nodes
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum LinkStyle {
+    Solid,
+    Dotted,
+    Thick,
+    Invisible,
+}
+
+/// A link between nodes
+#[derive(Clone)]
+struct Link {
+    /// Whether the link has an arrow head
+    ///
+    /// Bidirectional links (e.g. `<-->`) are rendered without arrow heads
+    directed: bool,
+    style: LinkStyle,
+    label: Option<String>,
+}
+
+struct FlowNode {
+    label: String,
+    shape: Shape,
+}
+
+struct Edge {
+    from: String,
+    to: String,
+    link: Link,
+}
+
+#[derive(Default)]
+struct Flowchart {
+    /// Whether the flowchart is laid out horizontally rather than vertically
+    horizontal: bool,
+
+    /// The nodes in the flowchart, in the order that they were declared
+    nodes: IndexMap<String, FlowNode>,
+
+    /// The edges between nodes
+    edges: Vec<Edge>,
+}
+
+/// A cursor over a statement within a line
+struct Cursor<'lt> {
+    /// The line, truncated to the end of the statement
+    text: &'lt str,
+
+    /// The current byte position within the line
+    pos: usize,
+
+    /// The index of the line
+    line: usize,
+}
+
+impl<'lt> Cursor<'lt> {
+    fn rest(&self) -> &'lt str {
+        &self.text[self.pos..]
+    }
+
+    fn is_done(&self) -> bool {
+        self.rest().trim().is_empty()
+    }
+
+    fn advance(&mut self, bytes: usize) {
+        self.pos += bytes;
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.advance(rest.len() - rest.trim_start().len());
+    }
+
+    fn eat(&mut self, prefix: &str) -> bool {
+        if self.rest().starts_with(prefix) {
+            self.advance(prefix.len());
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Take the next word (a run of alphanumeric characters or underscores)
+    fn word(&mut self) -> &'lt str {
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        self.advance(len);
+        &rest[..len]
+    }
+
+    fn error_at<S: Into<String>>(&self, pos: usize, message: S) -> SyntaxError {
+        SyntaxError::new(message, self.line, self.text[..pos].chars().count())
+    }
+
+    fn error<S: Into<String>>(&self, message: S) -> SyntaxError {
+        self.error_at(self.pos, message)
+    }
+}
+
+/// Split a line into the byte ranges of its `;` separated statements
+fn statements(line: &str) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    let mut in_quotes = false;
+    for (index, char) in line.char_indices() {
+        match char {
+            '"' => in_quotes = !in_quotes,
+            ';' if !in_quotes => {
+                ranges.push((start, index));
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    ranges.push((start, line.len()));
+    ranges
+}
+
+impl Flowchart {
+    /// Parse a flowchart
+    fn parse(code: &str, header: usize) -> Result<Self, SyntaxError> {
+        let mut flowchart = Self::default();
+
+        let mut in_header = true;
+        for (index, line) in code.lines().enumerate().skip(header) {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with("%%") {
+                continue;
+            }
+
+            for (start, end) in statements(line) {
+                let mut cursor = Cursor {
+                    text: &line[..end],
+                    pos: start,
+                    line: index,
+                };
+                if in_header {
+                    flowchart.parse_header(&mut cursor)?;
+                    in_header = false;
+                } else {
+                    flowchart.parse_statement(&mut cursor)?;
+                }
+            }
+        }
+
+        Ok(flowchart)
+    }
+
+    /// Parse the header of the flowchart e.g. `flowchart LR`
+    fn parse_header(&mut self, cursor: &mut Cursor) -> Result<(), SyntaxError> {
+        cursor.skip_whitespace();
+        cursor.word();
+        cursor.skip_whitespace();
+        if cursor.is_done() {
+            return Ok(());
+        }
+
+        let start = cursor.pos;
+        let direction = cursor.rest().split_whitespace().next().unwrap_or_default();
+        self.horizontal = match direction {
+            "TB" | "TD" | "BT" => false,
+            "LR" | "RL" => true,
+            _ => {
+                return Err(cursor.error_at(start, format!("Unknown direction `{direction}`")));
+            }
+        };
+        cursor.advance(direction.len());
+
+        if !cursor.is_done() {
+            return Err(cursor.error("Expected `;` or a new line after direction"));
+        }
+
+        Ok(())
+    }
+
+    /// Parse a statement e.g. `A[Start] --> B & C`
+    fn parse_statement(&mut self, cursor: &mut Cursor) -> Result<(), SyntaxError> {
+        cursor.skip_whitespace();
+        if cursor.is_done() {
+            return Ok(());
+        }
+
+        let keyword = cursor.rest().split_whitespace().next().unwrap_or_default();
+        if IGNORED.contains(&keyword) {
+            return Ok(());
+        }
+
+        let mut sources = self.parse_group(cursor)?;
+        loop {
+            cursor.skip_whitespace();
+            if cursor.is_done() {
+                break;
+            }
+
+            let link = Self::parse_link(cursor)?;
+            let targets = self.parse_group(cursor)?;
+            if link.style != LinkStyle::Invisible {
+                for from in &sources {
+                    for to in &targets {
+                        self.edges.push(Edge {
+                            from: from.clone(),
+                            to: to.clone(),
+                            link: link.clone(),
+                        });
+                    }
+                }
+            }
+            sources = targets;
+        }
+
+        Ok(())
+    }
+
+    /// Parse a group of one or more nodes separated by `&`
+    fn parse_group(&mut self, cursor: &mut Cursor) -> Result<Vec<String>, SyntaxError> {
+        let mut ids = vec![self.parse_node(cursor)?];
+        loop {
+            cursor.skip_whitespace();
+            if cursor.eat("&") {
+                ids.push(self.parse_node(cursor)?);
+            } else {
+                break;
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Parse a node, with an optional shape and label, returning its id
+    fn parse_node(&mut self, cursor: &mut Cursor) -> Result<String, SyntaxError> {
+        cursor.skip_whitespace();
+
+        let id = cursor.word().to_string();
+        if id.is_empty() {
+            return Err(cursor.error("Expected a node id"));
+        }
+
+        for (open, close, shape) in SHAPES {
+            let close = *close;
+            let start = cursor.pos;
+            if !cursor.eat(open) {
+                continue;
+            }
+
+            let unclosed =
+                || cursor.error_at(start, format!("Expected `{close}` to close node shape"));
+
+            let rest = cursor.rest();
+            let (label, consumed) = match rest.strip_prefix('"') {
+                Some(quoted) => match quoted.find('"') {
+                    Some(end) if quoted[end + 1..].starts_with(close) => {
+                        (&quoted[..end], end + 2 + close.len())
+                    }
+                    _ => return Err(unclosed()),
+                },
+                None => match rest.find(close) {
+                    Some(end) => (&rest[..end], end + close.len()),
+                    None => return Err(unclosed()),
+                },
+            };
+            cursor.advance(consumed);
+
+            self.nodes.insert(
+                id.clone(),
+                FlowNode {
+                    label: label_text(label),
+                    shape: *shape,
+                },
+            );
+
+            return Ok(id);
+        }
+
+        self.nodes.entry(id.clone()).or_insert_with(|| FlowNode {
+            label: id.clone(),
+            shape: Shape::Box,
+        });
+
+        Ok(id)
+    }
+
+    /// Parse a link e.g. `-->`, `-.->|label|`, `-- label -->`
+    fn parse_link(cursor: &mut Cursor) -> Result<Link, SyntaxError> {
+        static PLAIN_RE: Lazy<Regex> = Lazy::new(|| {
+            Regex::new(r"^(<)?(-{2,}>|-{3,}|-\.+->|-\.+-|={2,}>|={3,}|~~~)(?:\s*\|([^|]*)\|)?")
+                .expect("invalid regex")
+        });
+        static TEXT_RE: Lazy<Regex> = Lazy::new(|| {
+            Regex::new(r"^(<)?(--|-\.|==)\s+(.+?)\s+(-{2,}>|-{3,}|\.-+>|\.-+|={2,}>|={3,})")
+                .expect("invalid regex")
+        });
+
+        let rest = cursor.rest();
+        let (bidirectional, body, label, len) = if let Some(captures) = PLAIN_RE.captures(rest) {
+            (
+                captures.get(1).is_some(),
+                captures[2].to_string(),
+                captures.get(3).map(|label| label.as_str()),
+                captures[0].len(),
+            )
+        } else if let Some(captures) = TEXT_RE.captures(rest) {
+            (
+                captures.get(1).is_some(),
+                [&captures[2], &captures[4]].concat(),
+                captures.get(3).map(|label| label.as_str()),
+                captures[0].len(),
+            )
+        } else {
+            return Err(cursor.error("Expected a link (e.g. `-->`)"));
+        };
+        cursor.advance(len);
+
+        let style = if body.contains('~') {
+            LinkStyle::Invisible
+        } else if body.contains('=') {
+            LinkStyle::Thick
+        } else if body.contains('.') {
+            LinkStyle::Dotted
+        } else {
+            LinkStyle::Solid
+        };
+
+        let label = label.map(label_text).filter(|label| !label.is_empty());
+
+        Ok(Link {
+            directed: body.ends_with('>') && !bidirectional,
+            style,
+            label,
+        })
+    }
+
+    /// Convert the flowchart to Graphviz DOT
+    fn to_dot(&self) -> String {
+        fn quote(value: &str) -> String {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            ["\"", &value, "\""].concat()
+        }
+
+        let mut dot = String::from("digraph {\n");
+        if self.horizontal {
+            dot.push_str("rankdir=LR\n");
+        }
+
+        for (id, node) in &self.nodes {
+            writeln!(
+                dot,
+                "{} [shape={}, label={}]",
+                quote(id),
+                node.shape.as_dot(),
+                quote(&node.label)
+            )
+            .ok();
+        }
+
+        for Edge { from, to, link } in &self.edges {
+            let mut attrs = Vec::new();
+            if let Some(label) = &link.label {
+                attrs.push(format!("label={}", quote(label)));
+            }
+            match link.style {
+                LinkStyle::Dotted => attrs.push("style=dashed".to_string()),
+                LinkStyle::Thick => attrs.push("penwidth=2".to_string()),
+                _ => {}
+            }
+
+            let op = if link.directed { "->" } else { "--" };
+            let attrs = if attrs.is_empty() {
+                String::new()
+            } else {
+                format!(" [{}]", attrs.join(", "))
+            };
+            writeln!(dot, "{} {op} {}{attrs}", quote(from), quote(to)).ok();
+        }
+
+        dot.push('}');
+        dot
+    }
+
+    /// Render the flowchart to SVG
+    fn to_svg(&self, header: usize) -> Result<String, SyntaxError> {
+        if self.nodes.is_empty() {
+            // Avoid panic if graph is empty by returning empty SVG
+            return Ok(r#"<svg xmlns="http://www.w3.org/2000/svg"></svg>"#.to_string());
+        }
+
+        let dot = self.to_dot();
+        let graph = DotParser::new(&dot).process().map_err(|error| {
+            SyntaxError::new(format!("Unable to lay out flowchart: {error}"), header, 0)
+        })?;
+
+        let mut graph_builder = GraphBuilder::new();
+        graph_builder.visit_graph(&graph);
+        let mut visual_graph = graph_builder.get();
+
+        let mut svg_writer = SVGWriter::new();
+        visual_graph.do_it(false, false, false, &mut svg_writer);
+
+        Ok(svg_writer.finalize())
+    }
+}
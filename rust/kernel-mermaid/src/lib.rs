@@ -0,0 +1,305 @@
+use kernel::{
+    common::{
+        async_trait::async_trait, eyre::Result, once_cell::sync::Lazy, regex::Regex, tracing,
+    },
+    format::Format,
+    schema::{
+        CodeLocation, ExecutionMessage, ImageObject, MessageLevel, Node, SoftwareApplication,
+        SoftwareApplicationOptions,
+    },
+    svg_data_uri, Kernel, KernelInstance,
+};
+
+mod flowchart;
+mod sequence;
+
+const NAME: &str = "mermaid";
+
+/// A kernel for rendering Mermaid diagrams to SVGs
+///
+/// Currently supports a subset of the Mermaid syntax for flowcharts
+/// and sequence diagrams.
+#[derive(Default)]
+pub struct MermaidKernel {}
+
+impl Kernel for MermaidKernel {
+    fn name(&self) -> String {
+        NAME.to_string()
+    }
+
+    fn supports_languages(&self) -> Vec<Format> {
+        vec![Format::Mermaid]
+    }
+
+    fn executes_on_compile(&self) -> bool {
+        true
+    }
+
+    fn create_instance(&self) -> Result<Box<dyn KernelInstance>> {
+        Ok(Box::new(MermaidKernelInstance {}))
+    }
+}
+
+#[derive(Default)]
+pub struct MermaidKernelInstance {}
+
+#[async_trait]
+impl KernelInstance for MermaidKernelInstance {
+    fn name(&self) -> String {
+        NAME.to_string()
+    }
+
+    async fn execute(&mut self, code: &str) -> Result<(Vec<Node>, Vec<ExecutionMessage>)> {
+        tracing::debug!("Rendering Mermaid diagram to SVG");
+
+        let svg = match lines(code, 0).next() {
+            Some((line, column, header)) => {
+                let keyword = header.split_whitespace().next().unwrap_or_default();
+                let keyword = keyword.trim_end_matches(';');
+                match keyword {
+                    "flowchart" | "graph" => flowchart::render(code, line),
+                    "sequenceDiagram" => sequence::render(code, line),
+                    _ => Err(SyntaxError::new(
+                        format!("Unsupported diagram type `{keyword}`, expected `flowchart`, `graph` or `sequenceDiagram`"),
+                        line,
+                        column,
+                    )),
+                }
+            }
+            None => Err(SyntaxError::new(
+                "Expected a diagram type (e.g. `flowchart`)",
+                0,
+                0,
+            )),
+        };
+
+        Ok(match svg {
+            Ok(svg) => (vec![svg_to_image(&svg)], Vec::new()),
+            Err(error) => (Vec::new(), vec![error.into()]),
+        })
+    }
+
+    async fn info(&mut self) -> Result<SoftwareApplication> {
+        tracing::trace!("Getting Mermaid runtime info");
+
+        Ok(SoftwareApplication {
+            name: "Mermaid Kernel".to_string(),
+            options: Box::new(SoftwareApplicationOptions {
+                operating_system: Some(std::env::consts::OS.to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+    }
+}
+
+/// A syntax error in a diagram
+///
+/// Lines and columns are zero-based, consistent with [`CodeLocation`].
+#[derive(Debug)]
+pub(crate) struct SyntaxError {
+    message: String,
+    line: usize,
+    column: usize,
+}
+
+impl SyntaxError {
+    pub fn new<S: Into<String>>(message: S, line: usize, column: usize) -> Self {
+        Self {
+            message: message.into(),
+            line,
+            column,
+        }
+    }
+}
+
+impl From<SyntaxError> for ExecutionMessage {
+    fn from(error: SyntaxError) -> Self {
+        ExecutionMessage {
+            error_type: Some("SyntaxError".to_string()),
+            code_location: Some(CodeLocation {
+                start_line: Some(error.line as u64),
+                start_column: Some(error.column as u64),
+                ..Default::default()
+            }),
+            ..ExecutionMessage::new(MessageLevel::Error, error.message)
+        }
+    }
+}
+
+/// Iterate over the non-empty, non-comment lines of a diagram
+///
+/// Skips lines before `from` and yields the line index, the column of the
+/// first non-whitespace character, and the trimmed line.
+pub(crate) fn lines(code: &str, from: usize) -> impl Iterator<Item = (usize, usize, &str)> {
+    code.lines()
+        .enumerate()
+        .skip(from)
+        .filter_map(|(index, line)| {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with("%%") {
+                return None;
+            }
+
+            let column = line.chars().take_while(|c| c.is_whitespace()).count();
+            Some((index, column, trimmed))
+        })
+}
+
+/// Convert a label into text, replacing HTML line breaks with newlines
+pub(crate) fn label_text(label: &str) -> String {
+    static BR_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)<br\s*/?>").expect("invalid regex"));
+    BR_RE.replace_all(label.trim(), "\n").to_string()
+}
+
+/// Create an [`ImageObject`] with an SVG data URI
+fn svg_to_image(svg: &str) -> Node {
+    Node::ImageObject(ImageObject {
+        content_url: svg_data_uri(svg),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use common_dev::pretty_assertions::assert_eq;
+    use kernel::common::tokio;
+
+    use super::*;
+
+    /// Get the location of the first message
+    fn location(messages: &[ExecutionMessage]) -> (u64, u64) {
+        let location = messages[0]
+            .code_location
+            .as_ref()
+            .expect("should have location");
+        (
+            location.start_line.unwrap_or_default(),
+            location.start_column.unwrap_or_default(),
+        )
+    }
+
+    #[tokio::test]
+    async fn empty() -> Result<()> {
+        let mut kernel = MermaidKernelInstance {};
+
+        let (outputs, messages) = kernel.execute("").await?;
+        assert_eq!(
+            messages[0].message,
+            "Expected a diagram type (e.g. `flowchart`)"
+        );
+        assert!(outputs.is_empty());
+
+        let (outputs, messages) = kernel.execute("flowchart").await?;
+        assert_eq!(messages, vec![]);
+        assert!(matches!(outputs[0], Node::ImageObject(..)));
+
+        let (outputs, messages) = kernel.execute("sequenceDiagram").await?;
+        assert_eq!(messages, vec![]);
+        assert!(matches!(outputs[0], Node::ImageObject(..)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn flowcharts() -> Result<()> {
+        let mut kernel = MermaidKernelInstance {};
+
+        let (outputs, messages) = kernel
+            .execute(
+                "%% A comment
+flowchart LR
+    A[Start] --> B{Is it?}
+    B -- Yes --> C((OK))
+    B -->|No| D[(Database)] & E
+    C -.-> A; D ==> E
+",
+            )
+            .await?;
+        assert_eq!(messages, vec![]);
+        assert!(matches!(outputs[0], Node::ImageObject(..)));
+
+        let (outputs, messages) = kernel.execute("graph TD; A---B---C").await?;
+        assert_eq!(messages, vec![]);
+        assert!(matches!(outputs[0], Node::ImageObject(..)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sequence_diagrams() -> Result<()> {
+        let mut kernel = MermaidKernelInstance {};
+
+        let (outputs, messages) = kernel
+            .execute(
+                "sequenceDiagram
+    participant A as Alice
+    actor B as Bob
+    A->>B: Hello Bob, how are you?
+    B-->>A: Great!
+    Note over A,B: A typical interaction
+    A-)C: See you later
+",
+            )
+            .await?;
+        assert_eq!(messages, vec![]);
+        assert!(matches!(outputs[0], Node::ImageObject(..)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn syntax_errors() -> Result<()> {
+        let mut kernel = MermaidKernelInstance {};
+
+        let (outputs, messages) = kernel.execute("pie\n  \"A\": 1").await?;
+        assert_eq!(
+            messages[0].message,
+            "Unsupported diagram type `pie`, expected `flowchart`, `graph` or `sequenceDiagram`"
+        );
+        assert_eq!(messages[0].level, MessageLevel::Error);
+        assert_eq!(location(&messages), (0, 0));
+        assert!(outputs.is_empty());
+
+        let (outputs, messages) = kernel.execute("flowchart UP").await?;
+        assert_eq!(messages[0].message, "Unknown direction `UP`");
+        assert_eq!(location(&messages), (0, 10));
+        assert!(outputs.is_empty());
+
+        let (outputs, messages) = kernel
+            .execute(
+                "flowchart
+    A --> B
+    B -->
+",
+            )
+            .await?;
+        assert_eq!(messages[0].message, "Expected a node id");
+        assert_eq!(location(&messages), (2, 9));
+        assert!(outputs.is_empty());
+
+        let (outputs, messages) = kernel.execute("graph\n  A[Start --> B").await?;
+        assert_eq!(messages[0].message, "Expected `]` to close node shape");
+        assert_eq!(location(&messages), (1, 3));
+        assert!(outputs.is_empty());
+
+        let (outputs, messages) = kernel.execute("graph\n  A ->> B").await?;
+        assert_eq!(messages[0].message, "Expected a link (e.g. `-->`)");
+        assert_eq!(location(&messages), (1, 4));
+        assert!(outputs.is_empty());
+
+        let (outputs, messages) = kernel
+            .execute(
+                "sequenceDiagram
+    A->>B: Hello
+    A=>B: Oops
+",
+            )
+            .await?;
+        assert_eq!(messages[0].message, "Unrecognized statement");
+        assert_eq!(location(&messages), (2, 4));
+        assert!(outputs.is_empty());
+
+        Ok(())
+    }
+}
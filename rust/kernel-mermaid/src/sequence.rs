@@ -0,0 +1,418 @@
+//! Rendering of Mermaid sequence diagrams
+//!
+//! The layout of sequence diagrams is simple (a column per participant and
+//! a row per message or note) so they are rendered directly to SVG.
+
+use std::fmt::Write;
+
+use kernel::common::{once_cell::sync::Lazy, regex::Regex};
+
+use crate::{label_text, lines, SyntaxError};
+
+/// Render a sequence diagram to SVG
+///
+/// The `header` argument is the index of the line containing the
+/// `sequenceDiagram` keyword.
+pub(crate) fn render(code: &str, header: usize) -> Result<String, SyntaxError> {
+    let diagram = SequenceDiagram::parse(code, header)?;
+    Ok(diagram.to_svg())
+}
+
+/// Statements which are valid Mermaid but which are ignored when rendering
+const IGNORED: &[&str] = &[
+    "activate",
+    "alt",
+    "and",
+    "autonumber",
+    "box",
+    "break",
+    "create",
+    "critical",
+    "deactivate",
+    "destroy",
+    "else",
+    "end",
+    "loop",
+    "opt",
+    "par",
+    "rect",
+    "title",
+];
+
+/// The approximate width of a character of text
+const CHAR_WIDTH: f64 = 8.;
+
+/// The padding around text within boxes
+const PADDING: f64 = 10.;
+
+/// The minimum horizontal gap between boxes
+const GAP: f64 = 40.;
+
+/// The height of participant boxes
+const BOX_HEIGHT: f64 = 40.;
+
+/// The minimum width of participant boxes
+const MIN_BOX_WIDTH: f64 = 80.;
+
+/// The height of each row of messages or notes
+const ROW_HEIGHT: f64 = 40.;
+
+/// The width of the loop drawn for messages from a participant to itself
+const SELF_WIDTH: f64 = 30.;
+
+/// The margin around the diagram
+const MARGIN: f64 = 20.;
+
+/// A participant (or actor) in the diagram
+struct Participant {
+    id: String,
+    label: String,
+    actor: bool,
+}
+
+/// The head of a message arrow
+#[derive(Clone, Copy)]
+enum Head {
+    None,
+    Filled,
+    Open,
+    Cross,
+}
+
+/// The placement of a note
+enum Placement {
+    LeftOf(usize),
+    RightOf(usize),
+    Over(usize, usize),
+}
+
+enum Statement {
+    Message {
+        from: usize,
+        to: usize,
+        dotted: bool,
+        head: Head,
+        text: String,
+    },
+    Note {
+        placement: Placement,
+        text: String,
+    },
+}
+
+#[derive(Default)]
+struct SequenceDiagram {
+    participants: Vec<Participant>,
+    statements: Vec<Statement>,
+}
+
+/// Get the approximate width of text
+fn text_width(text: &str) -> f64 {
+    text.chars().count() as f64 * CHAR_WIDTH
+}
+
+/// Escape text for use in SVG
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+impl SequenceDiagram {
+    /// Parse a sequence diagram
+    fn parse(code: &str, header: usize) -> Result<Self, SyntaxError> {
+        static PARTICIPANT_RE: Lazy<Regex> = Lazy::new(|| {
+            Regex::new(r"^(participant|actor)\s+(\S+?)(?:\s+as\s+(.+))?$").expect("invalid regex")
+        });
+        static MESSAGE_RE: Lazy<Regex> = Lazy::new(|| {
+            Regex::new(r"^([^\s\-+:>]+?)\s*(-->>|->>|-->|->|--x|-x|--\)|-\))\s*[+-]?\s*([^\s:]+?)\s*(?::\s*(.*))?$")
+                .expect("invalid regex")
+        });
+        static NOTE_RE: Lazy<Regex> = Lazy::new(|| {
+            Regex::new(r"^(?i:note)\s+(left of|right of|over)\s+([^\s,:]+)(?:\s*,\s*([^\s,:]+))?\s*:\s*(.*)$")
+                .expect("invalid regex")
+        });
+
+        let mut diagram = Self::default();
+        for (line, column, text) in lines(code, header + 1) {
+            let text = text.trim_end_matches(';');
+            let keyword = text.split_whitespace().next().unwrap_or_default();
+            if IGNORED.contains(&keyword) {
+                continue;
+            }
+
+            if let Some(captures) = PARTICIPANT_RE.captures(text) {
+                let index = diagram.participant(&captures[2]);
+                let participant = &mut diagram.participants[index];
+                participant.actor = &captures[1] == "actor";
+                if let Some(label) = captures.get(3) {
+                    participant.label = label_text(label.as_str());
+                }
+            } else if let Some(captures) = MESSAGE_RE.captures(text) {
+                let from = diagram.participant(&captures[1]);
+                let to = diagram.participant(&captures[3]);
+                let arrow = &captures[2];
+                let head = match arrow.trim_start_matches('-') {
+                    ">>" => Head::Filled,
+                    ">" | "" => Head::None,
+                    "x" => Head::Cross,
+                    _ => Head::Open,
+                };
+                diagram.statements.push(Statement::Message {
+                    from,
+                    to,
+                    dotted: arrow.starts_with("--"),
+                    head,
+                    text: captures
+                        .get(4)
+                        .map(|text| label_text(text.as_str()))
+                        .unwrap_or_default(),
+                });
+            } else if let Some(captures) = NOTE_RE.captures(text) {
+                let first = diagram.participant(&captures[2]);
+                let placement = match captures[1].to_lowercase().as_str() {
+                    "left of" => Placement::LeftOf(first),
+                    "right of" => Placement::RightOf(first),
+                    _ => {
+                        let second = captures
+                            .get(3)
+                            .map_or(first, |id| diagram.participant(id.as_str()));
+                        Placement::Over(first.min(second), first.max(second))
+                    }
+                };
+                diagram.statements.push(Statement::Note {
+                    placement,
+                    text: label_text(&captures[4]),
+                });
+            } else {
+                return Err(SyntaxError::new("Unrecognized statement", line, column));
+            }
+        }
+
+        Ok(diagram)
+    }
+
+    /// Get the index of a participant, adding it if necessary
+    fn participant(&mut self, id: &str) -> usize {
+        match self
+            .participants
+            .iter()
+            .position(|participant| participant.id == id)
+        {
+            Some(index) => index,
+            None => {
+                self.participants.push(Participant {
+                    id: id.to_string(),
+                    label: id.to_string(),
+                    actor: false,
+                });
+                self.participants.len() - 1
+            }
+        }
+    }
+
+    /// Calculate the horizontal center of each participant
+    fn centers(&self, widths: &[f64]) -> Vec<f64> {
+        let count = widths.len();
+
+        // The minimum distance between the centers of adjacent participants
+        let mut gaps: Vec<f64> = widths
+            .windows(2)
+            .map(|pair| (pair[0] + pair[1]) / 2. + GAP)
+            .collect();
+        let mut widen = |index: usize, width: f64| {
+            if let Some(gap) = gaps.get_mut(index) {
+                *gap = gap.max(width);
+            }
+        };
+
+        for statement in &self.statements {
+            match statement {
+                Statement::Message { from, to, text, .. } => {
+                    let width = text_width(text) + GAP;
+                    let (first, last) = (*from.min(to), *from.max(to));
+                    if first == last {
+                        widen(first, width + SELF_WIDTH);
+                    } else {
+                        let span = (last - first) as f64;
+                        for index in first..last {
+                            widen(index, width / span);
+                        }
+                    }
+                }
+                Statement::Note {
+                    placement: Placement::RightOf(index),
+                    text,
+                } => widen(*index, text_width(text) + 2. * PADDING + GAP),
+                Statement::Note {
+                    placement: Placement::LeftOf(index),
+                    text,
+                } if *index > 0 => widen(index - 1, text_width(text) + 2. * PADDING + GAP),
+                _ => {}
+            }
+        }
+
+        let mut centers = Vec::with_capacity(count);
+        if let Some(first) = widths.first() {
+            centers.push(first / 2.);
+        }
+        for gap in gaps {
+            let previous = centers.last().copied().unwrap_or_default();
+            centers.push(previous + gap);
+        }
+        centers
+    }
+
+    /// Render the sequence diagram to SVG
+    fn to_svg(&self) -> String {
+        if self.participants.is_empty() {
+            return r#"<svg xmlns="http://www.w3.org/2000/svg"></svg>"#.to_string();
+        }
+
+        let widths: Vec<f64> = self
+            .participants
+            .iter()
+            .map(|participant| (text_width(&participant.label) + 2. * PADDING).max(MIN_BOX_WIDTH))
+            .collect();
+        let centers = self.centers(&widths);
+
+        let mut min_x: f64 = 0.;
+        let mut max_x: f64 = centers[centers.len() - 1] + widths[widths.len() - 1] / 2.;
+
+        let mut body = String::new();
+        let mut y = BOX_HEIGHT;
+        for statement in &self.statements {
+            y += ROW_HEIGHT;
+            match statement {
+                Statement::Message {
+                    from,
+                    to,
+                    dotted,
+                    head,
+                    text,
+                } => {
+                    let dash = if *dotted {
+                        r#" stroke-dasharray="4 3""#
+                    } else {
+                        ""
+                    };
+                    let marker = match head {
+                        Head::None => String::new(),
+                        Head::Filled => r#" marker-end="url(#filled)""#.to_string(),
+                        Head::Open => r#" marker-end="url(#open)""#.to_string(),
+                        Head::Cross => r#" marker-end="url(#cross)""#.to_string(),
+                    };
+                    let text = escape(&text.replace('\n', " "));
+
+                    let x1 = centers[*from];
+                    let x2 = centers[*to];
+                    if from == to {
+                        let x3 = x1 + SELF_WIDTH;
+                        writeln!(
+                            body,
+                            r#"<path d="M{x1},{y0} H{x3} V{y} H{x1}" fill="none" stroke="black"{dash}{marker}/>"#,
+                            y0 = y - 10.,
+                        )
+                        .ok();
+                        writeln!(
+                            body,
+                            r#"<text x="{x}" y="{y}" dominant-baseline="middle">{text}</text>"#,
+                            x = x3 + 6.,
+                            y = y - 5.,
+                        )
+                        .ok();
+                        max_x = max_x.max(x3 + 6. + text_width(&text));
+                        y += 10.;
+                    } else {
+                        writeln!(
+                            body,
+                            r#"<text x="{x}" y="{y0}" text-anchor="middle">{text}</text>"#,
+                            x = (x1 + x2) / 2.,
+                            y0 = y - 6.,
+                        )
+                        .ok();
+                        writeln!(
+                            body,
+                            r#"<line x1="{x1}" y1="{y}" x2="{x2}" y2="{y}" stroke="black"{dash}{marker}/>"#
+                        )
+                        .ok();
+                    }
+                }
+                Statement::Note { placement, text } => {
+                    let text = escape(&text.replace('\n', " "));
+                    let note_width = text_width(&text) + 2. * PADDING;
+                    let (left, width) = match placement {
+                        Placement::LeftOf(index) => {
+                            (centers[*index] - PADDING - note_width, note_width)
+                        }
+                        Placement::RightOf(index) => (centers[*index] + PADDING, note_width),
+                        Placement::Over(first, last) => {
+                            let span = centers[*last] - centers[*first] + 2. * PADDING;
+                            let width = span.max(note_width);
+                            ((centers[*first] + centers[*last] - width) / 2., width)
+                        }
+                    };
+                    min_x = min_x.min(left);
+                    max_x = max_x.max(left + width);
+
+                    writeln!(
+                        body,
+                        r##"<rect x="{left}" y="{top}" width="{width}" height="30" fill="#fff5ad" stroke="#aaaa33"/>"##,
+                        top = y - 20.,
+                    )
+                    .ok();
+                    writeln!(
+                        body,
+                        r#"<text x="{x}" y="{y}" text-anchor="middle" dominant-baseline="middle">{text}</text>"#,
+                        x = left + width / 2.,
+                        y = y - 5.,
+                    )
+                    .ok();
+                }
+            }
+        }
+        let bottom = y + ROW_HEIGHT;
+
+        // Participant boxes at the top and bottom, joined by lifelines
+        let mut participants = String::new();
+        for ((participant, center), width) in self.participants.iter().zip(&centers).zip(&widths) {
+            let left = center - width / 2.;
+            let radius = if participant.actor { 15. } else { 3. };
+            let label = escape(&participant.label.replace('\n', " "));
+            writeln!(
+                participants,
+                r#"<line x1="{center}" y1="{BOX_HEIGHT}" x2="{center}" y2="{bottom}" stroke="grey"/>"#
+            )
+            .ok();
+            for top in [0., bottom] {
+                writeln!(
+                    participants,
+                    r##"<rect x="{left}" y="{top}" width="{width}" height="{BOX_HEIGHT}" rx="{radius}" fill="#ececff" stroke="#9370db"/>"##
+                )
+                .ok();
+                writeln!(
+                    participants,
+                    r#"<text x="{center}" y="{y}" text-anchor="middle" dominant-baseline="middle">{label}</text>"#,
+                    y = top + BOX_HEIGHT / 2.,
+                )
+                .ok();
+            }
+        }
+
+        let view_x = min_x - MARGIN;
+        let view_y = -MARGIN;
+        let width = max_x - min_x + 2. * MARGIN;
+        let height = bottom + BOX_HEIGHT + 2. * MARGIN;
+
+        format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="{view_x} {view_y} {width} {height}" font-family="sans-serif" font-size="14">
+<defs>
+<marker id="filled" viewBox="0 0 10 10" refX="10" refY="5" markerWidth="8" markerHeight="8" orient="auto"><path d="M0,0 L10,5 L0,10 z"/></marker>
+<marker id="open" viewBox="0 0 10 10" refX="10" refY="5" markerWidth="8" markerHeight="8" orient="auto"><path d="M0,0 L10,5 L0,10" fill="none" stroke="black"/></marker>
+<marker id="cross" viewBox="0 0 10 10" refX="5" refY="5" markerWidth="8" markerHeight="8" orient="auto"><path d="M1,1 L9,9 M9,1 L1,9" stroke="black" stroke-width="2"/></marker>
+</defs>
+{participants}{body}</svg>"#
+        )
+    }
+}
@@ -195,6 +195,7 @@ pub trait Microkernel: Sync + Send + Kernel {
             output: None,
             errors: None,
             forks: Default::default(),
+            arrow: None,
         }))
     }
 }
@@ -243,6 +244,11 @@ pub struct MicrokernelInstance {
 
     /// A counter of forks of this microkernel instance
     forks: AtomicU64,
+
+    /// Whether the microkernel supports transferring variables as Arrow IPC files
+    ///
+    /// `None` until negotiated on the first Arrow task.
+    arrow: Option<bool>,
 }
 
 /// An input stream for a microkernel instance
@@ -275,6 +281,10 @@ enum MicrokernelErrors {
     Pipe(BufReader<File>),
 }
 
+/// The `errorType` of the message that microkernels respond with when they
+/// receive a task with a flag that they do not recognize
+const UNKNOWN_FLAG_ERROR: &str = "UnknownFlag";
+
 /// A Unicode flag used within messages sent and received to/from microkernels
 #[derive(Display)]
 #[strum(serialize_all = "UPPERCASE")]
@@ -301,6 +311,10 @@ enum MicrokernelFlag {
     Set,
     /// Sent by Rust to signal the start of a `remove` task
     Remove,
+    /// Sent by Rust to signal the start of a task to write a variable to an Arrow IPC file
+    GetArrow,
+    /// Sent by Rust to signal the start of a task to read a variable from an Arrow IPC file
+    SetArrow,
     /// Sent by the microkernel instance to signal the end of an output or message
     End,
 }
@@ -324,6 +338,8 @@ impl MicrokernelFlag {
             Get => "\u{10A51A}",
             Set => "\u{107070}",
             Remove => "\u{10C41C}",
+            GetArrow => "\u{10A770}",
+            SetArrow => "\u{10A771}",
             End => "\u{10CB40}",
         }
     }
@@ -548,6 +564,14 @@ impl KernelInstance for MicrokernelInstance {
         Ok(())
     }
 
+    async fn get_arrow(&mut self, name: &str, path: &Path) -> Result<bool> {
+        self.arrow_task(MicrokernelFlag::GetArrow, name, path).await
+    }
+
+    async fn set_arrow(&mut self, name: &str, path: &Path) -> Result<bool> {
+        self.arrow_task(MicrokernelFlag::SetArrow, name, path).await
+    }
+
     async fn fork(&mut self) -> Result<Box<dyn KernelInstance>> {
         #[cfg(unix)]
         {
@@ -648,6 +672,7 @@ impl KernelInstance for MicrokernelInstance {
                 output,
                 errors,
                 forks: Default::default(),
+                arrow: self.arrow,
             }))
         }
 
@@ -843,6 +868,56 @@ impl MicrokernelInstance {
         }
     }
 
+    /// Send an Arrow IPC task to this microkernel instance
+    ///
+    /// The microkernel responds with `true` if the variable was written to, or read from,
+    /// the file and `false` if the variable can not be represented in Arrow (e.g. it is
+    /// not a table or array). Microkernels that do not recognize the task (and so respond
+    /// with an [`UNKNOWN_FLAG_ERROR`]), or which lack an Arrow library, respond with an
+    /// error, in which case Arrow is not used for any subsequent tasks. Any other error
+    /// only affects the current variable, which the caller then transfers using JSON.
+    async fn arrow_task(&mut self, flag: MicrokernelFlag, name: &str, path: &Path) -> Result<bool> {
+        if self.arrow == Some(false) {
+            return Ok(false);
+        }
+
+        let (outputs, messages) = self
+            .send_receive(flag, [name, path.to_string_lossy().as_ref()])
+            .await?;
+
+        let errors = messages
+            .into_iter()
+            .filter(|message| {
+                matches!(message.level, MessageLevel::Error | MessageLevel::Exception)
+            })
+            .collect_vec();
+        if !errors.is_empty() {
+            let unsupported = errors.iter().any(|message| {
+                matches!(
+                    message.error_type.as_deref(),
+                    Some("ImportError" | UNKNOWN_FLAG_ERROR)
+                )
+            });
+            let errors = errors.into_iter().map(|message| message.message).join("");
+            if unsupported {
+                tracing::debug!(
+                    "Microkernel `{}` does not support Arrow: {errors}",
+                    self.name()
+                );
+                self.arrow = Some(false);
+            } else {
+                tracing::debug!(
+                    "Microkernel `{}` could not transfer `{name}` using Arrow: {errors}",
+                    self.name()
+                );
+            }
+            return Ok(false);
+        }
+        self.arrow = Some(true);
+
+        Ok(matches!(outputs.first(), Some(Node::Boolean(true))))
+    }
+
     /// Create an `Err` if messages from the kernel include an error
     fn check_for_errors(&self, messages: Vec<ExecutionMessage>, action: &str) -> Result<()> {
         if messages.iter().any(|m| m.level == MessageLevel::Error) {
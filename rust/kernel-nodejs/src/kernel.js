@@ -326,7 +326,9 @@ rl.on("line", (task) => {
         case FORK:
           return fork(lines.slice(1));
         default:
-          throw new Error(`Unrecognized task ${lines[0]}`);
+          const error = new Error(`Unrecognized task ${lines[0]}`);
+          error.name = "UnknownFlag";
+          throw error;
       }
     })();
   } catch (error) {
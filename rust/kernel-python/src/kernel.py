@@ -154,8 +154,15 @@ LIST = "LIST" if DEV_MODE else "\U0010c155"
 GET = "GET" if DEV_MODE else "\U0010a51a"
 SET = "SET" if DEV_MODE else "\U00107070"
 REMOVE = "REMOVE" if DEV_MODE else "\U0010c41c"
+GET_ARROW = "GET_ARROW" if DEV_MODE else "\U0010a770"
+SET_ARROW = "SET_ARROW" if DEV_MODE else "\U0010a771"
 END = "END" if DEV_MODE else "\U0010cb40"
 
+
+class UnknownFlag(Exception):
+    """Raised for tasks with a flag that is not recognized by this kernel."""
+
+
 # Try to get the maximum number of file descriptors the process can have open
 # SC_OPEN_MAX "The maximum number of files that a process can have open at any
 # time" sysconf(3)
@@ -447,6 +454,41 @@ try:
 except ImportError:
     MATPLOTLIB_AVAILABLE = False
 
+# Transfer of tables and arrays to and from Arrow IPC files
+try:
+    import pyarrow as pa
+    import pyarrow.feather
+
+    PYARROW_AVAILABLE = True
+
+    # Schema metadata used to indicate that an Arrow table, with a
+    # single column, represents an array rather than a table
+    ARROW_ARRAY_METADATA = {b"stencila:type": b"Array"}
+
+    def to_arrow(value: Any) -> Optional[pa.Table]:
+        if PANDAS_AVAILABLE and isinstance(value, pd.DataFrame):
+            return pa.Table.from_pandas(value, preserve_index=False)
+
+        if NUMPY_AVAILABLE and isinstance(value, np.ndarray) and value.ndim == 1:
+            table = pa.table({"value": value})
+            return table.replace_schema_metadata(ARROW_ARRAY_METADATA)
+
+        return None
+
+    def from_arrow(table: pa.Table) -> Any:
+        metadata = table.schema.metadata or {}
+        if metadata.get(b"stencila:type") == b"Array" and table.num_columns == 1:
+            column = table.column(0)
+            return column.to_numpy() if NUMPY_AVAILABLE else column.to_pylist()
+
+        if PANDAS_AVAILABLE:
+            return table.to_pandas()
+
+        return table.to_pydict()
+
+except ImportError:
+    PYARROW_AVAILABLE = False
+
 
 # Serialize a Python object as JSON
 def to_json(obj: Any) -> str:
@@ -674,6 +716,29 @@ def set_variable(name: str, value: str) -> None:
     CONTEXT[name] = from_json(value)
 
 
+# Write a variable to an Arrow IPC file
+def get_variable_arrow(name: str, path: str) -> None:
+    if not PYARROW_AVAILABLE:
+        raise ImportError("pyarrow is not available")
+
+    table = to_arrow(CONTEXT.get(name))
+    if table is None:
+        sys.stdout.write("false")
+        return
+
+    pyarrow.feather.write_feather(table, path, compression="uncompressed")
+    sys.stdout.write("true")
+
+
+# Set a variable from an Arrow IPC file
+def set_variable_arrow(name: str, path: str) -> None:
+    if not PYARROW_AVAILABLE:
+        raise ImportError("pyarrow is not available")
+
+    CONTEXT[name] = from_arrow(pyarrow.feather.read_table(path))
+    sys.stdout.write("true")
+
+
 # Remove a variable
 def remove_variable(name: str) -> None:
     CONTEXT.pop(name, None)
@@ -731,10 +796,14 @@ def main() -> None:
                 set_variable(lines[1], lines[2])
             elif task_type == REMOVE:
                 remove_variable(lines[1])
+            elif task_type == GET_ARROW:
+                get_variable_arrow(lines[1], lines[2])
+            elif task_type == SET_ARROW:
+                set_variable_arrow(lines[1], lines[2])
             elif task_type == FORK:
                 fork(lines[1:])
             else:
-                raise ValueError(f"Unrecognized task: {task_type}")
+                raise UnknownFlag(f"Unrecognized task: {task_type}")
 
         except KeyboardInterrupt:
             pass
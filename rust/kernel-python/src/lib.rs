@@ -64,7 +64,7 @@ mod tests {
         common::{
             eyre::{bail, Ok},
             indexmap::IndexMap,
-            tokio,
+            tempfile, tokio,
         },
         schema::{
             Array, ArrayHint, ArrayValidator, BooleanValidator, CodeLocation, Datatable,
@@ -1084,4 +1084,65 @@ func()",
 
         Ok(())
     }
+
+    /// `PythonKernel` specific test for transferring `pandas.DataFrame`s and
+    /// `numpy.ndarray`s as Arrow IPC files
+    #[test_log::test(tokio::test)]
+    async fn arrow() -> Result<()> {
+        let Some(mut instance) = start_instance::<PythonKernel>().await? else {
+            return Ok(());
+        };
+
+        let (.., messages) = instance
+            .execute("import numpy as np\nimport pandas as pd\nimport pyarrow")
+            .await?;
+        if messages
+            .first()
+            .and_then(|message| message.error_type.as_deref())
+            == Some("ModuleNotFoundError")
+        {
+            println!("Skipping test because `numpy`, `pandas` or `pyarrow` not available");
+            return Ok(());
+        }
+
+        let (.., messages) = instance
+            .execute(
+                "
+df1 = pd.DataFrame({
+    'c1': [True, False],
+    'c2': [1, 2],
+    'c3': [1.23, 4.56]
+})
+a1 = np.array([1, 2, 3])
+s1 = 'not a table'
+",
+            )
+            .await?;
+        assert_eq!(messages, []);
+
+        let dir = tempfile::tempdir()?;
+
+        let path = dir.path().join("df1.arrow");
+        assert!(instance.get_arrow("df1", &path).await?);
+        assert!(instance.set_arrow("df2", &path).await?);
+        assert_eq!(instance.get("df2").await?, instance.get("df1").await?);
+
+        let path = dir.path().join("a1.arrow");
+        assert!(instance.get_arrow("a1", &path).await?);
+        assert!(instance.set_arrow("a2", &path).await?);
+        assert_eq!(
+            instance.get("a2").await?,
+            Some(Node::Array(Array(vec![
+                Primitive::Integer(1),
+                Primitive::Integer(2),
+                Primitive::Integer(3)
+            ])))
+        );
+
+        let path = dir.path().join("s1.arrow");
+        assert!(!instance.get_arrow("s1", &path).await?);
+        assert!(!instance.get_arrow("undefined", &path).await?);
+
+        Ok(())
+    }
 }
@@ -15,6 +15,8 @@ LIST = ifelse(DEV_MODE, "LIST", "\U0010C155")
 GET = ifelse(DEV_MODE, "GET", "\U0010A51A")
 SET = ifelse(DEV_MODE, "SET", "\U00107070")
 REMOVE = ifelse(DEV_MODE, "REMOVE", "\U0010C41C")
+GET_ARROW = ifelse(DEV_MODE, "GET_ARROW", "\U0010A770")
+SET_ARROW = ifelse(DEV_MODE, "SET_ARROW", "\U0010A771")
 END = ifelse(DEV_MODE, "END", "\U0010CB40")
 
 # Ensure that required packages are attached and installed
@@ -499,6 +501,52 @@ set_variable <- function(name, value) {
   assign(name, from_json(value), envir = envir)
 }
 
+# Check that the `arrow` package is available for transfers using Arrow IPC files
+# Not in `requires` because it is a large package that is not installed automatically
+arrow_available <- function() {
+  if (requireNamespace("arrow", quietly = TRUE)) return(TRUE)
+  exception("arrow package is not available", "ImportError")
+  FALSE
+}
+
+# Write a variable to an Arrow IPC file
+# Vectors are written as a table with a single column and metadata indicating that it is an array
+get_variable_arrow <- function(name, path) {
+  if (!arrow_available()) return()
+
+  value <- try(get(name, envir = envir), silent = TRUE)
+  if (inherits(value, "try-error")) {
+    print(unbox(FALSE))
+    return()
+  } else if (is.data.frame(value)) {
+    table <- arrow::arrow_table(value)
+  } else if (is.atomic(value) && is.null(dim(value))) {
+    table <- arrow::arrow_table(value = value)
+    table$metadata <- list("stencila:type" = "Array")
+  } else {
+    print(unbox(FALSE))
+    return()
+  }
+
+  arrow::write_ipc_file(table, path, compression = "uncompressed")
+  print(unbox(TRUE))
+}
+
+# Set a variable from an Arrow IPC file
+set_variable_arrow <- function(name, path) {
+  if (!arrow_available()) return()
+
+  table <- arrow::read_ipc_file(path, as_data_frame = FALSE)
+  if (identical(table$metadata[["stencila:type"]], "Array") && table$num_columns == 1) {
+    value <- as.vector(table[[1]])
+  } else {
+    value <- as.data.frame(table)
+  }
+
+  assign(name, value, envir = envir)
+  print(unbox(TRUE))
+}
+
 # Remove a variable
 remove_variable <- function(name) {
   remove(list = name, envir = envir)
@@ -559,8 +607,10 @@ while (!is.null(stdin)) {
     else if (task_type == GET) get_variable(lines[2])
     else if (task_type == SET) set_variable(lines[2], lines[3])
     else if (task_type == REMOVE) remove_variable(lines[2])
+    else if (task_type == GET_ARROW) get_variable_arrow(lines[2], lines[3])
+    else if (task_type == SET_ARROW) set_variable_arrow(lines[2], lines[3])
     else if (task_type == FORK) fork(lines[2:length(lines)])
-    else exception(list(message = paste("Unrecognized task:", task_type), error_type = "MicrokernelError"))
+    else exception(paste("Unrecognized task:", task_type), "UnknownFlag")
   },
   warning = warning,
   error = exception,
@@ -0,0 +1,884 @@
+//! Rendering of Vega-Lite specifications to static SVG
+//!
+//! Supports single view specifications with `bar`, `line`, `area`, `point`,
+//! `circle` and `square` marks, `x`, `y` and `color` encoding channels, and
+//! simple aggregates (`count`, `sum`, `mean`, `min`, `max`). Temporal fields
+//! with string values are treated as ordinal.
+
+use std::{collections::HashMap, fmt::Write};
+
+use kernel::common::{
+    indexmap::IndexMap,
+    itertools::Itertools,
+    serde_json::{Map, Value},
+};
+
+/// The default color of marks
+const DEFAULT_COLOR: &str = "#4c78a8";
+
+/// The default categorical color scheme (Vega's `tableau10`)
+const SCHEME: &[&str] = &[
+    "#4c78a8", "#f58518", "#e45756", "#72b7b2", "#54a24b", "#eeca3b", "#b279a2", "#ff9da6",
+    "#9d755d", "#bab0ac",
+];
+
+/// The default width and height of a continuous axis
+const CONTINUOUS_SIZE: f64 = 300.;
+
+/// The default width of each band of a discrete axis
+const BAND_STEP: f64 = 20.;
+
+/// The approximate width of a character of axis label text
+const CHAR_WIDTH: f64 = 6.;
+
+/// Composite view operators which are not supported
+const COMPOSITES: &[&str] = &["layer", "concat", "hconcat", "vconcat", "facet", "repeat"];
+
+type Row = Map<String, Value>;
+
+/// The type of data in an encoding channel
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum DataType {
+    Quantitative,
+    Ordinal,
+    Nominal,
+    Temporal,
+}
+
+/// An encoding channel (e.g. `x`, `y`, `color`)
+struct Channel {
+    /// The field in the (transformed) data that the channel encodes
+    field: Option<String>,
+
+    /// The type of data
+    data_type: DataType,
+
+    /// The title to use for the axis or legend
+    title: String,
+
+    /// A constant value (e.g. for `"color": {"value": "red"}`)
+    value: Option<Value>,
+}
+
+/// An aggregation to apply to a field
+struct Aggregate {
+    op: String,
+    field: Option<String>,
+    name: String,
+}
+
+/// A positional scale
+enum Scale {
+    Linear {
+        min: f64,
+        max: f64,
+        step: f64,
+        range: f64,
+        reverse: bool,
+    },
+    Band {
+        domain: Vec<String>,
+        range: f64,
+    },
+}
+
+impl Scale {
+    /// Create a scale for a channel
+    fn new(channel: &Channel, rows: &[Row], extent: Option<(f64, f64)>, reverse: bool) -> Self {
+        let field = channel.field.as_deref().unwrap_or_default();
+        let values = rows.iter().filter_map(|row| row.get(field));
+
+        let is_linear = match channel.data_type {
+            DataType::Quantitative => true,
+            DataType::Temporal => rows
+                .iter()
+                .all(|row| !matches!(row.get(field), Some(Value::String(..)))),
+            _ => false,
+        };
+
+        if is_linear {
+            let (min, max) = extent.unwrap_or_else(|| {
+                values
+                    .filter_map(Value::as_f64)
+                    .fold((0f64, 0f64), |(min, max), value| {
+                        (min.min(value), max.max(value))
+                    })
+            });
+            let (min, max, step) = nice(min, max);
+            Scale::Linear {
+                min,
+                max,
+                step,
+                range: CONTINUOUS_SIZE,
+                reverse,
+            }
+        } else {
+            let domain = sorted_domain(values);
+            let range = (domain.len() as f64 * BAND_STEP).max(CONTINUOUS_SIZE / 2.);
+            Scale::Band { domain, range }
+        }
+    }
+
+    /// Set the range (i.e. size in pixels) of the scale
+    fn set_range(&mut self, size: f64) {
+        match self {
+            Scale::Linear { range, .. } | Scale::Band { range, .. } => *range = size,
+        }
+    }
+
+    fn range(&self) -> f64 {
+        match self {
+            Scale::Linear { range, .. } | Scale::Band { range, .. } => *range,
+        }
+    }
+
+    fn is_band(&self) -> bool {
+        matches!(self, Scale::Band { .. })
+    }
+
+    /// Get the position of a number on a linear scale
+    fn linear(&self, value: f64) -> f64 {
+        match self {
+            Scale::Linear {
+                min,
+                max,
+                range,
+                reverse,
+                ..
+            } => {
+                let fraction = if max > min {
+                    (value - min) / (max - min)
+                } else {
+                    0.
+                };
+                if *reverse {
+                    range * (1. - fraction)
+                } else {
+                    range * fraction
+                }
+            }
+            Scale::Band { .. } => 0.,
+        }
+    }
+
+    /// Get the width of each band
+    fn bandwidth(&self) -> f64 {
+        match self {
+            Scale::Band { domain, range } => range / (domain.len().max(1) as f64),
+            Scale::Linear { .. } => 5.,
+        }
+    }
+
+    /// Get the position of a value (the center of the band for band scales)
+    fn position(&self, value: &Value) -> Option<f64> {
+        match self {
+            Scale::Linear { .. } => value.as_f64().map(|value| self.linear(value)),
+            Scale::Band { domain, .. } => {
+                let key = key(value);
+                domain
+                    .iter()
+                    .position(|item| item == &key)
+                    .map(|index| (index as f64 + 0.5) * self.bandwidth())
+            }
+        }
+    }
+
+    /// Get the positions and labels of ticks
+    fn ticks(&self) -> Vec<(f64, String)> {
+        match self {
+            Scale::Linear { min, max, step, .. } => {
+                let count = ((max - min) / step).round() as usize;
+                (0..=count)
+                    .map(|index| {
+                        let value = min + index as f64 * step;
+                        (self.linear(value), format_number(value, *step))
+                    })
+                    .collect()
+            }
+            Scale::Band { domain, .. } => domain
+                .iter()
+                .enumerate()
+                .map(|(index, label)| ((index as f64 + 0.5) * self.bandwidth(), label.clone()))
+                .collect(),
+        }
+    }
+}
+
+/// Extend a domain to "nice" round numbers and calculate a tick step
+fn nice(min: f64, max: f64) -> (f64, f64, f64) {
+    let (min, max) = if max > min {
+        (min, max)
+    } else {
+        (min - 1., max + 1.)
+    };
+
+    let raw = (max - min) / 5.;
+    let magnitude = 10f64.powf(raw.log10().floor());
+    let step = [1., 2., 5., 10.]
+        .iter()
+        .map(|factor| factor * magnitude)
+        .find(|step| *step >= raw)
+        .unwrap_or(10. * magnitude);
+
+    (
+        (min / step).floor() * step,
+        (max / step).ceil() * step,
+        step,
+    )
+}
+
+/// Format a number for a tick label, using the number of decimals implied by the step
+fn format_number(value: f64, step: f64) -> String {
+    let decimals = if step >= 1. {
+        0
+    } else {
+        (-step.log10()).ceil() as usize
+    };
+    let formatted = format!("{value:.decimals$}");
+    if formatted
+        .trim_start_matches('-')
+        .chars()
+        .all(|c| c == '0' || c == '.')
+    {
+        formatted.trim_start_matches('-').to_string()
+    } else {
+        formatted
+    }
+}
+
+/// Get the key for a value in a discrete domain
+fn key(value: &Value) -> String {
+    match value {
+        Value::String(string) => string.clone(),
+        _ => value.to_string(),
+    }
+}
+
+/// Get the sorted, unique, keys of values
+///
+/// Numbers are sorted numerically, all other values by their keys.
+fn sorted_domain<'v>(values: impl Iterator<Item = &'v Value>) -> Vec<String> {
+    let mut values = values.collect_vec();
+    if values.iter().all(|value| value.is_number()) {
+        values.sort_by(|a, b| {
+            a.as_f64()
+                .unwrap_or_default()
+                .total_cmp(&b.as_f64().unwrap_or_default())
+        });
+    } else {
+        values.sort_by_key(|value| key(value));
+    }
+    values.into_iter().map(key).unique().collect()
+}
+
+/// Escape text for use in SVG
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Get a title from a string, or an object with a `text` property
+fn title(value: Option<&Value>) -> Option<String> {
+    match value {
+        Some(Value::String(title)) => Some(title.clone()),
+        Some(Value::Object(object)) => object
+            .get("text")
+            .and_then(|text| text.as_str())
+            .map(String::from),
+        _ => None,
+    }
+}
+
+/// Parse an encoding channel
+fn channel(
+    encoding: &Map<String, Value>,
+    name: &str,
+    rows: &[Row],
+    aggregates: &mut Vec<Aggregate>,
+) -> Result<Option<Channel>, String> {
+    let Some(definition) = encoding.get(name) else {
+        return Ok(None);
+    };
+    let Some(definition) = definition.as_object() else {
+        return Err(format!("Encoding for channel `{name}` should be an object"));
+    };
+
+    if let Some(value) = definition.get("value") {
+        return Ok(Some(Channel {
+            field: None,
+            data_type: DataType::Nominal,
+            title: String::new(),
+            value: Some(value.clone()),
+        }));
+    }
+
+    let field = definition
+        .get("field")
+        .and_then(|field| field.as_str())
+        .map(String::from);
+
+    let data_type = match definition.get("type").and_then(|data_type| data_type.as_str()) {
+        Some("quantitative") => DataType::Quantitative,
+        Some("ordinal") => DataType::Ordinal,
+        Some("nominal") => DataType::Nominal,
+        Some("temporal") => DataType::Temporal,
+        Some(data_type) => {
+            return Err(format!(
+                "Unknown type `{data_type}` for channel `{name}`, expected `quantitative`, `ordinal`, `nominal` or `temporal`"
+            ))
+        }
+        None => {
+            // Infer the type from the values of the field
+            let is_numeric = field.as_ref().map_or(true, |field| {
+                rows.iter()
+                    .all(|row| row.get(field).map_or(true, Value::is_number))
+            });
+            if is_numeric {
+                DataType::Quantitative
+            } else {
+                DataType::Nominal
+            }
+        }
+    };
+
+    let (field, default_title) = match definition
+        .get("aggregate")
+        .and_then(|aggregate| aggregate.as_str())
+    {
+        Some(op) => {
+            let op = if op == "average" { "mean" } else { op };
+            if !matches!(op, "count" | "sum" | "mean" | "min" | "max") {
+                return Err(format!(
+                    "Unsupported aggregate `{op}`, expected `count`, `sum`, `mean`, `min` or `max`"
+                ));
+            }
+            let (name, title) = match (op, &field) {
+                ("count", _) => ("__count".to_string(), "Count of Records".to_string()),
+                (_, Some(field)) => (
+                    format!("{op}_{field}"),
+                    format!("{} of {field}", op[..1].to_uppercase() + &op[1..]),
+                ),
+                (_, None) => {
+                    return Err(format!(
+                        "Aggregate `{op}` for channel `{name}` requires a field"
+                    ))
+                }
+            };
+            aggregates.push(Aggregate {
+                op: op.to_string(),
+                field: field.clone(),
+                name: name.clone(),
+            });
+            (Some(name), title)
+        }
+        None => {
+            if field.is_none() {
+                return Err(format!(
+                    "Encoding for channel `{name}` requires a `field` or `value`"
+                ));
+            }
+            let title = field.clone().unwrap_or_default();
+            (field, title)
+        }
+    };
+
+    let data_type = if definition.get("aggregate").is_some() {
+        DataType::Quantitative
+    } else {
+        data_type
+    };
+
+    Ok(Some(Channel {
+        field,
+        data_type,
+        title: title(definition.get("title")).unwrap_or(default_title),
+        value: None,
+    }))
+}
+
+/// Apply aggregates to rows, grouping by the non-aggregated fields
+fn aggregate(rows: Vec<Row>, aggregates: &[Aggregate], groupby: &[&str]) -> Vec<Row> {
+    if aggregates.is_empty() {
+        return rows;
+    }
+
+    let mut groups: IndexMap<Vec<String>, Vec<Row>> = IndexMap::new();
+    for row in rows {
+        let group_key = groupby
+            .iter()
+            .map(|field| row.get(*field).map(key).unwrap_or_default())
+            .collect_vec();
+        groups.entry(group_key).or_default().push(row);
+    }
+
+    groups
+        .into_values()
+        .map(|group| {
+            let mut result = Row::new();
+            for field in groupby {
+                if let Some(value) = group[0].get(*field) {
+                    result.insert(field.to_string(), value.clone());
+                }
+            }
+            for Aggregate { op, field, name } in aggregates {
+                let values = group
+                    .iter()
+                    .filter_map(|row| row.get(field.as_deref().unwrap_or_default()))
+                    .filter_map(Value::as_f64)
+                    .collect_vec();
+                let value = match op.as_str() {
+                    "count" => Some(group.len() as f64),
+                    "sum" => Some(values.iter().sum()),
+                    "mean" if !values.is_empty() => {
+                        Some(values.iter().sum::<f64>() / values.len() as f64)
+                    }
+                    "min" => values.iter().copied().reduce(f64::min),
+                    "max" => values.iter().copied().reduce(f64::max),
+                    _ => None,
+                };
+                result.insert(name.clone(), value.map_or(Value::Null, Value::from));
+            }
+            result
+        })
+        .collect()
+}
+
+/// Render a Vega-Lite specification to SVG
+///
+/// The `data` of the specification is expected to have been resolved
+/// to inline `values` before calling this function.
+pub(crate) fn render(spec: &Value) -> Result<String, String> {
+    let Some(spec) = spec.as_object() else {
+        return Err("Vega-Lite specification should be an object".to_string());
+    };
+
+    if let Some(composite) = COMPOSITES.iter().find(|name| spec.contains_key(**name)) {
+        return Err(format!(
+            "Composite views (`{composite}`) are not yet supported"
+        ));
+    }
+
+    let mark = match spec.get("mark") {
+        Some(Value::String(mark)) => mark.as_str(),
+        Some(Value::Object(mark)) => mark
+            .get("type")
+            .and_then(|mark| mark.as_str())
+            .unwrap_or_default(),
+        _ => return Err("Missing `mark` property".to_string()),
+    };
+    if !matches!(
+        mark,
+        "bar" | "line" | "area" | "point" | "circle" | "square"
+    ) {
+        return Err(format!(
+            "Unsupported mark `{mark}`, expected `bar`, `line`, `area`, `point`, `circle` or `square`"
+        ));
+    }
+
+    let rows: Vec<Row> = spec
+        .get("data")
+        .and_then(|data| data.get("values"))
+        .and_then(|values| values.as_array())
+        .map(|values| {
+            values
+                .iter()
+                .filter_map(|value| value.as_object().cloned())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let empty = Map::new();
+    let encoding = spec
+        .get("encoding")
+        .and_then(|encoding| encoding.as_object())
+        .unwrap_or(&empty);
+
+    let mut aggregates = Vec::new();
+    let x = channel(encoding, "x", &rows, &mut aggregates)?;
+    let y = channel(encoding, "y", &rows, &mut aggregates)?;
+    let color = channel(encoding, "color", &rows, &mut aggregates)?;
+    let (Some(x), Some(y)) = (x, y) else {
+        return Err("Both `x` and `y` encoding channels are required".to_string());
+    };
+
+    let groupby = [Some(&x), Some(&y), color.as_ref()]
+        .into_iter()
+        .flatten()
+        .filter_map(|channel| channel.field.as_deref())
+        .filter(|field| !aggregates.iter().any(|aggregate| aggregate.name == *field))
+        .unique()
+        .collect_vec();
+    let rows = aggregate(rows, &aggregates, &groupby);
+
+    let color_field = color.as_ref().and_then(|color| color.field.clone());
+    let color_domain = color_field
+        .as_ref()
+        .map(|field| sorted_domain(rows.iter().filter_map(|row| row.get(field))))
+        .unwrap_or_default();
+    let color_of = |row: &Row| -> String {
+        match (
+            &color_field,
+            color.as_ref().and_then(|color| color.value.as_ref()),
+        ) {
+            (Some(field), _) => {
+                let key = row.get(field).map(key).unwrap_or_default();
+                let index = color_domain
+                    .iter()
+                    .position(|item| item == &key)
+                    .unwrap_or(0);
+                SCHEME[index % SCHEME.len()].to_string()
+            }
+            (None, Some(value)) => key(value),
+            _ => DEFAULT_COLOR.to_string(),
+        }
+    };
+
+    // Bars and areas are stacked when there is a color field, so calculate
+    // stacked extents for the quantitative axis
+    let x_field = x.field.clone().unwrap_or_default();
+    let y_field = y.field.clone().unwrap_or_default();
+    let stacked = matches!(mark, "bar" | "area") && color_field.is_some();
+    let horizontal = mark == "bar"
+        && x.data_type == DataType::Quantitative
+        && y.data_type != DataType::Quantitative;
+    let (value_field, category_field) = if horizontal {
+        (&x_field, &y_field)
+    } else {
+        (&y_field, &x_field)
+    };
+    let extent = if stacked {
+        let mut totals: HashMap<String, (f64, f64)> = HashMap::new();
+        for row in &rows {
+            let value = row
+                .get(value_field)
+                .and_then(Value::as_f64)
+                .unwrap_or_default();
+            let total = totals
+                .entry(row.get(category_field).map(key).unwrap_or_default())
+                .or_default();
+            if value < 0. {
+                total.0 += value;
+            } else {
+                total.1 += value;
+            }
+        }
+        Some(
+            totals
+                .values()
+                .fold((0f64, 0f64), |(min, max), (neg, pos)| {
+                    (min.min(*neg), max.max(*pos))
+                }),
+        )
+    } else {
+        None
+    };
+
+    let mut x_scale = Scale::new(&x, &rows, if horizontal { extent } else { None }, false);
+    let mut y_scale = Scale::new(&y, &rows, if horizontal { None } else { extent }, true);
+    if let Some(width) = spec.get("width").and_then(Value::as_f64) {
+        x_scale.set_range(width);
+    }
+    if let Some(height) = spec.get("height").and_then(Value::as_f64) {
+        y_scale.set_range(height);
+    }
+    let (width, height) = (x_scale.range(), y_scale.range());
+
+    // Marks
+    let mut marks = String::new();
+    match mark {
+        "bar" => {
+            let mut offsets: HashMap<String, (f64, f64)> = HashMap::new();
+            for row in &rows {
+                let color = color_of(row);
+                let (Some(category), Some(value)) = (
+                    row.get(category_field),
+                    row.get(value_field).and_then(Value::as_f64),
+                ) else {
+                    continue;
+                };
+                let (start, end) = if stacked {
+                    let offset = offsets.entry(key(category)).or_default();
+                    let start = if value < 0. { offset.0 } else { offset.1 };
+                    if value < 0. {
+                        offset.0 += value;
+                    } else {
+                        offset.1 += value;
+                    }
+                    (start, start + value)
+                } else {
+                    (0., value)
+                };
+
+                if horizontal {
+                    let Some(center) = y_scale.position(category) else {
+                        continue;
+                    };
+                    let band = y_scale.bandwidth() * 0.9;
+                    let (x1, x2) = (x_scale.linear(start), x_scale.linear(end));
+                    writeln!(
+                        marks,
+                        r#"<rect x="{x}" y="{y}" width="{w}" height="{band}" fill="{color}"/>"#,
+                        x = x1.min(x2),
+                        y = center - band / 2.,
+                        w = (x2 - x1).abs(),
+                    )
+                    .ok();
+                } else {
+                    let Some(center) = x_scale.position(category) else {
+                        continue;
+                    };
+                    let band = x_scale.bandwidth() * 0.9;
+                    let (y1, y2) = (y_scale.linear(start), y_scale.linear(end));
+                    writeln!(
+                        marks,
+                        r#"<rect x="{x}" y="{y}" width="{band}" height="{h}" fill="{color}"/>"#,
+                        x = center - band / 2.,
+                        y = y1.min(y2),
+                        h = (y2 - y1).abs(),
+                    )
+                    .ok();
+                }
+            }
+        }
+        "line" | "area" => {
+            let series = rows.iter().into_group_map_by(|row| {
+                color_field
+                    .as_ref()
+                    .and_then(|field| row.get(field))
+                    .map(key)
+            });
+            let mut offsets: HashMap<String, f64> = HashMap::new();
+            for (.., rows) in series.into_iter().sorted_by_key(|(name, ..)| {
+                name.as_ref()
+                    .and_then(|name| color_domain.iter().position(|item| item == name))
+            }) {
+                let color = color_of(rows[0]);
+                let points = rows
+                    .iter()
+                    .filter_map(|row| {
+                        let x_value = row.get(&x_field)?;
+                        let y_value = row.get(&y_field).and_then(Value::as_f64)?;
+                        let base = if stacked {
+                            let offset = offsets.entry(key(x_value)).or_default();
+                            let base = *offset;
+                            *offset += y_value;
+                            base
+                        } else {
+                            0.
+                        };
+                        Some((
+                            x_scale.position(x_value)?,
+                            y_scale.linear(base + y_value),
+                            y_scale.linear(base),
+                        ))
+                    })
+                    .sorted_by(|a, b| a.0.total_cmp(&b.0))
+                    .collect_vec();
+                if points.is_empty() {
+                    continue;
+                }
+
+                let line = points
+                    .iter()
+                    .map(|(x, y, ..)| format!("{x},{y}"))
+                    .join(" L");
+                if mark == "line" {
+                    writeln!(
+                        marks,
+                        r#"<path d="M{line}" fill="none" stroke="{color}" stroke-width="2"/>"#
+                    )
+                    .ok();
+                } else {
+                    let base = points
+                        .iter()
+                        .rev()
+                        .map(|(x, .., base)| format!("{x},{base}"))
+                        .join(" L");
+                    writeln!(
+                        marks,
+                        r#"<path d="M{line} L{base} Z" fill="{color}" fill-opacity="0.7"/>"#
+                    )
+                    .ok();
+                }
+            }
+        }
+        _ => {
+            for row in &rows {
+                let color = color_of(row);
+                let (Some(cx), Some(cy)) = (
+                    row.get(&x_field).and_then(|value| x_scale.position(value)),
+                    row.get(&y_field).and_then(|value| y_scale.position(value)),
+                ) else {
+                    continue;
+                };
+                let shape = match mark {
+                    "circle" => format!(r#"<circle cx="{cx}" cy="{cy}" r="3.5" fill="{color}"/>"#),
+                    "square" => format!(
+                        r#"<rect x="{x}" y="{y}" width="7" height="7" fill="{color}"/>"#,
+                        x = cx - 3.5,
+                        y = cy - 3.5
+                    ),
+                    _ => format!(
+                        r#"<circle cx="{cx}" cy="{cy}" r="3.5" fill="none" stroke="{color}" stroke-width="2"/>"#
+                    ),
+                };
+                marks.push_str(&shape);
+                marks.push('\n');
+            }
+        }
+    }
+
+    // Axes, including grid lines for linear scales
+    let mut axes = String::new();
+    let x_ticks = x_scale.ticks();
+    let rotate = x_scale.is_band()
+        && x_ticks
+            .iter()
+            .any(|(.., label)| label.chars().count() as f64 * CHAR_WIDTH > x_scale.bandwidth());
+    let mut x_labels_height: f64 = 0.;
+    for (x, label) in &x_ticks {
+        if !x_scale.is_band() {
+            writeln!(
+                axes,
+                r##"<line x1="{x}" y1="0" x2="{x}" y2="{height}" stroke="#ddd"/>"##
+            )
+            .ok();
+        }
+        writeln!(
+            axes,
+            r##"<line x1="{x}" y1="{height}" x2="{x}" y2="{y}" stroke="#888"/>"##,
+            y = height + 5.
+        )
+        .ok();
+        let text = escape(label);
+        if rotate {
+            writeln!(
+                axes,
+                r#"<text x="{x}" y="{y}" text-anchor="end" transform="rotate(-45 {x} {y})">{text}</text>"#,
+                y = height + 10.,
+            )
+            .ok();
+            x_labels_height =
+                x_labels_height.max(label.chars().count() as f64 * CHAR_WIDTH * 0.7 + 10.);
+        } else {
+            writeln!(
+                axes,
+                r#"<text x="{x}" y="{y}" text-anchor="middle">{text}</text>"#,
+                y = height + 18.,
+            )
+            .ok();
+            x_labels_height = x_labels_height.max(20.);
+        }
+    }
+    let mut y_labels_width: f64 = 0.;
+    for (y, label) in y_scale.ticks() {
+        if !y_scale.is_band() {
+            writeln!(
+                axes,
+                r##"<line x1="0" y1="{y}" x2="{width}" y2="{y}" stroke="#ddd"/>"##
+            )
+            .ok();
+        }
+        writeln!(
+            axes,
+            r##"<line x1="-5" y1="{y}" x2="0" y2="{y}" stroke="#888"/>"##
+        )
+        .ok();
+        writeln!(
+            axes,
+            r#"<text x="-8" y="{y}" text-anchor="end" dominant-baseline="middle">{text}</text>"#,
+            text = escape(&label),
+        )
+        .ok();
+        y_labels_width = y_labels_width.max(label.chars().count() as f64 * CHAR_WIDTH + 10.);
+    }
+    writeln!(
+        axes,
+        r##"<line x1="0" y1="{height}" x2="{width}" y2="{height}" stroke="#888"/>"##
+    )
+    .ok();
+    writeln!(
+        axes,
+        r##"<line x1="0" y1="0" x2="0" y2="{height}" stroke="#888"/>"##
+    )
+    .ok();
+    writeln!(
+        axes,
+        r#"<text x="{x}" y="{y}" text-anchor="middle" font-weight="bold">{title}</text>"#,
+        x = width / 2.,
+        y = height + x_labels_height + 18.,
+        title = escape(&x.title),
+    )
+    .ok();
+    writeln!(
+        axes,
+        r#"<text x="{x}" y="{y}" text-anchor="middle" font-weight="bold" transform="rotate(-90 {x} {y})">{title}</text>"#,
+        x = -y_labels_width - 10.,
+        y = height / 2.,
+        title = escape(&y.title),
+    )
+    .ok();
+
+    // Legend for color fields
+    let mut legend = String::new();
+    let mut legend_width: f64 = 0.;
+    if let (Some(color), false) = (&color, color_domain.is_empty()) {
+        let left = width + 20.;
+        writeln!(
+            legend,
+            r#"<text x="{left}" y="0" font-weight="bold">{title}</text>"#,
+            title = escape(&color.title)
+        )
+        .ok();
+        legend_width = color.title.chars().count() as f64 * CHAR_WIDTH;
+        for (index, item) in color_domain.iter().enumerate() {
+            let y = 16. + index as f64 * 16.;
+            writeln!(
+                legend,
+                r#"<circle cx="{cx}" cy="{cy}" r="5" fill="{fill}"/><text x="{x}" y="{y}" dominant-baseline="middle">{text}</text>"#,
+                cx = left + 5.,
+                cy = y,
+                fill = SCHEME[index % SCHEME.len()],
+                x = left + 16.,
+                text = escape(item),
+            )
+            .ok();
+            legend_width = legend_width.max(16. + item.chars().count() as f64 * CHAR_WIDTH);
+        }
+        legend_width += 30.;
+    }
+
+    // Title
+    let title = title(spec.get("title"));
+    let title_height = if title.is_some() { 30. } else { 0. };
+
+    let left = y_labels_width + 30.;
+    let top = title_height + 10.;
+    let total_width = left + width + legend_width + 10.;
+    let total_height = top + height + x_labels_height + 30.;
+
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{total_width}" height="{total_height}" viewBox="0 0 {total_width} {total_height}" font-family="sans-serif" font-size="11">
+"#
+    );
+    if let Some(title) = title {
+        writeln!(
+            svg,
+            r#"<text x="{x}" y="20" text-anchor="middle" font-size="13" font-weight="bold">{title}</text>"#,
+            x = left + width / 2.,
+            title = escape(&title),
+        )
+        .ok();
+    }
+    write!(
+        svg,
+        r#"<g transform="translate({left},{top})">
+{axes}{marks}{legend}</g>
+</svg>"#
+    )
+    .ok();
+
+    Ok(svg)
+}
@@ -0,0 +1,417 @@
+use kernel::{
+    common::{
+        async_trait::async_trait,
+        base64::{engine::general_purpose::STANDARD, Engine as _},
+        eyre::{bail, Result},
+        serde_json::{self, json, Value},
+        tracing,
+    },
+    format::Format,
+    schema::{
+        CodeLocation, Datatable, ExecutionMessage, ImageObject, ImageObjectOptions, MessageLevel,
+        Node, SoftwareApplication, SoftwareApplicationOptions,
+    },
+    svg_data_uri, Kernel, KernelInstance, KernelVariableRequest, KernelVariableRequester,
+    KernelVariableResponder,
+};
+
+mod chart;
+
+const NAME: &str = "vegalite";
+
+/// The media type of Vega-Lite specifications
+pub const MEDIA_TYPE: &str = "application/vnd.vegalite.v5+json";
+
+/// A kernel for rendering Vega-Lite chart specifications
+///
+/// Outputs an [`ImageObject`] with a static SVG rendering of the chart as its
+/// `content_url`, so that it can be encoded to any format, and the Vega-Lite
+/// specification (with data inlined) as a data URI in its `embed_url`, so that the
+/// chart can be rendered interactively in HTML/DOM outputs.
+#[derive(Default)]
+pub struct VegaLiteKernel {}
+
+impl Kernel for VegaLiteKernel {
+    fn name(&self) -> String {
+        NAME.to_string()
+    }
+
+    fn supports_languages(&self) -> Vec<Format> {
+        vec![Format::VegaLite]
+    }
+
+    fn supports_variable_requests(&self) -> bool {
+        true
+    }
+
+    fn create_instance(&self) -> Result<Box<dyn KernelInstance>> {
+        Ok(Box::<VegaLiteKernelInstance>::default())
+    }
+}
+
+#[derive(Default)]
+pub struct VegaLiteKernelInstance {
+    /// The channel used to request variables from other kernels
+    ///
+    /// Used to get the data for a chart when a specification
+    /// has a `data.name` property.
+    variable_channel: Option<(KernelVariableRequester, KernelVariableResponder)>,
+}
+
+impl VegaLiteKernelInstance {
+    /// Request a variable from other kernels
+    async fn request_variable(&mut self, name: &str) -> Result<Option<Node>> {
+        let Some((requester, responder)) = &mut self.variable_channel else {
+            bail!("Variable requests are not enabled for this kernel instance")
+        };
+
+        let mut receiver = responder.resubscribe();
+        requester.send(KernelVariableRequest {
+            variable: name.to_string(),
+            instance: NAME.to_string(),
+        })?;
+        tracing::trace!("Sent request for variable `{name}`");
+
+        loop {
+            let response = receiver.recv().await?;
+            if response.variable == name {
+                return Ok(response.value);
+            }
+        }
+    }
+
+    /// Resolve the data of a specification to inline `values`
+    ///
+    /// If the specification has a `data.name` then the variable with that name
+    /// is requested from other kernels and converted to an array of row objects.
+    async fn resolve_data(&mut self, spec: &mut Value) -> Result<(), String> {
+        let Some(data) = spec.get_mut("data") else {
+            return Err("Missing `data` property".to_string());
+        };
+
+        if data.get("values").is_some() {
+            return Ok(());
+        }
+
+        if data.get("url").is_some() {
+            return Err(
+                "Loading data from a `url` is not supported, use `values` or the `name` of a variable"
+                    .to_string(),
+            );
+        }
+
+        let Some(name) = data.get("name").and_then(|name| name.as_str()) else {
+            return Err("Data should have either `values` or a `name`".to_string());
+        };
+        let name = name.to_string();
+
+        let values = match self.request_variable(&name).await {
+            Ok(Some(node)) => node_to_values(node)?,
+            Ok(None) => return Err(format!("Variable `{name}` does not exist")),
+            Err(error) => return Err(format!("While requesting variable `{name}`: {error}")),
+        };
+
+        *data = json!({ "values": values });
+
+        Ok(())
+    }
+}
+
+/// Convert a node into Vega-Lite data values (an array of row objects)
+fn node_to_values(node: Node) -> Result<Value, String> {
+    match node {
+        Node::Datatable(Datatable { columns, .. }) => {
+            let rows = columns.iter().map(|column| column.values.len()).max().unwrap_or(0);
+            let values = (0..rows)
+                .map(|row| {
+                    columns
+                        .iter()
+                        .map(|column| {
+                            let value = column
+                                .values
+                                .get(row)
+                                .and_then(|value| serde_json::to_value(value).ok())
+                                .unwrap_or(Value::Null);
+                            (column.name.clone(), value)
+                        })
+                        .collect()
+                })
+                .collect();
+            Ok(Value::Array(values))
+        }
+        node => match serde_json::to_value(&node) {
+            Ok(Value::Array(values)) if values.iter().all(Value::is_object) => {
+                Ok(Value::Array(values))
+            }
+            _ => Err(format!(
+                "Variable of type `{node}` can not be used as chart data, expected a `Datatable` or array of objects"
+            )),
+        },
+    }
+}
+
+#[async_trait]
+impl KernelInstance for VegaLiteKernelInstance {
+    fn name(&self) -> String {
+        NAME.to_string()
+    }
+
+    async fn execute(&mut self, code: &str) -> Result<(Vec<Node>, Vec<ExecutionMessage>)> {
+        tracing::debug!("Rendering Vega-Lite specification to SVG");
+
+        let mut spec: Value = match serde_json::from_str(code) {
+            Ok(spec) => spec,
+            Err(error) => {
+                let message = ExecutionMessage {
+                    error_type: Some("SyntaxError".to_string()),
+                    code_location: Some(CodeLocation {
+                        start_line: Some(error.line().saturating_sub(1) as u64),
+                        start_column: Some(error.column().saturating_sub(1) as u64),
+                        ..Default::default()
+                    }),
+                    ..ExecutionMessage::new(MessageLevel::Error, error.to_string())
+                };
+                return Ok((Vec::new(), vec![message]));
+            }
+        };
+
+        let svg = match self.resolve_data(&mut spec).await {
+            Ok(..) => chart::render(&spec),
+            Err(error) => Err(error),
+        };
+        let svg = match svg {
+            Ok(svg) => svg,
+            Err(error) => {
+                return Ok((
+                    Vec::new(),
+                    vec![ExecutionMessage::new(MessageLevel::Error, error)],
+                ))
+            }
+        };
+
+        let image = Node::ImageObject(ImageObject {
+            content_url: svg_data_uri(&svg),
+            media_type: Some("image/svg+xml".to_string()),
+            options: Box::new(ImageObjectOptions {
+                embed_url: Some(format!(
+                    "data:{MEDIA_TYPE};base64,{}",
+                    STANDARD.encode(spec.to_string())
+                )),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+
+        Ok((vec![image], Vec::new()))
+    }
+
+    async fn info(&mut self) -> Result<SoftwareApplication> {
+        tracing::trace!("Getting Vega-Lite runtime info");
+
+        Ok(SoftwareApplication {
+            name: "Vega-Lite Kernel".to_string(),
+            options: Box::new(SoftwareApplicationOptions {
+                operating_system: Some(std::env::consts::OS.to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+    }
+
+    fn variable_channel(
+        &mut self,
+        requester: KernelVariableRequester,
+        responder: KernelVariableResponder,
+    ) {
+        self.variable_channel = Some((requester, responder));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common_dev::pretty_assertions::assert_eq;
+    use kernel::{
+        common::tokio::{
+            self,
+            sync::{broadcast, mpsc},
+        },
+        schema::{DatatableColumn, Primitive},
+        KernelVariableResponse,
+    };
+
+    use super::*;
+
+    /// Get the static SVG rendering from the outputs of an execution
+    fn svg(outputs: &[Node]) -> &str {
+        let Some(Node::ImageObject(image)) = outputs.first() else {
+            panic!("expected an image")
+        };
+        assert_eq!(image.media_type.as_deref(), Some("image/svg+xml"));
+        image.content_url.as_str()
+    }
+
+    /// Get the Vega-Lite specification from the outputs of an execution
+    fn spec(outputs: &[Node]) -> Result<Value> {
+        let Some(Node::ImageObject(image)) = outputs.first() else {
+            panic!("expected an image")
+        };
+        let embed_url = image.options.embed_url.as_deref().unwrap_or_default();
+        let Some(base64) = embed_url.strip_prefix(&format!("data:{MEDIA_TYPE};base64,")) else {
+            panic!("expected a Vega-Lite data URI")
+        };
+        Ok(serde_json::from_slice(&STANDARD.decode(base64)?)?)
+    }
+
+    #[tokio::test]
+    async fn charts() -> Result<()> {
+        let mut kernel = VegaLiteKernelInstance::default();
+
+        let (outputs, messages) = kernel
+            .execute(
+                r#"{
+  "data": {"values": [{"a": "A", "b": 28}, {"a": "B", "b": 55}, {"a": "C", "b": 43}]},
+  "mark": "bar",
+  "encoding": {
+    "x": {"field": "a", "type": "nominal"},
+    "y": {"field": "b", "type": "quantitative"}
+  }
+}"#,
+            )
+            .await?;
+        assert_eq!(messages, vec![]);
+        assert!(svg(&outputs).starts_with("data:image/svg+xml,"));
+        assert_eq!(svg(&outputs).matches("%3Crect").count(), 3);
+
+        let (outputs, messages) = kernel
+            .execute(
+                r#"{
+  "title": "Counts",
+  "data": {"values": [{"g": "x", "c": "red"}, {"g": "x", "c": "blue"}, {"g": "y", "c": "red"}]},
+  "mark": {"type": "bar"},
+  "encoding": {
+    "x": {"field": "g"},
+    "y": {"aggregate": "count"},
+    "color": {"field": "c"}
+  }
+}"#,
+            )
+            .await?;
+        assert_eq!(messages, vec![]);
+        assert!(svg(&outputs).contains("Count of Records"));
+
+        for mark in ["line", "area", "point", "circle", "square"] {
+            let (outputs, messages) = kernel
+                .execute(&format!(
+                    r#"{{
+  "data": {{"values": [{{"x": 1, "y": 2}}, {{"x": 2, "y": 3.5}}, {{"x": 3, "y": -1}}]}},
+  "mark": "{mark}",
+  "encoding": {{"x": {{"field": "x"}}, "y": {{"field": "y"}}}}
+}}"#
+                ))
+                .await?;
+            assert_eq!(messages, vec![]);
+            assert!(svg(&outputs).starts_with("data:image/svg+xml,"));
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn errors() -> Result<()> {
+        let mut kernel = VegaLiteKernelInstance::default();
+
+        let (outputs, messages) = kernel.execute("{\n  \"mark\": bar\n}").await?;
+        assert_eq!(messages[0].error_type.as_deref(), Some("SyntaxError"));
+        assert_eq!(
+            messages[0]
+                .code_location
+                .as_ref()
+                .and_then(|location| location.start_line),
+            Some(1)
+        );
+        assert!(outputs.is_empty());
+
+        let (outputs, messages) = kernel.execute(r#"{"mark": "bar"}"#).await?;
+        assert_eq!(messages[0].message, "Missing `data` property");
+        assert!(outputs.is_empty());
+
+        let (outputs, messages) = kernel
+            .execute(r#"{"data": {"values": []}, "mark": "arc"}"#)
+            .await?;
+        assert_eq!(
+            messages[0].message,
+            "Unsupported mark `arc`, expected `bar`, `line`, `area`, `point`, `circle` or `square`"
+        );
+        assert!(outputs.is_empty());
+
+        let (outputs, messages) = kernel
+            .execute(r#"{"data": {"name": "df"}, "mark": "bar"}"#)
+            .await?;
+        assert_eq!(
+            messages[0].message,
+            "While requesting variable `df`: Variable requests are not enabled for this kernel instance"
+        );
+        assert!(outputs.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn variable_data() -> Result<()> {
+        let mut kernel = VegaLiteKernelInstance::default();
+
+        let (request_sender, mut request_receiver) = mpsc::unbounded_channel();
+        let (response_sender, response_receiver) = broadcast::channel(8);
+        kernel.variable_channel(request_sender, response_receiver);
+
+        tokio::spawn(async move {
+            while let Some(request) = request_receiver.recv().await {
+                let value = (request.variable == "df").then(|| {
+                    Node::Datatable(Datatable::new(vec![
+                        DatatableColumn::new(
+                            "a".to_string(),
+                            vec![
+                                Primitive::String("A".to_string()),
+                                Primitive::String("B".to_string()),
+                            ],
+                        ),
+                        DatatableColumn::new(
+                            "b".to_string(),
+                            vec![Primitive::Integer(1), Primitive::Integer(2)],
+                        ),
+                    ]))
+                });
+                response_sender
+                    .send(KernelVariableResponse {
+                        variable: request.variable,
+                        value,
+                        ..Default::default()
+                    })
+                    .ok();
+            }
+        });
+
+        let (outputs, messages) = kernel
+            .execute(
+                r#"{
+  "data": {"name": "df"},
+  "mark": "bar",
+  "encoding": {"x": {"field": "a"}, "y": {"field": "b"}}
+}"#,
+            )
+            .await?;
+        assert_eq!(messages, vec![]);
+        assert_eq!(svg(&outputs).matches("%3Crect").count(), 2);
+
+        // Data should be inlined into the specification
+        let spec = spec(&outputs)?;
+        assert_eq!(spec["data"]["values"][1], json!({"a": "B", "b": 2}));
+
+        let (.., messages) = kernel
+            .execute(r#"{"data": {"name": "foo"}, "mark": "bar"}"#)
+            .await?;
+        assert_eq!(messages[0].message, "Variable `foo` does not exist");
+
+        Ok(())
+    }
+}
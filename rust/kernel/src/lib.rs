@@ -81,6 +81,14 @@ pub trait Kernel: Sync + Send {
         false
     }
 
+    /// Should code for the kernel be executed when a document is compiled?
+    ///
+    /// Intended for kernels which are fast and have no side effects (e.g. those
+    /// that render diagrams) so that their outputs can be updated live.
+    fn executes_on_compile(&self) -> bool {
+        false
+    }
+
     /// Create a new instance of the kernel
     fn create_instance(&self) -> Result<Box<dyn KernelInstance>>;
 }
@@ -262,6 +270,24 @@ pub trait KernelInstance: Sync + Send {
         Ok(())
     }
 
+    /// Write a variable in the kernel instance to an Arrow IPC file
+    ///
+    /// Allows large `Datatable`s and arrays to be transferred between kernels
+    /// without serializing them to JSON. Returns `false` if the kernel does not
+    /// support Arrow, or the variable can not be represented as an Arrow table,
+    /// in which case callers should fall back to using `get`.
+    async fn get_arrow(&mut self, _name: &str, _path: &Path) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Set a variable in the kernel instance from an Arrow IPC file
+    ///
+    /// Returns `false` if the kernel does not support Arrow, in which case
+    /// callers should fall back to using `set`.
+    async fn set_arrow(&mut self, _name: &str, _path: &Path) -> Result<bool> {
+        Ok(false)
+    }
+
     /// Create a fork of the kernel instance
     async fn fork(&mut self) -> Result<Box<dyn KernelInstance>> {
         bail!("Kernel `{}` does not support forks", self.name())
@@ -331,6 +357,32 @@ pub enum KernelSignal {
     Kill,
 }
 
+/// Create a data URI for an SVG image
+///
+/// Used by kernels which render diagrams and charts to SVG (e.g. Graphviz, Mermaid)
+/// for the `content_url` of the `ImageObject`s that they output. Any XML declaration
+/// is removed, and characters escaped, based on the recommendation for creating SVG
+/// data URIs at https://gist.github.com/jennyknuth/222825e315d45a738ed9d6e04c7a88d0
+pub fn svg_data_uri(svg: &str) -> String {
+    let svg = svg
+        .replace(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\"?>",
+            "",
+        )
+        .replace('"', "\'")
+        .replace('%', "%25")
+        .replace('#', "%23")
+        .replace('{', "%7B")
+        .replace('}', "%7D")
+        .replace('<', "%3C")
+        .replace('>', "%3E")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    format!("data:image/svg+xml,{svg}")
+}
+
 /// Standard tests for implementations of the `Kernel` and `KernelInstance` traits
 pub mod tests {
     use std::{env, time::Duration};
@@ -8,20 +8,24 @@ use context::KernelContext;
 use kernel::{
     common::{
         eyre::{bail, Result},
+        once_cell::sync::Lazy,
+        tempfile::{tempdir, TempDir},
         tokio::{
-            self,
+            self, fs,
             sync::{broadcast, mpsc, Mutex, RwLock},
         },
         tracing,
     },
     format::Format,
-    schema::{ExecutionMessage, Node},
+    schema::{ExecutionMessage, Node, Variable},
     Kernel, KernelInstance, KernelVariableRequest, KernelVariableRequester, KernelVariableResponse,
 };
 use kernel_asciimath::AsciiMathKernel;
 use kernel_bash::BashKernel;
 use kernel_graphviz::GraphvizKernel;
 use kernel_jinja::JinjaKernel;
+use kernel_lua::LuaKernel;
+use kernel_mermaid::MermaidKernel;
 use kernel_nodejs::NodeJsKernel;
 use kernel_python::PythonKernel;
 use kernel_quickjs::QuickJsKernel;
@@ -29,25 +33,34 @@ use kernel_r::RKernel;
 use kernel_rhai::RhaiKernel;
 use kernel_style::StyleKernel;
 use kernel_tex::TexKernel;
+use kernel_vegalite::VegaLiteKernel;
 
 pub mod cli;
 
-/// Get a list of available kernels
-pub async fn list() -> Vec<Box<dyn Kernel>> {
-    let mut kernels = vec![
+/// Get a list of builtin kernels
+fn builtin() -> Vec<Box<dyn Kernel>> {
+    vec![
         // First so that it gets used for `js` rather than `NodeJsKernel`
         Box::<QuickJsKernel>::default() as Box<dyn Kernel>,
         Box::<AsciiMathKernel>::default() as Box<dyn Kernel>,
         Box::<BashKernel>::default() as Box<dyn Kernel>,
         Box::<GraphvizKernel>::default() as Box<dyn Kernel>,
         Box::<JinjaKernel>::default() as Box<dyn Kernel>,
+        Box::<LuaKernel>::default() as Box<dyn Kernel>,
+        Box::<MermaidKernel>::default() as Box<dyn Kernel>,
         Box::<NodeJsKernel>::default() as Box<dyn Kernel>,
         Box::<PythonKernel>::default() as Box<dyn Kernel>,
         Box::<RKernel>::default() as Box<dyn Kernel>,
         Box::<RhaiKernel>::default() as Box<dyn Kernel>,
         Box::<StyleKernel>::default() as Box<dyn Kernel>,
         Box::<TexKernel>::default() as Box<dyn Kernel>,
-    ];
+        Box::<VegaLiteKernel>::default() as Box<dyn Kernel>,
+    ]
+}
+
+/// Get a list of available kernels
+pub async fn list() -> Vec<Box<dyn Kernel>> {
+    let mut kernels = builtin();
 
     let provided_by_plugins = &mut plugins::kernels::list().await;
     kernels.append(provided_by_plugins);
@@ -55,6 +68,26 @@ pub async fn list() -> Vec<Box<dyn Kernel>> {
     kernels
 }
 
+/// Should code in a language be executed when a document is compiled?
+///
+/// The `language` argument can be the name of a kernel or a programming language.
+/// Only builtin kernels are considered since plugin kernels may be slow to start.
+/// The kernels which execute on compile are determined once, from their specs, so
+/// that no kernels are created, or checked for availability, on each compile.
+pub fn executes_on_compile(language: &str) -> bool {
+    static KERNELS: Lazy<Vec<Box<dyn Kernel>>> = Lazy::new(|| {
+        builtin()
+            .into_iter()
+            .filter(|kernel| kernel.executes_on_compile())
+            .collect()
+    });
+
+    let format = Format::from_name(language);
+    KERNELS
+        .iter()
+        .any(|kernel| kernel.name() == language || kernel.supports_language(&format))
+}
+
 /// Get the default kernel (used when no language is specified)
 pub fn default() -> Box<dyn Kernel> {
     Box::<QuickJsKernel>::default() as Box<dyn Kernel>
@@ -75,6 +108,8 @@ struct KernelInstanceEntry {
 
 type KernelInstances = Arc<RwLock<Vec<KernelInstanceEntry>>>;
 
+type KernelInstanceRef = Arc<Mutex<Box<dyn KernelInstance>>>;
+
 /// A collection of kernel instances associated with a document
 pub struct Kernels {
     /// The home directory of the kernels
@@ -90,6 +125,19 @@ pub struct Kernels {
 
     /// A sender for responses to kernels for variables
     variable_response_sender: broadcast::Sender<KernelVariableResponse>,
+
+    /// A temporary directory for files used to transfer variables between kernel instances
+    ///
+    /// `None` if the directory could not be created, in which case variables are
+    /// only transferred using JSON.
+    temp_dir: Option<TempDir>,
+
+    /// Code which has not been executed because its outputs were obtained from a cache
+    ///
+    /// A list of (id, code, language) tuples, in the order that the code should be executed.
+    /// Kept here, rather than by the executor of a document, so that it is only executed
+    /// when the state of the kernels is needed, even by later executions of the document.
+    deferred: Vec<(String, String, Option<String>)>,
 }
 
 impl fmt::Debug for Kernels {
@@ -129,11 +177,21 @@ impl Kernels {
             home.to_path_buf()
         };
 
+        let temp_dir = match tempdir() {
+            Ok(dir) => Some(dir),
+            Err(error) => {
+                tracing::error!("Unable to create temporary dir for kernels: {error}");
+                None
+            }
+        };
+
         Self {
             home,
             instances,
             variable_request_sender,
             variable_response_sender,
+            temp_dir,
+            deferred: Vec::new(),
         }
     }
 
@@ -144,6 +202,9 @@ impl Kernels {
     }
 
     /// A task to handle requests from kernels for variables in other contexts
+    ///
+    /// The kernels which make requests (e.g. Jinja) are embedded and receive variables
+    /// as nodes. So, unlike `mirror`, values are always transferred using `get`.
     async fn variable_requests_task(
         instances: KernelInstances,
         mut receiver: mpsc::UnboundedReceiver<KernelVariableRequest>,
@@ -196,14 +257,26 @@ impl Kernels {
         contexts
     }
 
+    /// Get a list of the variables in each kernel instance
+    ///
+    /// Instances that are busy (e.g. executing code) are skipped so
+    /// that the caller does not need to wait for them.
+    pub async fn variables(&self) -> Vec<Variable> {
+        let mut variables = Vec::new();
+        for entry in self.instances.read().await.iter() {
+            let Ok(mut instance) = entry.instance.try_lock() else {
+                continue;
+            };
+            variables.append(&mut instance.list().await.unwrap_or_default());
+        }
+        variables
+    }
+
     /// Create a kernel instance
     ///
     /// The `language` argument can be the name of a kernel or a programming language.
     /// If `language` is `None` then the default language is used.
-    async fn create_instance(
-        &mut self,
-        language: Option<&str>,
-    ) -> Result<Arc<Mutex<Box<dyn KernelInstance>>>> {
+    async fn create_instance(&mut self, language: Option<&str>) -> Result<KernelInstanceRef> {
         tracing::debug!(
             "Creating kernel instance for language {:?}",
             language.unwrap_or_default()
@@ -256,10 +329,7 @@ impl Kernels {
     ///
     /// If no language specified, and there is at least one kernel instance, returns the
     /// first instance.
-    async fn get_instance(
-        &mut self,
-        language: Option<&str>,
-    ) -> Result<Option<Arc<Mutex<Box<dyn KernelInstance>>>>> {
+    async fn get_instance(&mut self, language: Option<&str>) -> Result<Option<KernelInstanceRef>> {
         let format = language.map(Format::from_name);
 
         for entry in self.instances.read().await.iter() {
@@ -311,6 +381,22 @@ impl Kernels {
         instance.evaluate(code).await
     }
 
+    /// Fork a kernel instance
+    ///
+    /// The `language` argument is used in the same way as for `execute`, with an instance
+    /// created if necessary. The fork has the same state (e.g. variables) as the instance
+    /// but changes to its state do not affect the instance. Used to execute code
+    /// (e.g. to check that generated code runs) without side effects.
+    pub async fn fork(&mut self, language: Option<&str>) -> Result<Box<dyn KernelInstance>> {
+        let instance = match self.get_instance(language).await? {
+            Some(instance) => instance,
+            None => self.create_instance(language).await?,
+        };
+
+        let mut instance = instance.lock().await;
+        instance.fork().await
+    }
+
     /// Get a variable from the kernels
     ///
     /// Currently just iterates over kernels until the variable is found (if at all).
@@ -336,6 +422,108 @@ impl Kernels {
         instance.set(name, value).await
     }
 
+    /// Mirror a variable from another kernel instance into the instance for a language
+    ///
+    /// Returns `false` if the variable was not found in any other instance.
+    pub async fn mirror(&mut self, name: &str, language: Option<&str>) -> Result<bool> {
+        let target = match self.get_instance(language).await? {
+            Some(instance) => instance,
+            None => self.create_instance(language).await?,
+        };
+
+        self.mirror_into(name, &target).await
+    }
+
+    /// Mirror a variable from another kernel instance into an instance
+    ///
+    /// Tables and arrays are transferred as Arrow IPC files, in the temporary directory
+    /// of the kernels, when both instances support it. This avoids the cost of serializing
+    /// large values to, and from, JSON. Otherwise, falls back to using `get` and `set`.
+    async fn mirror_into(&self, name: &str, target: &KernelInstanceRef) -> Result<bool> {
+        let path = self
+            .temp_dir
+            .as_ref()
+            .map(|dir| dir.path().join(format!("{name}.arrow")));
+
+        for entry in self.instances.read().await.iter() {
+            if Arc::ptr_eq(&entry.instance, target) {
+                continue;
+            }
+
+            let mut source = entry.instance.lock().await;
+
+            if let Some(path) = &path {
+                let transferred = source.get_arrow(name, path).await?
+                    && target.lock().await.set_arrow(name, path).await?;
+
+                if path.exists() {
+                    if let Err(error) = fs::remove_file(path).await {
+                        tracing::debug!("Unable to remove Arrow file: {error}");
+                    }
+                }
+
+                if transferred {
+                    tracing::trace!("Mirrored `{name}` from `{}` using Arrow", entry.name);
+                    return Ok(true);
+                }
+            }
+
+            if let Some(value) = source.get(name).await? {
+                target.lock().await.set(name, &value).await?;
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Defer the execution of some code until the state of the kernels is needed
+    ///
+    /// The `id` is usually the id of the node that the code is from. Any code
+    /// previously deferred with the same id is replaced.
+    pub fn defer(&mut self, id: &str, code: &str, language: Option<&str>) {
+        self.undefer(id);
+        self.deferred
+            .push((id.to_string(), code.to_string(), language.map(String::from)));
+    }
+
+    /// Remove any deferred code with an id (e.g. because that code is about to be executed)
+    pub fn undefer(&mut self, id: &str) {
+        self.deferred.retain(|(deferred_id, ..)| deferred_id != id);
+    }
+
+    /// Whether there is any deferred code
+    pub fn has_deferred(&self) -> bool {
+        !self.deferred.is_empty()
+    }
+
+    /// Execute any deferred code
+    ///
+    /// Outputs are discarded and errors are logged.
+    pub async fn execute_deferred(&mut self) {
+        let deferred: Vec<_> = self.deferred.drain(..).collect();
+        for (id, code, language) in deferred {
+            if let Err(error) = self.execute(&code, language.as_deref()).await {
+                tracing::error!("While executing deferred code for `{id}`: {error}")
+            }
+        }
+    }
+
+    /// Stop all kernel instances
+    ///
+    /// Instances are removed so that they are not used again. An error stopping
+    /// one instance is logged, rather than returned, so that the others are still stopped.
+    pub async fn stop(&mut self) {
+        self.deferred.clear();
+
+        let entries = std::mem::take(&mut *self.instances.write().await);
+        for entry in entries {
+            if let Err(error) = entry.instance.lock().await.stop().await {
+                tracing::error!("While stopping kernel `{}`: {error}", entry.name)
+            }
+        }
+    }
+
     /// Remove a variable from the kernels
     pub async fn remove(&mut self, name: &str) -> Result<()> {
         // TODO: remove from all kernels that the variable has been mirrored to
@@ -382,4 +570,110 @@ mod tests {
 
         Ok(())
     }
+
+    // Test that stopping kernels removes their instances
+    #[test_log::test(tokio::test)]
+    async fn stop() -> Result<()> {
+        let mut kernels = Kernels::new_here();
+
+        kernels.execute("let a = 1", Some("rhai")).await?;
+        assert_eq!(kernels.kernel_contexts().await.len(), 1);
+
+        kernels.stop().await;
+        assert_eq!(kernels.kernel_contexts().await.len(), 0);
+
+        Ok(())
+    }
+
+    // Test mirroring of variables between Python and R kernels
+    #[test_log::test(tokio::test)]
+    async fn mirror_python_to_r() -> Result<()> {
+        if !(PythonKernel::default().is_available() && RKernel::default().is_available()) {
+            return Ok(());
+        }
+
+        let mut kernels = Kernels::new_here();
+
+        let (.., messages) = kernels
+            .execute("a = [1, 2, 3]\nb = 'hello'", Some("python"))
+            .await?;
+        assert_eq!(messages, vec![]);
+
+        assert!(kernels.mirror("a", Some("r")).await?);
+        let (node, messages) = kernels.evaluate("sum(a)", Some("r")).await?;
+        assert_eq!(messages, vec![]);
+        assert_eq!(node, Node::Integer(6));
+
+        assert!(kernels.mirror("b", Some("r")).await?);
+        let (node, messages) = kernels.evaluate("toupper(b)", Some("r")).await?;
+        assert_eq!(messages, vec![]);
+        assert_eq!(node, Node::String("HELLO".to_string()));
+
+        assert!(!kernels.mirror("c", Some("r")).await?);
+
+        Ok(())
+    }
+
+    // Test transfer of a datatable between Python and R kernels through Arrow
+    #[test_log::test(tokio::test)]
+    async fn datatable_python_to_r() -> Result<()> {
+        if !(PythonKernel::default().is_available() && RKernel::default().is_available()) {
+            return Ok(());
+        }
+
+        let mut kernels = Kernels::new_here();
+
+        let (.., messages) = kernels
+            .execute(
+                "import pandas as pd\nimport pyarrow\ndf = pd.DataFrame({'x': [1, 2, 3], 'y': ['a', 'b', 'c']})",
+                Some("python"),
+            )
+            .await?;
+        if !messages.is_empty() {
+            // Pandas or PyArrow not available
+            return Ok(());
+        }
+
+        let (node, ..) = kernels
+            .evaluate("requireNamespace('arrow', quietly = TRUE)", Some("r"))
+            .await?;
+        if node != Node::Boolean(true) {
+            return Ok(());
+        }
+
+        let path = kernels
+            .temp_dir
+            .as_ref()
+            .expect("should have temp dir")
+            .path()
+            .join("df.arrow");
+
+        let python = kernels.get_instance(Some("python")).await?.expect("python");
+        assert!(python.lock().await.get_arrow("df", &path).await?);
+        assert!(path.exists());
+
+        let r = kernels.get_instance(Some("r")).await?.expect("r");
+        assert!(r.lock().await.set_arrow("df", &path).await?);
+
+        let (node, messages) = kernels
+            .evaluate("is.data.frame(df) && nrow(df) == 3", Some("r"))
+            .await?;
+        assert_eq!(messages, vec![]);
+        assert_eq!(node, Node::Boolean(true));
+
+        // Mirrored explicitly, using Arrow
+        let (.., messages) = kernels
+            .execute("df2 = df[df['x'] > 1]", Some("python"))
+            .await?;
+        assert_eq!(messages, vec![]);
+
+        assert!(kernels.mirror("df2", Some("r")).await?);
+        let (node, messages) = kernels
+            .evaluate("paste(df2$y, collapse = '')", Some("r"))
+            .await?;
+        assert_eq!(messages, vec![]);
+        assert_eq!(node, Node::String("bc".to_string()));
+
+        Ok(())
+    }
 }
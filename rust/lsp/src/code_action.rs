@@ -0,0 +1,318 @@
+//! Handling of code action related messages
+//!
+//! See https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#textDocument_codeAction
+
+use std::{collections::HashMap, sync::Arc};
+
+use async_lsp::{
+    lsp_types::{
+        CodeAction, CodeActionKind, CodeActionOrCommand, CodeActionParams, CodeActionResponse,
+        Command, Range, TextEdit, Url, WorkspaceEdit,
+    },
+    ResponseError,
+};
+
+use codecs::{EncodeOptions, Format, Positions};
+use common::{serde_json::json, tokio::sync::RwLock, tracing};
+use document::Document;
+use schema::{
+    transforms::{transform_block, transform_inline},
+    Block, Inline, InstructionBlock, InstructionType, Node, NodeId, NodeType, Visitor, WalkControl,
+};
+
+use crate::{
+    commands::{ACCEPT_NODE, CHOOSE_NODE, REJECT_NODE, RUN_NODE},
+    text_document::TextNode,
+    utils::position_to_position16,
+};
+
+/// Handle a request for code actions for a range of a document
+///
+/// Provides actions for the node at the start of the range, and its ancestors,
+/// such as accepting or rejecting suggestions and transforming the node to another
+/// type. If the range is not empty, an action to wrap the selected blocks in an
+/// instruction is also provided.
+pub(crate) async fn request(
+    params: CodeActionParams,
+    format: Format,
+    source: Arc<RwLock<String>>,
+    root: Arc<RwLock<TextNode>>,
+    doc: Arc<RwLock<Document>>,
+) -> Result<Option<CodeActionResponse>, ResponseError> {
+    let uri = params.text_document.uri;
+    let range = params.range;
+
+    let root = root.read().await;
+    let Some(mut node) = root.node_at(range.start) else {
+        return Ok(None);
+    };
+
+    // Collect the node at the start of the range and its ancestors
+    let mut nodes = vec![node];
+    while let Some(parent) = root.flatten().find(|item| item.node_id == node.parent_id) {
+        nodes.push(parent);
+        node = parent;
+    }
+
+    let mut actions = Vec::new();
+
+    // Commands for suggestions and instructions
+    for node in &nodes {
+        let command = |title: &str, command: &str| {
+            CodeActionOrCommand::CodeAction(CodeAction {
+                title: title.to_string(),
+                kind: Some(CodeActionKind::QUICKFIX),
+                command: Some(Command::new(
+                    title.to_string(),
+                    command.to_string(),
+                    Some(vec![json!(uri), json!(node.node_type), json!(node.node_id)]),
+                )),
+                ..Default::default()
+            })
+        };
+
+        match node.node_type {
+            NodeType::SuggestionBlock => {
+                let mut accept = command("Accept suggestion", CHOOSE_NODE);
+                if let CodeActionOrCommand::CodeAction(CodeAction {
+                    command:
+                        Some(Command {
+                            arguments: Some(arguments),
+                            ..
+                        }),
+                    ..
+                }) = &mut accept
+                {
+                    arguments.push(json!(node.parent_id));
+                }
+                actions.push(accept);
+                actions.push(command("Reject suggestion", REJECT_NODE));
+            }
+            NodeType::InsertBlock
+            | NodeType::ReplaceBlock
+            | NodeType::DeleteBlock
+            | NodeType::InsertInline
+            | NodeType::ReplaceInline
+            | NodeType::DeleteInline => {
+                actions.push(command("Accept suggestion", ACCEPT_NODE));
+                actions.push(command("Reject suggestion", REJECT_NODE));
+            }
+            NodeType::InstructionBlock | NodeType::InstructionInline => {
+                actions.push(command("Run instruction", RUN_NODE));
+            }
+            _ => {}
+        }
+    }
+
+    // Transformations of nodes to other types. Only the first node, of those with available
+    // transformations, is transformed to avoid offering the same action for ancestors.
+    let transforms = nodes.iter().find_map(|node| {
+        let targets: &[(NodeType, &str)] = match node.node_type {
+            NodeType::CodeBlock => &[(NodeType::CodeChunk, "Convert to executable code chunk")],
+            NodeType::CodeChunk => &[(NodeType::CodeBlock, "Convert to code block")],
+            NodeType::CodeInline => &[(
+                NodeType::CodeExpression,
+                "Convert to executable code expression",
+            )],
+            NodeType::CodeExpression => &[(NodeType::CodeInline, "Convert to inline code")],
+            NodeType::Paragraph => &[(NodeType::List, "Convert to list")],
+            _ => return None,
+        };
+        Some((*node, targets))
+    });
+
+    // Blocks within the range (if any) to wrap in an instruction
+    let selected = (range.start != range.end)
+        .then(|| selected_blocks(&root, range))
+        .unwrap_or_default();
+
+    let node_ids = transforms
+        .iter()
+        .map(|(node, ..)| node.node_id.clone())
+        .chain(selected.iter().map(|node| node.node_id.clone()))
+        .collect();
+    let found = find_nodes(&doc, node_ids).await;
+
+    let source = source.read().await;
+    let encode = |node: Node| {
+        let format = format.clone();
+        async move {
+            codecs::to_string(
+                &node,
+                Some(EncodeOptions {
+                    format: Some(format),
+                    ..Default::default()
+                }),
+            )
+            .await
+        }
+    };
+
+    if let Some((node, targets)) = transforms {
+        for (node_type, title) in targets {
+            let transformed = match found.get(&node.node_id) {
+                Some(Found::Block(block)) => transform_block(block.clone(), *node_type).into(),
+                Some(Found::Inline(inline)) => transform_inline(inline.clone(), *node_type).into(),
+                None => continue,
+            };
+
+            match encode(transformed).await {
+                Ok(new_text) => actions.push(edit_action(
+                    title,
+                    &uri,
+                    replace(&source, node.range, new_text),
+                )),
+                Err(error) => tracing::debug!("While encoding transformed node: {error}"),
+            }
+        }
+    }
+
+    // Only wrap blocks (e.g. not list items or table rows)
+    let (selected, content): (Vec<&TextNode>, Vec<Block>) = selected
+        .into_iter()
+        .filter_map(|node| match found.get(&node.node_id) {
+            Some(Found::Block(block)) => Some((node, block.clone())),
+            _ => None,
+        })
+        .unzip();
+
+    if let (Some(first), Some(last)) = (selected.first(), selected.last()) {
+        let instruction = InstructionBlock {
+            content: Some(content),
+            ..InstructionBlock::new(InstructionType::Edit, Vec::new())
+        };
+
+        match encode(Node::InstructionBlock(instruction)).await {
+            Ok(new_text) => actions.push(edit_action(
+                "Wrap in edit instruction",
+                &uri,
+                replace(
+                    &source,
+                    Range::new(first.range.start, last.range.end),
+                    new_text,
+                ),
+            )),
+            Err(error) => tracing::debug!("While encoding instruction: {error}"),
+        }
+    }
+
+    Ok((!actions.is_empty()).then_some(actions))
+}
+
+/// Get the sibling nodes which intersect with a range
+fn selected_blocks(root: &TextNode, range: Range) -> Vec<&TextNode> {
+    // Find the deepest node containing the whole range
+    let mut parent = root;
+    while let Some(child) = parent
+        .children
+        .iter()
+        .find(|child| child.range.start <= range.start && child.range.end >= range.end)
+    {
+        parent = child;
+    }
+
+    parent
+        .children
+        .iter()
+        .filter(|child| child.range.start < range.end && child.range.end > range.start)
+        .collect()
+}
+
+/// Create a code action which applies a text edit
+fn edit_action(title: &str, uri: &Url, edit: TextEdit) -> CodeActionOrCommand {
+    CodeActionOrCommand::CodeAction(CodeAction {
+        title: title.to_string(),
+        kind: Some(CodeActionKind::REFACTOR_REWRITE),
+        edit: Some(WorkspaceEdit {
+            changes: Some(HashMap::from([(uri.clone(), vec![edit])])),
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+}
+
+/// Create a text edit which replaces a range of the source with new text
+///
+/// Retains any trailing whitespace within the range (e.g. blank lines after
+/// a block) so that the replaced content is separated from following content
+/// in the same way as the original.
+fn replace(source: &str, range: Range, new_text: String) -> TextEdit {
+    let positions = Positions::new(source);
+    let start = positions
+        .index_at_position16(position_to_position16(range.start))
+        .unwrap_or_default();
+    let end = positions
+        .index_at_position16(position_to_position16(range.end))
+        .unwrap_or_else(|| source.chars().count());
+
+    let old_text: String = source
+        .chars()
+        .skip(start)
+        .take(end.saturating_sub(start))
+        .collect();
+    let trailing = &old_text[old_text.trim_end().len()..];
+
+    TextEdit::new(range, [new_text.trim_end(), trailing].concat())
+}
+
+/// A block or inline node found in a document
+enum Found {
+    Block(Block),
+    Inline(Inline),
+}
+
+/// Find blocks and inlines, by node id, in the current root node of a document
+async fn find_nodes(doc: &Arc<RwLock<Document>>, node_ids: Vec<NodeId>) -> HashMap<NodeId, Found> {
+    if node_ids.is_empty() {
+        return HashMap::new();
+    }
+
+    let receiver = doc.read().await.watch();
+    let root = receiver.borrow();
+
+    let mut finder = Finder {
+        node_ids,
+        found: HashMap::new(),
+    };
+    finder.visit(&*root);
+    finder.found
+}
+
+/// A visitor which finds blocks and inlines by node id
+struct Finder {
+    /// The ids of the nodes to find
+    node_ids: Vec<NodeId>,
+
+    /// The nodes that have been found
+    found: HashMap<NodeId, Found>,
+}
+
+impl Visitor for Finder {
+    fn visit_block(&mut self, block: &Block) -> WalkControl {
+        if self.found.len() == self.node_ids.len() {
+            return WalkControl::Break;
+        }
+
+        if let Some(node_id) = block.node_id() {
+            if self.node_ids.contains(&node_id) {
+                self.found.insert(node_id, Found::Block(block.clone()));
+            }
+        }
+
+        WalkControl::Continue
+    }
+
+    fn visit_inline(&mut self, inline: &Inline) -> WalkControl {
+        if self.found.len() == self.node_ids.len() {
+            return WalkControl::Break;
+        }
+
+        if let Some(node_id) = inline.node_id() {
+            if self.node_ids.contains(&node_id) {
+                self.found.insert(node_id, Found::Inline(inline.clone()));
+            }
+        }
+
+        WalkControl::Continue
+    }
+}
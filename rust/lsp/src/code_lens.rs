@@ -13,8 +13,8 @@ use schema::{NodeType, ProvenanceCategory};
 
 use crate::{
     commands::{
-        ACCEPT_NODE, CANCEL_NODE, CHOOSE_NODE, HIDE_SUGGESTIONS_NODE, REJECT_NODE, RUN_NODE,
-        SHOW_SUGGESTIONS_NODE,
+        ACCEPT_NODE, CANCEL_NODE, CHOOSE_NODE, HIDE_SUGGESTIONS_NODE, PROMOTE_NODE, REJECT_NODE,
+        RUN_NODE, SHOW_SUGGESTIONS_NODE,
     },
     text_document::TextNode,
 };
@@ -45,6 +45,7 @@ pub(crate) async fn request(
                  parent_id,
                  node_type,
                  node_id,
+                 detail,
                  provenance,
                  ..
              }| {
@@ -68,6 +69,15 @@ pub(crate) async fn request(
                         // not fully implemented
                         vec![lens(RUN_NODE), lens(VIEW_NODE)]
                     }
+                    NodeType::InstructionBlock if detail.as_deref() == Some("chat") => {
+                        vec![
+                            lens(RUN_NODE),
+                            lens(PROMOTE_NODE),
+                            lens(HIDE_SUGGESTIONS_NODE),
+                            lens(SHOW_SUGGESTIONS_NODE),
+                            lens(VIEW_NODE),
+                        ]
+                    }
                     NodeType::InstructionBlock => {
                         vec![
                             lens(RUN_NODE),
@@ -182,6 +192,7 @@ pub(crate) async fn resolve(
             }
             Command::new("$(thumbsup) Accept".to_string(), command, arguments)
         }
+        PROMOTE_NODE => Command::new("$(arrow-up) Promote reply".to_string(), command, arguments),
         ACCEPT_NODE => Command::new("$(thumbsup) Accept".to_string(), command, arguments),
         REJECT_NODE => Command::new("$(thumbsdown) Reject".to_string(), command, arguments),
         VIEW_NODE => Command::new("$(preview) View".to_string(), command, arguments),
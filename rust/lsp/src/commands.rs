@@ -56,6 +56,7 @@ pub(super) const HIDE_SUGGESTIONS_NODE: &str = "stencila.hide-suggestions-node";
 pub(super) const SHOW_SUGGESTIONS_NODE: &str = "stencila.show-suggestions-node";
 
 pub(super) const CHOOSE_NODE: &str = "stencila.choose-node";
+pub(super) const PROMOTE_NODE: &str = "stencila.promote-node";
 pub(super) const ACCEPT_NODE: &str = "stencila.accept-node";
 pub(super) const REJECT_NODE: &str = "stencila.reject-node";
 
@@ -79,6 +80,7 @@ pub(super) fn commands() -> Vec<String> {
         HIDE_SUGGESTIONS_NODE,
         SHOW_SUGGESTIONS_NODE,
         CHOOSE_NODE,
+        PROMOTE_NODE,
         ACCEPT_NODE,
         REJECT_NODE,
         EXPORT_DOC,
@@ -244,6 +246,36 @@ pub(super) async fn execute_command(
                 true,
             )
         }
+        PROMOTE_NODE => {
+            args.next(); // Skip the currently unused node type arg
+            let chat_id = node_id_arg(args.next())?;
+            let message_id = match args.next() {
+                Some(arg) => Some(node_id_arg(Some(arg))?),
+                None => None,
+            };
+
+            let root = doc.read().await.watch().borrow().clone();
+            let patch =
+                match node_execute::promote_message(&root, &chat_id, message_id.as_ref()).await {
+                    Ok(patch) => patch,
+                    Err(error) => {
+                        client
+                            .show_message(ShowMessageParams {
+                                typ: MessageType::WARNING,
+                                message: format!("Unable to promote reply: {error}"),
+                            })
+                            .ok();
+                        return Ok(None);
+                    }
+                };
+
+            (
+                "Promoting reply".to_string(),
+                Command::PatchNode(patch),
+                false,
+                true,
+            )
+        }
         ACCEPT_NODE => {
             args.next(); // Skip the currently unused node type arg
             let node_id = node_id_arg(args.next())?;
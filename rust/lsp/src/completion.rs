@@ -2,63 +2,390 @@
 //!
 //! https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#textDocument_completion
 
-use std::sync::Arc;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use async_lsp::{
     lsp_types::{
         CompletionItem, CompletionItemKind, CompletionParams, CompletionResponse,
-        CompletionTriggerKind, Documentation, MarkupContent, MarkupKind,
+        CompletionTriggerKind, Documentation, MarkupContent, MarkupKind, Position,
     },
     ResponseError,
 };
 
+use codec_text_trait::TextCodec;
 use codecs::Positions;
 use common::{itertools::Itertools, tokio::sync::RwLock};
+use document::Document;
+use schema::{
+    Block, CreativeWorkTypeOrText, Inline, LabelType, Node, NodeType, Visitor, WalkControl,
+};
 
-use crate::utils::position_to_position16;
+use crate::{
+    text_document::TextNode,
+    utils::{crossref_target, position_to_position16, reference_id},
+};
 
 pub(super) async fn request(
     params: CompletionParams,
     source: Option<Arc<RwLock<String>>>,
+    root: Option<Arc<RwLock<TextNode>>>,
+    doc: Option<Arc<RwLock<Document>>>,
 ) -> Result<Option<CompletionResponse>, ResponseError> {
     // Get the trigger for the completion
     let trigger_kind = params.context.as_ref().map(|context| context.trigger_kind);
     let trigger_character = params.context.and_then(|context| context.trigger_character);
 
-    // Shortcuts that do not require getting the source
+    // Unable to proceed if no source available
+    let Some(source) = source else {
+        return Ok(None);
+    };
+
+    // Get the source on the line before the cursor
+    let cursor = params.text_document_position.position;
+    let line_before = {
+        let source = source.read().await;
+        let positions = Positions::new(&source);
+        let end = positions
+            .index_at_position16(position_to_position16(cursor))
+            .unwrap_or_else(|| source.chars().count());
+        let start = positions
+            .index_at_position16(position_to_position16(Position::new(cursor.line, 0)))
+            .unwrap_or(end);
+        source
+            .chars()
+            .skip(start)
+            .take(end.saturating_sub(start))
+            .collect::<String>()
+    };
+
+    // Assignees within instructions and citations elsewhere
     if (Some(CompletionTriggerKind::TRIGGER_CHARACTER), Some("@"))
         == (trigger_kind, trigger_character.as_deref())
+        || line_before.ends_with('@')
     {
-        return assignee_completion().await;
+        return if is_assignee_context(&line_before) {
+            let home = params
+                .text_document_position
+                .text_document
+                .uri
+                .to_file_path()
+                .ok()
+                .and_then(|path| path.parent().map(|dir| dir.to_path_buf()));
+            assignee_completion(home.as_deref()).await
+        } else {
+            cite_completion(doc).await
+        };
     }
 
-    // Unable to proceed if no source available
-    let Some(source) = source else {
+    // Cross-references to figures and tables
+    if line_before.ends_with("](#") {
+        return label_completion(doc).await;
+    }
+
+    // Sources of include blocks
+    if let Some(partial) = line_before
+        .trim_start()
+        .strip_prefix("::: include ")
+        .or_else(|| line_before.trim_start().strip_prefix("::: inc "))
+    {
+        let Ok(path) = params
+            .text_document_position
+            .text_document
+            .uri
+            .to_file_path()
+        else {
+            return Ok(None);
+        };
+        let Some(home) = path.parent() else {
+            return Ok(None);
+        };
+        return path_completion(home.to_path_buf(), partial.trim_start());
+    }
+
+    // Variables and parameters within code
+    if let Some(root) = root {
+        let node_type = root.read().await.node_at(cursor).map(|node| node.node_type);
+        if let Some(NodeType::CodeChunk | NodeType::CodeExpression) = node_type {
+            return code_completion(doc).await;
+        }
+    }
+
+    Ok(None)
+}
+
+/// Whether an `@` at the end of the line before the cursor is for the assignee of an instruction
+///
+/// Assignees can be specified at the start of instructions in Markdown (e.g. `::: edit @`),
+/// in the `:assign:` option of MyST directives, and in inline instructions (e.g. `[[edit @`).
+/// Otherwise, `@` is assumed to be the start of a citation.
+fn is_assignee_context(line_before: &str) -> bool {
+    let trimmed = line_before.trim_start();
+    trimmed.starts_with(":::")
+        || trimmed.starts_with('/')
+        || trimmed.starts_with(":assign:")
+        || line_before.contains("[[")
+}
+
+/// Provide completion list of variables in kernels and parameters in the document
+async fn code_completion(
+    doc: Option<Arc<RwLock<Document>>>,
+) -> Result<Option<CompletionResponse>, ResponseError> {
+    let Some(doc) = doc else {
         return Ok(None);
     };
 
-    // Get the source before the cursor
-    let source = source.read().await;
-    let cursor = params.text_document_position.position;
-    let positions = Positions::new(&source);
-    let end = positions
-        .index_at_position16(position_to_position16(cursor))
-        .unwrap_or_else(|| source.chars().count());
-    let start = end.saturating_sub(10);
-    let take = end - start;
-    let source_before: String = source.chars().skip(start).take(take).collect();
-
-    // Dispatch based on source before cursor
-    if source_before.ends_with('@') {
-        return assignee_completion().await;
+    let collector = collect(&doc).await;
+
+    // Do not wait for kernels if they are busy (e.g. executing code)
+    let kernels = doc.read().await.kernels();
+    let variables = match kernels.try_read() {
+        Ok(kernels) => kernels.variables().await,
+        Err(..) => Vec::new(),
+    };
+
+    let variables = variables.into_iter().map(|variable| {
+        let detail = match (&variable.native_type, &variable.programming_language) {
+            (Some(native_type), Some(lang)) => Some(format!("{native_type} ({lang})")),
+            (Some(native_type), None) => Some(native_type.clone()),
+            (None, Some(lang)) => Some(lang.clone()),
+            (None, None) => variable.node_type.clone(),
+        };
+
+        let documentation = variable.native_hint.map(|hint| {
+            Documentation::MarkupContent(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: hint,
+            })
+        });
+
+        CompletionItem {
+            kind: Some(CompletionItemKind::VARIABLE),
+            label: variable.name,
+            detail,
+            documentation,
+            ..Default::default()
+        }
+    });
+
+    let parameters = collector.parameters.into_iter().map(|name| CompletionItem {
+        kind: Some(CompletionItemKind::FIELD),
+        label: name,
+        detail: Some("Parameter".to_string()),
+        ..Default::default()
+    });
+
+    let items = variables
+        .chain(parameters)
+        .unique_by(|item| item.label.clone())
+        .collect();
+
+    Ok(Some(CompletionResponse::Array(items)))
+}
+
+/// Provide completion list of labelled figures and tables for cross-references
+async fn label_completion(
+    doc: Option<Arc<RwLock<Document>>>,
+) -> Result<Option<CompletionResponse>, ResponseError> {
+    let Some(doc) = doc else {
+        return Ok(None);
+    };
+
+    let items = collect(&doc)
+        .await
+        .labels
+        .into_iter()
+        .map(|(target, label, caption)| CompletionItem {
+            kind: Some(CompletionItemKind::REFERENCE),
+            label: target.clone(),
+            detail: Some(label),
+            documentation: caption.map(Documentation::String),
+            insert_text: Some(target),
+            ..Default::default()
+        })
+        .collect();
+
+    Ok(Some(CompletionResponse::Array(items)))
+}
+
+/// Provide completion list of references for citations
+async fn cite_completion(
+    doc: Option<Arc<RwLock<Document>>>,
+) -> Result<Option<CompletionResponse>, ResponseError> {
+    let Some(doc) = doc else {
+        return Ok(None);
+    };
+
+    let items = collect(&doc)
+        .await
+        .references
+        .into_iter()
+        .map(|(id, text)| CompletionItem {
+            kind: Some(CompletionItemKind::REFERENCE),
+            label: id,
+            detail: Some(text),
+            ..Default::default()
+        })
+        .collect();
+
+    Ok(Some(CompletionResponse::Array(items)))
+}
+
+/// Provide completion list of files and directories for include blocks
+fn path_completion(
+    home: PathBuf,
+    partial: &str,
+) -> Result<Option<CompletionResponse>, ResponseError> {
+    // Only complete the last segment of the path
+    let (dir, ..) = partial.rsplit_once('/').unwrap_or_default();
+    let Ok(entries) = std::fs::read_dir(home.join(dir)) else {
+        return Ok(None);
+    };
+
+    let items = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with('.') {
+                return None;
+            }
+
+            let is_dir = entry
+                .file_type()
+                .map_or(false, |file_type| file_type.is_dir());
+            let kind = if is_dir {
+                CompletionItemKind::FOLDER
+            } else {
+                CompletionItemKind::FILE
+            };
+
+            Some(CompletionItem {
+                kind: Some(kind),
+                label: name,
+                ..Default::default()
+            })
+        })
+        .sorted_by(|a, b| a.label.cmp(&b.label))
+        .collect();
+
+    Ok(Some(CompletionResponse::Array(items)))
+}
+
+/// Collect parameters, labels, and references from the current root node of a document
+async fn collect(doc: &Arc<RwLock<Document>>) -> Collector {
+    let receiver = doc.read().await.watch();
+    let root = receiver.borrow();
+
+    let mut collector = Collector::default();
+    collector.visit(&*root);
+    collector
+}
+
+/// A visitor which collects the names and targets of nodes used in completions
+#[derive(Default)]
+struct Collector {
+    /// The names of parameters
+    parameters: Vec<String>,
+
+    /// The cross-reference target, label, and caption of labelled figures and tables
+    labels: Vec<(String, String, Option<String>)>,
+
+    /// The id and text of references
+    references: Vec<(String, String)>,
+}
+
+impl Collector {
+    fn push_label(
+        &mut self,
+        id: Option<&str>,
+        label_type: &LabelType,
+        label: &Option<String>,
+        caption: &Option<Vec<Block>>,
+    ) {
+        let Some(label) = label else {
+            return;
+        };
+
+        let target = crossref_target(id, label_type, label);
+
+        let label = match label_type {
+            LabelType::FigureLabel => format!("Figure {label}"),
+            LabelType::TableLabel => format!("Table {label}"),
+        };
+
+        let caption = caption
+            .as_ref()
+            .and_then(|caption| caption.first())
+            .map(|first| first.to_text().0);
+
+        self.labels.push((target, label, caption));
     }
+}
 
-    Ok(None)
+impl Visitor for Collector {
+    fn visit_node(&mut self, node: &Node) -> WalkControl {
+        if let Node::Article(article) = node {
+            for reference in article.references.iter().flatten() {
+                if let CreativeWorkTypeOrText::CreativeWorkType(work) = reference {
+                    if let Some(id) = reference_id(work) {
+                        self.references
+                            .push((id.to_string(), work.to_text().0.trim().to_string()));
+                    }
+                }
+            }
+        }
+
+        WalkControl::Continue
+    }
+
+    fn visit_block(&mut self, block: &Block) -> WalkControl {
+        match block {
+            Block::Figure(figure) => self.push_label(
+                figure.id.as_deref(),
+                &LabelType::FigureLabel,
+                &figure.label,
+                &figure.caption,
+            ),
+            Block::Table(table) => self.push_label(
+                table.id.as_deref(),
+                &LabelType::TableLabel,
+                &table.label,
+                &table.caption,
+            ),
+            Block::CodeChunk(chunk) => {
+                if let Some(label_type) = &chunk.label_type {
+                    self.push_label(
+                        chunk.id.as_deref(),
+                        label_type,
+                        &chunk.label,
+                        &chunk.caption,
+                    )
+                }
+            }
+            _ => {}
+        }
+
+        WalkControl::Continue
+    }
+
+    fn visit_inline(&mut self, inline: &Inline) -> WalkControl {
+        if let Inline::Parameter(parameter) = inline {
+            self.parameters.push(parameter.name.clone());
+        }
+
+        WalkControl::Continue
+    }
 }
 
 /// Provide completion list for assignees of an instruction
-async fn assignee_completion() -> Result<Option<CompletionResponse>, ResponseError> {
-    let items = assistants::list(false)
+///
+/// Includes the workspace assistants of the document in `home`.
+async fn assignee_completion(
+    home: Option<&Path>,
+) -> Result<Option<CompletionResponse>, ResponseError> {
+    let items = assistants::list(false, home)
         .await
         .iter()
         // Filter out the generic assistants and this that are not available
@@ -111,3 +438,20 @@ async fn assignee_completion() -> Result<Option<CompletionResponse>, ResponseErr
 
     Ok(Some(CompletionResponse::Array(items)))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assignee_context() {
+        assert!(is_assignee_context("::: edit @"));
+        assert!(is_assignee_context("  ::: new @"));
+        assert!(is_assignee_context("/edit @"));
+        assert!(is_assignee_context(":assign: @"));
+        assert!(is_assignee_context("Some text [[edit @"));
+
+        assert!(!is_assignee_context("As shown by @"));
+        assert!(!is_assignee_context(":reps: 2 @"));
+    }
+}
@@ -0,0 +1,572 @@
+//! Handling of definition related messages
+//!
+//! See https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#textDocument_definition
+
+use std::sync::Arc;
+
+use async_lsp::{
+    lsp_types::{GotoDefinitionResponse, Location, Position, Range, Url},
+    ResponseError,
+};
+
+use codecs::Format;
+use common::{
+    itertools::Itertools,
+    once_cell::sync::Lazy,
+    regex::{self, Regex},
+    tokio::sync::RwLock,
+};
+use document::Document;
+use schema::{
+    Block, CodeLocation, ExecutionDependantNode, ExecutionDependencyNode, IfBlockClause, Inline,
+    LabelType, NodeId, NodeType, Visitor, WalkControl,
+};
+
+use crate::{text_document::TextNode, utils::crossref_target};
+
+/// Handle a request for the definition of the symbol at a position
+///
+/// Resolves variables used in code to the code chunks that assign them (or
+/// parameters that declare them), cross-reference links to the labelled figure or
+/// table, and citations to the entry for the reference.
+pub(crate) async fn request(
+    uri: Url,
+    position: Position,
+    source: Arc<RwLock<String>>,
+    root: Arc<RwLock<TextNode>>,
+    doc: Arc<RwLock<Document>>,
+) -> Result<Option<GotoDefinitionResponse>, ResponseError> {
+    let index = Index::new(&doc).await;
+    let source = source.read().await;
+    let root = root.read().await;
+
+    let Some(symbol) = Symbol::at(position, &source, &root, &index) else {
+        return Ok(None);
+    };
+
+    let ranges = match &symbol {
+        Symbol::Variable(name) => {
+            let definitions = index.definitions(name, &root);
+
+            // Prefer the last definition before the position, falling back to the first after it
+            // (e.g. when the variable is assigned in a chunk further down the document)
+            let before = definitions
+                .iter()
+                .filter(|range| range.start <= position)
+                .last()
+                .or_else(|| definitions.first());
+
+            before.cloned().into_iter().collect_vec()
+        }
+        Symbol::Label(target) => index.label(target, &root).into_iter().collect_vec(),
+        Symbol::Reference(target) => reference_range(target, &source).into_iter().collect_vec(),
+    };
+
+    let locations = ranges
+        .into_iter()
+        .map(|range| Location::new(uri.clone(), range))
+        .collect_vec();
+
+    Ok(match locations.len() {
+        0 => None,
+        1 => locations
+            .into_iter()
+            .next()
+            .map(GotoDefinitionResponse::Scalar),
+        _ => Some(GotoDefinitionResponse::Array(locations)),
+    })
+}
+
+/// A symbol that can be defined and referenced within a document
+pub(super) enum Symbol {
+    /// A variable assigned in a code chunk or declared by a parameter
+    Variable(String),
+
+    /// A labelled figure or table with the cross-reference target
+    Label(String),
+
+    /// A reference with the target used in citations to it
+    Reference(String),
+}
+
+impl Symbol {
+    /// Get the symbol at a position
+    pub fn at(position: Position, source: &str, root: &TextNode, index: &Index) -> Option<Self> {
+        let mut node = root.node_at(position)?;
+        loop {
+            match node.node_type {
+                NodeType::CodeChunk | NodeType::CodeExpression => {
+                    let word = word_at(position, source)?;
+
+                    // The label of a code chunk (on its first line) rather than a variable
+                    if position.line == node.range.start.line {
+                        if let Some((target, ..)) =
+                            index.labels.iter().find(|(_, node_id, name)| {
+                                node_id == &node.node_id && name.as_ref() == Some(&word)
+                            })
+                        {
+                            return Some(Symbol::Label(target.clone()));
+                        }
+                    }
+
+                    return Some(Symbol::Variable(word));
+                }
+                NodeType::IfBlockClause | NodeType::ForBlock
+                    if position.line == node.range.start.line =>
+                {
+                    return word_at(position, source).map(Symbol::Variable)
+                }
+                NodeType::Parameter => {
+                    return index
+                        .parameters
+                        .iter()
+                        .find(|(_, node_id)| node_id == &node.node_id)
+                        .map(|(name, ..)| Symbol::Variable(name.clone()))
+                }
+                NodeType::Link => {
+                    return index
+                        .links
+                        .iter()
+                        .find(|(_, node_id)| node_id == &node.node_id)
+                        .map(|(target, ..)| Symbol::Label(target.clone()))
+                }
+                NodeType::Cite => {
+                    return index
+                        .cites
+                        .iter()
+                        .find(|(_, node_id)| node_id == &node.node_id)
+                        .map(|(target, ..)| Symbol::Reference(target.clone()))
+                }
+                NodeType::Figure | NodeType::Table => {
+                    return index
+                        .labels
+                        .iter()
+                        .find(|(_, node_id, ..)| node_id == &node.node_id)
+                        .map(|(target, ..)| Symbol::Label(target.clone()))
+                }
+                _ => {}
+            }
+
+            // Walk up to the parent node
+            node = root.flatten().find(|item| item.node_id == node.parent_id)?;
+        }
+    }
+}
+
+/// Get the identifier (if any) at a position in the source
+///
+/// The `character` of the position is in UTF-16 code units (the LSP default) so is
+/// converted to an index of the characters in the line.
+pub(super) fn word_at(position: Position, source: &str) -> Option<String> {
+    let line = source.lines().nth(position.line as usize)?;
+    let chars = line.chars().collect_vec();
+
+    let mut units = 0;
+    let cursor = chars
+        .iter()
+        .position(|c| {
+            units += c.len_utf16();
+            units > position.character as usize
+        })
+        .unwrap_or(chars.len());
+
+    let is_word = |c: &char| c.is_alphanumeric() || *c == '_';
+    let start = chars[..cursor]
+        .iter()
+        .rposition(|c| !is_word(c))
+        .map_or(0, |index| index + 1);
+    let end = chars[cursor..]
+        .iter()
+        .position(|c| !is_word(c))
+        .map_or(chars.len(), |index| cursor + index);
+
+    let word: String = chars.get(start..end)?.iter().collect();
+    (!word.is_empty() && !word.starts_with(|c: char| c.is_ascii_digit())).then_some(word)
+}
+
+/// Get the range of the entry for a reference in the source
+///
+/// Looks for the `id` of the reference, usually within the YAML header of the document.
+pub(super) fn reference_range(target: &str, source: &str) -> Option<Range> {
+    let regex = Regex::new(&format!(
+        r#"\bid:\s*['"]?{}['"]?\s*$"#,
+        regex::escape(target)
+    ))
+    .ok()?;
+
+    source.lines().enumerate().find_map(|(line, text)| {
+        let found = regex.find(text)?;
+        let start = text[..found.start()].encode_utf16().count() as u32;
+        let end = text[..found.end()].encode_utf16().count() as u32;
+        Some(Range::new(
+            Position::new(line as u32, start),
+            Position::new(line as u32, end),
+        ))
+    })
+}
+
+/// Whether the programming language of some code is the same as `language`
+///
+/// Code in any language is treated as the same if either language is unknown.
+pub(super) fn same_language(language: Option<&str>, other: &Option<String>) -> bool {
+    match (language, other) {
+        (Some(language), Some(other)) => Format::from_name(language) == Format::from_name(other),
+        _ => true,
+    }
+}
+
+/// Get the byte offsets of the occurrences of an identifier within some code
+///
+/// As for the parsing of dependencies from code, ignores occurrences within string
+/// literals, or that are attributes (e.g. `other.name`).
+pub(super) fn identifier_offsets(code: &str, name: &str) -> Vec<usize> {
+    static REGEX: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(r#"("[^"]*"|'[^']*')|([A-Za-z_][A-Za-z0-9_]*)"#).expect("invalid regex")
+    });
+
+    REGEX
+        .captures_iter(code)
+        .filter_map(|captures| {
+            let found = captures.get(2)?;
+            let before = &code[..found.start()];
+            (found.as_str() == name
+                && !before.ends_with(|c: char| c.is_ascii_digit())
+                && !before.trim_end().ends_with(['.', '$', '@']))
+            .then_some(found.start())
+        })
+        .collect()
+}
+
+/// Get the range of a [`CodeLocation`] within a code chunk or expression
+pub(super) fn code_location_range(node: &TextNode, location: &CodeLocation) -> Range {
+    let (line_offset, column_offset) = if matches!(node.node_type, NodeType::CodeChunk) {
+        // Plus one for line with code chunk back ticks
+        (1, node.range.start.character)
+    } else {
+        // Plus one for the opening back tick of the code expression
+        (0, node.range.start.character + 1)
+    };
+
+    let line = |line: Option<u64>| node.range.start.line + line_offset + line.unwrap_or(0) as u32;
+    let column = |column: Option<u64>| column_offset + column.unwrap_or(0) as u32;
+
+    let start = Position::new(line(location.start_line), column(location.start_column));
+    let end = Position::new(
+        line(location.end_line.or(location.start_line)),
+        column(location.end_column.or(location.start_column)),
+    );
+
+    Range::new(start, end)
+}
+
+/// An index of the symbols, and the nodes defining or referring to them, in a document
+#[derive(Default)]
+pub(super) struct Index {
+    /// The variables assigned by code chunks
+    pub assigns: Vec<(String, NodeId, Option<CodeLocation>)>,
+
+    /// The variables used by code chunks and expressions
+    pub uses: Vec<(String, NodeId, Option<CodeLocation>)>,
+
+    /// The names of parameters
+    pub parameters: Vec<(String, NodeId)>,
+
+    /// The cross-reference targets of labelled figures, tables, and code chunks
+    ///
+    /// Also includes the name (the id or the label) used to create the target, if it
+    /// is in the source (i.e. is not an automatically generated label).
+    pub labels: Vec<(String, NodeId, Option<String>)>,
+
+    /// The targets of internal links (i.e. those starting with `#`)
+    pub links: Vec<(String, NodeId)>,
+
+    /// The targets of citations
+    pub cites: Vec<(String, NodeId)>,
+
+    /// The programming language and code of code chunks, code expressions,
+    /// `if` block clauses, and `for` blocks
+    pub code: Vec<(NodeId, Option<String>, String)>,
+}
+
+impl Index {
+    /// Create an index of the current root node of a document
+    pub async fn new(doc: &Arc<RwLock<Document>>) -> Self {
+        let receiver = doc.read().await.watch();
+        let root = receiver.borrow();
+
+        let mut index = Self::default();
+        index.visit(&*root);
+        index
+    }
+
+    /// Get the ranges of the nodes that define a variable
+    pub fn definitions(&self, name: &str, root: &TextNode) -> Vec<Range> {
+        let assigns = self
+            .assigns
+            .iter()
+            .filter(|(assigned, ..)| assigned == name)
+            .filter_map(|(_, node_id, location)| {
+                let node = root.flatten().find(|node| &node.node_id == node_id)?;
+                Some(match location {
+                    Some(location) => code_location_range(node, location),
+                    None => node.range,
+                })
+            });
+
+        let parameters = self
+            .parameters
+            .iter()
+            .filter(|(parameter, ..)| parameter == name)
+            .filter_map(|(_, node_id)| node_range(node_id, root));
+
+        assigns
+            .chain(parameters)
+            .sorted_by_key(|range| range.start)
+            .collect()
+    }
+
+    /// Get the ranges of the code that uses a variable
+    ///
+    /// Only code in the same programming language as `language` is included (or code
+    /// in any language if it is `None`). The parser only detects the variables used by
+    /// single line code so, for code chunks, the code is searched for the variable,
+    /// excluding where it is assigned.
+    pub fn uses(&self, name: &str, language: Option<&str>, root: &TextNode) -> Vec<Range> {
+        let mut ranges = Vec::new();
+        for (node_id, node_language, code) in &self.code {
+            if !same_language(language, node_language) {
+                continue;
+            }
+            let Some(node) = root.flatten().find(|node| &node.node_id == node_id) else {
+                continue;
+            };
+
+            if matches!(node.node_type, NodeType::CodeChunk) {
+                for offset in identifier_offsets(code, name) {
+                    let before = &code[..offset];
+                    let line = before.matches('\n').count() as u64;
+                    let column = before[before.rfind('\n').map_or(0, |index| index + 1)..]
+                        .chars()
+                        .count() as u64;
+
+                    let assigned = self.assigns.iter().any(|(assigned, id, location)| {
+                        assigned == name
+                            && id == node_id
+                            && location.as_ref().is_some_and(|location| {
+                                location.start_line == Some(line)
+                                    && location.start_column == Some(column)
+                            })
+                    });
+                    if assigned {
+                        continue;
+                    }
+
+                    let location = CodeLocation {
+                        start_line: Some(line),
+                        start_column: Some(column),
+                        end_line: Some(line),
+                        end_column: Some(column + name.chars().count() as u64),
+                        ..Default::default()
+                    };
+                    ranges.push(code_location_range(node, &location));
+                }
+            } else {
+                ranges.extend(
+                    self.uses
+                        .iter()
+                        .filter(|(used, id, ..)| used == name && id == node_id)
+                        .map(|(.., location)| match location {
+                            Some(location) => code_location_range(node, location),
+                            None => node.range,
+                        }),
+                );
+            }
+        }
+        ranges
+    }
+
+    /// Get the range of the labelled node with a cross-reference target
+    pub fn label(&self, target: &str, root: &TextNode) -> Option<Range> {
+        self.labels
+            .iter()
+            .find(|(label, ..)| label == target)
+            .and_then(|(_, node_id, ..)| node_range(node_id, root))
+    }
+
+    /// Get the ranges of the links to a cross-reference target
+    pub fn links(&self, target: &str, root: &TextNode) -> Vec<Range> {
+        self.links
+            .iter()
+            .filter(|(link, ..)| link == target)
+            .filter_map(|(_, node_id)| node_range(node_id, root))
+            .collect()
+    }
+
+    /// Get the ranges of the citations of a reference
+    pub fn cites(&self, target: &str, root: &TextNode) -> Vec<Range> {
+        self.cites
+            .iter()
+            .filter(|(cite, ..)| cite == target)
+            .filter_map(|(_, node_id)| node_range(node_id, root))
+            .collect()
+    }
+
+    /// Get the programming language of the code at a position (if any)
+    pub fn language_at(&self, position: Position, root: &TextNode) -> Option<String> {
+        let mut node = root.node_at(position)?;
+        loop {
+            if let Some((_, language, ..)) = self
+                .code
+                .iter()
+                .find(|(node_id, ..)| node_id == &node.node_id)
+            {
+                return language.clone();
+            }
+            node = root.flatten().find(|item| item.node_id == node.parent_id)?;
+        }
+    }
+
+    /// Push the code of a node and the variables assigned and used by it
+    ///
+    /// The analysis is done here, on demand, rather than when the document is compiled.
+    fn push_code(&mut self, node_id: NodeId, language: &Option<String>, code: &str) {
+        self.code
+            .push((node_id.clone(), language.clone(), code.to_string()));
+
+        let (dependencies, dependants) =
+            parsers::dependencies(code, language.as_deref().unwrap_or_default());
+
+        for dependant in dependants.into_iter().flatten() {
+            if let ExecutionDependantNode::Variable(variable) = dependant.dependant_node {
+                self.assigns
+                    .push((variable.name, node_id.clone(), dependant.code_location));
+            }
+        }
+
+        for dependency in dependencies.into_iter().flatten() {
+            if let ExecutionDependencyNode::Variable(variable) = dependency.dependency_node {
+                self.uses
+                    .push((variable.name, node_id.clone(), dependency.code_location));
+            }
+        }
+    }
+
+    fn push_label(
+        &mut self,
+        id: Option<&str>,
+        label_type: &LabelType,
+        label: &Option<String>,
+        label_automatically: &Option<bool>,
+        node_id: NodeId,
+    ) {
+        if let Some(label) = label {
+            let name = match id {
+                Some(id) => Some(id.to_string()),
+                None => (!label_automatically.unwrap_or(true)).then(|| label.clone()),
+            };
+            self.labels
+                .push((crossref_target(id, label_type, label), node_id, name));
+        }
+    }
+}
+
+/// Get the range of a node in the text document
+fn node_range(node_id: &NodeId, root: &TextNode) -> Option<Range> {
+    root.flatten()
+        .find(|node| &node.node_id == node_id)
+        .map(|node| node.range)
+}
+
+impl Visitor for Index {
+    fn visit_block(&mut self, block: &Block) -> WalkControl {
+        match block {
+            Block::Figure(figure) => self.push_label(
+                figure.id.as_deref(),
+                &LabelType::FigureLabel,
+                &figure.label,
+                &figure.label_automatically,
+                figure.node_id(),
+            ),
+            Block::Table(table) => self.push_label(
+                table.id.as_deref(),
+                &LabelType::TableLabel,
+                &table.label,
+                &table.label_automatically,
+                table.node_id(),
+            ),
+            Block::ForBlock(block) => {
+                self.assigns
+                    .push((block.variable.clone(), block.node_id(), None));
+                self.push_code(block.node_id(), &block.programming_language, &block.code);
+            }
+            Block::CodeChunk(chunk) => {
+                self.push_code(chunk.node_id(), &chunk.programming_language, &chunk.code);
+
+                if let Some(label_type) = &chunk.label_type {
+                    self.push_label(
+                        chunk.id.as_deref(),
+                        label_type,
+                        &chunk.label,
+                        &chunk.label_automatically,
+                        chunk.node_id(),
+                    )
+                }
+            }
+            _ => {}
+        }
+
+        WalkControl::Continue
+    }
+
+    fn visit_inline(&mut self, inline: &Inline) -> WalkControl {
+        match inline {
+            Inline::CodeExpression(expr) => {
+                self.push_code(expr.node_id(), &expr.programming_language, &expr.code);
+            }
+            Inline::Parameter(parameter) => {
+                self.parameters
+                    .push((parameter.name.clone(), parameter.node_id()));
+            }
+            Inline::Link(link) => {
+                if let Some(target) = link.target.strip_prefix('#') {
+                    self.links.push((target.to_string(), link.node_id()));
+                }
+            }
+            Inline::Cite(cite) => {
+                self.cites.push((cite.target.clone(), cite.node_id()));
+            }
+            Inline::CiteGroup(group) => {
+                for cite in &group.items {
+                    self.cites.push((cite.target.clone(), cite.node_id()));
+                }
+            }
+            _ => {}
+        }
+
+        WalkControl::Continue
+    }
+
+    fn visit_if_block_clause(&mut self, clause: &IfBlockClause) -> WalkControl {
+        self.push_code(clause.node_id(), &clause.programming_language, &clause.code);
+
+        WalkControl::Continue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn word_at_utf16() {
+        let word = |line, character| word_at(Position::new(line, character), "a = 1\n😀 + bcd");
+
+        assert_eq!(word(0, 0), Some("a".to_string()));
+        assert_eq!(word(0, 2), None);
+
+        // The emoji is two UTF-16 code units but one char
+        assert_eq!(word(1, 5), Some("bcd".to_string()));
+        assert_eq!(word(1, 7), Some("bcd".to_string()));
+        assert_eq!(word(1, 8), Some("bcd".to_string()));
+    }
+}
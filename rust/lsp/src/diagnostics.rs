@@ -50,7 +50,15 @@ impl Notification for PublishStatus {
 }
 
 /// Publish diagnostics
-pub(super) fn publish(uri: &Url, text_node: &TextNode, client: &mut ClientSocket) {
+///
+/// The `other` diagnostics (e.g. for broken include sources) are published
+/// along with those for the execution of nodes.
+pub(super) fn publish(
+    uri: &Url,
+    text_node: &TextNode,
+    other: Vec<Diagnostic>,
+    client: &mut ClientSocket,
+) {
     // Publish status notifications. As for diagnostics intentionally publishes an
     // empty set so as to clear existing decorations.
     let statuses = statuses(text_node);
@@ -63,7 +71,8 @@ pub(super) fn publish(uri: &Url, text_node: &TextNode, client: &mut ClientSocket
 
     // Publish diagnostics. This intentionally publishes an empty set so as to clear
     // any existing diagnostics.
-    let diagnostics = diagnostics(text_node);
+    let mut diagnostics = diagnostics(text_node);
+    diagnostics.extend(other);
     if let Err(error) = client.publish_diagnostics(PublishDiagnosticsParams {
         uri: uri.clone(),
         diagnostics,
@@ -0,0 +1,96 @@
+//! Handling of folding range related messages
+//!
+//! See https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#textDocument_foldingRange
+
+use std::sync::Arc;
+
+use async_lsp::{
+    lsp_types::{FoldingRange, FoldingRangeKind},
+    ResponseError,
+};
+
+use common::{itertools::Itertools, tokio::sync::RwLock};
+use schema::NodeType;
+
+use crate::text_document::TextNode;
+
+/// Handle a request for the folding ranges of a document
+///
+/// Provides folding ranges for multi-line sections, code chunks, and other
+/// container blocks, and for headings (from the heading to the line before the
+/// next heading of the same or higher level).
+pub(crate) async fn request(
+    root: Arc<RwLock<TextNode>>,
+) -> Result<Option<Vec<FoldingRange>>, ResponseError> {
+    let root = root.read().await;
+
+    let mut ranges = root
+        .flatten()
+        .filter(|node| {
+            use NodeType::*;
+            matches!(
+                node.node_type,
+                Admonition
+                    | CallBlock
+                    | CodeChunk
+                    | Figure
+                    | ForBlock
+                    | IfBlock
+                    | IfBlockClause
+                    | IncludeBlock
+                    | InstructionBlock
+                    | Section
+                    | StyledBlock
+                    | SuggestionBlock
+            )
+        })
+        .filter_map(|node| {
+            // Exclude the last line of the node (e.g. the closing fence of a code chunk)
+            // so that it remains visible when the node is folded
+            let end = node.range.end;
+            let last_line = if end.character == 0 {
+                end.line.checked_sub(1)?
+            } else {
+                end.line
+            };
+            let end_line = last_line.checked_sub(1)?;
+            (end_line > node.range.start.line).then(|| FoldingRange {
+                start_line: node.range.start.line,
+                end_line,
+                kind: Some(FoldingRangeKind::Region),
+                ..Default::default()
+            })
+        })
+        .collect_vec();
+
+    // Headings are not containers so fold from each heading to the next one
+    // of the same or a higher level (or to the end of the document)
+    let headings = root
+        .flatten()
+        .filter(|node| matches!(node.node_type, NodeType::Heading))
+        .filter_map(|node| {
+            let level = node.name.strip_prefix('H')?.parse::<u8>().ok()?;
+            Some((level, node.range.start.line))
+        })
+        .collect_vec();
+    for (index, (level, start_line)) in headings.iter().enumerate() {
+        let end_line = headings
+            .iter()
+            .skip(index + 1)
+            .find(|(next_level, ..)| next_level <= level)
+            .map_or(root.range.end.line, |(.., next_line)| {
+                next_line.saturating_sub(1)
+            });
+
+        if end_line > *start_line {
+            ranges.push(FoldingRange {
+                start_line: *start_line,
+                end_line,
+                kind: Some(FoldingRangeKind::Region),
+                ..Default::default()
+            });
+        }
+    }
+
+    Ok(Some(ranges))
+}
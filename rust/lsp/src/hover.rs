@@ -0,0 +1,308 @@
+//! Handling of hover related messages
+//!
+//! See https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#textDocument_hover
+
+use std::sync::Arc;
+
+use async_lsp::{
+    lsp_types::{Hover, HoverContents, MarkupContent, MarkupKind, Position},
+    ResponseError,
+};
+
+use codecs::{EncodeOptions, Format, Mapping, PoshMap};
+use common::{itertools::Itertools, tokio::sync::RwLock, tracing};
+use document::Document;
+use schema::{cord_provenance, Block, Inline, Node, NodeId, NodeType, Visitor, WalkControl};
+
+use crate::{
+    text_document::{TextNode, TextNodeExecution},
+    utils::position_to_position16,
+};
+
+/// The maximum number of characters of each output to show
+///
+/// Outputs longer than this (e.g. images encoded as data URIs) are
+/// summarized rather than rendered.
+const MAX_OUTPUT_CHARS: usize = 5000;
+
+/// Handle a request for hover information at a position
+///
+/// Provides details of the deepest node at the position, other than `Text`
+/// nodes for which details of the parent node are provided.
+pub(crate) async fn request(
+    position: Position,
+    source: Arc<RwLock<String>>,
+    generated: Arc<RwLock<(String, Mapping)>>,
+    root: Arc<RwLock<TextNode>>,
+    doc: Arc<RwLock<Document>>,
+) -> Result<Option<Hover>, ResponseError> {
+    let root = root.read().await;
+    let Some(mut node) = root.node_at(position) else {
+        return Ok(None);
+    };
+    if matches!(node.node_type, NodeType::Text) {
+        if let Some(parent) = root.flatten().find(|item| item.node_id == node.parent_id) {
+            node = parent;
+        }
+    }
+
+    let mut sections = vec![heading(node)];
+
+    if let Some(execution) = &node.execution {
+        if let Some(status) = status(execution) {
+            sections.push(status);
+        }
+
+        let outputs = outputs(&node.node_id, &doc).await;
+        if !outputs.is_empty() {
+            sections.push(["**Outputs**\n\n", &outputs.join("\n\n")].concat());
+        }
+
+        if let Some(messages) = messages(execution) {
+            sections.push(messages);
+        }
+    }
+
+    if let Some(provenance) = provenance(node) {
+        sections.push(provenance);
+    }
+
+    if let Some(authorship) = authorship(position, &source, &generated).await {
+        sections.push(authorship);
+    }
+
+    Ok(Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: sections.join("\n\n---\n\n"),
+        }),
+        range: Some(node.range),
+    }))
+}
+
+/// Create a heading with the type, name and detail of the node
+fn heading(node: &TextNode) -> String {
+    let mut heading = format!("**{}**", node.node_type);
+
+    let name = node.name.as_str();
+    if name != node.node_type.to_string() {
+        heading.push_str(&format!(" {name}"));
+    }
+
+    if let Some(detail) = &node.detail {
+        heading.push_str(&format!(": {detail}"));
+    }
+
+    heading
+}
+
+/// Create a summary of the execution status, duration and timing of a node
+fn status(execution: &TextNodeExecution) -> Option<String> {
+    let mut items = Vec::new();
+
+    if let Some(status) = &execution.status {
+        items.push(format!("Status: {status}"));
+    }
+
+    if let Some(required) = &execution.required {
+        items.push(format!("Required: {required}"));
+    }
+
+    if let Some(duration) = &execution.duration {
+        items.push(format!("Duration: {}", duration.humanize(true)));
+    }
+
+    if let Some(ended) = &execution.ended {
+        items.push(format!("Ended: {}", ended.humanize(false)));
+    }
+
+    (!items.is_empty()).then(|| items.join("  \n"))
+}
+
+/// Get the outputs of a node, rendered as Markdown
+async fn outputs(node_id: &NodeId, doc: &Arc<RwLock<Document>>) -> Vec<String> {
+    // Find the outputs within the current root node of the document
+    // (cloning them so that the watch borrow is not held across the await points below)
+    let outputs = {
+        let receiver = doc.read().await.watch();
+        let root = receiver.borrow();
+        let mut finder = OutputsFinder {
+            node_id: node_id.clone(),
+            outputs: None,
+        };
+        finder.visit(&*root);
+        finder.outputs.unwrap_or_default()
+    };
+
+    let mut rendered = Vec::with_capacity(outputs.len());
+    for output in outputs {
+        let markdown = match codecs::to_string(
+            &output,
+            Some(EncodeOptions {
+                format: Some(Format::Markdown),
+                ..Default::default()
+            }),
+        )
+        .await
+        {
+            Ok(markdown) => markdown,
+            Err(error) => {
+                tracing::debug!("While encoding output to Markdown: {error}");
+                continue;
+            }
+        };
+
+        if markdown.chars().count() > MAX_OUTPUT_CHARS {
+            rendered.push(format!("_{output} output too large to display_"));
+        } else {
+            rendered.push(markdown.trim().to_string());
+        }
+    }
+
+    rendered
+}
+
+/// A visitor which finds the outputs of an executable node
+struct OutputsFinder {
+    /// The id of the node to find
+    node_id: NodeId,
+
+    /// The outputs of the node, once found
+    outputs: Option<Vec<Node>>,
+}
+
+impl Visitor for OutputsFinder {
+    fn visit_block(&mut self, block: &Block) -> WalkControl {
+        if self.outputs.is_some() {
+            return WalkControl::Break;
+        }
+
+        if let Block::CodeChunk(chunk) = block {
+            if chunk.node_id() == self.node_id {
+                self.outputs = Some(chunk.outputs.clone().unwrap_or_default());
+                return WalkControl::Break;
+            }
+        }
+
+        WalkControl::Continue
+    }
+
+    fn visit_inline(&mut self, inline: &Inline) -> WalkControl {
+        if self.outputs.is_some() {
+            return WalkControl::Break;
+        }
+
+        if let Inline::CodeExpression(expr) = inline {
+            if expr.node_id() == self.node_id {
+                self.outputs = Some(expr.output.iter().map(|output| *output.clone()).collect());
+                return WalkControl::Break;
+            }
+        }
+
+        WalkControl::Continue
+    }
+}
+
+/// Create a list of the execution messages of a node
+fn messages(execution: &TextNodeExecution) -> Option<String> {
+    let messages = execution.messages.as_ref()?;
+    if messages.is_empty() {
+        return None;
+    }
+
+    let list = messages
+        .iter()
+        .map(|message| {
+            let level = &message.level;
+            let error_type = message
+                .error_type
+                .as_ref()
+                .map(|error_type| format!("{error_type}: "))
+                .unwrap_or_default();
+            format!("- **{level}** {error_type}{}", message.message)
+        })
+        .join("\n");
+
+    Some(["**Messages**\n\n", &list].concat())
+}
+
+/// Create a summary of the provenance of the node
+fn provenance(node: &TextNode) -> Option<String> {
+    let provenance = node.provenance.as_ref()?;
+    if provenance.is_empty() {
+        return None;
+    }
+
+    let summary = provenance
+        .iter()
+        .map(|count| {
+            format!(
+                "`{}` {}%",
+                count.provenance_category,
+                count.character_percent.unwrap_or_default()
+            )
+        })
+        .join(", ");
+
+    Some(format!("**Provenance**: {summary}"))
+}
+
+/// Create a description of the authorship of the content at a position
+async fn authorship(
+    position: Position,
+    source: &Arc<RwLock<String>>,
+    generated: &Arc<RwLock<(String, Mapping)>>,
+) -> Option<String> {
+    let source = source.read().await;
+    let generated = generated.read().await;
+    let (generated, mapping) = &*generated;
+
+    let poshmap = PoshMap::new(&source, generated, mapping.clone());
+    let (count, _authors, prov) =
+        *poshmap.position16_to_authorship(position_to_position16(position))?;
+
+    let authors = if count == 1 {
+        "1 author".to_string()
+    } else {
+        format!("{count} authors")
+    };
+
+    Some(format!(
+        "**Authorship**: {}, {authors}",
+        describe_provenance(prov)
+    ))
+}
+
+/// Describe a provenance byte e.g. "machine written, human edited"
+fn describe_provenance(prov: u8) -> String {
+    let display = cord_provenance::display(prov);
+
+    let mut parts = Vec::new();
+    let mut rest = display.as_str();
+    while !rest.is_empty() {
+        let digits = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+        let (count, code) = rest.split_at(digits);
+        let Some(code) = code.get(..2) else {
+            break;
+        };
+
+        let part = match code {
+            "Hw" => "human written",
+            "Mw" => "machine written",
+            "He" => "human edited",
+            "Me" => "machine edited",
+            "Hv" => "human verified",
+            "Mv" => "machine verified",
+            _ => break,
+        };
+        if count.is_empty() {
+            parts.push(part.to_string());
+        } else {
+            parts.push(format!("{part} ({count} times)"));
+        }
+
+        rest = &rest[digits + 2..];
+    }
+
+    format!("`{display}` ({})", parts.join(", "))
+}
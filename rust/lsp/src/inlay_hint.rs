@@ -0,0 +1,123 @@
+//! Handling of inlay hint related messages
+//!
+//! See https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#textDocument_inlayHint
+
+use std::{
+    hash::{Hash, Hasher},
+    sync::Arc,
+};
+
+use async_lsp::{
+    lsp_types::{InlayHint, InlayHintLabel, Position, Range},
+    ResponseError,
+};
+
+use common::{itertools::Itertools, seahash::SeaHasher, tokio::sync::RwLock};
+use schema::NodeType;
+
+use crate::text_document::{TextNode, TextNodeExecution};
+
+/// Handle a request for the inlay hints within a range of a document
+///
+/// Shows the value of code expressions after them and, at the end of the first line
+/// of other executable nodes, their execution count and duration and, for code chunks,
+/// the first line of their first output.
+pub(crate) async fn request(
+    range: Range,
+    source: Arc<RwLock<String>>,
+    root: Arc<RwLock<TextNode>>,
+) -> Result<Option<Vec<InlayHint>>, ResponseError> {
+    let line_lengths = source
+        .read()
+        .await
+        .lines()
+        .map(|line| line.encode_utf16().count() as u32)
+        .collect_vec();
+
+    let root = root.read().await;
+    let hints = root
+        .flatten()
+        .filter(|node| node.range.start <= range.end && node.range.end >= range.start)
+        .filter_map(|node| Some((node, node.execution.as_ref()?)))
+        .flat_map(|(node, execution)| hints(node, execution, &line_lengths))
+        .collect_vec();
+
+    Ok(Some(hints))
+}
+
+/// Calculate a digest of the properties of nodes that inlay hints are derived from
+///
+/// Used to avoid asking the client to refresh inlay hints when they have not changed.
+pub(crate) fn digest(root: &TextNode) -> u64 {
+    let mut hash = SeaHasher::new();
+    for node in root.flatten() {
+        let Some(execution) = &node.execution else {
+            continue;
+        };
+        node.range.start.line.hash(&mut hash);
+        node.range.end.line.hash(&mut hash);
+        node.range.end.character.hash(&mut hash);
+        execution.count.hash(&mut hash);
+        execution
+            .duration
+            .as_ref()
+            .map(|duration| duration.humanize(true))
+            .hash(&mut hash);
+        execution.output.hash(&mut hash);
+    }
+    hash.finish()
+}
+
+/// Create the inlay hints for an executable node
+fn hints(node: &TextNode, execution: &TextNodeExecution, line_lengths: &[u32]) -> Vec<InlayHint> {
+    let hint = |position: Position, label: String| InlayHint {
+        position,
+        label: InlayHintLabel::String(label),
+        kind: None,
+        text_edits: None,
+        tooltip: None,
+        padding_left: Some(true),
+        padding_right: None,
+        data: None,
+    };
+
+    // The values of code expressions are shown directly after them
+    if matches!(node.node_type, NodeType::CodeExpression) {
+        return execution
+            .output
+            .as_ref()
+            .map(|output| hint(node.range.end, format!("= {output}")))
+            .into_iter()
+            .collect();
+    }
+
+    // Other hints are shown at the end of the first line of the node
+    let line = node.range.start.line;
+    let Some(length) = line_lengths.get(line as usize) else {
+        return Vec::new();
+    };
+    let position = Position::new(line, *length);
+
+    let mut hints = Vec::new();
+
+    let stats = execution
+        .count
+        .map(|count| format!("#{count}"))
+        .into_iter()
+        .chain(
+            execution
+                .duration
+                .as_ref()
+                .map(|duration| duration.humanize(true)),
+        )
+        .join(" · ");
+    if !stats.is_empty() {
+        hints.push(hint(position, stats));
+    }
+
+    if let Some(output) = &execution.output {
+        hints.push(hint(position, format!("→ {output}")));
+    }
+
+    hints
+}
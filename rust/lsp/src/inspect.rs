@@ -406,7 +406,9 @@ impl Inspect for CodeChunk {
             required: self.options.execution_required.clone(),
             duration: self.options.execution_duration.clone(),
             ended: self.options.execution_ended.clone(),
+            count: self.options.execution_count,
             outputs: self.outputs.as_ref().map(|outputs| outputs.len()),
+            output: self.outputs.iter().flatten().next().and_then(output_hint),
             messages: self.options.execution_messages.clone(),
             ..Default::default()
         });
@@ -619,6 +621,7 @@ macro_rules! executable {
                     required: self.options.execution_required.clone(),
                     duration: self.options.execution_duration.clone(),
                     ended: self.options.execution_ended.clone(),
+                    count: self.options.execution_count,
                     messages: self.options.execution_messages.clone(),
                     ..Default::default()
                 });
@@ -637,35 +640,80 @@ executable!(
     IfBlock,
     IncludeBlock,
     Parameter,
-    InstructionBlock,
     InstructionInline
 );
 
-/// Implementation for executable nodes with provenance
-macro_rules! executable_with_provenance {
-    ($( $type:ident ),*) => {
-        $(impl Inspect for $type {
-            fn inspect(&self, inspector: &mut Inspector) {
-                // eprintln!("INSPECT EXEC PROV {}", self.node_id());
+impl Inspect for InstructionBlock {
+    fn inspect(&self, inspector: &mut Inspector) {
+        let execution = Some(TextNodeExecution {
+            mode: self.execution_mode.clone(),
+            status: self.options.execution_status.clone(),
+            required: self.options.execution_required.clone(),
+            duration: self.options.execution_duration.clone(),
+            ended: self.options.execution_ended.clone(),
+            count: self.options.execution_count,
+            messages: self.options.execution_messages.clone(),
+            ..Default::default()
+        });
 
-                let execution =  Some(TextNodeExecution{
-                    mode: self.execution_mode.clone(),
-                    status: self.options.execution_status.clone(),
-                    required: self.options.execution_required.clone(),
-                    duration: self.options.execution_duration.clone(),
-                    ended: self.options.execution_ended.clone(),
-                    messages: self.options.execution_messages.clone(),
-                    ..Default::default()
-                });
+        // Use the instruction type as the detail so that, for example,
+        // code lenses specific to chats can be provided
+        let detail = Some(self.instruction_type.to_string().to_lowercase());
 
-                let provenance = self.provenance.clone();
+        inspector.enter_node(
+            self.node_type(),
+            self.node_id(),
+            None,
+            detail,
+            execution,
+            None,
+        );
+        inspector.visit(self);
+        inspector.exit_node();
+    }
+}
 
-                inspector.enter_node(self.node_type(), self.node_id(), None, None, execution, provenance);
-                inspector.visit(self);
-                inspector.exit_node();
-            }
-        })*
-    };
+impl Inspect for CodeExpression {
+    fn inspect(&self, inspector: &mut Inspector) {
+        let execution = Some(TextNodeExecution {
+            mode: self.execution_mode.clone(),
+            status: self.options.execution_status.clone(),
+            required: self.options.execution_required.clone(),
+            duration: self.options.execution_duration.clone(),
+            ended: self.options.execution_ended.clone(),
+            count: self.options.execution_count,
+            output: self.output.as_deref().and_then(output_hint),
+            messages: self.options.execution_messages.clone(),
+            ..Default::default()
+        });
+
+        let provenance = self.provenance.clone();
+
+        inspector.enter_node(
+            self.node_type(),
+            self.node_id(),
+            None,
+            None,
+            execution,
+            provenance,
+        );
+        inspector.visit(self);
+        inspector.exit_node();
+    }
 }
 
-executable_with_provenance!(CodeExpression);
+/// Get a hint of an output of an executable node
+///
+/// Returns the first non-blank line of the text of the output (if any),
+/// truncated so that it is not too long to be shown inline.
+fn output_hint(output: &Node) -> Option<String> {
+    const MAX_CHARS: usize = 60;
+
+    let text = output.to_text().0;
+    let line = text.lines().map(str::trim).find(|line| !line.is_empty())?;
+
+    Some(match line.char_indices().nth(MAX_CHARS) {
+        Some((index, ..)) => [&line[..index], "…"].concat(),
+        None => line.to_string(),
+    })
+}
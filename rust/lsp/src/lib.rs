@@ -1,25 +1,35 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, path::PathBuf};
 
 use async_lsp::{lsp_types::Url, ClientSocket};
 
 use common::serde::Deserialize;
 
+mod code_action;
 mod code_lens;
 mod commands;
 mod completion;
 mod content;
+mod definition;
 mod diagnostics;
+mod folding_range;
 mod formatting;
+mod hover;
+mod inlay_hint;
 mod inspect;
 mod lifecycle;
+mod references;
+mod rename;
 mod run;
+mod semantic_tokens;
 mod symbols;
 mod text_document;
 mod utils;
+mod workspace;
 
 pub use run::run;
 use schema::Person;
 use text_document::TextDocument;
+use workspace::{OpenedDocuments, WorkspaceIndex};
 
 /// The state of the language server
 pub(crate) struct ServerState {
@@ -33,6 +43,18 @@ pub(crate) struct ServerState {
 
     /// The documents opened by the client that are handled by this server
     documents: HashMap<Url, TextDocument>,
+
+    /// The URIs of the documents opened by the client
+    ///
+    /// Shared with workspace indexing tasks which need to know which documents are
+    /// open but which do not have access to `documents`.
+    opened: OpenedDocuments,
+
+    /// The folders of the workspace opened by the client
+    workspace_folders: Vec<PathBuf>,
+
+    /// An index of the symbols in the documents in the workspace
+    workspace: WorkspaceIndex,
 }
 
 #[derive(Deserialize)]
@@ -6,17 +6,20 @@ use std::ops::ControlFlow;
 
 use async_lsp::{
     lsp_types::{
-        CodeLensOptions, CompletionOptions, DocumentSymbolOptions, ExecuteCommandOptions,
-        InitializeResult, InitializedParams, MessageType, OneOf, ServerCapabilities, ServerInfo,
-        ShowMessageParams, TextDocumentSyncCapability, TextDocumentSyncKind,
-        WorkDoneProgressOptions,
+        CodeActionKind, CodeActionOptions, CodeActionProviderCapability, CodeLensOptions,
+        CompletionOptions, DocumentSymbolOptions, ExecuteCommandOptions,
+        FoldingRangeProviderCapability, HoverProviderCapability, InitializeResult,
+        InitializedParams, MessageType, OneOf, SemanticTokensFullOptions, SemanticTokensOptions,
+        SemanticTokensServerCapabilities, ServerCapabilities, ServerInfo, ShowMessageParams,
+        TextDocumentSyncCapability, TextDocumentSyncKind, Url, WorkDoneProgressOptions,
+        WorkspaceFolder,
     },
     Error, LanguageClient, ResponseError,
 };
 
-use common::serde_json;
+use common::{itertools::Itertools, serde_json, tokio};
 
-use crate::{commands, ServerState};
+use crate::{commands, semantic_tokens, workspace, ServerState};
 
 pub const STENCILA_VERSION: &str = env!("CARGO_PKG_VERSION");
 
@@ -36,6 +39,26 @@ pub(super) fn initialize_options(state: &mut ServerState, options: serde_json::V
     }
 }
 
+/// Initialize the workspace folders
+///
+/// Falls back to the deprecated `root_uri` if the client does not provide
+/// workspace folders.
+pub(super) fn initialize_workspace(
+    state: &mut ServerState,
+    folders: Option<Vec<WorkspaceFolder>>,
+    root_uri: Option<Url>,
+) {
+    let uris = match folders {
+        Some(folders) => folders.into_iter().map(|folder| folder.uri).collect(),
+        None => root_uri.into_iter().collect_vec(),
+    };
+
+    state.workspace_folders = uris
+        .into_iter()
+        .filter_map(|uri| uri.to_file_path().ok())
+        .collect();
+}
+
 /// Initialize the language server and respond with its capabilities
 pub(super) async fn initialize() -> Result<InitializeResult, ResponseError> {
     Ok(InitializeResult {
@@ -44,17 +67,39 @@ pub(super) async fn initialize() -> Result<InitializeResult, ResponseError> {
             version: Some(STENCILA_VERSION.to_string()),
         }),
         capabilities: ServerCapabilities {
-            text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+            text_document_sync: Some(TextDocumentSyncCapability::Kind(
+                TextDocumentSyncKind::INCREMENTAL,
+            )),
             document_symbol_provider: Some(OneOf::Right(DocumentSymbolOptions {
                 label: Some("Nodes".to_string()),
                 work_done_progress_options: WorkDoneProgressOptions {
                     work_done_progress: None,
                 },
             })),
+            semantic_tokens_provider: Some(
+                SemanticTokensServerCapabilities::SemanticTokensOptions(SemanticTokensOptions {
+                    legend: semantic_tokens::legend(),
+                    full: Some(SemanticTokensFullOptions::Bool(true)),
+                    ..Default::default()
+                }),
+            ),
+            folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+            hover_provider: Some(HoverProviderCapability::Simple(true)),
+            inlay_hint_provider: Some(OneOf::Left(true)),
+            definition_provider: Some(OneOf::Left(true)),
+            references_provider: Some(OneOf::Left(true)),
+            rename_provider: Some(OneOf::Left(true)),
             completion_provider: Some(CompletionOptions {
-                trigger_characters: Some(vec!["@".to_string()]),
+                trigger_characters: Some(vec!["@".to_string(), "#".to_string(), "/".to_string()]),
                 ..Default::default()
             }),
+            code_action_provider: Some(CodeActionProviderCapability::Options(CodeActionOptions {
+                code_action_kinds: Some(vec![
+                    CodeActionKind::QUICKFIX,
+                    CodeActionKind::REFACTOR_REWRITE,
+                ]),
+                ..Default::default()
+            })),
             code_lens_provider: Some(CodeLensOptions {
                 resolve_provider: Some(true),
             }),
@@ -63,15 +108,28 @@ pub(super) async fn initialize() -> Result<InitializeResult, ResponseError> {
                 ..Default::default()
             }),
             document_formatting_provider: Some(OneOf::Left(true)),
+            workspace_symbol_provider: Some(OneOf::Left(true)),
             ..ServerCapabilities::default()
         },
     })
 }
 
 /// Handle the notification from the client that the connection has been initialized
+///
+/// Registers watchers for changes to documents and starts indexing the documents
+/// in the workspace.
 pub(super) fn initialized(
-    _state: &mut ServerState,
+    state: &mut ServerState,
     _params: InitializedParams,
 ) -> ControlFlow<Result<(), Error>> {
+    let folders = state.workspace_folders.clone();
+    let opened = state.opened.clone();
+    let index = state.workspace.clone();
+    let client = state.client.clone();
+    tokio::spawn(async move {
+        workspace::register_watchers(client.clone()).await;
+        workspace::index_folders(folders, opened, index, client).await
+    });
+
     ControlFlow::Continue(())
 }
@@ -0,0 +1,71 @@
+//! Handling of references related messages
+//!
+//! See https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#textDocument_references
+
+use std::sync::Arc;
+
+use async_lsp::{
+    lsp_types::{Location, Position, Url},
+    ResponseError,
+};
+
+use common::{itertools::Itertools, tokio::sync::RwLock};
+use document::Document;
+
+use crate::{
+    definition::{reference_range, Index, Symbol},
+    text_document::TextNode,
+};
+
+/// Handle a request for the references to the symbol at a position
+///
+/// Provides the code using a variable, the links to a labelled figure or table,
+/// and the citations of a reference. The definitions of these are also included
+/// if `include_declaration` is true.
+pub(crate) async fn request(
+    uri: Url,
+    position: Position,
+    include_declaration: bool,
+    source: Arc<RwLock<String>>,
+    root: Arc<RwLock<TextNode>>,
+    doc: Arc<RwLock<Document>>,
+) -> Result<Option<Vec<Location>>, ResponseError> {
+    let index = Index::new(&doc).await;
+    let source = source.read().await;
+    let root = root.read().await;
+
+    let Some(symbol) = Symbol::at(position, &source, &root, &index) else {
+        return Ok(None);
+    };
+
+    let (declarations, references) = match &symbol {
+        Symbol::Variable(name) => {
+            let language = index.language_at(position, &root);
+            (
+                index.definitions(name, &root),
+                index.uses(name, language.as_deref(), &root),
+            )
+        }
+        Symbol::Label(target) => (
+            index.label(target, &root).into_iter().collect_vec(),
+            index.links(target, &root),
+        ),
+        Symbol::Reference(target) => (
+            reference_range(target, &source).into_iter().collect_vec(),
+            index.cites(target, &root),
+        ),
+    };
+
+    let locations = if include_declaration {
+        declarations.into_iter().chain(references).collect_vec()
+    } else {
+        references
+    }
+    .into_iter()
+    .sorted_by_key(|range| range.start)
+    .dedup()
+    .map(|range| Location::new(uri.clone(), range))
+    .collect_vec();
+
+    Ok((!locations.is_empty()).then_some(locations))
+}
@@ -0,0 +1,487 @@
+//! Handling of rename related messages
+//!
+//! See https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#textDocument_rename
+
+use std::{collections::HashMap, sync::Arc};
+
+use async_lsp::{
+    lsp_types::{Position, Range, TextEdit, Url, WorkspaceEdit},
+    ErrorCode, ResponseError,
+};
+
+use codecs::Positions;
+use common::{
+    itertools::Itertools,
+    once_cell::sync::Lazy,
+    regex::{self, Regex},
+    tokio::sync::RwLock,
+};
+use document::Document;
+use schema::{NodeId, NodeType};
+
+use crate::{
+    definition::{identifier_offsets, reference_range, same_language, Index, Symbol},
+    text_document::TextNode,
+    utils::{position16_to_position, position_to_position16},
+};
+
+/// Handle a request to rename the symbol at a position
+///
+/// Renames variables (in code chunks, code expressions, and the code of `if` and `for` blocks,
+/// as well as parameters), the labels of figures, tables and code chunks (and cross-references to
+/// them), and the ids of references (and citations of them).
+///
+/// Variables are renamed wherever the identifier occurs in code (other than in string literals
+/// and attributes) in the same programming language as the code being renamed from. Labels which
+/// are automatically generated are not in the source and so can not be renamed.
+pub(crate) async fn request(
+    uri: Url,
+    position: Position,
+    new_name: String,
+    source: Arc<RwLock<String>>,
+    root: Arc<RwLock<TextNode>>,
+    doc: Arc<RwLock<Document>>,
+) -> Result<Option<WorkspaceEdit>, ResponseError> {
+    let index = Index::new(&doc).await;
+    let source = source.read().await;
+    let root = root.read().await;
+
+    let Some(symbol) = Symbol::at(position, &source, &root, &index) else {
+        return Ok(None);
+    };
+
+    let edits = rename(&symbol, position, &new_name, &index, &root, &source)?;
+    if edits.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(WorkspaceEdit {
+        changes: Some(HashMap::from([(uri, edits)])),
+        ..Default::default()
+    }))
+}
+
+/// Create the edits to rename a symbol
+fn rename(
+    symbol: &Symbol,
+    position: Position,
+    new_name: &str,
+    index: &Index,
+    root: &TextNode,
+    source: &str,
+) -> Result<Vec<TextEdit>, ResponseError> {
+    let text = SourceText::new(source);
+
+    let edits = match symbol {
+        Symbol::Variable(name) => {
+            static IDENTIFIER: Lazy<Regex> =
+                Lazy::new(|| Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*$").expect("invalid regex"));
+            if !IDENTIFIER.is_match(new_name) {
+                return Err(invalid_name(new_name));
+            }
+
+            let language = index.language_at(position, root);
+            rename_variable(name, new_name, language.as_deref(), index, root, &text)
+        }
+        Symbol::Label(target) => {
+            static LABEL: Lazy<Regex> =
+                Lazy::new(|| Regex::new(r"^[\w\-]+$").expect("invalid regex"));
+            if !LABEL.is_match(new_name) {
+                return Err(invalid_name(new_name));
+            }
+
+            rename_label(target, new_name, index, root, &text)?
+        }
+        Symbol::Reference(target) => {
+            static ID: Lazy<Regex> =
+                Lazy::new(|| Regex::new(r"^[A-Za-z0-9_\-]+$").expect("invalid regex"));
+            if !ID.is_match(new_name) {
+                return Err(invalid_name(new_name));
+            }
+
+            rename_reference(target, new_name, index, root, &text)
+        }
+    };
+
+    Ok(edits
+        .into_iter()
+        .unique_by(|edit| edit.range.start)
+        .collect())
+}
+
+/// Create an error for an invalid new name
+fn invalid_name(name: &str) -> ResponseError {
+    ResponseError::new(
+        ErrorCode::INVALID_PARAMS,
+        format!("`{name}` is not a valid name for this symbol"),
+    )
+}
+
+/// Rename a variable in code and parameters
+///
+/// If `language` is `None` (e.g. when renaming a parameter) then the variable is
+/// renamed in code of any language.
+fn rename_variable(
+    name: &str,
+    new_name: &str,
+    language: Option<&str>,
+    index: &Index,
+    root: &TextNode,
+    text: &SourceText,
+) -> Vec<TextEdit> {
+    let mut edits = Vec::new();
+
+    for (node_id, node_language, code) in &index.code {
+        if !same_language(language, node_language) {
+            continue;
+        }
+
+        let Some(node) = find_node(root, node_id) else {
+            continue;
+        };
+
+        // Get the start index, in the source, of the code of the node.
+        let start = match node.node_type {
+            // For `if` and `for` blocks the code is on the first line of the node
+            // and is searched in full to include the variable of `for` blocks
+            NodeType::IfBlockClause | NodeType::ForBlock => {
+                let first_line = text.slice(Range::new(
+                    node.range.start,
+                    Position::new(node.range.start.line + 1, 0),
+                ));
+                let offset = text.index(node.range.start);
+                edits.append(&mut text.replace_identifier(&first_line, offset, name, new_name));
+                continue;
+            }
+            // For code chunks and expressions, search for the code within the node
+            // (the code may be preceded by fences, backticks, etc)
+            _ => {
+                let node_text = text.slice(node.range);
+                let Some(byte_offset) = node_text.find(code.as_str()) else {
+                    continue;
+                };
+                text.index(node.range.start) + node_text[..byte_offset].chars().count()
+            }
+        };
+
+        edits.append(&mut text.replace_identifier(code, start, name, new_name));
+    }
+
+    for (parameter, node_id) in &index.parameters {
+        if parameter != name {
+            continue;
+        }
+        let Some(node) = find_node(root, node_id) else {
+            continue;
+        };
+
+        // Only rename the first occurrence (the name of the parameter)
+        let node_text = text.slice(node.range);
+        let start = text.index(node.range.start);
+        edits.extend(
+            text.replace_identifier(&node_text, start, name, new_name)
+                .into_iter()
+                .take(1),
+        );
+    }
+
+    edits
+}
+
+/// Rename the label of a figure, table, or code chunk, and links to it
+fn rename_label(
+    target: &str,
+    new_name: &str,
+    index: &Index,
+    root: &TextNode,
+    text: &SourceText,
+) -> Result<Vec<TextEdit>, ResponseError> {
+    let Some((_, node_id, name)) = index.labels.iter().find(|(label, ..)| label == target) else {
+        return Ok(Vec::new());
+    };
+
+    let Some(name) = name else {
+        return Err(ResponseError::new(
+            ErrorCode::INVALID_REQUEST,
+            "Automatically generated labels can not be renamed",
+        ));
+    };
+
+    // The new target keeps any prefix (e.g. `fig-`) of the current target
+    let new_target = [&target[..target.len().saturating_sub(name.len())], new_name].concat();
+
+    let mut edits = Vec::new();
+
+    // Rename the id or label on the first line of the node
+    if let Some(node) = find_node(root, node_id) {
+        let first_line = text.slice(Range::new(
+            node.range.start,
+            Position::new(node.range.start.line + 1, 0),
+        ));
+        let start = text.index(node.range.start);
+        edits.extend(
+            text.replace_word(&first_line, start, name, new_name)
+                .into_iter()
+                .take(1),
+        );
+    }
+
+    // Rename the target in links
+    for (link, node_id) in &index.links {
+        if link != target {
+            continue;
+        }
+        let Some(node) = find_node(root, node_id) else {
+            continue;
+        };
+
+        let node_text = text.slice(node.range);
+        let Some(byte_offset) = node_text.find(&["#", target].concat()) else {
+            continue;
+        };
+        let start = text.index(node.range.start) + node_text[..byte_offset].chars().count() + 1;
+        edits.push(text.edit(start, target, &new_target));
+    }
+
+    Ok(edits)
+}
+
+/// Rename the id of a reference, and citations of it
+fn rename_reference(
+    target: &str,
+    new_name: &str,
+    index: &Index,
+    root: &TextNode,
+    text: &SourceText,
+) -> Vec<TextEdit> {
+    let mut edits = Vec::new();
+
+    if let Some(range) = reference_range(target, text.source) {
+        let line = text.slice(range);
+        let start = text.index(range.start);
+        edits.extend(
+            text.replace_word(&line, start, target, new_name)
+                .into_iter()
+                .take(1),
+        );
+    }
+
+    for (cite, node_id) in &index.cites {
+        if cite != target {
+            continue;
+        }
+        let Some(node) = find_node(root, node_id) else {
+            continue;
+        };
+
+        let node_text = text.slice(node.range);
+        let start = text.index(node.range.start);
+        edits.extend(
+            text.replace_word(&node_text, start, target, new_name)
+                .into_iter()
+                .take(1),
+        );
+    }
+
+    edits
+}
+
+/// Find a node in the text document
+fn find_node<'root>(root: &'root TextNode, node_id: &NodeId) -> Option<&'root TextNode> {
+    root.flatten().find(|node| &node.node_id == node_id)
+}
+
+/// The source of a document with functions for translating between character indices and positions
+struct SourceText<'source> {
+    source: &'source str,
+    positions: Positions<'source>,
+}
+
+impl<'source> SourceText<'source> {
+    fn new(source: &'source str) -> Self {
+        Self {
+            source,
+            positions: Positions::new(source),
+        }
+    }
+
+    /// Get the character index of a position
+    fn index(&self, position: Position) -> usize {
+        self.positions
+            .index_at_position16(position_to_position16(position))
+            .unwrap_or_else(|| self.source.chars().count())
+    }
+
+    /// Get the text within a range
+    fn slice(&self, range: Range) -> String {
+        let start = self.index(range.start);
+        let end = self.index(range.end);
+        self.source
+            .chars()
+            .skip(start)
+            .take(end.saturating_sub(start))
+            .collect()
+    }
+
+    /// Create an edit replacing `old` starting at a character index with `new`
+    fn edit(&self, start: usize, old: &str, new: &str) -> TextEdit {
+        let end = start + old.chars().count();
+        TextEdit::new(
+            Range::new(
+                position16_to_position(self.positions.position16_at_index(start)),
+                position16_to_position(self.positions.position16_at_index(end)),
+            ),
+            new.to_string(),
+        )
+    }
+
+    /// Create edits replacing occurrences of a word within some text
+    ///
+    /// The `offset` is the character index of the start of the text within the source.
+    fn replace_word(&self, text: &str, offset: usize, old: &str, new: &str) -> Vec<TextEdit> {
+        let Ok(regex) = Regex::new(&format!(r"\b{}\b", regex::escape(old))) else {
+            return Vec::new();
+        };
+
+        regex
+            .find_iter(text)
+            .map(|found| self.edit(offset + text[..found.start()].chars().count(), old, new))
+            .collect()
+    }
+
+    /// Create edits replacing occurrences of an identifier within some code
+    fn replace_identifier(&self, code: &str, offset: usize, old: &str, new: &str) -> Vec<TextEdit> {
+        identifier_offsets(code, old)
+            .into_iter()
+            .map(|index| self.edit(offset + code[..index].chars().count(), old, new))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use schema::{Article, Block, CodeChunk, Cord, LabelType, Node, Visitor};
+
+    use super::*;
+
+    /// Create an index, and text nodes, for code chunks each occupying three lines
+    fn setup(chunks: Vec<CodeChunk>) -> (Index, TextNode) {
+        let children = chunks
+            .iter()
+            .enumerate()
+            .map(|(index, chunk)| {
+                let line = index as u32 * 4;
+                TextNode {
+                    range: Range::new(Position::new(line, 0), Position::new(line + 3, 0)),
+                    node_type: NodeType::CodeChunk,
+                    node_id: chunk.node_id(),
+                    ..Default::default()
+                }
+            })
+            .collect();
+
+        let article = Article::new(chunks.into_iter().map(Block::CodeChunk).collect());
+        let root = TextNode {
+            node_type: NodeType::Article,
+            node_id: article.node_id(),
+            children,
+            ..Default::default()
+        };
+
+        let mut index = Index::default();
+        index.visit(&Node::Article(article));
+
+        (index, root)
+    }
+
+    fn chunk(lang: &str, code: &str) -> CodeChunk {
+        CodeChunk {
+            programming_language: Some(lang.to_string()),
+            ..CodeChunk::new(Cord::from(code))
+        }
+    }
+
+    #[test]
+    fn variable_in_same_language() {
+        let source = "```python exec\nx = 1\n```\n\n```r exec\nx <- 2\n```\n\n```python exec\nprint('x', x)\n```\n";
+        let (index, root) = setup(vec![
+            chunk("python", "x = 1"),
+            chunk("r", "x <- 2"),
+            chunk("python", "print('x', x)"),
+        ]);
+
+        let edits = rename(
+            &Symbol::Variable("x".to_string()),
+            Position::new(1, 0),
+            "y",
+            &index,
+            &root,
+            source,
+        )
+        .expect("should rename");
+
+        // Renamed in the Python chunks (but not within the string) and not in the R chunk
+        let ranges = edits.iter().map(|edit| edit.range).collect_vec();
+        assert_eq!(
+            ranges,
+            vec![
+                Range::new(Position::new(1, 0), Position::new(1, 1)),
+                Range::new(Position::new(9, 11), Position::new(9, 12))
+            ]
+        );
+    }
+
+    #[test]
+    fn variable_in_multiline_code() {
+        let source = "```python exec\nx = 1\n```\n\n```python exec\nz = 3\nprint(x + z)\n```\n";
+        let (index, root) = setup(vec![
+            chunk("python", "x = 1"),
+            chunk("python", "z = 3\nprint(x + z)"),
+        ]);
+
+        let edits = rename(
+            &Symbol::Variable("x".to_string()),
+            Position::new(1, 0),
+            "a",
+            &index,
+            &root,
+            source,
+        )
+        .expect("should rename");
+
+        let ranges = edits.iter().map(|edit| edit.range).collect_vec();
+        assert_eq!(
+            ranges,
+            vec![
+                Range::new(Position::new(1, 0), Position::new(1, 1)),
+                Range::new(Position::new(6, 6), Position::new(6, 7))
+            ]
+        );
+
+        // The use of the variable in the multi-line code is also a reference to it
+        assert_eq!(
+            index.uses("x", Some("python"), &root),
+            vec![Range::new(Position::new(6, 6), Position::new(6, 7))]
+        );
+    }
+
+    #[test]
+    fn automatic_labels() {
+        let source = "```python exec\nplot()\n```\n";
+        let (index, root) = setup(vec![CodeChunk {
+            label_type: Some(LabelType::FigureLabel),
+            label: Some("1".to_string()),
+            ..chunk("python", "plot()")
+        }]);
+
+        let result = rename(
+            &Symbol::Label("fig-1".to_string()),
+            Position::new(0, 0),
+            "2",
+            &index,
+            &root,
+            source,
+        );
+        assert!(result.is_err());
+    }
+}
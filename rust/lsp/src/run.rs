@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, ops::ControlFlow};
 
 use async_lsp::lsp_types::{notification, request};
 use async_lsp::{
@@ -8,10 +8,12 @@ use async_lsp::{
 };
 use tower::ServiceBuilder;
 
-use common::{serde_json, tracing};
+use common::{serde_json, tokio, tracing};
 
 use crate::{
-    code_lens, commands, completion, content, formatting, lifecycle, symbols, text_document,
+    code_action, code_lens, commands, completion, content, definition, folding_range, formatting,
+    hover, inlay_hint, lifecycle, references, rename, semantic_tokens, symbols, text_document,
+    workspace::{self, OpenedDocuments, WorkspaceIndex},
     ServerState,
 };
 
@@ -21,7 +23,10 @@ pub async fn run() {
         let mut router = Router::new(ServerState {
             client: client.clone(),
             documents: HashMap::new(),
+            opened: OpenedDocuments::default(),
             options: None,
+            workspace_folders: Vec::new(),
+            workspace: WorkspaceIndex::default(),
         });
 
         router
@@ -29,6 +34,8 @@ pub async fn run() {
                 if let Some(options) = params.initialization_options {
                     lifecycle::initialize_options(state, options);
                 }
+                #[allow(deprecated)]
+                lifecycle::initialize_workspace(state, params.workspace_folders, params.root_uri);
                 async move { lifecycle::initialize().await }
             })
             .notification::<notification::Initialized>(lifecycle::initialized);
@@ -39,6 +46,16 @@ pub async fn run() {
             .notification::<notification::DidSaveTextDocument>(text_document::did_save)
             .notification::<notification::DidCloseTextDocument>(text_document::did_close);
 
+        router.notification::<notification::DidChangeWatchedFiles>(|state, params| {
+            let opened = state.opened.clone();
+            let index = state.workspace.clone();
+            let client = state.client.clone();
+            tokio::spawn(async move {
+                workspace::files_changed(params.changes, opened, index, client).await
+            });
+            ControlFlow::Continue(())
+        });
+
         router.request::<request::DocumentSymbolRequest, _>(|state, params| {
             let uri = params.text_document.uri;
             let root = state
@@ -53,13 +70,168 @@ pub async fn run() {
             }
         });
 
+        router.request::<request::WorkspaceSymbolRequest, _>(|state, params| {
+            let index = state.workspace.clone();
+            async move { workspace::symbols(params.query, index).await }
+        });
+
+        router.request::<request::SemanticTokensFullRequest, _>(|state, params| {
+            let uri = params.text_document.uri;
+            let text_doc = state
+                .documents
+                .get(&uri)
+                .map(|text_doc| (text_doc.source.clone(), text_doc.root.clone()));
+            async move {
+                match text_doc {
+                    Some((source, root)) => semantic_tokens::request(source, root).await,
+                    None => Ok(None),
+                }
+            }
+        });
+
+        router.request::<request::FoldingRangeRequest, _>(|state, params| {
+            let uri = params.text_document.uri;
+            let root = state
+                .documents
+                .get(&uri)
+                .map(|text_doc| text_doc.root.clone());
+            async move {
+                match root {
+                    Some(root) => folding_range::request(root).await,
+                    None => Ok(None),
+                }
+            }
+        });
+
+        router.request::<request::InlayHintRequest, _>(|state, params| {
+            let uri = params.text_document.uri;
+            let text_doc = state
+                .documents
+                .get(&uri)
+                .map(|text_doc| (text_doc.source.clone(), text_doc.root.clone()));
+            async move {
+                match text_doc {
+                    Some((source, root)) => inlay_hint::request(params.range, source, root).await,
+                    None => Ok(None),
+                }
+            }
+        });
+
+        router.request::<request::HoverRequest, _>(|state, params| {
+            let uri = &params.text_document_position_params.text_document.uri;
+            let position = params.text_document_position_params.position;
+            let text_doc = state.documents.get(uri).map(|text_doc| {
+                (
+                    text_doc.source.clone(),
+                    text_doc.generated.clone(),
+                    text_doc.root.clone(),
+                    text_doc.doc.clone(),
+                )
+            });
+            async move {
+                match text_doc {
+                    Some((source, generated, root, doc)) => {
+                        hover::request(position, source, generated, root, doc).await
+                    }
+                    None => Ok(None),
+                }
+            }
+        });
+
+        router.request::<request::GotoDefinition, _>(|state, params| {
+            let uri = params.text_document_position_params.text_document.uri;
+            let position = params.text_document_position_params.position;
+            let text_doc = state.documents.get(&uri).map(|text_doc| {
+                (
+                    text_doc.source.clone(),
+                    text_doc.root.clone(),
+                    text_doc.doc.clone(),
+                )
+            });
+            async move {
+                match text_doc {
+                    Some((source, root, doc)) => {
+                        definition::request(uri, position, source, root, doc).await
+                    }
+                    None => Ok(None),
+                }
+            }
+        });
+
+        router.request::<request::References, _>(|state, params| {
+            let uri = params.text_document_position.text_document.uri;
+            let position = params.text_document_position.position;
+            let include_declaration = params.context.include_declaration;
+            let text_doc = state.documents.get(&uri).map(|text_doc| {
+                (
+                    text_doc.source.clone(),
+                    text_doc.root.clone(),
+                    text_doc.doc.clone(),
+                )
+            });
+            async move {
+                match text_doc {
+                    Some((source, root, doc)) => {
+                        references::request(uri, position, include_declaration, source, root, doc)
+                            .await
+                    }
+                    None => Ok(None),
+                }
+            }
+        });
+
+        router.request::<request::Rename, _>(|state, params| {
+            let uri = params.text_document_position.text_document.uri;
+            let position = params.text_document_position.position;
+            let new_name = params.new_name;
+            let text_doc = state.documents.get(&uri).map(|text_doc| {
+                (
+                    text_doc.source.clone(),
+                    text_doc.root.clone(),
+                    text_doc.doc.clone(),
+                )
+            });
+            async move {
+                match text_doc {
+                    Some((source, root, doc)) => {
+                        rename::request(uri, position, new_name, source, root, doc).await
+                    }
+                    None => Ok(None),
+                }
+            }
+        });
+
         router.request::<request::Completion, _>(|state, params| {
             let uri = &params.text_document_position.text_document.uri;
-            let source = state
-                .documents
-                .get(uri)
-                .map(|text_doc| text_doc.source.clone());
-            async move { completion::request(params, source).await }
+            let (source, root, doc) = match state.documents.get(uri) {
+                Some(text_doc) => (
+                    Some(text_doc.source.clone()),
+                    Some(text_doc.root.clone()),
+                    Some(text_doc.doc.clone()),
+                ),
+                None => (None, None, None),
+            };
+            async move { completion::request(params, source, root, doc).await }
+        });
+
+        router.request::<request::CodeActionRequest, _>(|state, params| {
+            let uri = &params.text_document.uri;
+            let text_doc = state.documents.get(uri).map(|text_doc| {
+                (
+                    text_doc.format.clone(),
+                    text_doc.source.clone(),
+                    text_doc.root.clone(),
+                    text_doc.doc.clone(),
+                )
+            });
+            async move {
+                match text_doc {
+                    Some((format, source, root, doc)) => {
+                        code_action::request(params, format, source, root, doc).await
+                    }
+                    None => Ok(None),
+                }
+            }
         });
 
         router
@@ -0,0 +1,174 @@
+//! Handling of semantic tokens related messages
+//!
+//! See https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#textDocument_semanticTokens
+
+use std::sync::Arc;
+
+use async_lsp::{
+    lsp_types::{
+        Range, SemanticToken, SemanticTokenModifier, SemanticTokenType, SemanticTokens,
+        SemanticTokensLegend, SemanticTokensResult,
+    },
+    ResponseError,
+};
+
+use common::{itertools::Itertools, tokio::sync::RwLock};
+use schema::{ExecutionRequired, ExecutionStatus, NodeType};
+
+use crate::text_document::{TextNode, TextNodeExecution};
+
+/// The types of semantic tokens
+///
+/// The index of each type in this list is used to encode tokens so the
+/// order must not be changed without changing `token_type` below.
+const TOKEN_TYPES: &[&str] = &[
+    "keyword",
+    "function",
+    "macro",
+    "variable",
+    // Custom token type for suggestions
+    "suggestion",
+];
+
+/// The modifiers of semantic tokens
+///
+/// As for `TOKEN_TYPES`, the index of each modifier is used to encode
+/// tokens (as a bit set).
+const TOKEN_MODIFIERS: &[&str] = &[
+    // Custom modifiers for the execution state of executable nodes
+    "fresh", "stale", "running", "failed",
+];
+
+/// Get the legend of semantic tokens types and modifiers
+pub(super) fn legend() -> SemanticTokensLegend {
+    SemanticTokensLegend {
+        token_types: TOKEN_TYPES
+            .iter()
+            .map(|name| SemanticTokenType::new(*name))
+            .collect(),
+        token_modifiers: TOKEN_MODIFIERS
+            .iter()
+            .map(|name| SemanticTokenModifier::new(*name))
+            .collect(),
+    }
+}
+
+/// Handle a request for the semantic tokens of a whole document
+///
+/// Tokens are created for executable nodes, instructions and suggestions. For
+/// block nodes, only the first line of the node (e.g. the line with the opening fence
+/// of a code chunk) is tokenized so that the highlighting of its content (e.g. by the
+/// editor's grammar for the programming language) is not overridden. For inline nodes,
+/// the whole of the first line of the node is tokenized.
+pub(crate) async fn request(
+    source: Arc<RwLock<String>>,
+    root: Arc<RwLock<TextNode>>,
+) -> Result<Option<SemanticTokensResult>, ResponseError> {
+    let line_lengths = source
+        .read()
+        .await
+        .lines()
+        .map(|line| line.encode_utf16().count() as u32)
+        .collect_vec();
+
+    let root = root.read().await;
+    let tokens = root
+        .flatten()
+        .filter_map(|node| {
+            let token_type = token_type(&node.node_type)?;
+            let token_modifiers = node
+                .execution
+                .as_ref()
+                .map(token_modifiers)
+                .unwrap_or_default();
+
+            let Range { start, end } = node.range;
+            let line_length = line_lengths.get(start.line as usize).copied()?;
+            let end = if end.line == start.line {
+                end.character.min(line_length)
+            } else {
+                line_length
+            };
+            let length = end.checked_sub(start.character)?;
+
+            (length > 0).then_some((start, length, token_type, token_modifiers))
+        })
+        .sorted_by_key(|(start, ..)| *start)
+        .collect_vec();
+
+    // Encode the tokens relative to each other, skipping any that overlap with the
+    // previous token (e.g. a code expression within an inline instruction) since
+    // overlapping tokens are not supported by most clients
+    let mut data = Vec::with_capacity(tokens.len());
+    let mut prev_line = 0;
+    let mut prev_start = 0;
+    let mut prev_end = None;
+    for (start, length, token_type, token_modifiers_bitset) in tokens {
+        if let Some((line, character)) = prev_end {
+            if start.line == line && start.character < character {
+                continue;
+            }
+        }
+
+        let delta_line = start.line - prev_line;
+        let delta_start = if delta_line == 0 {
+            start.character - prev_start
+        } else {
+            start.character
+        };
+
+        data.push(SemanticToken {
+            delta_line,
+            delta_start,
+            length,
+            token_type,
+            token_modifiers_bitset,
+        });
+
+        prev_line = start.line;
+        prev_start = start.character;
+        prev_end = Some((start.line, start.character + length));
+    }
+
+    Ok(Some(SemanticTokensResult::Tokens(SemanticTokens {
+        result_id: None,
+        data,
+    })))
+}
+
+/// Get the index of the token type for a node type (if any)
+fn token_type(node_type: &NodeType) -> Option<u32> {
+    use NodeType::*;
+    Some(match node_type {
+        CallBlock | ForBlock | IfBlock | IfBlockClause | IncludeBlock => 0,
+        CodeChunk | CodeExpression | Button => 1,
+        InstructionBlock | InstructionInline => 2,
+        Parameter => 3,
+        SuggestionBlock | SuggestionInline | InsertBlock | InsertInline | DeleteBlock
+        | DeleteInline | ReplaceBlock | ReplaceInline | ModifyBlock | ModifyInline => 4,
+        _ => return None,
+    })
+}
+
+/// Get the bit set of token modifiers for the execution state of a node
+fn token_modifiers(execution: &TextNodeExecution) -> u32 {
+    let index = if matches!(
+        execution.status,
+        Some(ExecutionStatus::Scheduled | ExecutionStatus::Pending | ExecutionStatus::Running)
+    ) {
+        2
+    } else if matches!(
+        execution.status,
+        Some(ExecutionStatus::Errors | ExecutionStatus::Exceptions)
+    ) {
+        3
+    } else {
+        match execution.required {
+            Some(ExecutionRequired::No) => 0,
+            Some(..) => 1,
+            None => return 0,
+        }
+    };
+
+    1 << index
+}
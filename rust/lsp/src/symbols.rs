@@ -24,10 +24,12 @@ pub(crate) async fn request(
     Ok(Some(DocumentSymbolResponse::Nested(symbols)))
 }
 
-/// Create a [`DocumentSymbol`] for a [`TextNode`]
-fn symbol(node: &TextNode) -> Option<DocumentSymbol> {
+/// Get the [`SymbolKind`] for a [`NodeType`]
+///
+/// Returns `None` for node types that should not have symbols.
+pub(super) fn kind(node_type: &NodeType) -> Option<SymbolKind> {
     use NodeType::*;
-    let kind = match node.node_type {
+    Some(match node_type {
         // Primitive node types
         Null => SymbolKind::NULL,
         Boolean => SymbolKind::BOOLEAN,
@@ -56,7 +58,12 @@ fn symbol(node: &TextNode) -> Option<DocumentSymbol> {
         TableCell | Text => return None,
 
         _ => SymbolKind::CONSTRUCTOR,
-    };
+    })
+}
+
+/// Create a [`DocumentSymbol`] for a [`TextNode`]
+fn symbol(node: &TextNode) -> Option<DocumentSymbol> {
+    let kind = kind(&node.node_type)?;
 
     let name = node.name.clone();
 
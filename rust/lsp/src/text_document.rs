@@ -3,33 +3,45 @@
 //! See https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#textDocument_synchronization
 
 use core::time;
-use std::{ops::ControlFlow, path::PathBuf, sync::Arc};
+use std::{
+    collections::HashMap,
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
 
 use async_lsp::{
     lsp_types::{
         DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
-        DidSaveTextDocumentParams, Position, Range, Url,
+        DidSaveTextDocumentParams, Position, Range, TextDocumentContentChangeEvent, Url,
     },
     ClientSocket, Error, ErrorCode, LanguageClient, ResponseError,
 };
 
-use codecs::{DecodeOptions, EncodeInfo, EncodeOptions, Format};
+use codecs::{DecodeOptions, EncodeInfo, EncodeOptions, Format, Mapping, Positions};
 use common::{
-    eyre::{bail, Report},
+    eyre::{bail, Report, Result},
+    itertools::Itertools,
     tokio::{
         self,
         sync::{mpsc, watch, RwLock},
     },
     tracing,
 };
-use document::Document;
+use document::{Command, Document};
 use schema::{
-    Author, AuthorRole, AuthorRoleName, Duration, ExecutionMessage, ExecutionMode,
-    ExecutionRequired, ExecutionStatus, Node, NodeId, NodeType, Person, ProvenanceCount, Timestamp,
-    Visitor,
+    Author, AuthorRole, AuthorRoleName, Block, Duration, ExecutionMessage, ExecutionMode,
+    ExecutionRequired, ExecutionStatus, Integer, Node, NodeId, NodeProperty, NodeType, Patch,
+    PatchOp, PatchPath, PatchSlot, PatchValue, Person, ProvenanceCount, Timestamp, Visitor,
 };
 
-use crate::{diagnostics, inspect::Inspector, ServerState};
+use crate::{
+    diagnostics, inlay_hint, inspect::Inspector, utils::position_to_position16, workspace,
+    ServerState,
+};
 
 /// A Stencila `Node` within a `TextDocument`
 ///
@@ -44,7 +56,6 @@ pub(super) struct TextNode {
     pub parent_type: NodeType,
 
     /// The id of the parent of the node
-    #[allow(unused)]
     pub parent_id: NodeId,
 
     /// The type of the node
@@ -91,7 +102,9 @@ pub(super) struct TextNodeExecution {
     pub required: Option<ExecutionRequired>,
     pub duration: Option<Duration>,
     pub ended: Option<Timestamp>,
+    pub count: Option<Integer>,
     pub outputs: Option<usize>,
+    pub output: Option<String>,
     pub messages: Option<Vec<ExecutionMessage>>,
     pub authors: Option<Vec<Author>>,
 }
@@ -144,17 +157,22 @@ impl<'a> Iterator for TextNodeIterator<'a> {
 impl TextNode {
     /// Get the node id at a position (if any)
     pub fn node_id_at(&self, position: Position) -> Option<NodeId> {
+        self.node_at(position).map(|node| node.node_id.clone())
+    }
+
+    /// Get the node at a position (if any)
+    pub fn node_at(&self, position: Position) -> Option<&TextNode> {
         // Search through children (and thus recursively through all
         // descendants so that the deepest (most narrow range) node is selected)
         for child in &self.children {
-            if let Some(node_id) = child.node_id_at(position) {
-                return Some(node_id);
+            if let Some(node) = child.node_at(position) {
+                return Some(node);
             }
         }
 
         // If no descendants in range then check if this is
         if position >= self.range.start && position < self.range.end {
-            return Some(self.node_id.clone());
+            return Some(self);
         }
 
         None
@@ -172,13 +190,27 @@ pub(super) struct TextDocument {
     pub format: Format,
 
     /// The source text of the document e.g. Markdown
+    ///
+    /// This is updated in the `update_task` (i.e. is debounced).
     pub source: Arc<RwLock<String>>,
 
+    /// The latest text of the document
+    ///
+    /// Incremental changes from the client are applied to this on each
+    /// change before it is sent to the `update_task`.
+    text: String,
+
     /// The root node in the text document
     ///
     /// This is updated in the `update_task`.
     pub root: Arc<RwLock<TextNode>>,
 
+    /// The content generated by encoding the Stencila document, and its mapping
+    ///
+    /// Used, along with `source`, to get the authorship of content at a position.
+    /// This is updated in the `watch_task`.
+    pub generated: Arc<RwLock<(String, Mapping)>>,
+
     /// The Stencila document for the text document
     ///
     /// This is also updated in the `update_task`.
@@ -204,7 +236,7 @@ impl TextDocument {
         let Some(home) = path.parent() else {
             bail!("File does not have a parent dir")
         };
-        let doc = Document::init(home.into(), None)?;
+        let doc = Document::init(home.into(), None, Some(path.clone()))?;
 
         let person = user.unwrap_or_else(|| Person {
             given_names: Some(vec!["Anonymous".to_string()]),
@@ -219,20 +251,36 @@ impl TextDocument {
 
         let watch_receiver = doc.watch();
 
-        let source_string = source.clone();
+        let text = source.clone();
 
-        let source = Arc::new(RwLock::new(source));
+        let source = Arc::new(RwLock::new(text.clone()));
         let root = Arc::new(RwLock::new(TextNode::default()));
+        // The root is not derived from any version of the source until the first update
+        let root_version = Arc::new(AtomicU64::new(u64::MAX));
+        let generated = Arc::new(RwLock::new((String::new(), Mapping::none())));
         let doc = Arc::new(RwLock::new(doc));
 
         let (update_sender, update_receiver) = mpsc::unbounded_channel();
+        let (applied_sender, applied_receiver) = watch::channel(0);
 
         {
             let format = format.clone();
             let source = source.clone();
+            let root = root.clone();
+            let root_version = root_version.clone();
             let doc = doc.clone();
             tokio::spawn(async {
-                Self::update_task(update_receiver, format, source, doc, author_role).await;
+                Self::update_task(
+                    update_receiver,
+                    format,
+                    source,
+                    root,
+                    root_version,
+                    applied_sender,
+                    doc,
+                    author_role,
+                )
+                .await;
             });
         }
 
@@ -240,19 +288,33 @@ impl TextDocument {
             let format = format.clone();
             let source = source.clone();
             let root = root.clone();
+            let generated = generated.clone();
             tokio::spawn(async move {
-                Self::watch_task(watch_receiver, uri, format, source, root, client).await;
+                Self::watch_task(
+                    watch_receiver,
+                    applied_receiver,
+                    uri,
+                    format,
+                    source,
+                    root,
+                    root_version,
+                    generated,
+                    client,
+                )
+                .await;
             });
         }
 
-        if let Err(error) = update_sender.send(source_string) {
+        if let Err(error) = update_sender.send(text.clone()) {
             tracing::error!("While sending initial source: {error}");
         }
 
         Ok(TextDocument {
             format,
             source,
+            text,
             root,
+            generated,
             doc,
             update_sender,
         })
@@ -268,10 +330,24 @@ impl TextDocument {
     ///   is a tradeoff here between granularity and latency
     ///
     /// - to avoid excessive compute decoding the document on each keypress
+    ///
+    /// Where possible, only the top-level blocks affected by changes are decoded
+    /// and the document is updated using a patch for those blocks (see `update_blocks`).
+    /// Otherwise, the whole document is decoded and merged into the Stencila document.
+    ///
+    /// Each new source is given a version number. Once the Stencila document has applied
+    /// the update for a version, the source is updated and the version is sent to the
+    /// `watch_task` which records the version that the root `TextNode` is up to date with
+    /// (in `root_version`). Blocks are only patched if the root is up to date with the old
+    /// source because, otherwise, the ranges of blocks may be wrong.
+    #[allow(clippy::too_many_arguments)]
     async fn update_task(
         mut receiver: mpsc::UnboundedReceiver<String>,
         format: Format,
         source: Arc<RwLock<String>>,
+        root: Arc<RwLock<TextNode>>,
+        root_version: Arc<AtomicU64>,
+        applied_sender: watch::Sender<u64>,
         doc: Arc<RwLock<Document>>,
         author_role: AuthorRole,
     ) {
@@ -280,6 +356,7 @@ impl TextDocument {
         const DEBOUNCE_DELAY_MILLIS: u64 = 500;
         let debounce = time::Duration::from_millis(DEBOUNCE_DELAY_MILLIS);
 
+        let mut version = 0;
         let mut latest_source = None;
         loop {
             // Debounce updates
@@ -304,55 +381,330 @@ impl TextDocument {
                 }
             };
 
-            // Update the source
-            *source.write().await = new_source.clone();
+            let old_source = source.read().await.clone();
+            version += 1;
+
+            // Attempt to only update the blocks affected by the change. This requires that
+            // the root `TextNode` (which has the ranges of blocks) is up to date with the old source.
+            let updated = if root_version.load(Ordering::SeqCst) == version - 1 {
+                match Self::update_blocks(
+                    &old_source,
+                    &new_source,
+                    &format,
+                    &root,
+                    &doc,
+                    &author_role,
+                )
+                .await
+                {
+                    Ok(updated) => updated,
+                    Err(error) => {
+                        tracing::debug!(
+                            "While updating blocks, will update whole document: {error}"
+                        );
+                        false
+                    }
+                }
+            } else {
+                false
+            };
+            if !updated {
+                Self::update_document(&new_source, &format, &doc, &author_role).await;
+            }
+
+            // Wait for the Stencila document to apply the update before updating the source
+            // and notifying the `watch_task` of the version that has been applied
+            if let Err(error) = doc.read().await.sync().await {
+                tracing::error!("While waiting for document to update: {error}");
+            }
+            *source.write().await = new_source;
+            applied_sender.send(version).ok();
+        }
+    }
 
-            // Decode the document
-            let node = match codecs::from_str(
-                &new_source,
-                Some(DecodeOptions {
-                    format: Some(format.clone()),
-                    ..Default::default()
-                }),
+    /// Decode the whole of the new source and merge it into the Stencila document
+    async fn update_document(
+        new_source: &str,
+        format: &Format,
+        doc: &RwLock<Document>,
+        author_role: &AuthorRole,
+    ) {
+        // Decode the document
+        let node = match codecs::from_str(
+            new_source,
+            Some(DecodeOptions {
+                format: Some(format.clone()),
+                ..Default::default()
+            }),
+        )
+        .await
+        {
+            Ok(node) => node,
+            Err(error) => {
+                tracing::error!("While decoding document: {error}");
+                return;
+            }
+        };
+
+        // Update the Stencila document with the new node
+        let doc = doc.write().await;
+        if let Err(error) = doc
+            .update(
+                node,
+                Some(Format::Markdown),
+                Some(vec![author_role.clone()]),
             )
             .await
-            {
-                Ok(node) => node,
-                Err(error) => {
-                    tracing::error!("While decoding document: {error}");
-                    continue;
-                }
+        {
+            tracing::error!("While updating node: {error}");
+        }
+    }
+
+    /// Update the top-level blocks of the document affected by a change to its source
+    ///
+    /// Gets the range of characters that differ between the old and new source, uses the ranges
+    /// of the top-level blocks in the root `TextNode` to determine which blocks are affected
+    /// (including one block either side, for context), decodes just the new source for those
+    /// blocks, and sends a patch for them to the document (followed by a command to compile it,
+    /// because patches do not trigger compilation). Blocks which have the same type
+    /// are diffed so that properties not in the source (e.g. outputs) are retained.
+    ///
+    /// Returns `false` if the change could not be applied in this way (e.g. it is within
+    /// front matter, or the ranges of blocks are not available) and the whole document should
+    /// be decoded instead.
+    async fn update_blocks(
+        old: &str,
+        new: &str,
+        format: &Format,
+        root: &RwLock<TextNode>,
+        doc: &RwLock<Document>,
+        author_role: &AuthorRole,
+    ) -> Result<bool> {
+        // Get the range of characters, in the old source, that changed
+        let old_len = old.chars().count();
+        let new_len = new.chars().count();
+        let prefix = old
+            .chars()
+            .zip(new.chars())
+            .take_while(|(a, b)| a == b)
+            .count();
+        if prefix == old_len && old_len == new_len {
+            return Ok(true);
+        }
+        let suffix = old
+            .chars()
+            .rev()
+            .zip(new.chars().rev())
+            .take_while(|(a, b)| a == b)
+            .count()
+            .min(old_len.min(new_len) - prefix);
+        let (start, end) = (prefix, old_len - suffix);
+
+        // Get the id of the article and its top-level blocks
+        let (article_id, block_ids) = {
+            let receiver = doc.read().await.watch();
+            let root = receiver.borrow();
+            let Node::Article(article) = &*root else {
+                return Ok(false);
             };
+            let Some(block_ids) = article
+                .content
+                .iter()
+                .map(Block::node_id)
+                .collect::<Option<Vec<_>>>()
+            else {
+                return Ok(false);
+            };
+            (article.node_id(), block_ids)
+        };
+        if block_ids.is_empty() {
+            return Ok(false);
+        }
 
-            // Update the Stencila document with the new node
-            let doc = doc.write().await;
-            if let Err(error) = doc
-                .update(
-                    node.clone(),
-                    Some(Format::Markdown),
-                    Some(vec![author_role.clone()]),
-                )
-                .await
-            {
-                tracing::error!("While updating node: {error}");
+        // Get the range of characters, in the old source, of each block
+        let ranges = {
+            let root = root.read().await;
+            let ranges: HashMap<&NodeId, Range> = root
+                .children
+                .iter()
+                .map(|node| (&node.node_id, node.range))
+                .collect();
+
+            let positions = Positions::new(old);
+            let index = |position: Position| {
+                positions
+                    .index_at_position16(position_to_position16(position))
+                    .unwrap_or(old_len)
+            };
+
+            let mut indices = Vec::with_capacity(block_ids.len());
+            for node_id in &block_ids {
+                let Some(range) = ranges.get(node_id) else {
+                    return Ok(false);
+                };
+                indices.push(index(range.start)..index(range.end));
+            }
+            indices
+        };
+
+        // Get the indices of the first and last blocks affected
+        let count = ranges.len();
+        let first = ranges
+            .iter()
+            .position(|range| range.end >= start)
+            .unwrap_or(count - 1)
+            .saturating_sub(1);
+        let Some(last) = ranges.iter().rposition(|range| range.start <= end) else {
+            // Change is before the first block
+            return Ok(false);
+        };
+        let last = (last + 1).min(count - 1);
+
+        // Get the range of characters, in the new source, of the affected blocks
+        let from = ranges[first].start;
+        if from > start {
+            // Change is before the first block
+            return Ok(false);
+        }
+        let to = ranges.get(last + 1).map_or(old_len, |range| range.start) + new_len - old_len;
+
+        // Decode the new source for the affected blocks (avoiding decoding
+        // a thematic break at the start as front matter)
+        let slice: String = new.chars().skip(from).take(to - from).collect();
+        if slice.trim_start().starts_with("---") {
+            return Ok(false);
+        }
+        let Node::Article(article) = codecs::from_str(
+            &slice,
+            Some(DecodeOptions {
+                format: Some(format.clone()),
+                ..Default::default()
+            }),
+        )
+        .await?
+        else {
+            return Ok(false);
+        };
+        let new_blocks = article.content;
+
+        // Get the current affected blocks
+        let old_blocks = {
+            let receiver = doc.read().await.watch();
+            let root = receiver.borrow();
+            let Node::Article(article) = &*root else {
+                return Ok(false);
+            };
+            if article.content.len() != count {
+                return Ok(false);
+            }
+            article.content[first..=last].to_vec()
+        };
+
+        let format = Some(Format::Markdown);
+        let authors = Some(vec![author_role.clone()]);
+
+        // Diff blocks of the same type at the start, and end, of the affected blocks
+        let same_type = |(old, new): &(&Block, &Block)| old.node_type() == new.node_type();
+        let leading = old_blocks
+            .iter()
+            .zip(new_blocks.iter())
+            .take_while(same_type)
+            .count();
+        let trailing = old_blocks[leading..]
+            .iter()
+            .rev()
+            .zip(new_blocks[leading..].iter().rev())
+            .take_while(same_type)
+            .count();
+
+        let mut ops = Vec::new();
+        let pairs = (0..leading).map(|offset| (offset, offset)).chain(
+            (1..=trailing).map(|offset| (old_blocks.len() - offset, new_blocks.len() - offset)),
+        );
+        for (old_index, new_index) in pairs {
+            let patch = schema::diff(
+                &old_blocks[old_index],
+                &new_blocks[new_index],
+                format.clone(),
+                authors.clone(),
+            )?;
+            for (mut path, op) in patch.ops {
+                path.push_front(PatchSlot::Index(first + old_index));
+                path.push_front(PatchSlot::Property(NodeProperty::Content));
+                ops.push((path, op));
             }
         }
+
+        // Replace the other blocks
+        let old_count = old_blocks.len() - trailing;
+        let new_count = new_blocks.len() - trailing;
+        if leading < old_count {
+            ops.push((
+                PatchPath::from(NodeProperty::Content),
+                PatchOp::Remove((first + leading..first + old_count).collect()),
+            ));
+        }
+        if leading < new_count {
+            ops.push((
+                PatchPath::from(NodeProperty::Content),
+                PatchOp::Insert(
+                    new_blocks
+                        .into_iter()
+                        .enumerate()
+                        .take(new_count)
+                        .skip(leading)
+                        .map(|(index, block)| (first + index, PatchValue::Block(block)))
+                        .collect(),
+                ),
+            ));
+        }
+
+        if !ops.is_empty() {
+            let doc = doc.read().await;
+            doc.patch(Patch {
+                node_id: Some(article_id),
+                ops,
+                format,
+                authors,
+            })?;
+            doc.command(Command::CompileDocument).await?;
+        }
+
+        Ok(true)
     }
 
     /// An async background task that watches the document
+    ///
+    /// Updates the root `TextNode` when the Stencila document changes, or when the `update_task`
+    /// has applied a new version of the source. The version is read before the node so that
+    /// the recorded `root_version` is never ahead of the node the root was derived from.
+    #[allow(clippy::too_many_arguments)]
     async fn watch_task(
         mut receiver: watch::Receiver<Node>,
+        mut applied_receiver: watch::Receiver<u64>,
         uri: Url,
         format: Format,
         source: Arc<RwLock<String>>,
         root: Arc<RwLock<TextNode>>,
+        root_version: Arc<AtomicU64>,
+        generated: Arc<RwLock<(String, Mapping)>>,
         mut client: ClientSocket,
     ) {
-        while receiver.changed().await.is_ok() {
+        let mut inlay_hints_digest = None;
+        loop {
+            let changed = tokio::select! {
+                changed = receiver.changed() => changed,
+                changed = applied_receiver.changed() => changed,
+            };
+            if changed.is_err() {
+                break;
+            }
+
+            let version = *applied_receiver.borrow_and_update();
             let node = receiver.borrow_and_update().clone();
 
             // Encode the document to get generated content and mapping
-            let (generated, EncodeInfo { mapping, .. }) = match codecs::to_string_with_info(
+            let (generated_content, EncodeInfo { mapping, .. }) = match codecs::to_string_with_info(
                 &node,
                 Some(EncodeOptions {
                     format: Some(format.clone()),
@@ -370,20 +722,41 @@ impl TextDocument {
 
             // Walk the node to collect nodes and diagnostics
             let source = source.read().await;
-            let mut inspector = Inspector::new(&source, &generated, mapping);
+            let mut inspector = Inspector::new(&source, &generated_content, mapping.clone());
             inspector.visit(&node);
 
             // Publish diagnostics and update the root TextNode
+            let mut inlay_hints_changed = false;
             if let Some(text_node) = inspector.root() {
                 //eprintln!("ROOT: {text_node:#?}");
-                diagnostics::publish(&uri, &text_node, &mut client);
+                let other = match uri.to_file_path().ok().as_deref().and_then(Path::parent) {
+                    Some(home) => workspace::check(&node, &text_node, home).await,
+                    None => Vec::new(),
+                };
+                diagnostics::publish(&uri, &text_node, other, &mut client);
+
+                let digest = inlay_hint::digest(&text_node);
+                inlay_hints_changed = inlay_hints_digest.replace(digest) != Some(digest);
+
                 *root.write().await = text_node;
+                root_version.store(version, Ordering::SeqCst);
             }
 
+            // Update the generated content and mapping
+            *generated.write().await = (generated_content, mapping);
+
             // Ask the client to refresh code lenses. This is important for things
             // like provenance statistics code lenses which should be updated on each
             // update to the document
             client.code_lens_refresh(()).await.ok();
+
+            // Ask the client to refresh inlay hints so that they show the latest
+            // execution results (only if those have changed since, unlike code lenses,
+            // the document changes during execution, e.g. the status of nodes, do not
+            // usually affect them)
+            if inlay_hints_changed {
+                client.inlay_hint_refresh(()).await.ok();
+            }
         }
     }
 }
@@ -412,6 +785,9 @@ pub(super) fn did_open(
         }
     };
 
+    if let Ok(mut opened) = state.opened.write() {
+        opened.insert(uri.clone());
+    }
     state.documents.insert(uri, doc);
 
     ControlFlow::Continue(())
@@ -424,10 +800,10 @@ pub(super) fn did_change(
 ) -> ControlFlow<Result<(), Error>> {
     let uri = params.text_document.uri;
     if let Some(doc) = state.documents.get_mut(&uri) {
-        // TODO: This assumes a whole document change (with TextDocumentSyncKind::FULL in initialize):
-        // needs more defensiveness and potentially implement incremental sync
-        let source = params.content_changes[0].text.clone();
-        if let Err(error) = doc.update_sender.send(source) {
+        for change in params.content_changes {
+            apply_change(&mut doc.text, change);
+        }
+        if let Err(error) = doc.update_sender.send(doc.text.clone()) {
             tracing::error!("While sending updated source: {error}");
         }
     } else {
@@ -437,11 +813,39 @@ pub(super) fn did_change(
     ControlFlow::Continue(())
 }
 
+/// Apply a change from the client to the text of a document
+///
+/// Changes without a range replace the whole text.
+fn apply_change(text: &mut String, change: TextDocumentContentChangeEvent) {
+    let Some(range) = change.range else {
+        *text = change.text;
+        return;
+    };
+
+    let (start, end) = {
+        let positions = Positions::new(text.as_str());
+        let byte_index = |position: Position| {
+            positions
+                .index_at_position16(position_to_position16(position))
+                .and_then(|index| text.char_indices().nth(index))
+                .map_or(text.len(), |(index, ..)| index)
+        };
+        (byte_index(range.start), byte_index(range.end))
+    };
+
+    text.replace_range(start..end.max(start), &change.text);
+}
+
 /// Handle a notification from the client that a text document was save
 pub(super) fn did_save(
-    _state: &mut ServerState,
-    _params: DidSaveTextDocumentParams,
+    state: &mut ServerState,
+    params: DidSaveTextDocumentParams,
 ) -> ControlFlow<Result<(), Error>> {
+    // Update the symbols for the document in the workspace index
+    let uri = params.text_document.uri;
+    let index = state.workspace.clone();
+    tokio::spawn(async move { workspace::index_document(uri, index).await });
+
     ControlFlow::Continue(())
 }
 
@@ -450,7 +854,112 @@ pub(super) fn did_close(
     state: &mut ServerState,
     params: DidCloseTextDocumentParams,
 ) -> ControlFlow<Result<(), Error>> {
+    if let Ok(mut opened) = state.opened.write() {
+        opened.remove(&params.text_document.uri);
+    }
     state.documents.remove(&params.text_document.uri);
 
     ControlFlow::Continue(())
 }
+
+#[cfg(test)]
+mod tests {
+    use common::tokio::time::{sleep, Instant};
+
+    use super::*;
+
+    /// Wait until the `update_task` has applied the latest text of a document
+    /// and the `watch_task` has updated the root `TextNode`
+    async fn settle(doc: &TextDocument) {
+        let start = Instant::now();
+        while *doc.source.read().await != doc.text || doc.root.read().await.children.is_empty() {
+            assert!(
+                start.elapsed() < time::Duration::from_secs(10),
+                "Timed out waiting for document to update"
+            );
+            sleep(time::Duration::from_millis(50)).await;
+        }
+        sleep(time::Duration::from_millis(200)).await;
+    }
+
+    /// Patches from execution interleaved with edits should not cause edits to be
+    /// applied to the wrong blocks, or the patched properties to be lost
+    #[tokio::test]
+    async fn edits_interleaved_with_patches() -> Result<()> {
+        let uri = Url::parse("file:///tmp/interleaved.md")?;
+        let text = "First paragraph.\n\n```python exec\nx = 1\n```\n\nSecond paragraph.\n";
+        let mut doc = TextDocument::new(uri, text.into(), ClientSocket::new_closed(), None)?;
+        settle(&doc).await;
+
+        let chunk_id = {
+            let receiver = doc.doc.read().await.watch();
+            let Node::Article(article) = &*receiver.borrow() else {
+                bail!("Expected an article")
+            };
+            assert_eq!(article.content.len(), 3);
+            article.content[1]
+                .node_id()
+                .expect("Code chunk should have an id")
+        };
+
+        for count in 1..=6 {
+            // An edit to the first paragraph
+            apply_change(
+                &mut doc.text,
+                TextDocumentContentChangeEvent {
+                    range: Some(Range::new(Position::new(0, 5), Position::new(0, 5))),
+                    range_length: None,
+                    text: count.to_string(),
+                },
+            );
+            doc.update_sender.send(doc.text.clone())?;
+
+            // A patch, as if from execution, sent while the edit is being debounced
+            doc.doc.read().await.patch(Patch {
+                node_id: Some(chunk_id.clone()),
+                ops: vec![(
+                    PatchPath::from(NodeProperty::ExecutionCount),
+                    PatchOp::Set(PatchValue::Json(count.into())),
+                )],
+                ..Default::default()
+            })?;
+
+            // Only wait on some iterations so that some edits are applied before
+            // the root is updated for the previous edit
+            if count % 2 == 0 {
+                settle(&doc).await;
+            }
+        }
+        settle(&doc).await;
+
+        let receiver = doc.doc.read().await.watch();
+        let node = receiver.borrow().clone();
+        let Node::Article(article) = &node else {
+            bail!("Expected an article")
+        };
+        let Block::CodeChunk(chunk) = &article.content[1] else {
+            bail!("Expected a code chunk")
+        };
+        assert_eq!(chunk.options.execution_count, Some(6));
+
+        let options = Some(EncodeOptions {
+            format: Some(Format::Markdown),
+            ..Default::default()
+        });
+        let expected = codecs::from_str(
+            &doc.text,
+            Some(DecodeOptions {
+                format: Some(Format::Markdown),
+                ..Default::default()
+            }),
+        )
+        .await?;
+        assert_eq!(
+            codecs::to_string(&node, options.clone()).await?,
+            codecs::to_string(&expected, options).await?
+        );
+        assert!(doc.text.starts_with("First654321 paragraph."));
+
+        Ok(())
+    }
+}
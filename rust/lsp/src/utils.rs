@@ -1,6 +1,7 @@
 use async_lsp::lsp_types::{Position, Range};
 
 use codecs::{Position16, Range16};
+use schema::{CreativeWorkType, LabelType};
 
 /// Convert a Stencila [`Range16`] to a LSP [`Range`]
 pub(super) fn range16_to_range(range: Range16) -> Range {
@@ -25,3 +26,49 @@ pub(super) fn position_to_position16(position: Position) -> Position16 {
         column: position.character as usize,
     }
 }
+
+/// Get the target used in cross-reference links to a labelled figure or table
+///
+/// Uses the `id` of the node, if any, otherwise a slug of the label type
+/// and the label e.g. `fig-1` (so links are of the form `[Figure 1](#fig-1)`).
+pub(super) fn crossref_target(id: Option<&str>, label_type: &LabelType, label: &str) -> String {
+    if let Some(id) = id {
+        return id.to_string();
+    }
+
+    let prefix = match label_type {
+        LabelType::FigureLabel => "fig",
+        LabelType::TableLabel => "tab",
+    };
+    format!("{prefix}-{label}")
+}
+
+/// Get the id of a reference (i.e. the target used in citations to it)
+pub(super) fn reference_id(reference: &CreativeWorkType) -> Option<&str> {
+    macro_rules! variants {
+        ($( $variant:ident ),*) => {
+            match reference {
+                $(CreativeWorkType::$variant(work) => work.id.as_deref(),)*
+            }
+        };
+    }
+    variants!(
+        Article,
+        AudioObject,
+        Claim,
+        Collection,
+        Comment,
+        Datatable,
+        Figure,
+        ImageObject,
+        MediaObject,
+        Periodical,
+        PublicationIssue,
+        PublicationVolume,
+        Review,
+        SoftwareApplication,
+        SoftwareSourceCode,
+        Table,
+        VideoObject
+    )
+}
@@ -0,0 +1,426 @@
+//! Indexing of the documents in the workspace
+//!
+//! Enables `workspace/symbol` requests to be answered for all documents in the
+//! workspace (not just those opened by the client) and diagnostics to be published
+//! for unopened documents. Documents are re-indexed when the client notifies the
+//! server that they have been created, changed or deleted.
+//!
+//! See https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#workspace_symbol
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{self, Arc},
+};
+
+use async_lsp::{
+    lsp_types::{
+        Diagnostic, DiagnosticSeverity, DidChangeWatchedFilesRegistrationOptions, FileChangeType,
+        FileEvent, FileSystemWatcher, GlobPattern, Location, OneOf, PublishDiagnosticsParams,
+        Range, Registration, RegistrationParams, Url, WorkspaceSymbol, WorkspaceSymbolResponse,
+    },
+    ClientSocket, LanguageClient, ResponseError,
+};
+
+use codecs::{DecodeOptions, EncodeInfo, EncodeOptions, Format};
+use common::{
+    eyre::{bail, Result},
+    glob::glob,
+    itertools::Itertools,
+    serde_json,
+    tokio::{self, fs, sync::RwLock},
+    tracing,
+};
+use schema::{Block, CreativeWorkTypeOrText, Inline, Node, NodeId, NodeType, Visitor, WalkControl};
+
+use crate::{inspect::Inspector, symbols, text_document::TextNode, utils::reference_id};
+
+/// The file extensions of the documents that are indexed
+const EXTENSIONS: &[&str] = &["smd", "myst", "md"];
+
+/// The maximum number of symbols to return for a `workspace/symbol` request
+const MAX_SYMBOLS: usize = 500;
+
+/// An index of the symbols in each of the documents in the workspace
+pub(super) type WorkspaceIndex = Arc<RwLock<HashMap<Url, Vec<WorkspaceSymbol>>>>;
+
+/// The documents currently opened by the client
+///
+/// Diagnostics for these documents are published as they change, so are not
+/// published when they are indexed. A synchronous lock is used so that it
+/// can be updated from notification handlers.
+pub(super) type OpenedDocuments = Arc<sync::RwLock<HashSet<Url>>>;
+
+/// Is a document currently opened by the client?
+fn is_opened(opened: &OpenedDocuments, uri: &Url) -> bool {
+    opened.read().map_or(false, |opened| opened.contains(uri))
+}
+
+/// Should a file be indexed?
+///
+/// Files in hidden directories (e.g. `.git`, `.stencila`) and dependencies are skipped.
+fn is_indexed(path: &Path) -> bool {
+    let has_extension = path.extension().map_or(false, |ext| {
+        EXTENSIONS.contains(&ext.to_string_lossy().as_ref())
+    });
+
+    has_extension
+        && !path.components().any(|component| {
+            let name = component.as_os_str().to_string_lossy();
+            (name.starts_with('.') && name.len() > 1) || name == "node_modules"
+        })
+}
+
+/// Register watchers with the client for changes to the documents in the workspace
+pub(super) async fn register_watchers(mut client: ClientSocket) {
+    let options = DidChangeWatchedFilesRegistrationOptions {
+        watchers: vec![FileSystemWatcher {
+            glob_pattern: GlobPattern::String(format!("**/*.{{{}}}", EXTENSIONS.join(","))),
+            kind: None,
+        }],
+    };
+
+    let register_options = match serde_json::to_value(options) {
+        Ok(value) => Some(value),
+        Err(error) => {
+            tracing::error!("While serializing watcher options: {error}");
+            return;
+        }
+    };
+
+    if let Err(error) = client
+        .register_capability(RegistrationParams {
+            registrations: vec![Registration {
+                id: "workspace-watcher".to_string(),
+                method: "workspace/didChangeWatchedFiles".to_string(),
+                register_options,
+            }],
+        })
+        .await
+    {
+        tracing::debug!("While registering file watchers: {error}");
+    }
+}
+
+/// Index all the documents in workspace folders
+///
+/// Diagnostics are published for each document, other than those that are opened by
+/// the client (for which diagnostics are published on each change).
+pub(super) async fn index_folders(
+    folders: Vec<PathBuf>,
+    opened: OpenedDocuments,
+    index: WorkspaceIndex,
+    mut client: ClientSocket,
+) {
+    // Walk the folders on a blocking thread so as not to block the server
+    let paths = tokio::task::spawn_blocking(move || {
+        folders
+            .iter()
+            .flat_map(|folder| {
+                EXTENSIONS.iter().flat_map(move |ext| {
+                    let pattern = folder.join("**").join(["*.", ext].concat());
+                    glob(&pattern.to_string_lossy())
+                        .into_iter()
+                        .flatten()
+                        .flatten()
+                })
+            })
+            .filter(|path| is_indexed(path))
+            .unique()
+            .collect_vec()
+    })
+    .await
+    .unwrap_or_default();
+
+    for path in paths {
+        index_path(&path, &opened, &index, &mut client).await;
+    }
+}
+
+/// Update the index for changes to files in the workspace
+pub(super) async fn files_changed(
+    changes: Vec<FileEvent>,
+    opened: OpenedDocuments,
+    index: WorkspaceIndex,
+    mut client: ClientSocket,
+) {
+    for FileEvent { uri, typ } in changes {
+        let Ok(path) = uri.to_file_path() else {
+            continue;
+        };
+        if !is_indexed(&path) {
+            continue;
+        }
+
+        if typ == FileChangeType::DELETED {
+            index.write().await.remove(&uri);
+            publish_diagnostics(uri, Vec::new(), &opened, &mut client);
+        } else {
+            index_path(&path, &opened, &index, &mut client).await;
+        }
+    }
+}
+
+/// Index a file and publish diagnostics for it
+async fn index_path(
+    path: &Path,
+    opened: &OpenedDocuments,
+    index: &WorkspaceIndex,
+    client: &mut ClientSocket,
+) {
+    let Ok(uri) = Url::from_file_path(path) else {
+        return;
+    };
+
+    let (symbols, diagnostics) = match index_file(&uri, path).await {
+        Ok(result) => result,
+        Err(error) => {
+            tracing::debug!("While indexing `{}`: {error}", path.display());
+            return;
+        }
+    };
+
+    publish_diagnostics(uri.clone(), diagnostics, opened, client);
+
+    index.write().await.insert(uri, symbols);
+}
+
+/// Publish diagnostics for a document if it is not opened by the client
+fn publish_diagnostics(
+    uri: Url,
+    diagnostics: Vec<Diagnostic>,
+    opened: &OpenedDocuments,
+    client: &mut ClientSocket,
+) {
+    if is_opened(opened, &uri) {
+        return;
+    }
+
+    if let Err(error) = client.publish_diagnostics(PublishDiagnosticsParams {
+        uri,
+        diagnostics,
+        version: None,
+    }) {
+        tracing::error!("While publishing diagnostics: {error}");
+    }
+}
+
+/// Index a single document (e.g. after it has been saved)
+pub(super) async fn index_document(uri: Url, index: WorkspaceIndex) {
+    let Ok(path) = uri.to_file_path() else {
+        return;
+    };
+
+    match index_file(&uri, &path).await {
+        Ok((symbols, ..)) => {
+            index.write().await.insert(uri, symbols);
+        }
+        Err(error) => tracing::debug!("While indexing `{}`: {error}", path.display()),
+    }
+}
+
+/// Index a file by decoding it into a node and creating symbols and diagnostics for it
+async fn index_file(uri: &Url, path: &Path) -> Result<(Vec<WorkspaceSymbol>, Vec<Diagnostic>)> {
+    let Some(home) = path.parent() else {
+        bail!("File does not have a parent dir")
+    };
+
+    let format = Format::from_path(path);
+    let source = fs::read_to_string(path).await?;
+
+    let node = codecs::from_str(
+        &source,
+        Some(DecodeOptions {
+            format: Some(format.clone()),
+            ..Default::default()
+        }),
+    )
+    .await?;
+
+    let (generated, EncodeInfo { mapping, .. }) = codecs::to_string_with_info(
+        &node,
+        Some(EncodeOptions {
+            format: Some(format),
+            ..Default::default()
+        }),
+    )
+    .await?;
+
+    let mut inspector = Inspector::new(&source, &generated, mapping);
+    inspector.visit(&node);
+    let Some(root) = inspector.root() else {
+        return Ok((Vec::new(), Vec::new()));
+    };
+
+    let container_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string());
+
+    let symbols = root
+        .flatten()
+        .filter(|node| {
+            matches!(
+                node.node_type,
+                NodeType::Heading | NodeType::Figure | NodeType::Table | NodeType::Parameter
+            )
+        })
+        .filter_map(|node| {
+            Some(WorkspaceSymbol {
+                name: match &node.detail {
+                    Some(detail) => format!("{} {detail}", node.name),
+                    None => node.name.clone(),
+                },
+                kind: symbols::kind(&node.node_type)?,
+                tags: None,
+                container_name: container_name.clone(),
+                location: OneOf::Left(Location::new(uri.clone(), node.range)),
+                data: None,
+            })
+        })
+        .collect();
+
+    let diagnostics = check(&node, &root, home).await;
+
+    Ok((symbols, diagnostics))
+}
+
+/// Handle a `workspace/symbol` request
+///
+/// Returns symbols, across all documents in the workspace, with names that
+/// contain the query (ignoring case).
+pub(super) async fn symbols(
+    query: String,
+    index: WorkspaceIndex,
+) -> Result<Option<WorkspaceSymbolResponse>, ResponseError> {
+    let query = query.to_lowercase();
+
+    let symbols = index
+        .read()
+        .await
+        .values()
+        .flatten()
+        .filter(|symbol| query.is_empty() || symbol.name.to_lowercase().contains(&query))
+        .take(MAX_SYMBOLS)
+        .cloned()
+        .collect();
+
+    Ok(Some(WorkspaceSymbolResponse::Nested(symbols)))
+}
+
+/// Check a document for broken sources of include and call blocks, and for unresolved citations
+///
+/// Used both for documents in the workspace and those opened by the client. Citations are
+/// only checked if the document has references of its own (otherwise they are likely to be
+/// resolved from elsewhere, e.g. a bibliography file).
+pub(super) async fn check(node: &Node, root: &TextNode, home: &Path) -> Vec<Diagnostic> {
+    let mut checker = Checker::default();
+    checker.visit(node);
+
+    let range = |node_id: &NodeId| {
+        root.flatten()
+            .find(|node| &node.node_id == node_id)
+            .map(|node| node.range)
+    };
+
+    let mut diagnostics = Vec::new();
+
+    for (node_id, source) in checker.sources {
+        if source.starts_with("http://") || source.starts_with("https://") {
+            continue;
+        }
+
+        if fs::try_exists(home.join(&source)).await.unwrap_or(true) {
+            continue;
+        }
+
+        // Only highlight the start of the node (since it may have content)
+        let Some(range) = range(&node_id) else {
+            continue;
+        };
+        diagnostics.push(Diagnostic {
+            range: Range::new(range.start, range.start),
+            severity: Some(DiagnosticSeverity::ERROR),
+            message: format!("Source file `{source}` does not exist"),
+            ..Default::default()
+        });
+    }
+
+    if checker.references.is_empty() {
+        return diagnostics;
+    }
+
+    let cites = checker.cites.into_iter().filter_map(|(node_id, target)| {
+        if checker.references.contains(&target) {
+            return None;
+        }
+
+        Some(Diagnostic {
+            range: range(&node_id)?,
+            severity: Some(DiagnosticSeverity::WARNING),
+            message: format!("Unresolved citation `{target}`"),
+            ..Default::default()
+        })
+    });
+    diagnostics.extend(cites);
+
+    diagnostics
+}
+
+/// A visitor which collects the sources, citations, and references of a document
+#[derive(Default)]
+struct Checker {
+    /// The sources of include and call blocks
+    sources: Vec<(NodeId, String)>,
+
+    /// The targets of citations
+    cites: Vec<(NodeId, String)>,
+
+    /// The ids of references
+    references: HashSet<String>,
+}
+
+impl Visitor for Checker {
+    fn visit_node(&mut self, node: &Node) -> WalkControl {
+        if let Node::Article(article) = node {
+            for reference in article.references.iter().flatten() {
+                if let CreativeWorkTypeOrText::CreativeWorkType(work) = reference {
+                    if let Some(id) = reference_id(work) {
+                        self.references.insert(id.to_string());
+                    }
+                }
+            }
+        }
+
+        WalkControl::Continue
+    }
+
+    fn visit_block(&mut self, block: &Block) -> WalkControl {
+        match block {
+            Block::IncludeBlock(block) => {
+                self.sources.push((block.node_id(), block.source.clone()));
+            }
+            Block::CallBlock(block) => {
+                self.sources.push((block.node_id(), block.source.clone()));
+            }
+            _ => {}
+        }
+
+        WalkControl::Continue
+    }
+
+    fn visit_inline(&mut self, inline: &Inline) -> WalkControl {
+        match inline {
+            Inline::Cite(cite) => {
+                self.cites.push((cite.node_id(), cite.target.clone()));
+            }
+            Inline::CiteGroup(group) => {
+                for cite in &group.items {
+                    self.cites.push((cite.node_id(), cite.target.clone()));
+                }
+            }
+            _ => {}
+        }
+
+        WalkControl::Continue
+    }
+}
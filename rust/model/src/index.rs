@@ -0,0 +1,603 @@
+//! An index of passages from the files in a project
+//!
+//! Used to retrieve passages relevant to an instruction (e.g. "consistent with the
+//! methods in chapter 2") so that they can be added to the context of a generation task.
+//!
+//! Documents are chunked into passages at headings, and data files (e.g. data
+//! dictionaries) into groups of rows. Passages are embedded using [`Embeddings`].
+//! When embeddings are not available (i.e. when the `fastembed` feature is not enabled)
+//! a simpler, term frequency based, similarity is used instead.
+//!
+//! The index is stored in the application's cache directory and is updated
+//! incrementally, re-indexing only files that have changed since they were last indexed.
+//! Once opened, the index for a project is kept in memory for subsequent searches.
+//! Chunking and embedding are CPU intensive so are done on blocking threads.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::read_dir,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::UNIX_EPOCH,
+};
+
+use app::{get_app_dir, DirType};
+use codec_text_trait::to_text;
+use codecs::{DecodeOptions, LossesResponse};
+use common::{
+    eyre::Result,
+    itertools::Itertools,
+    once_cell::sync::Lazy,
+    seahash::SeaHasher,
+    serde::{Deserialize, Serialize},
+    serde_json,
+    tokio::{self, fs, sync::Mutex},
+    tracing,
+};
+use context::Passage;
+use schema::{Article, Block, Node};
+
+use crate::Embeddings;
+
+/// The file extensions of documents that are indexed
+const DOCUMENT_EXTENSIONS: &[&str] = &["md", "smd", "myst"];
+
+/// The file extensions of data files (e.g. data dictionaries) that are indexed
+const DATA_EXTENSIONS: &[&str] = &["csv", "tsv"];
+
+/// The maximum number of files in a project that are indexed
+const MAX_FILES: usize = 1_000;
+
+/// The maximum number of characters in a passage
+const MAX_PASSAGE_CHARS: usize = 1_500;
+
+/// The maximum number of rows of a data file that are indexed
+const MAX_DATA_ROWS: usize = 500;
+
+/// The number of rows of a data file in each passage
+const DATA_ROWS_PER_PASSAGE: usize = 20;
+
+/// The minimum similarity, of embeddings, for a passage to be considered relevant
+const MIN_EMBEDDINGS_SCORE: f32 = 0.5;
+
+/// The minimum similarity, of term frequencies, for a passage to be considered relevant
+const MIN_TERMS_SCORE: f32 = 0.15;
+
+/// The names of directories that are not walked when finding the files in a project
+///
+/// In addition to these, hidden directories (e.g. `.git`, `.stencila`) are not walked.
+const IGNORED_DIRS: &[&str] = &["node_modules", "target", "venv", "__pycache__"];
+
+/// The indices that have been opened, by the root directory of their project
+///
+/// Kept so that indices do not need to be read from disk for each search. Each index
+/// has its own lock so that updating the index of one project does not block searches
+/// of others.
+static INDICES: Lazy<Mutex<HashMap<PathBuf, Arc<Mutex<Index>>>>> = Lazy::new(Mutex::default);
+
+/// An index of the passages in the files of a project
+#[derive(Default, Serialize, Deserialize)]
+#[serde(crate = "common::serde")]
+pub struct Index {
+    /// The root directory of the project
+    #[serde(skip)]
+    root: PathBuf,
+
+    /// The files that have been indexed and their last modification time (in seconds)
+    files: BTreeMap<String, u64>,
+
+    /// The indexed passages
+    passages: Vec<IndexedPassage>,
+}
+
+/// A passage in the index
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "common::serde")]
+struct IndexedPassage {
+    /// The path of the file, relative to the root of the project
+    path: String,
+
+    /// The text of the passage
+    text: String,
+
+    /// The embedding of the text, if embeddings are available
+    vector: Option<Vec<f32>>,
+}
+
+impl Index {
+    /// Search the index for the project containing a directory
+    ///
+    /// The root of the project is the closest ancestor of `dir` containing a `.git`
+    /// or `.stencila` directory, falling back to `dir` itself.
+    ///
+    /// The index is opened, if it has not been already, and updated before it is searched.
+    /// Passages from the `exclude` file (usually the document that the query is from)
+    /// are not returned.
+    pub async fn search_project(
+        dir: &Path,
+        query: &str,
+        top_k: usize,
+        exclude: Option<&Path>,
+    ) -> Result<Vec<Passage>> {
+        let root = project_root(dir);
+
+        // Only hold the lock on all indices while getting the index for the project
+        // (opening it without the lock and, if another search opened it meanwhile, using that)
+        let index = INDICES.lock().await.get(&root).cloned();
+        let index = match index {
+            Some(index) => index,
+            None => {
+                let index = Arc::new(Mutex::new(Self::open(root.clone()).await?));
+                INDICES.lock().await.entry(root).or_insert(index).clone()
+            }
+        };
+
+        let query_vector = embed(vec![query.to_string()])
+            .await?
+            .and_then(|vectors| vectors.into_iter().next());
+
+        let mut index = index.lock().await;
+        if index.update().await? {
+            fs::write(
+                Self::path(&index.root).await?,
+                serde_json::to_string(&*index)?,
+            )
+            .await?;
+        }
+
+        let exclude = exclude
+            .and_then(|path| path.strip_prefix(&index.root).ok())
+            .map(|path| path.to_string_lossy().to_string());
+
+        Ok(index.search(query, query_vector.as_deref(), top_k, exclude.as_deref()))
+    }
+
+    /// Open the index for a project from disk
+    ///
+    /// Starts afresh if the index can not be read (e.g. because it does not exist
+    /// yet or was written by a previous version).
+    async fn open(root: PathBuf) -> Result<Self> {
+        let path = Self::path(&root).await?;
+
+        let mut index = fs::read_to_string(&path)
+            .await
+            .ok()
+            .and_then(|json| serde_json::from_str::<Index>(&json).ok())
+            .unwrap_or_default();
+        index.root = root;
+
+        Ok(index)
+    }
+
+    /// Get the path of the file in which the index for a project is stored
+    async fn path(root: &Path) -> Result<PathBuf> {
+        let dir = get_app_dir(DirType::Cache, true)?.join("index");
+        fs::create_dir_all(&dir).await?;
+
+        let mut hash = SeaHasher::new();
+        root.hash(&mut hash);
+        Ok(dir.join(format!("{:016x}.json", hash.finish())))
+    }
+
+    /// Update the index, re-indexing files that have been added or changed
+    /// and removing those that no longer exist
+    ///
+    /// Returns `true` if the index was changed.
+    async fn update(&mut self) -> Result<bool> {
+        let root = self.root.clone();
+        let files = tokio::task::spawn_blocking(move || project_files(&root)).await?;
+
+        let mut current = BTreeMap::new();
+        for path in files {
+            let Ok(modified) = fs::metadata(&path)
+                .await
+                .and_then(|metadata| metadata.modified())
+                .map(|modified| {
+                    modified
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs()
+                })
+            else {
+                continue;
+            };
+
+            let relative = path
+                .strip_prefix(&self.root)
+                .unwrap_or(&path)
+                .to_string_lossy()
+                .to_string();
+            current.insert(relative, (path, modified));
+        }
+
+        let changed = current
+            .iter()
+            .filter(|(relative, (.., modified))| self.files.get(*relative) != Some(modified))
+            .map(|(relative, ..)| relative.clone())
+            .collect_vec();
+        let removed = self
+            .files
+            .keys()
+            .filter(|relative| !current.contains_key(*relative))
+            .cloned()
+            .collect_vec();
+
+        if changed.is_empty() && removed.is_empty() {
+            return Ok(false);
+        }
+
+        // Remove passages for changed and removed files
+        self.passages
+            .retain(|passage| !changed.contains(&passage.path) && !removed.contains(&passage.path));
+        for relative in &removed {
+            self.files.remove(relative);
+        }
+
+        // Chunk changed files into passages and embed them
+        for relative in changed {
+            let (path, modified) = &current[&relative];
+
+            let texts = match chunk_file(path).await {
+                Ok(texts) => texts,
+                Err(error) => {
+                    tracing::debug!("While indexing `{}`: {error}", path.display());
+                    Vec::new()
+                }
+            };
+
+            let mut vectors = embed(texts.clone())
+                .await?
+                .map(|vectors| vectors.into_iter());
+
+            for text in texts {
+                let vector = vectors.as_mut().and_then(|vectors| vectors.next());
+                self.passages.push(IndexedPassage {
+                    path: relative.clone(),
+                    text,
+                    vector,
+                });
+            }
+
+            self.files.insert(relative, *modified);
+        }
+
+        Ok(true)
+    }
+
+    /// Search the index for the passages most similar to a query
+    ///
+    /// Uses the embedding of the query, if available, falling back to term frequencies.
+    /// Returns up to `top_k` passages, with a similarity above a minimum score, in
+    /// descending order of similarity. Passages from the `exclude` path (relative to the
+    /// root of the project) are ignored.
+    fn search(
+        &self,
+        query: &str,
+        query_vector: Option<&[f32]>,
+        top_k: usize,
+        exclude: Option<&str>,
+    ) -> Vec<Passage> {
+        let query_terms = terms(query);
+
+        self.passages
+            .iter()
+            .filter(|passage| Some(passage.path.as_str()) != exclude)
+            .filter_map(|passage| {
+                let score = match (query_vector, &passage.vector) {
+                    (Some(query), Some(vector)) => {
+                        let score = Embeddings::calculate_similarity(query, vector);
+                        (score >= MIN_EMBEDDINGS_SCORE).then_some(score)
+                    }
+                    _ => {
+                        let score = terms_similarity(&query_terms, &terms(&passage.text));
+                        (score >= MIN_TERMS_SCORE).then_some(score)
+                    }
+                }?;
+                Some((score, passage))
+            })
+            .sorted_by(|(a, ..), (b, ..)| b.total_cmp(a))
+            .take(top_k)
+            .map(|(score, passage)| Passage {
+                path: passage.path.clone(),
+                text: passage.text.clone(),
+                score,
+            })
+            .collect()
+    }
+}
+
+/// Embed texts on a blocking thread
+///
+/// Returns `None` if embeddings are not available.
+async fn embed(texts: Vec<String>) -> Result<Option<Vec<Vec<f32>>>> {
+    tokio::task::spawn_blocking(move || {
+        let mut embeddings = Embeddings::default();
+        embeddings.build(texts)?;
+        Ok(embeddings.vectors().map(|vectors| vectors.to_vec()))
+    })
+    .await?
+}
+
+/// Get the root directory of the project containing a directory
+fn project_root(dir: &Path) -> PathBuf {
+    dir.ancestors()
+        .find(|dir| dir.join(".git").exists() || dir.join(".stencila").exists())
+        .unwrap_or(dir)
+        .to_path_buf()
+}
+
+/// Get the files in a project that should be indexed
+///
+/// Walks the project's directories, without descending into hidden or ignored directories
+/// (so that large directories of dependencies are not walked), until `MAX_FILES` are found.
+/// This is blocking so should be called using `spawn_blocking`.
+fn project_files(root: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().to_string();
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+
+            if file_type.is_dir() {
+                if !name.starts_with('.') && !IGNORED_DIRS.contains(&name.as_str()) {
+                    dirs.push(path);
+                }
+            } else if file_type.is_file() {
+                let ext = path
+                    .extension()
+                    .map(|ext| ext.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                if DOCUMENT_EXTENSIONS.contains(&ext.as_str())
+                    || DATA_EXTENSIONS.contains(&ext.as_str())
+                {
+                    files.push(path);
+                    if files.len() >= MAX_FILES {
+                        return files;
+                    }
+                }
+            }
+        }
+    }
+    files
+}
+
+/// Chunk a file into the texts of passages
+async fn chunk_file(path: &Path) -> Result<Vec<String>> {
+    let ext = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    if DATA_EXTENSIONS.contains(&ext.as_str()) {
+        let content = fs::read_to_string(path).await?;
+        return Ok(tokio::task::spawn_blocking(move || chunk_data(&content)).await?);
+    }
+
+    let node = codecs::from_path(
+        path,
+        Some(DecodeOptions {
+            losses: LossesResponse::Ignore,
+            ..Default::default()
+        }),
+    )
+    .await?;
+
+    let Node::Article(Article { content, .. }) = node else {
+        return Ok(Vec::new());
+    };
+
+    Ok(tokio::task::spawn_blocking(move || chunk_blocks(&content)).await?)
+}
+
+/// Chunk blocks into the texts of passages
+///
+/// A new passage is started at each heading and when a passage becomes too long.
+/// Each passage starts with the text of the heading it is under (if any) so that
+/// references such as "the methods section" can be matched.
+fn chunk_blocks(blocks: &[Block]) -> Vec<String> {
+    let mut passages = Vec::new();
+    let mut heading = String::new();
+    let mut passage = String::new();
+
+    for block in blocks {
+        let text = to_text(block);
+        let text = text.trim();
+        if text.is_empty() {
+            continue;
+        }
+
+        let is_heading = matches!(block, Block::Heading(..));
+        if is_heading || passage.len() + text.len() > MAX_PASSAGE_CHARS {
+            if passage.len() > heading.len() {
+                passages.push(passage.trim().to_string());
+            }
+
+            if is_heading {
+                heading = text.to_string();
+            }
+            passage = heading.clone();
+
+            if is_heading {
+                continue;
+            }
+        }
+
+        if !passage.is_empty() {
+            passage.push_str("\n\n");
+        }
+        passage.push_str(text);
+    }
+
+    if passage.len() > heading.len() {
+        passages.push(passage.trim().to_string());
+    }
+
+    passages
+}
+
+/// Chunk a delimited data file into the texts of passages
+///
+/// Each passage includes the header row so that the meaning of columns is available.
+fn chunk_data(content: &str) -> Vec<String> {
+    let mut lines = content.lines().filter(|line| !line.trim().is_empty());
+    let Some(header) = lines.next() else {
+        return Vec::new();
+    };
+
+    lines
+        .take(MAX_DATA_ROWS)
+        .chunks(DATA_ROWS_PER_PASSAGE)
+        .into_iter()
+        .map(|rows| [header, "\n", &rows.collect_vec().join("\n")].concat())
+        .collect()
+}
+
+/// Get the frequencies of the terms in some text
+fn terms(text: &str) -> HashMap<String, f32> {
+    let mut terms = HashMap::new();
+    for term in text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|term| term.chars().count() > 2)
+    {
+        *terms.entry(term.to_lowercase()).or_insert(0.0) += 1.0;
+    }
+    terms
+}
+
+/// Calculate the cosine similarity of the term frequencies of two texts
+fn terms_similarity(a: &HashMap<String, f32>, b: &HashMap<String, f32>) -> f32 {
+    let dot_product: f32 = a
+        .iter()
+        .filter_map(|(term, x)| b.get(term).map(|y| x * y))
+        .sum();
+    let magnitude_a: f32 = a.values().map(|x| x * x).sum::<f32>().sqrt();
+    let magnitude_b: f32 = b.values().map(|y| y * y).sum::<f32>().sqrt();
+
+    if magnitude_a == 0.0 || magnitude_b == 0.0 {
+        return 0.0;
+    }
+
+    dot_product / (magnitude_a * magnitude_b)
+}
+
+#[cfg(test)]
+mod tests {
+    use schema::shortcuts::{h1, p, t};
+
+    use super::*;
+
+    #[test]
+    fn chunks_blocks_at_headings() {
+        let passages = chunk_blocks(&[
+            p([t("Preamble.")]),
+            h1([t("Methods")]),
+            p([t("We sampled 100 sites.")]),
+            p([t("Sites were chosen randomly.")]),
+            h1([t("Results")]),
+            h1([t("Discussion")]),
+            p([t("It worked.")]),
+        ]);
+
+        assert_eq!(
+            passages,
+            vec![
+                "Preamble.",
+                "Methods\n\nWe sampled 100 sites.\n\nSites were chosen randomly.",
+                "Discussion\n\nIt worked."
+            ]
+        );
+    }
+
+    #[test]
+    fn chunks_data_with_header() {
+        let rows = (0..25).map(|row| format!("var{row},Variable {row}"));
+        let content = std::iter::once("name,description".to_string())
+            .chain(rows)
+            .join("\n");
+
+        let passages = chunk_data(&content);
+        assert_eq!(passages.len(), 2);
+        assert!(passages[0].starts_with("name,description\nvar0,"));
+        assert!(passages[1].starts_with("name,description\nvar20,"));
+    }
+
+    #[test]
+    fn terms_similarity_prefers_overlap() {
+        let query = terms("consistent with the methods in chapter 2");
+        let methods = terms("Methods: samples were collected following the methods of chapter 1");
+        let results = terms("Results: the samples showed significant differences");
+
+        assert!(terms_similarity(&query, &methods) > terms_similarity(&query, &results));
+        assert_eq!(terms_similarity(&query, &HashMap::new()), 0.0);
+    }
+
+    #[test]
+    fn project_files_skips_ignored_dirs() -> Result<()> {
+        let dir = common::tempfile::tempdir()?;
+        let root = dir.path();
+        for path in [
+            "a.md",
+            "b.txt",
+            "sub/c.csv",
+            "node_modules/pkg/d.md",
+            ".stencila/e.md",
+        ] {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().expect("should have parent"))?;
+            std::fs::write(path, "")?;
+        }
+
+        let files = project_files(root)
+            .into_iter()
+            .map(|path| path.strip_prefix(root).unwrap_or(&path).to_path_buf())
+            .sorted()
+            .collect_vec();
+        assert_eq!(
+            files,
+            vec![PathBuf::from("a.md"), PathBuf::from("sub/c.csv")]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn search_excludes_and_applies_threshold() {
+        let passage = |path: &str, text: &str| IndexedPassage {
+            path: path.to_string(),
+            text: text.to_string(),
+            vector: None,
+        };
+        let index = Index {
+            passages: vec![
+                passage("methods.md", "Methods: samples were collected at sites"),
+                passage("current.md", "Methods: samples were collected at sites"),
+                passage("other.md", "Acknowledgements: thanks to all"),
+            ],
+            ..Default::default()
+        };
+
+        let paths = |passages: Vec<Passage>| {
+            passages
+                .into_iter()
+                .map(|passage| passage.path)
+                .collect_vec()
+        };
+
+        assert_eq!(
+            paths(index.search("how were samples collected", None, 5, None)),
+            vec!["methods.md", "current.md"]
+        );
+        assert_eq!(
+            paths(index.search("how were samples collected", None, 5, Some("current.md"))),
+            vec!["methods.md"]
+        );
+    }
+}
@@ -1,15 +1,10 @@
 //! Functions used as filters and elsewhere in `minijinja` templates
 
-use std::{
-    hash::{Hash, Hasher},
-    sync::Mutex,
-};
+use std::collections::BTreeMap;
 
 use common::{
     eyre::{eyre, Report, Result},
     minijinja::{value::ViaDeserialize, Environment, Error, UndefinedBehavior, Value},
-    once_cell::sync::Lazy,
-    seahash::SeaHasher,
     serde_json, serde_yaml,
 };
 use schema::{
@@ -18,27 +13,21 @@ use schema::{
 
 use crate::GenerateTask;
 
+/// Partial templates which prompts can `{% include %}` using their name
+pub type Partials = BTreeMap<String, String>;
+
 /// Render a prompt with the task as context
+///
+/// Partials included in the prompt are loaded from the partials of the task
+/// so that those of one workspace are not available to the prompts of another.
 pub fn render_template(prompt: &str, task: &GenerateTask) -> Result<String> {
-    let mut hasher = SeaHasher::new();
-    prompt.hash(&mut hasher);
-    let hash = hasher.finish().to_string();
+    let mut env = environment();
 
-    let mut env = ENV
-        .lock()
-        .map_err(|error| eyre!("Unable to lock ENV: {error}"))?;
+    let partials = task.partials().clone();
+    env.set_loader(move |name| Ok(partials.get(name).cloned()));
 
-    let template = match env.get_template(&hash) {
-        Ok(template) => template,
-        _ => {
-            env.add_template_owned(hash.clone(), prompt.to_string())
-                .map_err(minijinja_error_to_eyre)?;
-            env.get_template(&hash).expect("Should be just added")
-        }
-    };
-
-    let rendered = template
-        .render(task)
+    let rendered = env
+        .render_str(prompt, task)
         .map_err(minijinja_error_to_eyre)?
         .trim()
         .to_string();
@@ -46,8 +35,8 @@ pub fn render_template(prompt: &str, task: &GenerateTask) -> Result<String> {
     Ok(rendered)
 }
 
-/// A template environment for rendering system prompts
-static ENV: Lazy<Mutex<Environment>> = Lazy::new(|| {
+/// Create a template environment for rendering system prompts
+fn environment() -> Environment<'static> {
     let mut env = Environment::new();
 
     // Set the most lenient undefined behavior to avoid errors
@@ -71,8 +60,8 @@ static ENV: Lazy<Mutex<Environment>> = Lazy::new(|| {
     env.add_filter("insert_code_chunk_shots", insert_code_chunk_shots);
     env.add_filter("insert_math_block_shots", insert_math_block_shots);
 
-    Mutex::new(env)
-});
+    env
+}
 
 /// Expand a `minijinja` error to include the sources of the error (location etc)
 fn minijinja_error_to_eyre(error: Error) -> Report {
@@ -1,4 +1,4 @@
-use std::str::FromStr;
+use std::{str::FromStr, sync::Arc};
 
 use codec_markdown_trait::to_markdown;
 use context::Context;
@@ -16,6 +16,7 @@ use common::{
     serde_with::skip_serializing_none,
     smart_default::SmartDefault,
     strum::Display,
+    tokio::sync::mpsc::UnboundedSender,
     tracing,
 };
 use format::Format;
@@ -40,7 +41,20 @@ pub use merge;
 pub use schema;
 pub use secrets;
 
+mod index;
 mod jinja;
+mod sse;
+mod tools;
+mod usage;
+
+pub use index::Index;
+pub use jinja::Partials;
+pub use sse::server_sent_events;
+pub use tools::{Tool, ToolCall, Tools, MAX_TOOL_ROUNDS};
+pub use usage::GenerateUsage;
+
+/// The number of passages from the project's files to add to the context of a task
+const PASSAGES_TOP_K: usize = 5;
 
 /// An instruction created within a document
 #[skip_serializing_none]
@@ -110,6 +124,18 @@ impl Instruction {
         })
     }
 
+    /// Create a chat instruction from some text
+    pub fn chat_text<S: AsRef<str>>(text: S) -> Self {
+        Instruction::Block(InstructionBlock {
+            instruction_type: schema::InstructionType::Chat,
+            messages: vec![InstructionMessage {
+                parts: vec![MessagePart::Text(text.into())],
+                ..Default::default()
+            }],
+            ..Default::default()
+        })
+    }
+
     /// Create a block instruction from some text with content
     pub fn block_text_with<S: AsRef<str>, C: IntoIterator<Item = Block>>(
         text: S,
@@ -142,6 +168,12 @@ impl Instruction {
 
         match self {
             Instruction::Block(node) => {
+                // The replies in a chat are already in its messages (suggestions
+                // are replies that have been promoted) so do not repeat them
+                if matches!(node.instruction_type, schema::InstructionType::Chat) {
+                    return messages;
+                }
+
                 for suggestion in node.suggestions.iter().flatten() {
                     // Note: this encodes suggestion content to Markdown. Using the
                     // format used by the particular assistant e.g. HTML may be more appropriate
@@ -213,12 +245,18 @@ pub enum InstructionType {
     InsertInlines,
     ModifyBlocks,
     ModifyInlines,
+    Chat,
 }
 
 impl From<&Instruction> for InstructionType {
     fn from(instruct: &Instruction) -> Self {
         use InstructionType::*;
         match instruct {
+            Instruction::Block(InstructionBlock {
+                instruction_type: schema::InstructionType::Chat,
+                ..
+            }) => Chat,
+
             Instruction::Block(InstructionBlock { content: None, .. }) => InsertBlocks,
 
             Instruction::Block(InstructionBlock {
@@ -250,6 +288,11 @@ impl Embeddings {
         self.vectors.is_none()
     }
 
+    /// Get the vectors of the embeddings, if they have been built
+    pub fn vectors(&self) -> Option<&[Vec<f32>]> {
+        self.vectors.as_deref()
+    }
+
     pub fn iter_items(&self) -> impl Iterator<Item = (&str, &[f32])> {
         match (self.texts.as_ref(), self.vectors.as_ref()) {
             (Some(texts), Some(vectors)) => texts
@@ -394,6 +437,21 @@ pub struct GenerateTask {
 
     /// An optional system prompt
     system_prompt: Option<String>,
+
+    /// Tools that the model may call while performing the task
+    ///
+    /// Not serialized because tools are only available to models
+    /// running within this process.
+    #[serde(skip)]
+    tools: Option<Arc<dyn Tools>>,
+
+    /// Whether to retrieve passages relevant to the instruction when preparing the task
+    #[serde(skip)]
+    retrieve_passages: bool,
+
+    /// Partial templates which the system prompt may include
+    #[serde(skip)]
+    partials: Arc<Partials>,
 }
 
 impl GenerateTask {
@@ -410,6 +468,54 @@ impl GenerateTask {
         }
     }
 
+    /// Attach tools that the model may call while performing the task
+    pub fn with_tools(mut self, tools: Option<Arc<dyn Tools>>) -> Self {
+        self.tools = tools;
+        self
+    }
+
+    /// Set whether passages relevant to the instruction should be retrieved when preparing the task
+    pub fn with_passages(mut self, retrieve: bool) -> Self {
+        self.retrieve_passages = retrieve;
+        self
+    }
+
+    /// Set the partial templates which the system prompt may include
+    pub fn with_partials(mut self, partials: Arc<Partials>) -> Self {
+        self.partials = partials;
+        self
+    }
+
+    /// Get the partial templates which the system prompt may include
+    pub fn partials(&self) -> &Arc<Partials> {
+        &self.partials
+    }
+
+    /// Get the tools attached to the task
+    ///
+    /// Use `offered_tools` to get the tools that should be offered to the model.
+    pub fn tools(&self) -> Option<&dyn Tools> {
+        self.tools.as_deref()
+    }
+
+    /// Get the tools that the model may call while performing the task
+    ///
+    /// Only those tools named in the `tools` option are offered. Returns `None` if
+    /// no tools were requested, or none of those requested are attached to the task,
+    /// so that models can fall back to a single, possibly streamed, request.
+    pub fn offered_tools(&self, options: &GenerateOptions) -> Option<(&dyn Tools, Vec<Tool>)> {
+        let tools = self.tools.as_deref()?;
+        let names = options.tools.as_ref()?;
+
+        let offered = tools
+            .list()
+            .into_iter()
+            .filter(|tool| names.contains(&tool.name))
+            .collect_vec();
+
+        (!offered.is_empty()).then_some((tools, offered))
+    }
+
     /// Get the task's instruction
     pub fn instruction(&self) -> &Instruction {
         &self.instruction
@@ -462,6 +568,11 @@ impl GenerateTask {
         &self.system_prompt
     }
 
+    /// Get the task's context
+    pub fn context(&self) -> Option<&Context> {
+        self.context.as_ref()
+    }
+
     /// Prepare the task to be executed by a particular assistant
     #[tracing::instrument(skip_all)]
     pub async fn prepare(
@@ -499,6 +610,30 @@ impl GenerateTask {
             self.content_formatted = Some(content);
         }
 
+        // Retrieve passages from the files in the document's project that are relevant
+        // to the instruction (other than from the document itself), if requested and not disabled.
+        // Failure to retrieve passages should not prevent the task from being performed.
+        if let Some(context) = self.context.as_mut() {
+            if let (Some(home), true, false, false) = (
+                &context.home,
+                self.retrieve_passages,
+                context.skip_passages,
+                self.instruction_text.trim().is_empty(),
+            ) {
+                let passages = Index::search_project(
+                    home,
+                    &self.instruction_text,
+                    PASSAGES_TOP_K,
+                    context.path.as_deref(),
+                )
+                .await;
+                match passages {
+                    Ok(passages) => context.passages = passages,
+                    Err(error) => tracing::warn!("While retrieving relevant passages: {error}"),
+                }
+            }
+        }
+
         // Render the system prompt with this task as context
         if let Some(prompt) = system_prompt {
             let rendered = jinja::render_template(prompt, self)?;
@@ -510,6 +645,27 @@ impl GenerateTask {
             self.system_prompt = Some(rendered);
         }
 
+        // Add any relevant passages to the system prompt, unless its template already uses them
+        let passages = self
+            .context
+            .as_ref()
+            .map(|context| context.passages.as_slice())
+            .unwrap_or_default();
+        if !passages.is_empty()
+            && !system_prompt.is_some_and(|prompt| prompt.contains("context.passages"))
+        {
+            let passages = passages
+                .iter()
+                .map(|passage| format!("From `{}`:\n\n{}", passage.path, passage.text))
+                .join("\n\n---\n\n");
+            let section = format!("The following passages, from other files in the project, may be relevant to the instruction:\n\n{passages}");
+
+            self.system_prompt = Some(match self.system_prompt.take() {
+                Some(prompt) => [prompt, section].join("\n\n"),
+                None => section,
+            });
+        }
+
         Ok(())
     }
 }
@@ -544,6 +700,14 @@ pub struct GenerateOptions {
     #[arg(long)]
     pub assistant: Option<String>,
 
+    /// The name of the model to use
+    ///
+    /// Specify this option to use a specific model instead of the models of the
+    /// assistant (e.g. when evaluating an assistant with different models).
+    /// Supported by specialized assistants.
+    #[arg(long)]
+    pub model: Option<String>,
+
     /// Prepare a generation task (e.g. render a system prompt) but do not actually generate content
     ///
     /// Assistant implementations should respect this option by returning an empty `GenerateOutput`
@@ -690,6 +854,29 @@ pub struct GenerateOptions {
     #[arg(long)]
     pub image_style: Option<String>,
 
+    /// The names of the tools that the model may call while performing the task.
+    ///
+    /// Tools are opt-in: none are offered to the model unless listed here (e.g. in the
+    /// front matter of an assistant). Because it runs code, `execute_code` is never
+    /// offered unless explicitly listed. Not supported by OpenAI-compatible endpoints.
+    #[arg(long, value_delimiter = ',')]
+    pub tools: Option<Vec<String>>,
+
+    /// The maximum number of rounds of repairing generated code chunks.
+    ///
+    /// If greater than zero, generated `CodeChunk`s are executed in forks of the document's
+    /// kernels and any errors are sent back to the model so that it can correct the code.
+    #[arg(long)]
+    pub repair_rounds: Option<u8>,
+
+    /// Whether to retrieve passages, relevant to the instruction, from the files in the
+    /// document's project and add them to the context of the task.
+    ///
+    /// Retrieval is opt-in: passages are only retrieved if this is `true` (e.g. in the
+    /// front matter of an assistant), and retrieval is not disabled for the document.
+    #[arg(long)]
+    pub passages: Option<bool>,
+
     /// The type of node that each decoded node should be transformed to
     #[serde(
         deserialize_with = "deserialize_option_node_type",
@@ -772,6 +959,17 @@ pub struct GenerateOutput {
 
     /// Stencila Schema nodes generated by the assistant or decoded from `content`
     pub nodes: Nodes,
+
+    /// A transcript of any tool calls, and their results, made while generating the output
+    ///
+    /// Not added to the messages of the instruction, which are the conversation
+    /// with the user, but recorded as the messages of the suggestion created
+    /// from the output so that users can see what the model ran.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub transcript: Vec<InstructionMessage>,
+
+    /// The usage of the model(s) while generating the output
+    pub usage: Option<GenerateUsage>,
 }
 
 impl GenerateOutput {
@@ -785,6 +983,8 @@ impl GenerateOutput {
             format: Format::Unknown,
             content: (String::new()),
             nodes: Nodes::Blocks(vec![]),
+            transcript: Vec::new(),
+            usage: None,
         })
     }
 
@@ -820,7 +1020,7 @@ impl GenerateOutput {
         // Transform content to blocks or inlines depending upon instruction type
         let nodes = if matches!(
             InstructionType::from(instruction),
-            InstructionType::InsertBlocks | InstructionType::ModifyBlocks
+            InstructionType::InsertBlocks | InstructionType::ModifyBlocks | InstructionType::Chat
         ) {
             Nodes::Blocks(content)
         } else {
@@ -901,6 +1101,8 @@ impl GenerateOutput {
             format,
             content: text,
             nodes,
+            transcript: Vec::new(),
+            usage: None,
         })
     }
 
@@ -943,6 +1145,8 @@ impl GenerateOutput {
             format,
             content: url,
             nodes,
+            transcript: Vec::new(),
+            usage: None,
         })
     }
 
@@ -1013,14 +1217,34 @@ impl GenerateOutput {
 
     /// Create a `SuggestionInline` from the output that can be used for the `suggestion`
     /// property of the instruction
+    ///
+    /// The latency of the model(s) is recorded as the execution duration of the suggestion,
+    /// and the transcript as its messages.
     pub fn to_suggestion_inline(self) -> SuggestionInline {
-        SuggestionInline::new(self.nodes.into_inlines())
+        SuggestionInline {
+            prompt_tokens: self.usage.as_ref().map(|usage| usage.prompt_tokens),
+            completion_tokens: self.usage.as_ref().map(|usage| usage.completion_tokens),
+            cost: self.usage.as_ref().and_then(|usage| usage.cost),
+            execution_duration: self.usage.as_ref().map(GenerateUsage::duration),
+            messages: (!self.transcript.is_empty()).then_some(self.transcript),
+            ..SuggestionInline::new(self.nodes.into_inlines())
+        }
     }
 
     /// Create a `SuggestionBlock` from the output that can be used for the `suggestion`
     /// property of the instruction
+    ///
+    /// The latency of the model(s) is recorded as the execution duration of the suggestion,
+    /// and the transcript as its messages.
     pub fn to_suggestion_block(self) -> SuggestionBlock {
-        SuggestionBlock::new(self.nodes.into_blocks())
+        SuggestionBlock {
+            prompt_tokens: self.usage.as_ref().map(|usage| usage.prompt_tokens),
+            completion_tokens: self.usage.as_ref().map(|usage| usage.completion_tokens),
+            cost: self.usage.as_ref().and_then(|usage| usage.cost),
+            execution_duration: self.usage.as_ref().map(GenerateUsage::duration),
+            messages: (!self.transcript.is_empty()).then_some(self.transcript),
+            ..SuggestionBlock::new(self.nodes.into_blocks())
+        }
     }
 }
 
@@ -1285,8 +1509,36 @@ pub trait Model: Sync + Send {
         task: &GenerateTask,
         options: &GenerateOptions,
     ) -> Result<GenerateOutput>;
+
+    /// Perform a generation task, streaming the generated text as it arrives
+    ///
+    /// This default implementation performs the task and then sends all of
+    /// the generated text at once. Models that support streaming should override
+    /// this to send the text as it is generated.
+    async fn perform_task_stream(
+        &self,
+        task: &GenerateTask,
+        options: &GenerateOptions,
+        sender: GenerateSender,
+    ) -> Result<GenerateOutput> {
+        let output = self.perform_task(task, options).await?;
+
+        if output.kind == GenerateKind::Text {
+            sender.send(output.content.clone()).ok();
+        }
+
+        Ok(output)
+    }
 }
 
+/// A sender of the text generated so far by a model
+///
+/// Each message is all of the text generated so far, rather than only the latest
+/// chunk, so that receivers can simply replace any previous partial output and
+/// can skip intermediate messages if they fall behind. If the receiver is dropped
+/// then models should stop generating.
+pub type GenerateSender = UnboundedSender<String>;
+
 /// The provider of a model
 pub enum ModelType {
     Builtin,
@@ -1370,4 +1622,98 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn chat_messages() {
+        let Instruction::Block(mut chat) = Instruction::chat_text("load the data") else {
+            unreachable!()
+        };
+        chat.messages
+            .push(InstructionMessage::assistant("data = read.csv('data.csv')"));
+        chat.messages.push(InstructionMessage::user("now plot it"));
+        chat.suggestions = Some(vec![SuggestionBlock::new(Vec::new())]);
+        let chat = Instruction::Block(chat);
+
+        assert_eq!(InstructionType::from(&chat), InstructionType::Chat);
+
+        // Suggestions are not repeated as messages
+        let roles = chat
+            .messages()
+            .into_iter()
+            .map(|message| message.role)
+            .collect_vec();
+        assert_eq!(
+            roles,
+            vec![None, Some(MessageRole::Assistant), Some(MessageRole::User)]
+        );
+    }
+
+    #[test]
+    fn suggestion_has_transcript() {
+        let output = GenerateOutput {
+            transcript: vec![
+                InstructionMessage::assistant("Calling `list_variables`"),
+                InstructionMessage::user("a: Integer"),
+            ],
+            ..Default::default()
+        };
+        let suggestion = output.to_suggestion_block();
+        assert_eq!(suggestion.messages.map(|messages| messages.len()), Some(2));
+
+        let suggestion = GenerateOutput::default().to_suggestion_block();
+        assert_eq!(suggestion.messages, None);
+    }
+
+    #[test]
+    fn tools_are_opt_in() {
+        #[derive(Debug)]
+        struct TestTools;
+
+        #[async_trait]
+        impl Tools for TestTools {
+            fn list(&self) -> Vec<Tool> {
+                ["list_variables", "execute_code"]
+                    .into_iter()
+                    .map(|name| Tool {
+                        name: name.to_string(),
+                        description: String::new(),
+                        parameters: Default::default(),
+                    })
+                    .collect()
+            }
+
+            async fn call(
+                &self,
+                name: &str,
+                _arguments: &common::serde_json::Value,
+            ) -> Result<String> {
+                Ok(name.to_string())
+            }
+        }
+
+        let names = |options: &GenerateOptions, task: &GenerateTask| {
+            task.offered_tools(options)
+                .map(|(.., offered)| offered.into_iter().map(|tool| tool.name).collect_vec())
+        };
+
+        let task = GenerateTask::new(Instruction::block_text("do it"), None);
+        let options = GenerateOptions {
+            tools: Some(vec!["list_variables".to_string()]),
+            ..Default::default()
+        };
+        assert_eq!(names(&options, &task), None);
+
+        let task = task.with_tools(Some(Arc::new(TestTools)));
+        assert_eq!(names(&GenerateOptions::default(), &task), None);
+        assert_eq!(
+            names(&options, &task),
+            Some(vec!["list_variables".to_string()])
+        );
+
+        let options = GenerateOptions {
+            tools: Some(vec!["unknown".to_string()]),
+            ..Default::default()
+        };
+        assert_eq!(names(&options, &task), None);
+    }
 }
@@ -0,0 +1,141 @@
+//! Reading of server-sent events from streaming HTTP responses
+//!
+//! Used by models whose APIs stream generated text as server-sent events.
+//!
+//! See https://html.spec.whatwg.org/multipage/server-sent-events.html
+
+use common::{
+    eyre::Result,
+    futures::{Stream, StreamExt},
+    reqwest::Response,
+};
+
+/// Read the data of each server-sent event in a response
+///
+/// Calls `data` with the data of each event as it is received. Stops reading
+/// when `data` returns `false` (e.g. for the `[DONE]` event that some APIs send)
+/// or when the response ends.
+pub async fn server_sent_events<F>(response: Response, data: F) -> Result<()>
+where
+    F: FnMut(&str) -> Result<bool>,
+{
+    events(response.bytes_stream(), data).await
+}
+
+/// Read the data of each server-sent event in a stream of bytes
+///
+/// Chunks of bytes may end part way through a line (or a UTF-8 character)
+/// so they are buffered until a complete line is received.
+async fn events<S, B, E, F>(mut stream: S, mut data: F) -> Result<()>
+where
+    S: Stream<Item = Result<B, E>> + Unpin,
+    B: AsRef<[u8]>,
+    E: std::error::Error + Send + Sync + 'static,
+    F: FnMut(&str) -> Result<bool>,
+{
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut event = String::new();
+
+    while let Some(bytes) = stream.next().await {
+        buffer.extend_from_slice(bytes?.as_ref());
+
+        // Process each complete line in the buffer
+        while let Some(position) = buffer.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=position).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+
+            if line.is_empty() {
+                // A blank line ends the event
+                if !event.is_empty() {
+                    if !data(&event)? {
+                        return Ok(());
+                    }
+                    event.clear();
+                }
+            } else if let Some(value) = line.strip_prefix("data:") {
+                // Data over several lines is joined with newlines
+                if !event.is_empty() {
+                    event.push('\n');
+                }
+                event.push_str(value.strip_prefix(' ').unwrap_or(value));
+            }
+        }
+    }
+
+    // Handle any final event not followed by a blank line
+    if !event.is_empty() {
+        data(&event)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use common::{futures::stream, tokio};
+
+    use super::*;
+
+    /// Get the data of the events in a sequence of chunks
+    async fn data(chunks: &[&[u8]]) -> Result<Vec<String>> {
+        let stream = stream::iter(chunks.iter().map(Ok::<_, io::Error>));
+        let mut events = Vec::new();
+        super::events(stream, |data| {
+            events.push(data.to_string());
+            Ok(data != "[DONE]")
+        })
+        .await?;
+        Ok(events)
+    }
+
+    #[tokio::test]
+    async fn split_chunks() -> Result<()> {
+        // Lines, and a multi-byte character (é), split across chunks
+        assert_eq!(
+            data(&[
+                b"da",
+                b"ta: {\"a\":",
+                b" 1}\n",
+                b"\ndata: caf\xc3",
+                b"\xa9\r\n\r",
+                b"\n"
+            ])
+            .await?,
+            vec![r#"{"a": 1}"#, "café"]
+        );
+
+        // Stops after the `[DONE]` event
+        assert_eq!(
+            data(&[b"data: one\n\ndata: [DONE]\n\ndata: two\n\n"]).await?,
+            vec!["one", "[DONE]"]
+        );
+
+        // Final event without a trailing blank line
+        assert_eq!(
+            data(&[b"data: one\n\n", b"data: two"]).await?,
+            vec!["one", "two"]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn multi_line_data() -> Result<()> {
+        // Data fields over several lines are joined with newlines, and
+        // other fields (e.g. `event:`), and comments, are ignored
+        assert_eq!(
+            data(&[
+                b": a comment\nevent: delta\ndata: line one\ndata:line two\n",
+                b"data:\n\n",
+                b"data: next\n\n"
+            ])
+            .await?,
+            vec!["line one\nline two\n", "next"]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+use std::fmt::Debug;
+
+use common::{
+    async_trait::async_trait,
+    eyre::{bail, Result},
+    serde::{Deserialize, Serialize},
+    serde_json::Value,
+    tracing,
+};
+use schema::{Author, ExecutionMessage, InstructionMessage, MessagePart, MessageRole};
+
+use crate::Model;
+
+/// The maximum number of rounds of tool calls a model may make while performing a task
+///
+/// Guards against models that keep calling tools without ever generating a response.
+pub const MAX_TOOL_ROUNDS: usize = 5;
+
+/// A tool that a model can call while performing a task
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "common::serde")]
+pub struct Tool {
+    /// The name of the tool
+    pub name: String,
+
+    /// A description of what the tool does, for the model
+    pub description: String,
+
+    /// A JSON Schema describing the arguments of the tool
+    pub parameters: Value,
+}
+
+/// A call to a tool made by a model
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "common::serde")]
+pub struct ToolCall {
+    /// The id of the call, used by the model to match results to calls
+    pub id: String,
+
+    /// The name of the tool called
+    pub name: String,
+
+    /// The arguments of the call
+    pub arguments: Value,
+}
+
+/// A set of tools that models can call while performing a task
+///
+/// Implemented outside of this crate (e.g. by the executor of a document
+/// so that models can run code in the document's kernels) and attached to
+/// a `GenerateTask` using `GenerateTask::with_tools`. Only the tools named in
+/// the `tools` generate option are offered to models (see `GenerateTask::offered_tools`).
+/// Assistants may also use them to check generated code.
+#[async_trait]
+pub trait Tools: Debug + Send + Sync {
+    /// Get the list of tools available
+    fn list(&self) -> Vec<Tool>;
+
+    /// Call a tool with arguments and get its result as text
+    async fn call(&self, name: &str, arguments: &Value) -> Result<String>;
+
+    /// Execute code, without side effects, and get any resulting messages
+    ///
+    /// Each item is some code and its language, executed in order. Used to check
+    /// that generated code runs before it is suggested. This default implementation
+    /// returns an error because checking code is not supported.
+    async fn check_code(
+        &self,
+        _code: &[(String, Option<String>)],
+    ) -> Result<Vec<ExecutionMessage>> {
+        bail!("Checking code is not supported")
+    }
+}
+
+impl ToolCall {
+    /// Perform the call using a set of tools
+    ///
+    /// Returns the result of the call, and messages recording the call and the result,
+    /// for adding to the transcript of the task. Errors, including calls to tools that
+    /// were not offered to the model, are returned as the result so that the model can
+    /// use them to correct its next call.
+    pub async fn perform(
+        &self,
+        tools: &dyn Tools,
+        offered: &[Tool],
+        model: &dyn Model,
+    ) -> (String, [InstructionMessage; 2]) {
+        tracing::debug!("Calling tool `{}`", self.name);
+
+        let result = if !offered.iter().any(|tool| tool.name == self.name) {
+            format!("Error: Tool `{}` is not available", self.name)
+        } else {
+            match tools.call(&self.name, &self.arguments).await {
+                Ok(result) => result,
+                Err(error) => format!("Error: {error}"),
+            }
+        };
+
+        let call = InstructionMessage {
+            role: Some(MessageRole::Assistant),
+            parts: vec![MessagePart::Text(
+                format!(
+                    "Called tool `{}` with arguments `{}`",
+                    self.name, self.arguments
+                )
+                .into(),
+            )],
+            authors: Some(vec![Author::SoftwareApplication(
+                model.to_software_application(),
+            )]),
+            ..Default::default()
+        };
+
+        let output = InstructionMessage {
+            role: Some(MessageRole::System),
+            parts: vec![MessagePart::Text(
+                format!("Tool `{}` returned:\n\n```\n{result}\n```", self.name).into(),
+            )],
+            ..Default::default()
+        };
+
+        (result, [call, output])
+    }
+}
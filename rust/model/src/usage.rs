@@ -0,0 +1,146 @@
+use std::{ops::AddAssign, time::Instant};
+
+use common::serde::{Deserialize, Serialize};
+use schema::{Duration, TimeUnit};
+
+/// The cost of models in US dollars per million prompt and completion tokens
+///
+/// Each entry is matched against the start of the name of a model, with the
+/// longest matching entry used (so that, for example, `openai/gpt-4o` is not
+/// priced as `openai/gpt-4`). Based on the published prices of each provider.
+/// Models running locally (e.g. via Ollama) are free.
+const COSTS: &[(&str, f64, f64)] = &[
+    ("anthropic/claude-2", 8.0, 24.0),
+    ("anthropic/claude-3-5-sonnet", 3.0, 15.0),
+    ("anthropic/claude-3-haiku", 0.25, 1.25),
+    ("anthropic/claude-3-opus", 15.0, 75.0),
+    ("anthropic/claude-3-sonnet", 3.0, 15.0),
+    ("anthropic/claude-instant", 0.8, 2.4),
+    ("google/gemini-1.0-pro", 0.5, 1.5),
+    ("google/gemini-1.5-flash", 0.35, 1.05),
+    ("google/gemini-1.5-pro", 3.5, 10.5),
+    ("google/gemini-pro", 0.5, 1.5),
+    ("mistral/codestral", 1.0, 3.0),
+    ("mistral/mistral-large", 4.0, 12.0),
+    ("mistral/mistral-medium", 2.7, 8.1),
+    ("mistral/mistral-small", 1.0, 3.0),
+    ("mistral/open-mistral-7b", 0.25, 0.25),
+    ("mistral/open-mixtral-8x22b", 2.0, 6.0),
+    ("mistral/open-mixtral-8x7b", 0.7, 0.7),
+    ("ollama/", 0.0, 0.0),
+    ("openai/gpt-3.5-turbo", 0.5, 1.5),
+    ("openai/gpt-4", 30.0, 60.0),
+    ("openai/gpt-4-0125", 10.0, 30.0),
+    ("openai/gpt-4-1106", 10.0, 30.0),
+    ("openai/gpt-4-32k", 60.0, 120.0),
+    ("openai/gpt-4-turbo", 10.0, 30.0),
+    ("openai/gpt-4o", 5.0, 15.0),
+    ("openai/gpt-4o-mini", 0.15, 0.6),
+];
+
+/// Estimate the cost, in US dollars, of using a model
+///
+/// Returns `None` if the model is not in the table of costs.
+pub fn cost(model: &str, prompt_tokens: u64, completion_tokens: u64) -> Option<f64> {
+    let (.., prompt, completion) = COSTS
+        .iter()
+        .filter(|(prefix, ..)| model.starts_with(prefix))
+        .max_by_key(|(prefix, ..)| prefix.len())?;
+
+    Some((prompt * prompt_tokens as f64 + completion * completion_tokens as f64) / 1_000_000.)
+}
+
+/// The usage of a model when generating output
+///
+/// Recorded by each model provider so that the amount, and estimated cost, of
+/// generation can be reported for each suggestion.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase", crate = "common::serde")]
+pub struct GenerateUsage {
+    /// The number of tokens in the prompt
+    pub prompt_tokens: u64,
+
+    /// The number of tokens generated
+    pub completion_tokens: u64,
+
+    /// The estimated cost in US dollars, if known
+    pub cost: Option<f64>,
+
+    /// The time, in milliseconds, that the model took to respond
+    pub latency: u64,
+}
+
+impl GenerateUsage {
+    /// Create a usage record for a request to a model that started at `started`
+    pub fn new(model: &str, prompt_tokens: u64, completion_tokens: u64, started: Instant) -> Self {
+        Self {
+            prompt_tokens,
+            completion_tokens,
+            cost: cost(model, prompt_tokens, completion_tokens),
+            latency: started.elapsed().as_millis() as u64,
+        }
+    }
+
+    /// Get the latency as a duration
+    pub fn duration(&self) -> Duration {
+        Duration {
+            value: self.latency as i64,
+            time_unit: TimeUnit::Millisecond,
+            ..Default::default()
+        }
+    }
+}
+
+impl AddAssign for GenerateUsage {
+    fn add_assign(&mut self, other: Self) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.cost = match (self.cost, other.cost) {
+            (Some(a), Some(b)) => Some(a + b),
+            (a, b) => a.or(b),
+        };
+        self.latency += other.latency;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cost_uses_longest_prefix() {
+        assert_eq!(cost("openai/gpt-4o-2024-05-13", 1_000_000, 0), Some(5.0));
+        assert_eq!(cost("openai/gpt-4o-mini", 1_000_000, 0), Some(0.15));
+        assert_eq!(
+            cost("openai/gpt-4o-mini-2024-07-18", 0, 1_000_000),
+            Some(0.6)
+        );
+        assert_eq!(cost("openai/gpt-4-0613", 1_000_000, 0), Some(30.0));
+        assert_eq!(
+            cost("openai/gpt-4-turbo-2024-04-09", 0, 1_000_000),
+            Some(30.0)
+        );
+        assert_eq!(cost("ollama/llama3:8b", 1000, 1000), Some(0.0));
+        assert_eq!(cost("unknown/model", 1000, 1000), None);
+    }
+
+    #[test]
+    fn usage_add_assign() {
+        let mut usage = GenerateUsage {
+            prompt_tokens: 10,
+            completion_tokens: 5,
+            cost: None,
+            latency: 100,
+        };
+        usage += GenerateUsage {
+            prompt_tokens: 20,
+            completion_tokens: 10,
+            cost: Some(0.5),
+            latency: 200,
+        };
+        assert_eq!(usage.prompt_tokens, 30);
+        assert_eq!(usage.completion_tokens, 15);
+        assert_eq!(usage.cost, Some(0.5));
+        assert_eq!(usage.latency, 300);
+    }
+}
@@ -1,17 +1,19 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Instant};
 
 use model::{
     common::{
         async_trait::async_trait,
-        eyre::{bail, Result},
+        eyre::{bail, eyre, Result},
         itertools::Itertools,
-        reqwest::Client,
+        reqwest::{Client, Response},
         serde::{Deserialize, Serialize},
+        serde_json,
         serde_with::skip_serializing_none,
         tracing,
     },
-    schema::MessagePart,
-    secrets, GenerateOptions, GenerateOutput, GenerateTask, Model, ModelIO, ModelType,
+    schema::{MessagePart, MessageRole},
+    secrets, server_sent_events, GenerateOptions, GenerateOutput, GenerateSender, GenerateTask,
+    GenerateUsage, Model, ModelIO, ModelType, ToolCall, MAX_TOOL_ROUNDS,
 };
 
 /// The base URL for the Anthropic API
@@ -68,10 +70,40 @@ impl Model for AnthropicModel {
         &[ModelIO::Text]
     }
 
+    #[tracing::instrument(skip(self))]
     async fn perform_task(
         &self,
         task: &GenerateTask,
         options: &GenerateOptions,
+    ) -> Result<GenerateOutput> {
+        self.messages(task, options, None).await
+    }
+
+    #[tracing::instrument(skip(self, sender))]
+    async fn perform_task_stream(
+        &self,
+        task: &GenerateTask,
+        options: &GenerateOptions,
+        sender: GenerateSender,
+    ) -> Result<GenerateOutput> {
+        self.messages(task, options, Some(sender)).await
+    }
+}
+
+impl AnthropicModel {
+    /// Send a request to the Messages API
+    ///
+    /// If a `sender` is supplied then the response is streamed and the text
+    /// generated so far is sent on it as each chunk arrives.
+    ///
+    /// If tools are offered for the task then the model may call them, over several
+    /// requests, before it generates its response. In that case the response is not
+    /// streamed but is sent on the `sender` once complete.
+    async fn messages(
+        &self,
+        task: &GenerateTask,
+        options: &GenerateOptions,
+        sender: Option<GenerateSender>,
     ) -> Result<GenerateOutput> {
         let system = match &task.system_prompt() {
             Some(prompt) => prompt.clone(),
@@ -81,22 +113,18 @@ impl Model for AnthropicModel {
         let messages = task
             .instruction_messages()
             .iter()
-            .map(|message| {
-                println!("{message:#?}");
-
-                let role = message
-                    .role
-                    .clone()
-                    .unwrap_or_default()
-                    .to_string()
-                    .to_lowercase();
+            .filter_map(|message| {
+                let role = match message.role.clone().unwrap_or_default() {
+                    // System messages are not part of the conversation
+                    MessageRole::System => return None,
+                    role => role.to_string().to_lowercase(),
+                };
 
                 let content = message
                     .parts
                     .iter()
                     .filter_map(|part| match part {
-                        MessagePart::Text(text) => Some(ContentPart {
-                            r#type: "text".to_string(),
+                        MessagePart::Text(text) => Some(ContentPart::Text {
                             text: text.to_value_string(),
                         }),
                         _ => {
@@ -109,28 +137,176 @@ impl Model for AnthropicModel {
                     })
                     .collect_vec();
 
-                Message { role, content }
+                Some(Message { role, content })
             })
             .collect_vec();
 
-        let request = MessagesRequest {
+        let mut request = MessagesRequest {
             model: self.model.clone(),
             system,
             max_tokens: options.max_tokens.unwrap_or(1024),
             temperature: options.temperature,
             messages,
+            tools: None,
+            stream: None,
         };
 
         if options.dry_run {
             return GenerateOutput::empty(self);
         }
 
+        let started = Instant::now();
+        let mut usage = MessagesUsage::default();
+        let mut transcript = Vec::new();
+        let text = if let Some((tools, offered)) = task.offered_tools(options) {
+            request.tools = Some(
+                offered
+                    .iter()
+                    .cloned()
+                    .map(|tool| ToolDefinition {
+                        name: tool.name,
+                        description: tool.description,
+                        input_schema: tool.parameters,
+                    })
+                    .collect(),
+            );
+
+            // Perform any tool calls and send the results back to the model until
+            // it responds without calling any tools
+            let mut text = None;
+            for _ in 0..MAX_TOOL_ROUNDS {
+                let response: MessagesResponse = self.send(&request).await?.json().await?;
+                usage.input_tokens += response.usage.input_tokens;
+                usage.output_tokens += response.usage.output_tokens;
+
+                let calls = response
+                    .content
+                    .iter()
+                    .filter_map(|part| match part {
+                        ContentPart::ToolUse { id, name, input } => Some(ToolCall {
+                            id: id.clone(),
+                            name: name.clone(),
+                            arguments: input.clone(),
+                        }),
+                        _ => None,
+                    })
+                    .collect_vec();
+                if calls.is_empty() {
+                    text = Some(response.text());
+                    break;
+                }
+
+                request.messages.push(Message {
+                    role: "assistant".to_string(),
+                    content: response.content,
+                });
+
+                let mut results = Vec::new();
+                for call in calls {
+                    let (result, messages) = call.perform(tools, &offered, self).await;
+                    transcript.extend(messages);
+
+                    results.push(ContentPart::ToolResult {
+                        tool_use_id: call.id,
+                        content: result,
+                    });
+                }
+                request.messages.push(Message {
+                    role: "user".to_string(),
+                    content: results,
+                });
+            }
+
+            let Some(text) = text else {
+                bail!(
+                    "Model `{}` did not respond within {MAX_TOOL_ROUNDS} rounds of tool calls",
+                    self.name()
+                )
+            };
+
+            if let Some(sender) = sender {
+                sender.send(text.clone()).ok();
+            }
+
+            text
+        } else if let Some(sender) = sender {
+            request.stream = Some(true);
+            let response = self.send(&request).await?;
+
+            let mut text = String::new();
+            let mut index = 0;
+            server_sent_events(response, |data| {
+                let event: MessagesEvent = serde_json::from_str(data)?;
+                match event.r#type.as_str() {
+                    "message_start" => {
+                        if let Some(message) = event.message {
+                            usage.input_tokens += message.usage.input_tokens;
+                        }
+                        Ok(true)
+                    }
+                    "message_delta" => {
+                        if let Some(delta) = event.usage {
+                            usage.output_tokens += delta.output_tokens;
+                        }
+                        Ok(true)
+                    }
+                    "content_block_delta" => {
+                        let Some(delta) = event.delta.and_then(|delta| delta.text) else {
+                            return Ok(true);
+                        };
+
+                        // Separate content blocks in the same way as for non-streamed responses
+                        let block = event.index.unwrap_or_default();
+                        if block != index && !text.is_empty() {
+                            text += "\n\n";
+                        }
+                        index = block;
+
+                        text += &delta;
+                        if sender.send(text.clone()).is_err() {
+                            bail!("Generation cancelled because receiver was dropped");
+                        }
+
+                        Ok(true)
+                    }
+                    "message_stop" => Ok(false),
+                    "error" => Err(eyre!(
+                        "{}",
+                        event.error.map(|error| error.message).unwrap_or_default()
+                    )),
+                    _ => Ok(true),
+                }
+            })
+            .await?;
+            text
+        } else {
+            let response: MessagesResponse = self.send(&request).await?.json().await?;
+            usage = response.usage;
+            response.text()
+        };
+
+        let mut output =
+            GenerateOutput::from_text(self, task.format(), task.instruction(), options, text)
+                .await?;
+        output.transcript = transcript;
+        output.usage = Some(GenerateUsage::new(
+            &self.name(),
+            usage.input_tokens,
+            usage.output_tokens,
+            started,
+        ));
+
+        Ok(output)
+    }
+
+    /// Send a request to the Messages API and check that it was successful
+    async fn send(&self, request: &MessagesRequest) -> Result<Response> {
         let response = self
             .client
             .post(format!("{BASE_URL}/messages/"))
             .header("x-api-key", secrets::env_or_get(API_KEY)?)
             .header("anthropic-version", API_VERSION)
-            .json(&request)
+            .json(request)
             .send()
             .await?;
 
@@ -139,15 +315,7 @@ impl Model for AnthropicModel {
             bail!("{error}: {message}");
         }
 
-        let response: MessagesResponse = response.json().await?;
-
-        let text = response
-            .content
-            .into_iter()
-            .map(|part| part.text)
-            .join("\n\n");
-
-        GenerateOutput::from_text(self, task.format(), task.instruction(), options, text).await
+        Ok(response)
     }
 }
 
@@ -183,17 +351,29 @@ pub async fn list() -> Result<Vec<Arc<dyn Model>>> {
 
 /// A part within the content of a message in the Messages API
 ///
-/// Note: at present only `text` type is handled
+/// Note: at present only text, and tool use and result, parts are handled
 #[derive(Debug, Serialize, Deserialize)]
-#[serde(crate = "model::common::serde")]
-struct ContentPart {
-    r#type: String,
-    text: String,
+#[serde(
+    tag = "type",
+    rename_all = "snake_case",
+    crate = "model::common::serde"
+)]
+enum ContentPart {
+    Text {
+        text: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+    ToolResult {
+        tool_use_id: String,
+        content: String,
+    },
 }
 
 /// A Messages API message
-///
-/// Note: at present only text content is handled
 #[derive(Debug, Serialize)]
 #[serde(crate = "model::common::serde")]
 struct Message {
@@ -201,6 +381,15 @@ struct Message {
     content: Vec<ContentPart>,
 }
 
+/// A tool definition in a Messages API request
+#[derive(Debug, Serialize)]
+#[serde(crate = "model::common::serde")]
+struct ToolDefinition {
+    name: String,
+    description: String,
+    input_schema: serde_json::Value,
+}
+
 /// A Messages API request body
 ///
 /// Based on https://docs.anthropic.com/en/api/messages.
@@ -214,6 +403,8 @@ struct MessagesRequest {
     max_tokens: u16,
     temperature: Option<f32>,
     messages: Vec<Message>,
+    tools: Option<Vec<ToolDefinition>>,
+    stream: Option<bool>,
 }
 
 /// A Messages API response body
@@ -225,6 +416,66 @@ struct MessagesRequest {
 #[serde(crate = "model::common::serde")]
 struct MessagesResponse {
     content: Vec<ContentPart>,
+    #[serde(default)]
+    usage: MessagesUsage,
+}
+
+/// The token usage of a Messages API request
+#[derive(Default, Deserialize)]
+#[serde(default, crate = "model::common::serde")]
+struct MessagesUsage {
+    input_tokens: u64,
+    output_tokens: u64,
+}
+
+impl MessagesResponse {
+    /// Get the text of the response, ignoring any tool use parts
+    fn text(&self) -> String {
+        self.content
+            .iter()
+            .filter_map(|part| match part {
+                ContentPart::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .join("\n\n")
+    }
+}
+
+/// A Messages API streaming event
+///
+/// Based on https://docs.anthropic.com/en/api/messages-streaming.
+/// Note: at present only the fields needed for text deltas, usage and errors are handled.
+#[derive(Deserialize)]
+#[serde(crate = "model::common::serde")]
+struct MessagesEvent {
+    r#type: String,
+    index: Option<usize>,
+    message: Option<MessagesEventMessage>,
+    delta: Option<MessagesEventDelta>,
+    usage: Option<MessagesUsage>,
+    error: Option<MessagesEventError>,
+}
+
+/// The message of a `message_start` streaming event
+#[derive(Deserialize)]
+#[serde(crate = "model::common::serde")]
+struct MessagesEventMessage {
+    #[serde(default)]
+    usage: MessagesUsage,
+}
+
+/// The delta of a `content_block_delta` streaming event
+#[derive(Deserialize)]
+#[serde(crate = "model::common::serde")]
+struct MessagesEventDelta {
+    text: Option<String>,
+}
+
+/// The error of an `error` streaming event
+#[derive(Deserialize)]
+#[serde(crate = "model::common::serde")]
+struct MessagesEventError {
+    message: String,
 }
 
 #[cfg(test)]
@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Instant};
 
 use cached::proc_macro::cached;
 
@@ -9,11 +9,13 @@ use model::{
         itertools::Itertools,
         reqwest::Client,
         serde::{Deserialize, Serialize},
+        serde_json,
         serde_with::skip_serializing_none,
         tracing,
     },
     schema::{ImageObject, MessagePart, MessageRole},
-    secrets, GenerateOptions, GenerateOutput, GenerateTask, Model, ModelIO, ModelType,
+    secrets, server_sent_events, GenerateOptions, GenerateOutput, GenerateSender, GenerateTask,
+    GenerateUsage, Model, ModelIO, ModelType,
 };
 
 const BASE_URL: &str = "https://generativelanguage.googleapis.com/v1";
@@ -74,6 +76,31 @@ impl Model for GoogleModel {
         &self,
         task: &GenerateTask,
         options: &GenerateOptions,
+    ) -> Result<GenerateOutput> {
+        self.generate_content(task, options, None).await
+    }
+
+    #[tracing::instrument(skip(self, sender))]
+    async fn perform_task_stream(
+        &self,
+        task: &GenerateTask,
+        options: &GenerateOptions,
+        sender: GenerateSender,
+    ) -> Result<GenerateOutput> {
+        self.generate_content(task, options, Some(sender)).await
+    }
+}
+
+impl GoogleModel {
+    /// Generate content
+    ///
+    /// If a `sender` is supplied then the content is streamed and the text
+    /// generated so far is sent on it as each chunk arrives.
+    async fn generate_content(
+        &self,
+        task: &GenerateTask,
+        options: &GenerateOptions,
+        sender: Option<GenerateSender>,
     ) -> Result<GenerateOutput> {
         let contents = task
             .system_prompt()
@@ -147,12 +174,16 @@ impl Model for GoogleModel {
             return GenerateOutput::empty(self);
         }
 
+        let method = if sender.is_some() {
+            "streamGenerateContent?alt=sse"
+        } else {
+            "generateContent"
+        };
+
+        let started = Instant::now();
         let response = self
             .client
-            .post(format!(
-                "{}/models/{}:generateContent",
-                BASE_URL, self.model
-            ))
+            .post(format!("{}/models/{}:{method}", BASE_URL, self.model))
             .query(&[("key", secrets::env_or_get(API_KEY)?)])
             .json(&request)
             .send()
@@ -163,31 +194,74 @@ impl Model for GoogleModel {
             bail!("{error}: {message}");
         }
 
-        let mut response: GenerateContentResponse = response.json().await?;
+        let mut usage = UsageMetadata::default();
+        let content = if let Some(sender) = sender {
+            let mut text = String::new();
+            let mut blob = None;
+            server_sent_events(response, |data| {
+                let response: GenerateContentResponse = serde_json::from_str(data)?;
+                if let Some(metadata) = response.usage_metadata {
+                    // Each chunk has the cumulative usage so far
+                    usage = metadata;
+                }
+                let Some(candidate) = response.candidates.into_iter().next() else {
+                    return Ok(true);
+                };
 
-        let content = response
-            .candidates
-            .swap_remove(0)
-            .content
-            .parts
-            .swap_remove(0);
+                for part in candidate.content.parts {
+                    if let Some(content) = part.text {
+                        text += &content;
+                        if sender.send(text.clone()).is_err() {
+                            bail!("Generation cancelled because receiver was dropped");
+                        }
+                    } else if part.inline_data.is_some() {
+                        blob = Some(part);
+                    }
+                }
+
+                Ok(true)
+            })
+            .await?;
 
-        match content {
+            blob.unwrap_or_else(|| Part::text(&text))
+        } else {
+            let mut response: GenerateContentResponse = response.json().await?;
+            if let Some(metadata) = response.usage_metadata.take() {
+                usage = metadata;
+            }
+
+            response
+                .candidates
+                .swap_remove(0)
+                .content
+                .parts
+                .swap_remove(0)
+        };
+
+        let mut output = match content {
             Part {
                 text: Some(text), ..
             } => {
                 GenerateOutput::from_text(self, task.format(), task.instruction(), options, text)
-                    .await
+                    .await?
             }
             Part {
                 inline_data: Some(Blob { mime_type, data }),
                 ..
             } => {
                 GenerateOutput::from_url(self, &mime_type, format!("{};base64,{}", mime_type, data))
-                    .await
+                    .await?
             }
             _ => bail!("Unexpected response content part"),
-        }
+        };
+        output.usage = Some(GenerateUsage::new(
+            &self.name(),
+            usage.prompt_token_count,
+            usage.candidates_token_count,
+            started,
+        ));
+
+        Ok(output)
     }
 }
 
@@ -228,9 +302,21 @@ struct GenerateContentRequest {
 /// Based on https://ai.google.dev/api/rest/v1beta/GenerateContentResponse.
 /// Note: at present the `promptFeedback` field ignored.
 #[derive(Deserialize)]
-#[serde(crate = "model::common::serde")]
+#[serde(rename_all = "camelCase", crate = "model::common::serde")]
 struct GenerateContentResponse {
+    #[serde(default)]
     candidates: Vec<Candidate>,
+    usage_metadata: Option<UsageMetadata>,
+}
+
+/// The token usage of a generate content request
+///
+/// Based on https://ai.google.dev/api/rest/v1beta/GenerateContentResponse#UsageMetadata.
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "camelCase", crate = "model::common::serde")]
+struct UsageMetadata {
+    prompt_token_count: u64,
+    candidates_token_count: u64,
 }
 
 /// A candidate in a generate content response
@@ -252,6 +338,7 @@ struct Candidate {
 #[serde(crate = "model::common::serde")]
 struct Content {
     role: Option<Role>,
+    #[serde(default)]
     parts: Vec<Part>,
 }
 
@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Instant};
 
 use cached::proc_macro::cached;
 
@@ -9,11 +9,13 @@ use model::{
         itertools::Itertools,
         reqwest::Client,
         serde::{Deserialize, Serialize},
+        serde_json,
         serde_with::skip_serializing_none,
         tracing,
     },
     schema::{MessagePart, MessageRole},
-    secrets, GenerateOptions, GenerateOutput, GenerateTask, Model, ModelIO, ModelType,
+    secrets, server_sent_events, GenerateOptions, GenerateOutput, GenerateSender, GenerateTask,
+    GenerateUsage, Model, ModelIO, ModelType,
 };
 
 const BASE_URL: &str = "https://api.mistral.ai/v1";
@@ -70,6 +72,31 @@ impl Model for MistralModel {
         &self,
         task: &GenerateTask,
         options: &GenerateOptions,
+    ) -> Result<GenerateOutput> {
+        self.chat_completion(task, options, None).await
+    }
+
+    #[tracing::instrument(skip(self, sender))]
+    async fn perform_task_stream(
+        &self,
+        task: &GenerateTask,
+        options: &GenerateOptions,
+        sender: GenerateSender,
+    ) -> Result<GenerateOutput> {
+        self.chat_completion(task, options, Some(sender)).await
+    }
+}
+
+impl MistralModel {
+    /// Create a chat completion
+    ///
+    /// If a `sender` is supplied then the completion is streamed and the text
+    /// generated so far is sent on it as each chunk arrives.
+    async fn chat_completion(
+        &self,
+        task: &GenerateTask,
+        options: &GenerateOptions,
+        sender: Option<GenerateSender>,
     ) -> Result<GenerateOutput> {
         let messages = task
             .system_prompt()
@@ -111,12 +138,14 @@ impl Model for MistralModel {
             top_p: options.top_p,
             max_tokens: options.max_tokens,
             random_seed: options.seed,
+            stream: sender.is_some().then_some(true),
         };
 
         if options.dry_run {
             return GenerateOutput::empty(self);
         }
 
+        let started = Instant::now();
         let response = self
             .client
             .post(format!("{BASE_URL}/chat/completions"))
@@ -129,11 +158,52 @@ impl Model for MistralModel {
             bail!(error);
         }
 
-        let mut response: ChatCompletionResponse = response.json().await?;
+        let mut usage = ChatCompletionUsage::default();
+        let text = if let Some(sender) = sender {
+            let mut text = String::new();
+            server_sent_events(response, |data| {
+                if data == "[DONE]" {
+                    return Ok(false);
+                }
+
+                let mut chunk: ChatCompletionChunk = serde_json::from_str(data)?;
+                if let Some(chunk_usage) = chunk.usage {
+                    usage = chunk_usage;
+                }
+                if chunk.choices.is_empty() {
+                    return Ok(true);
+                }
+                let Some(content) = chunk.choices.swap_remove(0).delta.content else {
+                    return Ok(true);
+                };
 
-        let text = response.choices.swap_remove(0).message.content;
+                text += &content;
+                if sender.send(text.clone()).is_err() {
+                    bail!("Generation cancelled because receiver was dropped");
+                }
+
+                Ok(true)
+            })
+            .await?;
+            text
+        } else {
+            let mut response: ChatCompletionResponse = response.json().await?;
+            usage = response.usage;
 
-        GenerateOutput::from_text(self, task.format(), task.instruction(), options, text).await
+            response.choices.swap_remove(0).message.content
+        };
+
+        let mut output =
+            GenerateOutput::from_text(self, task.format(), task.instruction(), options, text)
+                .await?;
+        output.usage = Some(GenerateUsage::new(
+            &self.name(),
+            usage.prompt_tokens,
+            usage.completion_tokens,
+            started,
+        ));
+
+        Ok(output)
     }
 }
 
@@ -168,6 +238,7 @@ struct ChatCompletionRequest {
     top_p: Option<f32>,
     max_tokens: Option<u16>,
     random_seed: Option<i32>,
+    stream: Option<bool>,
 }
 
 /// A chat completion response
@@ -178,6 +249,16 @@ struct ChatCompletionRequest {
 #[serde(crate = "model::common::serde")]
 struct ChatCompletionResponse {
     choices: Vec<ChatCompletionChoice>,
+    #[serde(default)]
+    usage: ChatCompletionUsage,
+}
+
+/// The token usage of a chat completion request
+#[derive(Default, Deserialize)]
+#[serde(default, crate = "model::common::serde")]
+struct ChatCompletionUsage {
+    prompt_tokens: u64,
+    completion_tokens: u64,
 }
 
 /// A choice within a `ChatCompletionResponse`
@@ -190,6 +271,31 @@ struct ChatCompletionChoice {
     message: ChatMessage,
 }
 
+/// A chunk of a streamed chat completion response
+///
+/// Note: at present several other fields are ignored.
+#[derive(Deserialize)]
+#[serde(crate = "model::common::serde")]
+struct ChatCompletionChunk {
+    choices: Vec<ChatCompletionChunkChoice>,
+    /// Only present in the last chunk
+    usage: Option<ChatCompletionUsage>,
+}
+
+/// A choice within a `ChatCompletionChunk`
+#[derive(Deserialize)]
+#[serde(crate = "model::common::serde")]
+struct ChatCompletionChunkChoice {
+    delta: ChatCompletionDelta,
+}
+
+/// The change to the content of a message within a `ChatCompletionChunkChoice`
+#[derive(Deserialize)]
+#[serde(crate = "model::common::serde")]
+struct ChatCompletionDelta {
+    content: Option<String>,
+}
+
 /// A chat message within a `ChatCompletionRequest` or a `ChatCompletionResponse`
 #[skip_serializing_none]
 #[derive(Serialize, Deserialize)]
@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Instant};
 
 use ollama_rs::{
     generation::{
@@ -12,12 +12,14 @@ use ollama_rs::{
 use model::{
     common::{
         async_trait::async_trait,
-        eyre::{eyre, Result},
+        eyre::{bail, eyre, Result},
+        futures::StreamExt,
         inflector::Inflector,
         tracing,
     },
     schema::{self, ImageObject, MessagePart},
-    GenerateOptions, GenerateOutput, GenerateTask, Model, ModelIO, ModelType,
+    GenerateOptions, GenerateOutput, GenerateSender, GenerateTask, GenerateUsage, Model, ModelIO,
+    ModelType,
 };
 
 /// A model running on a Ollama (https://github.com/jmorganca/ollama/) server
@@ -118,10 +120,36 @@ impl Model for OllamaModel {
         &[ModelIO::Text]
     }
 
+    #[tracing::instrument(skip(self))]
     async fn perform_task(
         &self,
         task: &GenerateTask,
         options: &GenerateOptions,
+    ) -> Result<GenerateOutput> {
+        self.chat(task, options, None).await
+    }
+
+    #[tracing::instrument(skip(self, sender))]
+    async fn perform_task_stream(
+        &self,
+        task: &GenerateTask,
+        options: &GenerateOptions,
+        sender: GenerateSender,
+    ) -> Result<GenerateOutput> {
+        self.chat(task, options, Some(sender)).await
+    }
+}
+
+impl OllamaModel {
+    /// Send chat messages to the model
+    ///
+    /// If a `sender` is supplied then the response is streamed and the text
+    /// generated so far is sent on it as each chunk arrives.
+    async fn chat(
+        &self,
+        task: &GenerateTask,
+        options: &GenerateOptions,
+        sender: Option<GenerateSender>,
     ) -> Result<GenerateOutput> {
         let messages = task
             .system_prompt()
@@ -228,18 +256,71 @@ impl Model for OllamaModel {
             return GenerateOutput::empty(self);
         }
 
-        let response = self
-            .client
-            .send_chat_messages(request)
-            .await
-            .map_err(|error| eyre!(error))?;
+        let started = Instant::now();
+        let mut prompt_tokens = 0;
+        let mut completion_tokens = 0;
+        let text = if let Some(sender) = sender {
+            let mut stream = self
+                .client
+                .send_chat_messages_stream(request)
+                .await
+                .map_err(|error| eyre!(error))?;
+
+            let mut text = String::new();
+            while let Some(response) = stream.next().await {
+                let Ok(response) = response else {
+                    bail!(
+                        "Error while streaming response from model `{}`",
+                        self.name()
+                    );
+                };
+
+                if let Some(message) = response.message {
+                    text += &message.content;
+                    if sender.send(text.clone()).is_err() {
+                        bail!("Generation cancelled because receiver was dropped");
+                    }
+                }
+
+                if let Some(data) = response.final_data {
+                    prompt_tokens = data.prompt_eval_count as u64;
+                    completion_tokens = data.eval_count as u64;
+                }
 
-        let text = response
-            .message
-            .map(|message| message.content)
-            .unwrap_or_default();
+                if response.done {
+                    break;
+                }
+            }
+            text
+        } else {
+            let response = self
+                .client
+                .send_chat_messages(request)
+                .await
+                .map_err(|error| eyre!(error))?;
+
+            if let Some(data) = &response.final_data {
+                prompt_tokens = data.prompt_eval_count as u64;
+                completion_tokens = data.eval_count as u64;
+            }
+
+            response
+                .message
+                .map(|message| message.content)
+                .unwrap_or_default()
+        };
 
-        GenerateOutput::from_text(self, task.format(), task.instruction(), options, text).await
+        let mut output =
+            GenerateOutput::from_text(self, task.format(), task.instruction(), options, text)
+                .await?;
+        output.usage = Some(GenerateUsage::new(
+            &self.name(),
+            prompt_tokens,
+            completion_tokens,
+            started,
+        ));
+
+        Ok(output)
     }
 }
 
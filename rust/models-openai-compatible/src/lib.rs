@@ -0,0 +1,189 @@
+use std::{path::PathBuf, sync::Arc};
+
+use app::{get_app_dir, DirType};
+use model::{
+    common::{
+        eyre::{eyre, Result},
+        serde::Deserialize,
+        tokio::fs::read_to_string,
+        toml, tracing,
+    },
+    Model, ModelIO,
+};
+use models_openai::OpenAIModel;
+
+/// The name of the file, in the Stencila config directory, declaring endpoints
+const CONFIG_FILE: &str = "models.toml";
+
+/// The names of providers which can not be used as the names of endpoints
+const RESERVED_NAMES: &[&str] = &[
+    "anthropic",
+    "google",
+    "mistral",
+    "ollama",
+    "openai",
+    "stencila",
+];
+
+/// The default context length of models that do not declare one
+const CONTEXT_LENGTH: usize = 4_096;
+
+/// The models config file
+///
+/// An example of a config file declaring two endpoints:
+///
+/// ```toml
+/// [[openai-compatible]]
+/// name = "vllm"
+/// url = "http://localhost:8000/v1"
+/// models = [
+///   { name = "meta-llama/Meta-Llama-3-8B-Instruct", context-length = 8192 }
+/// ]
+///
+/// [[openai-compatible]]
+/// name = "lmstudio"
+/// url = "http://localhost:1234/v1"
+/// api-key = "LMSTUDIO_API_KEY"
+/// models = [
+///   { name = "llava-v1.5-7b", inputs = ["text", "image"] }
+/// ]
+/// ```
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "kebab-case", crate = "model::common::serde")]
+struct Config {
+    /// The OpenAI-compatible endpoints
+    #[serde(default)]
+    openai_compatible: Vec<Endpoint>,
+}
+
+/// A server with an OpenAI-compatible API
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", crate = "model::common::serde")]
+struct Endpoint {
+    /// The name of the endpoint
+    ///
+    /// Used as the prefix of the names of the endpoint's models e.g. `vllm/llama3`.
+    name: String,
+
+    /// The base URL of the API e.g. `http://localhost:8000/v1`
+    url: String,
+
+    /// The name of the env var or secret for the API key, if one is required
+    api_key: Option<String>,
+
+    /// The models available at the endpoint
+    #[serde(default)]
+    models: Vec<EndpointModel>,
+}
+
+/// A model available at an endpoint
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", crate = "model::common::serde")]
+struct EndpointModel {
+    /// The name of the model used in requests to the endpoint
+    name: String,
+
+    /// The context length of the model
+    context_length: Option<usize>,
+
+    /// The types of input that the model consumes (defaults to text only)
+    inputs: Option<Vec<ModelIO>>,
+
+    /// The types of output that the model generates (defaults to text only)
+    outputs: Option<Vec<ModelIO>>,
+}
+
+/// Get the path of the models config file
+fn config_path() -> Result<PathBuf> {
+    Ok(get_app_dir(DirType::Config, false)?.join(CONFIG_FILE))
+}
+
+/// Read the models config file
+///
+/// Returns the default, empty, config if the file does not exist.
+async fn read_config() -> Result<Config> {
+    let path = config_path()?;
+    if !path.exists() {
+        return Ok(Config::default());
+    }
+
+    let content = read_to_string(&path).await?;
+    toml::from_str(&content).map_err(|error| eyre!("While parsing `{}`: {error}", path.display()))
+}
+
+/// Get a list of the models at all OpenAI-compatible endpoints
+///
+/// Endpoints are declared in the `models.toml` file in the Stencila config directory.
+/// Endpoints are not queried for their models: only the models declared for each
+/// endpoint are listed. Endpoints whose names clash with builtin providers are ignored.
+pub async fn list() -> Result<Vec<Arc<dyn Model>>> {
+    let config = read_config().await?;
+
+    let mut models = Vec::new();
+    for endpoint in config.openai_compatible {
+        if RESERVED_NAMES.contains(&endpoint.name.as_str()) || endpoint.name.contains('/') {
+            tracing::warn!(
+                "OpenAI-compatible endpoint name `{}` is not allowed so endpoint will be ignored",
+                endpoint.name
+            );
+            continue;
+        }
+
+        for model in endpoint.models {
+            models.push(Arc::new(OpenAIModel::compatible(
+                endpoint.name.clone(),
+                endpoint.url.clone(),
+                endpoint.api_key.clone(),
+                model.name,
+                model.context_length.unwrap_or(CONTEXT_LENGTH),
+                model.inputs.unwrap_or_else(|| vec![ModelIO::Text]),
+                model.outputs.unwrap_or_else(|| vec![ModelIO::Text]),
+            )) as Arc<dyn Model>)
+        }
+    }
+
+    Ok(models)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_config() -> Result<()> {
+        let config: Config = toml::from_str(
+            r#"
+[[openai-compatible]]
+name = "vllm"
+url = "http://localhost:8000/v1"
+models = [
+  { name = "meta-llama/Meta-Llama-3-8B-Instruct", context-length = 8192 }
+]
+
+[[openai-compatible]]
+name = "lmstudio"
+url = "http://localhost:1234/v1"
+api-key = "LMSTUDIO_API_KEY"
+models = [
+  { name = "llava-v1.5-7b", inputs = ["text", "image"] }
+]
+"#,
+        )?;
+
+        assert_eq!(config.openai_compatible.len(), 2);
+
+        let vllm = &config.openai_compatible[0];
+        assert_eq!(vllm.name, "vllm");
+        assert_eq!(vllm.api_key, None);
+        assert_eq!(vllm.models[0].context_length, Some(8192));
+
+        let lmstudio = &config.openai_compatible[1];
+        assert_eq!(lmstudio.api_key.as_deref(), Some("LMSTUDIO_API_KEY"));
+        assert_eq!(
+            lmstudio.models[0].inputs,
+            Some(vec![ModelIO::Text, ModelIO::Image])
+        );
+
+        Ok(())
+    }
+}
@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Instant};
 
 use async_openai::{
     config::OpenAIConfig,
@@ -6,9 +6,11 @@ use async_openai::{
         ChatCompletionRequestAssistantMessage, ChatCompletionRequestMessage,
         ChatCompletionRequestMessageContentPart, ChatCompletionRequestMessageContentPartImage,
         ChatCompletionRequestMessageContentPartText, ChatCompletionRequestSystemMessage,
-        ChatCompletionRequestUserMessage, ChatCompletionRequestUserMessageContent,
-        CreateChatCompletionRequest, CreateImageRequestArgs, Image, ImageDetail, ImageQuality,
-        ImageSize, ImageStyle, ImageUrl, ResponseFormat, Stop,
+        ChatCompletionRequestToolMessage, ChatCompletionRequestUserMessage,
+        ChatCompletionRequestUserMessageContent, ChatCompletionStreamOptions, ChatCompletionTool,
+        ChatCompletionToolType, CreateChatCompletionRequest, CreateImageRequestArgs,
+        FunctionObject, Image, ImageDetail, ImageQuality, ImageSize, ImageStyle, ImageUrl,
+        ResponseFormat, Stop,
     },
     Client,
 };
@@ -18,19 +20,35 @@ use model::{
     common::{
         async_trait::async_trait,
         eyre::{bail, Result},
+        futures::StreamExt,
         inflector::Inflector,
         itertools::Itertools,
-        tracing,
+        serde_json, tracing,
     },
     schema::{ImageObject, MessagePart, MessageRole},
-    secrets, GenerateOptions, GenerateOutput, GenerateTask, Model, ModelIO, ModelType,
+    secrets, GenerateOptions, GenerateOutput, GenerateSender, GenerateTask, GenerateUsage, Model,
+    ModelIO, ModelType, ToolCall, MAX_TOOL_ROUNDS,
 };
 
 /// The name of the env var or secret for the API key
 const API_KEY: &str = "OPENAI_API_KEY";
 
-/// A model running on OpenAI
+/// A model running on OpenAI, or on a server with an OpenAI-compatible API
 pub struct OpenAIModel {
+    /// The provider of the model
+    ///
+    /// Used as the prefix of the name of the model. Either `openai` or
+    /// the name of an OpenAI-compatible endpoint.
+    provider: String,
+
+    /// The base URL of the API if not the OpenAI API
+    api_base: Option<String>,
+
+    /// The name of the env var or secret for the API key
+    ///
+    /// May be `None` for OpenAI-compatible endpoints which do not require a key.
+    api_key: Option<String>,
+
     /// The OpenAI name for a model including any tag e.g. "llama2:13b"
     ///
     /// Used as the required `model` parameter in each request to `POST /api/generate`
@@ -56,6 +74,33 @@ impl OpenAIModel {
         outputs: Vec<ModelIO>,
     ) -> Self {
         Self {
+            provider: "openai".to_string(),
+            api_base: None,
+            api_key: Some(API_KEY.to_string()),
+            model,
+            context_length,
+            inputs,
+            outputs,
+        }
+    }
+
+    /// Create a model running on a server with an OpenAI-compatible API
+    ///
+    /// The `provider` is used as the prefix of the name of the model and
+    /// `api_key`, if any, is the name of the env var or secret for the API key.
+    pub fn compatible(
+        provider: String,
+        api_base: String,
+        api_key: Option<String>,
+        model: String,
+        context_length: usize,
+        inputs: Vec<ModelIO>,
+        outputs: Vec<ModelIO>,
+    ) -> Self {
+        Self {
+            provider,
+            api_base: Some(api_base),
+            api_key,
             model,
             context_length,
             inputs,
@@ -67,15 +112,25 @@ impl OpenAIModel {
 #[async_trait]
 impl Model for OpenAIModel {
     fn name(&self) -> String {
-        format!("openai/{}", self.model)
+        format!("{}/{}", self.provider, self.model)
     }
 
     fn r#type(&self) -> ModelType {
-        ModelType::Remote
+        match &self.api_base {
+            Some(api_base)
+                if api_base.contains("://localhost") || api_base.contains("://127.0.0.1") =>
+            {
+                ModelType::Local
+            }
+            _ => ModelType::Remote,
+        }
     }
 
     fn publisher(&self) -> String {
-        "OpenAI".to_string()
+        match self.api_base {
+            Some(..) => self.provider.to_title_case(),
+            None => "OpenAI".to_string(),
+        }
     }
 
     fn title(&self) -> String {
@@ -86,11 +141,12 @@ impl Model for OpenAIModel {
         } else if self.model.starts_with("dall-e") {
             "DALL·E".to_string()
         } else {
-            let name = self
+            // Models on OpenAI-compatible endpoints may include an organization e.g. `meta-llama/Llama-3-8b`
+            let model = self
                 .model
-                .split_once('-')
-                .map(|(name, ..)| name)
-                .unwrap_or(self.model.as_str());
+                .rsplit_once('/')
+                .map_or(self.model.as_str(), |(.., model)| model);
+            let name = model.split_once('-').map_or(model, |(name, ..)| name);
             name.to_title_case()
         }
     }
@@ -120,6 +176,7 @@ impl Model for OpenAIModel {
         &self.outputs
     }
 
+    #[tracing::instrument(skip(self))]
     async fn perform_task(
         &self,
         task: &GenerateTask,
@@ -127,7 +184,7 @@ impl Model for OpenAIModel {
     ) -> Result<GenerateOutput> {
         use ModelIO::*;
         match (task.input(), task.output()) {
-            (Text, Text) => self.chat_completion(task, options).await,
+            (Text, Text) => self.chat_completion(task, options, None).await,
             (Text, Image) => self.create_image(task, options).await,
             _ => bail!(
                 "{} to {} is not supported by model `{}`",
@@ -137,22 +194,65 @@ impl Model for OpenAIModel {
             ),
         }
     }
+
+    #[tracing::instrument(skip(self, sender))]
+    async fn perform_task_stream(
+        &self,
+        task: &GenerateTask,
+        options: &GenerateOptions,
+        sender: GenerateSender,
+    ) -> Result<GenerateOutput> {
+        use ModelIO::*;
+        match (task.input(), task.output()) {
+            (Text, Text) => self.chat_completion(task, options, Some(sender)).await,
+            _ => self.perform_task(task, options).await,
+        }
+    }
 }
 
 impl OpenAIModel {
-    /// Create a client with the correct API key
-    fn client() -> Result<Client<OpenAIConfig>> {
+    /// Create a client for the OpenAI API with the correct API key
+    fn openai_client() -> Result<Client<OpenAIConfig>> {
         let api_key = secrets::env_or_get(API_KEY)?;
         Ok(Client::with_config(
             OpenAIConfig::new().with_api_key(api_key),
         ))
     }
 
+    /// Create a client for the API of the model
+    ///
+    /// For OpenAI-compatible endpoints, an empty API key is used if none is required.
+    fn client(&self) -> Result<Client<OpenAIConfig>> {
+        let Some(api_base) = &self.api_base else {
+            return Self::openai_client();
+        };
+
+        let api_key = match &self.api_key {
+            Some(name) => secrets::env_or_get(name)?,
+            None => String::new(),
+        };
+
+        Ok(Client::with_config(
+            OpenAIConfig::new()
+                .with_api_base(api_base)
+                .with_api_key(api_key),
+        ))
+    }
+
+    /// Create a chat completion
+    ///
+    /// If a `sender` is supplied then the completion is streamed and the text
+    /// generated so far is sent on it as each chunk arrives.
+    ///
+    /// If tools are offered for the task then the model may call them, over several
+    /// requests, before it generates its response. In that case the response is not
+    /// streamed but is sent on the `sender` once complete.
     #[tracing::instrument(skip_all)]
     async fn chat_completion(
         &self,
         task: &GenerateTask,
         options: &GenerateOptions,
+        sender: Option<GenerateSender>,
     ) -> Result<GenerateOutput> {
         tracing::debug!("Sending chat completion request");
 
@@ -236,7 +336,7 @@ impl OpenAIModel {
             .collect();
 
         // Create the request
-        let request = CreateChatCompletionRequest {
+        let mut request = CreateChatCompletionRequest {
             model: self.model.clone(),
             messages,
             presence_penalty: options.repeat_penalty,
@@ -277,17 +377,150 @@ impl OpenAIModel {
         );
 
         // Send the request
-        let client = Self::client()?;
-        let mut response = client.chat().create(request).await?;
-
-        // Get the content of the first message
-        let text = response
-            .choices
-            .pop()
-            .and_then(|choice| choice.message.content)
-            .unwrap_or_default();
+        let client = self.client()?;
+        let started = Instant::now();
+        let mut prompt_tokens = 0;
+        let mut completion_tokens = 0;
+        let mut transcript = Vec::new();
+        // Tools are not offered to models at OpenAI-compatible endpoints because
+        // many of those servers do not support them
+        let offered_tools = match self.api_base {
+            None => task.offered_tools(options),
+            Some(..) => None,
+        };
+        let text = if let Some((tools, offered)) = offered_tools {
+            request.tools = Some(
+                offered
+                    .iter()
+                    .cloned()
+                    .map(|tool| ChatCompletionTool {
+                        r#type: ChatCompletionToolType::Function,
+                        function: FunctionObject {
+                            name: tool.name,
+                            description: Some(tool.description),
+                            parameters: Some(tool.parameters),
+                        },
+                    })
+                    .collect(),
+            );
+
+            // Perform any tool calls and send the results back to the model until
+            // it responds without calling any tools
+            let mut text = None;
+            for _ in 0..MAX_TOOL_ROUNDS {
+                let mut response = client.chat().create(request.clone()).await?;
+                if let Some(usage) = response.usage {
+                    prompt_tokens += usage.prompt_tokens as u64;
+                    completion_tokens += usage.completion_tokens as u64;
+                }
+
+                let Some(message) = response.choices.pop().map(|choice| choice.message) else {
+                    bail!("Response from model `{}` had no choices", self.name())
+                };
+
+                let calls = message.tool_calls.unwrap_or_default();
+                if calls.is_empty() {
+                    text = Some(message.content.unwrap_or_default());
+                    break;
+                }
+
+                request
+                    .messages
+                    .push(ChatCompletionRequestMessage::Assistant(
+                        ChatCompletionRequestAssistantMessage {
+                            content: message.content,
+                            tool_calls: Some(calls.clone()),
+                            ..Default::default()
+                        },
+                    ));
+
+                for call in calls {
+                    let call = ToolCall {
+                        id: call.id,
+                        arguments: serde_json::from_str(&call.function.arguments)
+                            .unwrap_or_default(),
+                        name: call.function.name,
+                    };
+                    let (result, messages) = call.perform(tools, &offered, self).await;
+                    transcript.extend(messages);
+
+                    request.messages.push(ChatCompletionRequestMessage::Tool(
+                        ChatCompletionRequestToolMessage {
+                            content: result,
+                            tool_call_id: call.id,
+                        },
+                    ));
+                }
+            }
+
+            let Some(text) = text else {
+                bail!(
+                    "Model `{}` did not respond within {MAX_TOOL_ROUNDS} rounds of tool calls",
+                    self.name()
+                )
+            };
+
+            if let Some(sender) = sender {
+                sender.send(text.clone()).ok();
+            }
+
+            text
+        } else if let Some(sender) = sender {
+            // Stream the content of the first choice, requesting usage in the final chunk
+            request.stream_options = Some(ChatCompletionStreamOptions {
+                include_usage: true,
+            });
+            let mut stream = client.chat().create_stream(request).await?;
+            let mut text = String::new();
+            while let Some(response) = stream.next().await {
+                let response = response?;
+                if let Some(usage) = response.usage {
+                    prompt_tokens += usage.prompt_tokens as u64;
+                    completion_tokens += usage.completion_tokens as u64;
+                }
+
+                let Some(content) = response
+                    .choices
+                    .into_iter()
+                    .find(|choice| choice.index == 0)
+                    .and_then(|choice| choice.delta.content)
+                else {
+                    continue;
+                };
+
+                text += &content;
+                if sender.send(text.clone()).is_err() {
+                    bail!("Generation cancelled because receiver was dropped");
+                }
+            }
+            text
+        } else {
+            let mut response = client.chat().create(request).await?;
+            if let Some(usage) = response.usage {
+                prompt_tokens += usage.prompt_tokens as u64;
+                completion_tokens += usage.completion_tokens as u64;
+            }
+
+            // Get the content of the first message
+            response
+                .choices
+                .pop()
+                .and_then(|choice| choice.message.content)
+                .unwrap_or_default()
+        };
 
-        GenerateOutput::from_text(self, task.format(), task.instruction(), options, text).await
+        let mut output =
+            GenerateOutput::from_text(self, task.format(), task.instruction(), options, text)
+                .await?;
+        output.transcript = transcript;
+        output.usage = Some(GenerateUsage::new(
+            &self.name(),
+            prompt_tokens,
+            completion_tokens,
+            started,
+        ));
+
+        Ok(output)
     }
 
     #[tracing::instrument(skip_all)]
@@ -410,7 +643,7 @@ impl OpenAIModel {
         }
 
         // Send the request
-        let client = Self::client()?;
+        let client = self.client()?;
         let mut response = client.images().create(request).await?;
 
         // Get the output
@@ -440,7 +673,7 @@ impl OpenAIModel {
 /// remote APIs need to be called to get a list of available models.
 #[cached(time = 3600, result = true)]
 pub async fn list() -> Result<Vec<Arc<dyn Model>>> {
-    let Ok(client) = OpenAIModel::client() else {
+    let Ok(client) = OpenAIModel::openai_client() else {
         tracing::trace!("The environment variable or secret `{API_KEY}` is not available");
         return Ok(vec![]);
     };
@@ -0,0 +1,427 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use model::{
+    common::{
+        async_trait::async_trait,
+        eyre::{bail, Result},
+        itertools::Itertools,
+        seahash,
+        serde::{Deserialize, Serialize},
+        serde_json,
+        tokio::fs,
+        tracing,
+    },
+    GenerateOptions, GenerateOutput, GenerateSender, GenerateTask, Model, ModelIO, ModelType,
+};
+
+/// The name of the env var for the directory of recorded fixtures
+const FIXTURES_DIR: &str = "STENCILA_MODEL_FIXTURES";
+
+/// The default directory of recorded fixtures, relative to the workspace of the document
+const FIXTURES_DIR_DEFAULT: &str = "fixtures/models";
+
+/// Whether a model records, or replays, responses
+enum Mode {
+    /// Perform tasks using another model and record its responses
+    Record(Arc<dyn Model>),
+
+    /// Replay previously recorded responses
+    Replay,
+}
+
+/// A model which records, or replays, the responses of another model
+///
+/// Allows prompts to be tested deterministically, and without network access
+/// or API keys, by first recording the responses of a model (using the name
+/// `record/<MODEL>` e.g. `record/openai/gpt-4o`) and then replaying them
+/// (using the name `replay/<MODEL>`).
+///
+/// Responses are stored as JSON files in a fixtures directory (the
+/// `STENCILA_MODEL_FIXTURES` env var, defaulting to `fixtures/models` within the
+/// workspace of the document) and are keyed by a hash of the model name, the
+/// rendered system prompt, the messages of the instruction, and the generation
+/// options. Because the system prompt is rendered with the document's context
+/// (e.g. its kernel variables and package versions), a response is only replayed
+/// for the same context that it was recorded with.
+pub struct ReplayModel {
+    /// The name of the model that is recorded or replayed
+    model: String,
+
+    /// Whether this model records or replays
+    mode: Mode,
+
+    /// The directory of fixtures
+    ///
+    /// If `None`, resolved against the workspace of the document for each task.
+    dir: Option<PathBuf>,
+}
+
+impl ReplayModel {
+    /// Create a model which records the responses of another model
+    pub fn record(model: Arc<dyn Model>) -> Self {
+        Self {
+            model: model.name(),
+            mode: Mode::Record(model),
+            dir: fixtures_dir(),
+        }
+    }
+
+    /// Create a model which replays the recorded responses of a model
+    pub fn replay(model: &str) -> Self {
+        Self {
+            model: model.to_string(),
+            mode: Mode::Replay,
+            dir: fixtures_dir(),
+        }
+    }
+
+    /// Use a different directory of fixtures
+    pub fn with_dir(mut self, dir: &Path) -> Self {
+        self.dir = Some(dir.to_path_buf());
+        self
+    }
+
+    /// Get the directory of fixtures for a task
+    ///
+    /// Relative to the workspace of the task's document, falling back
+    /// to the current directory if the task has no document context.
+    fn fixtures_dir(&self, task: &GenerateTask) -> PathBuf {
+        if let Some(dir) = &self.dir {
+            return dir.clone();
+        }
+
+        task.context()
+            .and_then(|context| context.workspace())
+            .unwrap_or_default()
+            .join(FIXTURES_DIR_DEFAULT)
+    }
+
+    /// Get the path of the fixture for a task
+    fn fixture_path(dir: &Path, fixture: &Fixture) -> PathBuf {
+        let key = [
+            fixture.model.as_str(),
+            fixture.system.as_deref().unwrap_or_default(),
+            &fixture.messages.iter().join("\n"),
+            &serde_json::to_string(&fixture.options).unwrap_or_default(),
+        ]
+        .join("\n---\n");
+        let hash = seahash::hash(key.as_bytes());
+
+        dir.join(format!("{hash:016x}.json"))
+    }
+
+    /// Perform a task, optionally streaming the generated text
+    async fn perform(
+        &self,
+        task: &GenerateTask,
+        options: &GenerateOptions,
+        sender: Option<GenerateSender>,
+    ) -> Result<GenerateOutput> {
+        let fixture = Fixture::new(&self.model, task, options);
+        let dir = self.fixtures_dir(task);
+        let path = Self::fixture_path(&dir, &fixture);
+
+        match &self.mode {
+            Mode::Record(model) => {
+                let output = match sender {
+                    Some(sender) => model.perform_task_stream(task, options, sender).await?,
+                    None => model.perform_task(task, options).await?,
+                };
+
+                if options.dry_run {
+                    return Ok(output);
+                }
+
+                let fixture = Fixture {
+                    output: Some(output),
+                    ..fixture
+                };
+                fs::create_dir_all(&dir).await?;
+                fs::write(&path, serde_json::to_string_pretty(&fixture)?).await?;
+                tracing::debug!("Recorded response to `{}`", path.display());
+
+                Ok(fixture.output.unwrap_or_default())
+            }
+            Mode::Replay => {
+                if !path.exists() {
+                    bail!(
+                        "No recorded response for model `{}` at `{}`; record one using model `record/{}`",
+                        self.model,
+                        path.display(),
+                        self.model
+                    )
+                }
+
+                let json = fs::read_to_string(&path).await?;
+                let fixture: Fixture = serde_json::from_str(&json)?;
+                let output = fixture.output.unwrap_or_default();
+
+                if let Some(sender) = sender {
+                    sender.send(output.content.clone()).ok();
+                }
+
+                Ok(output)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Model for ReplayModel {
+    fn name(&self) -> String {
+        match self.mode {
+            Mode::Record(..) => format!("record/{}", self.model),
+            Mode::Replay => format!("replay/{}", self.model),
+        }
+    }
+
+    fn r#type(&self) -> ModelType {
+        match &self.mode {
+            Mode::Record(model) => model.r#type(),
+            Mode::Replay => ModelType::Builtin,
+        }
+    }
+
+    fn context_length(&self) -> usize {
+        match &self.mode {
+            Mode::Record(model) => model.context_length(),
+            Mode::Replay => 0,
+        }
+    }
+
+    fn supported_inputs(&self) -> &[ModelIO] {
+        match &self.mode {
+            Mode::Record(model) => model.supported_inputs(),
+            Mode::Replay => &[
+                ModelIO::Text,
+                ModelIO::Image,
+                ModelIO::Audio,
+                ModelIO::Video,
+                ModelIO::Nodes,
+            ],
+        }
+    }
+
+    fn supported_outputs(&self) -> &[ModelIO] {
+        match &self.mode {
+            Mode::Record(model) => model.supported_outputs(),
+            Mode::Replay => &[
+                ModelIO::Text,
+                ModelIO::Image,
+                ModelIO::Audio,
+                ModelIO::Video,
+                ModelIO::Nodes,
+            ],
+        }
+    }
+
+    async fn perform_task(
+        &self,
+        task: &GenerateTask,
+        options: &GenerateOptions,
+    ) -> Result<GenerateOutput> {
+        self.perform(task, options, None).await
+    }
+
+    async fn perform_task_stream(
+        &self,
+        task: &GenerateTask,
+        options: &GenerateOptions,
+        sender: GenerateSender,
+    ) -> Result<GenerateOutput> {
+        self.perform(task, options, Some(sender)).await
+    }
+}
+
+/// A recorded request to, and response from, a model
+///
+/// The request is stored, in addition to its hash, so that changes to
+/// fixtures (e.g. after changing a prompt) are easy to review.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "model::common::serde")]
+struct Fixture {
+    /// The name of the model
+    model: String,
+
+    /// The rendered system prompt
+    system: Option<String>,
+
+    /// The role and parts of each message of the instruction
+    messages: Vec<String>,
+
+    /// The generation options
+    options: GenerateOptions,
+
+    /// The output of the model
+    output: Option<GenerateOutput>,
+}
+
+impl Fixture {
+    /// Create a fixture, without output, for a task
+    ///
+    /// Only the role and parts of each message are used because other properties
+    /// (e.g. the timestamps of authors) differ between runs.
+    fn new(model: &str, task: &GenerateTask, options: &GenerateOptions) -> Self {
+        let messages = task
+            .instruction_messages()
+            .iter()
+            .map(|message| {
+                format!(
+                    "{}: {}",
+                    message.role.clone().unwrap_or_default(),
+                    serde_json::to_string(&message.parts).unwrap_or_default()
+                )
+            })
+            .collect();
+
+        Self {
+            model: model.to_string(),
+            system: task.system_prompt().clone(),
+            messages,
+            options: options.clone(),
+            output: None,
+        }
+    }
+}
+
+/// Get the directory of fixtures set using the env var, if any
+fn fixtures_dir() -> Option<PathBuf> {
+    env::var(FIXTURES_DIR).ok().map(PathBuf::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use model::{
+        common::{tempfile::tempdir, tokio},
+        context::Context,
+        test_task_repeat_word,
+    };
+
+    use super::*;
+
+    /// A model which always responds with the same text
+    struct FixedModel;
+
+    #[async_trait]
+    impl Model for FixedModel {
+        fn name(&self) -> String {
+            "test/fixed".to_string()
+        }
+
+        fn supported_inputs(&self) -> &[ModelIO] {
+            &[ModelIO::Text]
+        }
+
+        fn supported_outputs(&self) -> &[ModelIO] {
+            &[ModelIO::Text]
+        }
+
+        async fn perform_task(
+            &self,
+            task: &GenerateTask,
+            options: &GenerateOptions,
+        ) -> Result<GenerateOutput> {
+            GenerateOutput::from_text(
+                self,
+                task.format(),
+                task.instruction(),
+                options,
+                "HELLO".to_string(),
+            )
+            .await
+        }
+    }
+
+    #[tokio::test]
+    async fn record_then_replay() -> Result<()> {
+        let dir = tempdir()?;
+        let task = test_task_repeat_word();
+        let options = GenerateOptions::default();
+
+        let replay = ReplayModel::replay("test/fixed").with_dir(dir.path());
+        assert!(replay.perform_task(&task, &options).await.is_err());
+
+        let record = ReplayModel::record(Arc::new(FixedModel)).with_dir(dir.path());
+        assert_eq!(record.name(), "record/test/fixed");
+        let recorded = record.perform_task(&task, &options).await?;
+        assert_eq!(recorded.content, "HELLO");
+
+        let replayed = replay.perform_task(&task, &options).await?;
+        assert_eq!(replayed.content, "HELLO");
+
+        // Different options should not replay the same response
+        let options = GenerateOptions {
+            temperature: Some(0.5),
+            ..Default::default()
+        };
+        assert!(replay.perform_task(&task, &options).await.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn keyed_on_rendered_prompt() -> Result<()> {
+        let dir = tempdir()?;
+        let options = GenerateOptions::default();
+        let template = &"Write about {{ context.title }}".to_string();
+
+        // Use contexts with fixed content so that the rendered prompt is deterministic
+        let task = |title: &str| async move {
+            let context = Context {
+                title: Some(title.to_string()),
+                ..Default::default()
+            };
+            let mut task =
+                GenerateTask::new(test_task_repeat_word().instruction().clone(), Some(context));
+            task.prepare(None, None, Some(template)).await?;
+            Result::<GenerateTask>::Ok(task)
+        };
+
+        let record = ReplayModel::record(Arc::new(FixedModel)).with_dir(dir.path());
+        record.perform_task(&task("Cats").await?, &options).await?;
+
+        let replay = ReplayModel::replay("test/fixed").with_dir(dir.path());
+        assert!(replay
+            .perform_task(&task("Cats").await?, &options)
+            .await
+            .is_ok());
+
+        // The same template rendered with a different context should not replay the same response
+        assert!(replay
+            .perform_task(&task("Dogs").await?, &options)
+            .await
+            .is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn fixtures_in_workspace() -> Result<()> {
+        let workspace = tempdir()?;
+        let home = workspace.path().join("docs");
+        std::fs::create_dir_all(workspace.path().join(".stencila"))?;
+        std::fs::create_dir_all(&home)?;
+
+        let context = Context {
+            home: Some(home),
+            ..Default::default()
+        };
+        let task = GenerateTask::new(test_task_repeat_word().instruction().clone(), Some(context));
+        let options = GenerateOptions::default();
+
+        let record = ReplayModel {
+            dir: None,
+            ..ReplayModel::record(Arc::new(FixedModel))
+        };
+        record.perform_task(&task, &options).await?;
+
+        let dir = workspace.path().join(FIXTURES_DIR_DEFAULT);
+        assert_eq!(std::fs::read_dir(dir)?.count(), 1);
+
+        Ok(())
+    }
+}
@@ -1,9 +1,18 @@
+use std::path::PathBuf;
+
 use cli_utils::table::{self, Attribute, Cell, CellAlignment, Color};
 use model::{
+    codecs,
     common::{
         clap::{self, Args, Parser, Subcommand},
         eyre::Result,
+        glob::glob,
+        indexmap::IndexMap,
         itertools::Itertools,
+        tracing,
+    },
+    schema::{
+        Author, AuthorRoleAuthor, Duration, SuggestionBlock, SuggestionInline, Visitor, WalkControl,
     },
     ModelAvailability, ModelType,
 };
@@ -18,6 +27,7 @@ pub struct Cli {
 #[derive(Debug, Subcommand)]
 enum Command {
     List(List),
+    Usage(Usage),
 }
 
 impl Cli {
@@ -29,6 +39,7 @@ impl Cli {
 
         match command {
             Command::List(list) => list.run().await?,
+            Command::Usage(usage) => usage.run().await?,
         }
 
         Ok(())
@@ -100,3 +111,189 @@ impl List {
         Ok(())
     }
 }
+
+/// The extensions of the files, within directories, that are scanned for model usage
+const EXTENSIONS: &[&str] = &["json", "yaml", "cbor", "smd", "myst", "md"];
+
+/// Summarize the usage of models across documents
+///
+/// Totals the tokens, estimated cost, and time of the suggestions made by each
+/// model in one or more documents or directories of documents.
+#[derive(Debug, Args)]
+struct Usage {
+    /// The documents, or directories of documents, to summarize
+    ///
+    /// Defaults to the current directory.
+    paths: Vec<PathBuf>,
+}
+
+impl Usage {
+    async fn run(self) -> Result<()> {
+        let paths = if self.paths.is_empty() {
+            vec![PathBuf::from(".")]
+        } else {
+            self.paths
+        };
+
+        let files = paths.into_iter().flat_map(|path| {
+            if path.is_dir() {
+                EXTENSIONS
+                    .iter()
+                    .flat_map(|ext| {
+                        let pattern = path.join("**").join(["*.", ext].concat());
+                        glob(&pattern.to_string_lossy())
+                            .into_iter()
+                            .flatten()
+                            .flatten()
+                    })
+                    .filter(|path| {
+                        // Skip files in hidden directories (e.g. `.git`, `.stencila`) and dependencies
+                        !path.components().any(|component| {
+                            let name = component.as_os_str().to_string_lossy();
+                            (name.starts_with('.') && name.len() > 1)
+                                || name == "node_modules"
+                                || name == "target"
+                        })
+                    })
+                    .collect_vec()
+            } else {
+                vec![path]
+            }
+        });
+
+        let mut totaller = Totaller::default();
+        for file in files.unique() {
+            match codecs::from_path(&file, None).await {
+                Ok(node) => totaller.visit(&node),
+                Err(error) => tracing::debug!("While decoding `{}`: {error}", file.display()),
+            }
+        }
+
+        let mut table = table::new();
+        table.set_header([
+            "Model",
+            "Suggestions",
+            "Prompt tokens",
+            "Completion tokens",
+            "Cost (USD)",
+            "Time (s)",
+        ]);
+
+        let mut total = Totals::default();
+        for (model, totals) in totaller.models {
+            table.add_row(totals.row(Cell::new(model).add_attribute(Attribute::Bold)));
+            total += totals;
+        }
+        table.add_row(total.row(Cell::new("Total").add_attribute(Attribute::Bold)));
+
+        println!("{table}");
+
+        Ok(())
+    }
+}
+
+/// Totals of the usage of a model
+#[derive(Default)]
+struct Totals {
+    suggestions: u64,
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    cost: f64,
+    millis: u64,
+}
+
+impl Totals {
+    /// Add the usage of a suggestion
+    fn add(
+        &mut self,
+        prompt_tokens: &Option<u64>,
+        completion_tokens: &Option<u64>,
+        cost: &Option<f64>,
+        duration: &Option<Duration>,
+    ) {
+        self.suggestions += 1;
+        self.prompt_tokens += prompt_tokens.unwrap_or_default();
+        self.completion_tokens += completion_tokens.unwrap_or_default();
+        self.cost += cost.unwrap_or_default();
+        self.millis += duration
+            .as_ref()
+            .map(Duration::to_millis)
+            .unwrap_or_default();
+    }
+
+    /// Create a table row for the totals
+    fn row(&self, first: Cell) -> [Cell; 6] {
+        [
+            first,
+            Cell::new(self.suggestions).set_alignment(CellAlignment::Right),
+            Cell::new(self.prompt_tokens).set_alignment(CellAlignment::Right),
+            Cell::new(self.completion_tokens).set_alignment(CellAlignment::Right),
+            Cell::new(format!("{:.4}", self.cost)).set_alignment(CellAlignment::Right),
+            Cell::new(format!("{:.1}", self.millis as f64 / 1000.))
+                .set_alignment(CellAlignment::Right),
+        ]
+    }
+}
+
+impl std::ops::AddAssign for Totals {
+    fn add_assign(&mut self, other: Self) {
+        self.suggestions += other.suggestions;
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.cost += other.cost;
+        self.millis += other.millis;
+    }
+}
+
+/// A visitor which totals the usage of each model across suggestions
+#[derive(Default)]
+struct Totaller {
+    models: IndexMap<String, Totals>,
+}
+
+impl Totaller {
+    /// Get the totals for the model that authored a suggestion
+    fn totals(&mut self, authors: &Option<Vec<Author>>) -> &mut Totals {
+        let model = authors
+            .iter()
+            .flatten()
+            .find_map(|author| {
+                let app = match author {
+                    Author::SoftwareApplication(app) => app,
+                    Author::AuthorRole(role) => match &role.author {
+                        AuthorRoleAuthor::SoftwareApplication(app) => app,
+                        _ => return None,
+                    },
+                    _ => return None,
+                };
+                Some(app.id.clone().unwrap_or_else(|| app.name.clone()))
+            })
+            .unwrap_or_else(|| "unknown".to_string());
+
+        self.models.entry(model).or_default()
+    }
+}
+
+impl Visitor for Totaller {
+    fn visit_suggestion_block(&mut self, block: &SuggestionBlock) -> WalkControl {
+        self.totals(&block.authors).add(
+            &block.prompt_tokens,
+            &block.completion_tokens,
+            &block.cost,
+            &block.execution_duration,
+        );
+
+        WalkControl::Continue
+    }
+
+    fn visit_suggestion_inline(&mut self, inline: &SuggestionInline) -> WalkControl {
+        self.totals(&inline.authors).add(
+            &inline.prompt_tokens,
+            &inline.completion_tokens,
+            &inline.cost,
+            &inline.execution_duration,
+        );
+
+        WalkControl::Continue
+    }
+}
@@ -16,13 +16,14 @@ pub mod cli;
 
 /// Get a list of available models
 pub async fn list() -> Vec<Arc<dyn Model>> {
-    let futures = (0..=4).map(|provider| async move {
+    let futures = (0..=5).map(|provider| async move {
         let (provider, result) = match provider {
             0 => ("Anthropic", models_anthropic::list().await),
             1 => ("Google", models_google::list().await),
             2 => ("Mistral", models_mistral::list().await),
             3 => ("Ollama", models_ollama::list().await),
             4 => ("OpenAI", models_openai::list().await),
+            5 => ("OpenAI-compatible", models_openai_compatible::list().await),
             _ => return vec![],
         };
 
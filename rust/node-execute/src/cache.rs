@@ -0,0 +1,245 @@
+use std::{
+    fs::{create_dir_all, read, write},
+    hash::{Hash, Hasher},
+    path::PathBuf,
+};
+
+use app::{get_app_dir, DirType};
+use codec_cbor::r#trait::CborCodec;
+use common::{
+    eyre::Result,
+    seahash::SeaHasher,
+    serde::{Deserialize, Serialize},
+    tracing,
+};
+use schema::{CompilationDigest, ExecutionMessage, Node};
+
+/// An on-disk cache of the outputs of executed code
+///
+/// Entries are keyed by the compilation digest of the code combined with
+/// a digest of the code that was executed before it (see [`ExecutionCache::key`]).
+/// This allows outputs to be reused across executions of a document (e.g. in
+/// CI builds) when nothing upstream of a node has changed.
+pub(crate) struct ExecutionCache {
+    /// The directory in which cache entries are stored
+    dir: PathBuf,
+}
+
+/// A cached execution result
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "common::serde")]
+pub(crate) struct CachedExecution {
+    /// The outputs of the execution
+    pub outputs: Option<Vec<Node>>,
+
+    /// The messages emitted during the execution
+    pub messages: Option<Vec<ExecutionMessage>>,
+}
+
+impl ExecutionCache {
+    /// Open the cache in the application's cache directory
+    pub fn open() -> Result<Self> {
+        Self::open_in(get_app_dir(DirType::Cache, true)?.join("outputs"))
+    }
+
+    /// Open the cache in a directory, creating it if necessary
+    pub fn open_in(dir: PathBuf) -> Result<Self> {
+        create_dir_all(&dir)?;
+
+        Ok(Self { dir })
+    }
+
+    /// Calculate the cache key for a node
+    ///
+    /// Uses the semantic digest of the node, if available, falling back to
+    /// the state digest. The `upstream` digest is combined with the node's
+    /// own `dependencies_digest` so that a cached entry is only reused if
+    /// everything that may have affected the kernel state is unchanged.
+    pub fn key(digest: &CompilationDigest, upstream: u64) -> u64 {
+        let mut hash = SeaHasher::new();
+        digest
+            .semantic_digest
+            .unwrap_or(digest.state_digest)
+            .hash(&mut hash);
+        digest.dependencies_digest.hash(&mut hash);
+        upstream.hash(&mut hash);
+        hash.finish()
+    }
+
+    /// Get the path of the file for a cache key
+    fn path(&self, key: u64) -> PathBuf {
+        self.dir.join(format!("{key:016x}.cbor"))
+    }
+
+    /// Get a cached execution, if any, for a key
+    ///
+    /// Errors reading or decoding an entry are logged and treated as a cache miss.
+    pub fn get(&self, key: u64) -> Option<CachedExecution> {
+        let path = self.path(key);
+        if !path.exists() {
+            return None;
+        }
+
+        match read(&path)
+            .map_err(Into::into)
+            .and_then(|bytes| CachedExecution::from_cbor(&bytes))
+        {
+            Ok(cached) => Some(cached),
+            Err(error) => {
+                tracing::warn!(
+                    "While reading execution cache entry `{}`: {error}",
+                    path.display()
+                );
+                None
+            }
+        }
+    }
+
+    /// Store an execution in the cache
+    pub fn set(&self, key: u64, cached: &CachedExecution) -> Result<()> {
+        write(self.path(key), cached.to_cbor()?)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::read_dir, path::Path, sync::Arc};
+
+    use common::{
+        tempfile::tempdir,
+        tokio::{
+            self,
+            sync::{
+                mpsc::{self, UnboundedReceiver},
+                RwLock,
+            },
+        },
+    };
+    use kernels::Kernels;
+    use schema::{Article, Block, CodeChunk, Cord, Patch};
+
+    use crate::{Executable, Executor};
+
+    use super::*;
+
+    /// Create an executor which uses a cache in a directory
+    fn executor(home: &Path, cache: &Path) -> Result<(Executor, UnboundedReceiver<Patch>)> {
+        let kernels = Arc::new(RwLock::new(Kernels::new(home)));
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        let mut executor = Executor::new(home.to_path_buf(), kernels, sender, None, None);
+        executor.cache = Some(ExecutionCache::open_in(cache.to_path_buf())?);
+
+        Ok((executor, receiver))
+    }
+
+    /// Compile and execute a code chunk, applying patches to it, and
+    /// return whether its outputs were obtained from the cache
+    async fn run(dir: &Path, chunk: &mut CodeChunk) -> Result<bool> {
+        let (mut executor, mut receiver) = executor(dir, &dir.join("cache"))?;
+
+        chunk.compile(&mut executor).await;
+        while let Ok(patch) = receiver.try_recv() {
+            schema::patch(chunk, patch)?;
+        }
+
+        chunk.execute(&mut executor).await;
+        while let Ok(patch) = receiver.try_recv() {
+            schema::patch(chunk, patch)?;
+        }
+
+        let deferred = executor.kernels().await.has_deferred();
+        Ok(deferred)
+    }
+
+    /// Compile and execute an article of code chunks, applying patches to it, and
+    /// return the number of kernel instances started (i.e. zero if no code was executed)
+    async fn run_article(dir: &Path, codes: &[&str]) -> Result<usize> {
+        let (mut executor, mut receiver) = executor(dir, &dir.join("cache"))?;
+
+        let mut root = Node::Article(Article::new(
+            codes
+                .iter()
+                .map(|code| Block::CodeChunk(chunk(code)))
+                .collect(),
+        ));
+
+        executor.compile(&mut root).await?;
+        while let Ok(patch) = receiver.try_recv() {
+            schema::patch(&mut root, patch)?;
+        }
+
+        executor.execute(&mut root).await?;
+        while let Ok(patch) = receiver.try_recv() {
+            schema::patch(&mut root, patch)?;
+        }
+
+        let instances = executor.kernels().await.kernel_contexts().await.len();
+        Ok(instances)
+    }
+
+    fn chunk(code: &str) -> CodeChunk {
+        CodeChunk {
+            programming_language: Some("rhai".to_string()),
+            ..CodeChunk::new(Cord::from(code))
+        }
+    }
+
+    #[tokio::test]
+    async fn hit() -> Result<()> {
+        let dir = tempdir()?;
+
+        let mut first = chunk("1 + 2");
+        assert!(!run(dir.path(), &mut first).await?);
+
+        // A different chunk with the same code, in a new executor, should get a cache hit
+        let mut second = chunk("1 + 2");
+        assert!(run(dir.path(), &mut second).await?);
+        assert_eq!(second.outputs, first.outputs);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn fully_cached_executes_nothing() -> Result<()> {
+        let dir = tempdir()?;
+        let codes = ["let a = 1;", "a + 2"];
+
+        assert_eq!(run_article(dir.path(), &codes).await?, 1);
+
+        // All outputs are obtained from the cache so no kernel should be started
+        assert_eq!(run_article(dir.path(), &codes).await?, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn miss_after_dependency_changes() -> Result<()> {
+        let dir = tempdir()?;
+        write(dir.path().join("data.txt"), "one")?;
+
+        let code = "// @reads data.txt\n1 + 2";
+        assert!(!run(dir.path(), &mut chunk(code)).await?);
+        assert!(run(dir.path(), &mut chunk(code)).await?);
+
+        write(dir.path().join("data.txt"), "two")?;
+        assert!(!run(dir.path(), &mut chunk(code)).await?);
+        assert!(run(dir.path(), &mut chunk(code)).await?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn impure_never_cached() -> Result<()> {
+        let dir = tempdir()?;
+
+        let code = "// @impure\n1 + 2";
+        assert!(!run(dir.path(), &mut chunk(code)).await?);
+        assert!(!run(dir.path(), &mut chunk(code)).await?);
+        assert_eq!(read_dir(dir.path().join("cache"))?.count(), 0);
+
+        Ok(())
+    }
+}
@@ -1,4 +1,4 @@
-use schema::{CodeChunk, LabelType, NodeProperty};
+use schema::{CodeChunk, ExecutionMode, LabelType, NodeProperty};
 
 use crate::{interrupt_impl, pending_impl, prelude::*};
 
@@ -10,14 +10,11 @@ impl Executable for CodeChunk {
 
         // Some code chunks should be executed during "compile" to
         // enable live updates (e.g. Graphviz, Mermaid)
-        // TODO: consider having a way to specify which code chunks and/or
-        // which kernels should execute at compile time (e.g. could have
-        // a compile method on kernels)
         let lang = self
             .programming_language
             .as_ref()
             .map_or_else(String::new, |lang| lang.trim().to_lowercase());
-        if lang == "dot" || lang == "graphviz" {
+        if kernels::executes_on_compile(&lang) {
             return self.execute(executor).await;
         }
 
@@ -26,12 +23,16 @@ impl Executable for CodeChunk {
             self.programming_language.as_deref().unwrap_or_default(),
         );
 
+        let mut compilation_digest = info.compilation_digest;
+        compilation_digest.dependencies_digest =
+            executor.dependencies_digest(&info.execution_tags).await;
+
         let execution_required =
-            execution_required_digests(&self.options.execution_digest, &info.compilation_digest);
+            execution_required_digests(&self.options.execution_digest, &compilation_digest);
         executor.patch(
             &node_id,
             [
-                set(NodeProperty::CompilationDigest, info.compilation_digest),
+                set(NodeProperty::CompilationDigest, compilation_digest),
                 set(NodeProperty::ExecutionTags, info.execution_tags),
                 set(NodeProperty::ExecutionRequired, execution_required),
             ],
@@ -84,6 +85,11 @@ impl Executable for CodeChunk {
             &self.options.execution_digest,
         ) {
             tracing::trace!("Skipping CodeChunk {node_id}");
+
+            // The kernels have the state resulting from when the code was last
+            // executed (if ever), so reflect that in the cache keys of subsequent code
+            executor.cache_chain(&self.options.execution_digest);
+
             return WalkControl::Break;
         }
 
@@ -102,20 +108,58 @@ impl Executable for CodeChunk {
         if !self.code.trim().is_empty() {
             let started = Timestamp::now();
 
-            let (outputs, messages) = executor
-                .kernels()
-                .await
-                .execute(&self.code, self.programming_language.as_deref())
-                .await
-                .unwrap_or_else(|error| {
-                    (
-                        Vec::new(),
-                        vec![error_to_execution_message("While executing code", error)],
+            // Use cached outputs, unless the code is always executed or is tagged as impure
+            let cacheable = !matches!(self.execution_mode, Some(ExecutionMode::Always))
+                && !self
+                    .options
+                    .execution_tags
+                    .iter()
+                    .flatten()
+                    .any(|tag| tag.name == "impure");
+            // Forget any code of this chunk deferred during a previous execution
+            executor.kernels().await.undefer(&node_id.to_string());
+            let cached = if cacheable {
+                executor
+                    .cache_get(
+                        &node_id,
+                        &self.code,
+                        self.programming_language.as_deref(),
+                        &compilation_digest,
                     )
-                });
+                    .await
+            } else {
+                None
+            };
 
-            let outputs = (!outputs.is_empty()).then_some(outputs);
-            let messages = (!messages.is_empty()).then_some(messages);
+            let (outputs, messages) = if let Some(cached) = cached {
+                tracing::trace!("Using cached outputs for CodeChunk {node_id}");
+                (cached.outputs, cached.messages)
+            } else {
+                executor.cache_replay().await;
+
+                let (outputs, messages) = executor
+                    .kernels()
+                    .await
+                    .execute(&self.code, self.programming_language.as_deref())
+                    .await
+                    .unwrap_or_else(|error| {
+                        (
+                            Vec::new(),
+                            vec![error_to_execution_message("While executing code", error)],
+                        )
+                    });
+
+                let outputs = (!outputs.is_empty()).then_some(outputs);
+                let messages = (!messages.is_empty()).then_some(messages);
+
+                if cacheable {
+                    executor.cache_set(&compilation_digest, &outputs, &messages);
+                } else {
+                    executor.cache_chain(&None);
+                }
+
+                (outputs, messages)
+            };
 
             let ended = Timestamp::now();
 
@@ -71,6 +71,8 @@ impl Executable for CodeExpression {
         if !self.code.trim().is_empty() {
             let started = Timestamp::now();
 
+            executor.cache_replay().await;
+
             let (output, messages) = executor
                 .kernels
                 .write()
@@ -99,7 +99,11 @@ impl Executable for ForBlock {
         if !(variable.is_empty() && self.code.trim().is_empty()) {
             is_empty = false;
 
-            // Evaluate code in kernels to get the iterable
+            // Evaluate code in kernels to get the iterable. Because the block
+            // assigns its variable, chain its digest onto the upstream digest
+            // used for caching the outputs of nested code.
+            executor.cache_replay().await;
+            executor.cache_chain(&compilation_digest);
             let (output, mut code_messages) = executor
                 .kernels
                 .write()
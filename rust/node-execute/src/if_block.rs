@@ -224,6 +224,7 @@ impl Executable for IfBlockClause {
         let is_empty = self.code.trim().is_empty();
         let (is_active, mut status) = if !is_empty {
             // Evaluate code in kernels
+            executor.cache_replay().await;
             let (output, mut code_messages) = executor
                 .kernels
                 .write()
@@ -1,6 +1,18 @@
 use assistants::assistant::GenerateOptions;
-use common::futures::future;
-use schema::{authorship, InstructionBlock, SuggestionStatus};
+use codecs::{DecodeOptions, Format};
+use common::{
+    eyre::{bail, eyre, Result},
+    futures::future,
+    tokio::{
+        self,
+        sync::mpsc::{unbounded_channel, UnboundedReceiver},
+    },
+};
+use schema::{
+    authorship, Article, Author, AuthorRole, AuthorRoleName, Block, InstructionBlock,
+    InstructionMessage, InstructionType, MessagePart, MessageRole, NodeId, Patch, PatchPath,
+    PatchSlot, SuggestionBlock, SuggestionStatus, Visitor,
+};
 
 use crate::{interrupt_impl, pending_impl, prelude::*};
 
@@ -32,6 +44,13 @@ impl Executable for InstructionBlock {
 
         tracing::debug!("Executing InstructionBlock {node_id}");
 
+        if matches!(self.instruction_type, InstructionType::Chat) {
+            execute_chat(self, executor, &node_id).await;
+
+            // Continue to execute executable nodes in `content` and/or `suggestion`
+            return WalkControl::Continue;
+        }
+
         executor.patch(
             &node_id,
             [
@@ -70,23 +89,42 @@ impl Executable for InstructionBlock {
             let started = Timestamp::now();
 
             // Create a future for each replicate
-            let temperature = self
-                .model
-                .as_ref()
-                .and_then(|model| model.temperature)
-                .map(|temp| (temp as f32 / 100.).min(100.));
+            let temperature = temperature(self);
             let dry_run = executor.options.dry_run;
+            // Getting the context also runs any code deferred because of cache hits so
+            // that the kernel state is up to date for any tools that the assistant calls
             let context = executor.context().await;
+            let tools = executor.tools();
+
+            // Add an empty suggestion for each replicate which is updated as its
+            // content is generated and replaced once generation is complete
+            let first = self.suggestions.as_ref().map_or(0, Vec::len);
+            executor.patch(
+                &node_id,
+                [
+                    append(
+                        NodeProperty::Suggestions,
+                        vec![SuggestionBlock::new(Vec::new()); replicates],
+                    ),
+                    none(NodeProperty::HideSuggestions),
+                ],
+            );
+
+            let executor = &*executor;
             let mut futures = Vec::new();
-            for _ in 0..replicates {
+            for replicate in 0..replicates {
                 let instruction = self.clone();
                 let context = context.clone();
-                futures.push(async {
+                let tools = tools.clone();
+                let node_id = &node_id;
+                futures.push(async move {
                     let started = Timestamp::now();
 
-                    // Get the `assistants` crate to execute this instruction
-                    let (authors, mut suggestion, mut messages) =
-                        match assistants::execute_instruction(
+                    // Get the `assistants` crate to execute this instruction while
+                    // streaming the generated content into the suggestion
+                    let (sender, receiver) = unbounded_channel();
+                    let (result, ..) = tokio::join!(
+                        assistants::execute_instruction_stream(
                             instruction,
                             context,
                             GenerateOptions {
@@ -94,23 +132,27 @@ impl Executable for InstructionBlock {
                                 dry_run,
                                 ..Default::default()
                             },
-                        )
-                        .await
-                        {
-                            Ok(output) => (
-                                Some(output.authors.clone()),
-                                Some(output.to_suggestion_block()),
-                                Vec::new(),
-                            ),
-                            Err(error) => (
-                                None,
-                                None,
-                                vec![error_to_execution_message(
-                                    "While performing instruction",
-                                    error,
-                                )],
-                            ),
-                        };
+                            Some(tools),
+                            sender,
+                        ),
+                        stream_suggestion(executor, node_id, first + replicate, receiver)
+                    );
+
+                    let (authors, mut suggestion, mut messages) = match result {
+                        Ok(output) => (
+                            Some(output.authors.clone()),
+                            Some(output.to_suggestion_block()),
+                            Vec::new(),
+                        ),
+                        Err(error) => (
+                            None,
+                            None,
+                            vec![error_to_execution_message(
+                                "While performing instruction",
+                                error,
+                            )],
+                        ),
+                    };
 
                     if let Some(suggestion) = suggestion.as_mut() {
                         // Apply authorship to the suggestion.
@@ -125,12 +167,14 @@ impl Executable for InstructionBlock {
                             }
                         }
 
-                        // Record execution time for the suggestion
+                        // Record execution time for the suggestion, unless the latency of
+                        // the model that generated it has already been recorded
                         let ended = Timestamp::now();
-                        let duration = Some(execution_duration(&started, &ended));
-                        let ended = Some(ended);
-                        suggestion.execution_duration = duration;
-                        suggestion.execution_ended = ended;
+                        if suggestion.execution_duration.is_none() {
+                            suggestion.execution_duration =
+                                Some(execution_duration(&started, &ended));
+                        }
+                        suggestion.execution_ended = Some(ended);
                     }
 
                     (suggestion, messages)
@@ -189,9 +233,446 @@ impl Executable for InstructionBlock {
         let node_id = self.node_id();
         tracing::debug!("Interrupting InstructionBlock {node_id}");
 
+        // Remove the placeholders for any suggestions that were being generated. This
+        // is done whenever the instruction was pending or running (rather than only if this
+        // node has placeholders) in case the patch adding them has not yet been applied to it.
+        if interruption(&self.options.execution_status).is_some() {
+            let suggestions = self
+                .suggestions
+                .iter()
+                .flatten()
+                .filter(|suggestion| !is_placeholder(suggestion))
+                .cloned()
+                .collect::<Vec<_>>();
+            executor.patch(
+                &node_id,
+                [if suggestions.is_empty() {
+                    clear(NodeProperty::Suggestions)
+                } else {
+                    set(NodeProperty::Suggestions, suggestions)
+                }],
+            );
+        }
+
         interrupt_impl!(self, executor, &node_id);
 
         // Continue to interrupt executable nodes in `content`
         WalkControl::Continue
     }
 }
+
+/// Execute a chat by generating a reply to the latest message from the user
+///
+/// Unlike other instructions, a chat does not generate suggestions (and `replicates`
+/// is ignored). Instead, a placeholder reply is pushed onto the messages of the chat and
+/// replaced once generation is complete (so that any follow ups from the user during
+/// generation are kept). The whole conversation, along with the context of the document,
+/// is sent to the model when the user follows up. Replies are generated without tools
+/// and can be promoted into suggestions using [`promote_message`].
+async fn execute_chat(chat: &InstructionBlock, executor: &mut Executor, node_id: &NodeId) {
+    let Some(latest) = chat.messages.last() else {
+        executor.patch(
+            node_id,
+            [
+                set(NodeProperty::ExecutionStatus, ExecutionStatus::Empty),
+                set(NodeProperty::ExecutionRequired, ExecutionRequired::No),
+                none(NodeProperty::ExecutionDuration),
+                none(NodeProperty::ExecutionEnded),
+            ],
+        );
+        return;
+    };
+
+    if matches!(latest.role, Some(MessageRole::Assistant)) {
+        tracing::trace!("Chat {node_id} has no new messages to reply to");
+        executor.patch(
+            node_id,
+            [set(NodeProperty::ExecutionRequired, ExecutionRequired::No)],
+        );
+        return;
+    }
+
+    executor.patch(
+        node_id,
+        [
+            set(NodeProperty::ExecutionStatus, ExecutionStatus::Running),
+            none(NodeProperty::ExecutionMessages),
+        ],
+    );
+
+    let started = Timestamp::now();
+
+    let temperature = temperature(chat);
+    let dry_run = executor.options.dry_run;
+    let context = executor.context().await;
+
+    // Add an empty reply which is updated as its content is generated
+    // and replaced once generation is complete
+    let index = chat.messages.len();
+    executor.patch(
+        node_id,
+        [push(
+            NodeProperty::Messages,
+            InstructionMessage::assistant(""),
+        )],
+    );
+
+    let executor = &*executor;
+    let (sender, receiver) = unbounded_channel();
+    let (result, ..) = tokio::join!(
+        assistants::execute_instruction_stream(
+            chat.clone(),
+            context,
+            GenerateOptions {
+                temperature,
+                dry_run,
+                ..Default::default()
+            },
+            None,
+            sender,
+        ),
+        stream_message(executor, node_id, index, receiver)
+    );
+
+    // Replace the placeholder reply with the generated reply (or remove it if there
+    // was an error) rather than setting all messages, so that any follow ups are kept
+    let mut execution_messages = Vec::new();
+    let reply = match result {
+        Ok(output) => {
+            let message = InstructionMessage {
+                role: Some(MessageRole::Assistant),
+                ..output.to_message()
+            };
+            PatchOp::Replace(vec![(index, message.to_value().unwrap_or_default())])
+        }
+        Err(error) => {
+            execution_messages.push(error_to_execution_message(
+                "While performing instruction",
+                error,
+            ));
+            PatchOp::Remove(vec![index])
+        }
+    };
+
+    let execution_messages = (!execution_messages.is_empty()).then_some(execution_messages);
+
+    let ended = Timestamp::now();
+
+    let status = execution_status(&execution_messages);
+    let required = execution_required_status(&status);
+    let duration = execution_duration(&started, &ended);
+    let count = chat.options.execution_count.unwrap_or_default() + 1;
+
+    executor.patch(
+        node_id,
+        [
+            (NodeProperty::Messages, reply),
+            set(NodeProperty::ExecutionStatus, status),
+            set(NodeProperty::ExecutionRequired, required),
+            set(NodeProperty::ExecutionMessages, execution_messages),
+            set(NodeProperty::ExecutionDuration, duration),
+            set(NodeProperty::ExecutionEnded, ended),
+            set(NodeProperty::ExecutionCount, count),
+        ],
+    );
+}
+
+/// Promote a message in a chat into a suggestion
+///
+/// Creates a patch which appends the message, decoded from Markdown into blocks,
+/// to the suggestions of the chat so that it can be reviewed and accepted like
+/// any other suggestion. If no message id is supplied, the latest reply is promoted.
+pub async fn promote_message(
+    root: &Node,
+    chat_id: &NodeId,
+    message_id: Option<&NodeId>,
+) -> Result<Patch> {
+    let mut finder = Finder {
+        node_id: chat_id,
+        found: None,
+    };
+    finder.visit(root);
+    let Some(chat) = finder.found else {
+        bail!("No instruction with id `{chat_id}`")
+    };
+
+    let message = match message_id {
+        Some(message_id) => chat
+            .messages
+            .iter()
+            .find(|message| &message.node_id() == message_id),
+        None => chat
+            .messages
+            .iter()
+            .rev()
+            .find(|message| matches!(message.role, Some(MessageRole::Assistant))),
+    }
+    .ok_or_else(|| eyre!("No message to promote in instruction `{chat_id}`"))?;
+
+    let text = message
+        .parts
+        .iter()
+        .filter_map(|part| match part {
+            MessagePart::Text(text) => Some(text.to_value_string()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("");
+    let Some(content) = decode_blocks(&text).await else {
+        bail!("Unable to decode message into blocks")
+    };
+
+    // The authors of the message (i.e. the assistant and model) are the generators of the suggestion
+    let authors = message
+        .authors
+        .iter()
+        .flatten()
+        .filter_map(|author| match author {
+            Author::SoftwareApplication(app) => {
+                Some(AuthorRole::software(app.clone(), AuthorRoleName::Generator))
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let mut suggestion = SuggestionBlock::new(content);
+    if !authors.is_empty() {
+        authorship(&mut suggestion, authors)?;
+    }
+
+    Ok(Patch {
+        node_id: Some(chat_id.clone()),
+        ops: vec![
+            (
+                PatchPath::from(NodeProperty::Suggestions),
+                PatchOp::Append(vec![suggestion.to_value()?]),
+            ),
+            (
+                PatchPath::from(NodeProperty::HideSuggestions),
+                PatchOp::Set(PatchValue::None),
+            ),
+        ],
+        ..Default::default()
+    })
+}
+
+/// A visitor which finds an [`InstructionBlock`] with a node id
+struct Finder<'a> {
+    node_id: &'a NodeId,
+    found: Option<InstructionBlock>,
+}
+
+impl Visitor for Finder<'_> {
+    fn visit_block(&mut self, block: &Block) -> WalkControl {
+        if let Block::InstructionBlock(instruction) = block {
+            if &instruction.node_id() == self.node_id {
+                self.found = Some(instruction.clone());
+                return WalkControl::Break;
+            }
+        }
+
+        WalkControl::Continue
+    }
+}
+
+/// Get the temperature option of an instruction as a value between 0 and 1
+fn temperature(instruction: &InstructionBlock) -> Option<f32> {
+    instruction
+        .model
+        .as_ref()
+        .and_then(|model| model.temperature)
+        .map(|temp| (temp as f32 / 100.).min(100.))
+}
+
+/// Update the text of a reply in a chat as it is generated
+///
+/// Patches the text generated so far into the message at `index` within the
+/// messages of the chat. As for suggestions, any text received while the previous
+/// text was being patched is skipped in favour of the latest.
+async fn stream_message(
+    executor: &Executor,
+    node_id: &NodeId,
+    index: usize,
+    mut receiver: UnboundedReceiver<String>,
+) {
+    while let Some(mut text) = receiver.recv().await {
+        while let Ok(latest) = receiver.try_recv() {
+            text = latest;
+        }
+
+        executor.patch_paths(
+            node_id,
+            [(
+                PatchPath::from([
+                    PatchSlot::Property(NodeProperty::Messages),
+                    PatchSlot::Index(index),
+                    PatchSlot::Property(NodeProperty::Parts),
+                ]),
+                PatchOp::Set(vec![MessagePart::from(text)].to_value().unwrap_or_default()),
+            )],
+        );
+    }
+}
+
+/// Whether a suggestion is a placeholder for one that is being generated
+///
+/// Placeholders are added when an instruction is executed and are replaced, with
+/// suggestions that have `execution_ended` set, once generation is complete.
+fn is_placeholder(suggestion: &SuggestionBlock) -> bool {
+    suggestion.execution_ended.is_none()
+        && suggestion.suggestion_status.is_none()
+        && suggestion.feedback.is_none()
+}
+
+/// Update the content of a suggestion as the text for it is generated
+///
+/// Decodes the text generated so far and patches it into the content of the
+/// suggestion at `index` within the instruction's suggestions. Any text received
+/// while the previous text was being decoded is skipped in favour of the latest.
+async fn stream_suggestion(
+    executor: &Executor,
+    node_id: &NodeId,
+    index: usize,
+    mut receiver: UnboundedReceiver<String>,
+) {
+    while let Some(mut text) = receiver.recv().await {
+        while let Ok(latest) = receiver.try_recv() {
+            text = latest;
+        }
+
+        let Some(content) = decode_blocks(&text).await else {
+            continue;
+        };
+
+        executor.patch_paths(
+            node_id,
+            [(
+                PatchPath::from([
+                    PatchSlot::Property(NodeProperty::Suggestions),
+                    PatchSlot::Index(index),
+                    PatchSlot::Property(NodeProperty::Content),
+                ]),
+                PatchOp::Set(content.to_value().unwrap_or_default()),
+            )],
+        );
+    }
+}
+
+/// Decode Markdown generated by an assistant into blocks
+async fn decode_blocks(text: &str) -> Option<Vec<Block>> {
+    let Ok(Node::Article(Article { content, .. })) = codecs::from_str(
+        text,
+        Some(DecodeOptions {
+            format: Some(Format::Markdown),
+            ..Default::default()
+        }),
+    )
+    .await
+    else {
+        return None;
+    };
+
+    Some(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{create_dir_all, write},
+        path::Path,
+        sync::Arc,
+    };
+
+    use common::{
+        tempfile::tempdir,
+        tokio::sync::{mpsc, RwLock},
+    };
+    use kernels::Kernels;
+
+    use super::*;
+
+    /// Execute a document, applying the patches from execution to it
+    async fn execute(home: &Path, root: &mut Node) -> Result<()> {
+        let kernels = Arc::new(RwLock::new(Kernels::new(home)));
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+
+        crate::execute(
+            home.to_path_buf(),
+            Arc::new(RwLock::new(root.clone())),
+            kernels,
+            sender,
+            None,
+            None,
+        )
+        .await?;
+
+        while let Ok(patch) = receiver.try_recv() {
+            schema::patch(root, patch)?;
+        }
+
+        Ok(())
+    }
+
+    /// Get the chat at the start of a document
+    fn chat(root: &Node) -> Result<&InstructionBlock> {
+        let Node::Article(Article { content, .. }) = root else {
+            bail!("Expected an article")
+        };
+        let Some(Block::InstructionBlock(chat)) = content.first() else {
+            bail!("Expected a chat")
+        };
+        Ok(chat)
+    }
+
+    #[tokio::test]
+    async fn chat_reply_promoted_to_suggestion() -> Result<()> {
+        // A workspace assistant, without models, so that its prompt is used as the reply
+        let home = tempdir()?;
+        let dir = home.path().join(".stencila").join("assistants");
+        create_dir_all(&dir)?;
+        write(
+            dir.join("coder.md"),
+            "---\nversion: \"0.1.0\"\ninstruction-type: chat\n---\n\nReplies with code\n\n---\n\n```python exec\nx = 1\n```\n",
+        )?;
+
+        let mut root = Node::Article(Article::new(vec![Block::InstructionBlock(
+            InstructionBlock {
+                instruction_type: InstructionType::Chat,
+                messages: vec![InstructionMessage::from("set x to one")],
+                assignee: Some("coder".to_string()),
+                ..Default::default()
+            },
+        )]));
+
+        // The reply is added to the messages of the chat
+        execute(home.path(), &mut root).await?;
+        let messages = &chat(&root)?.messages;
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1].role, Some(MessageRole::Assistant));
+        assert!(matches!(
+            &messages[1].parts[..],
+            [MessagePart::Text(text)] if text.to_value_string().contains("x = 1")
+        ));
+        assert_eq!(
+            chat(&root)?.options.execution_status,
+            Some(ExecutionStatus::Succeeded)
+        );
+
+        // Without a follow up from the user there is nothing to reply to
+        execute(home.path(), &mut root).await?;
+        assert_eq!(chat(&root)?.messages.len(), 2);
+
+        // The reply is promoted into a suggestion
+        let chat_id = chat(&root)?.node_id();
+        let patch = promote_message(&root, &chat_id, None).await?;
+        schema::patch(&mut root, patch)?;
+        let suggestions = chat(&root)?.suggestions.clone().unwrap_or_default();
+        assert_eq!(suggestions.len(), 1);
+        assert!(matches!(
+            &suggestions[0].content[..],
+            [Block::CodeChunk(chunk)] if chunk.code.to_string() == "x = 1"
+        ));
+
+        Ok(())
+    }
+}
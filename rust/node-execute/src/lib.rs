@@ -9,20 +9,33 @@ use codec_text_trait::TextCodec;
 use common::{
     clap::{self, Args},
     eyre::Result,
+    rand,
     serde::{Deserialize, Serialize},
-    tokio::sync::{mpsc::UnboundedSender, RwLock, RwLockWriteGuard},
+    tokio::{
+        fs,
+        sync::{mpsc::UnboundedSender, RwLock, RwLockWriteGuard},
+    },
     tracing,
 };
 use context::Context;
 use kernels::Kernels;
+use model::Tools;
 use schema::{
-    Block, CompilationDigest, ExecutionMode, Inline, InstructionBlock, InstructionInline, Node,
-    NodeId, NodeProperty, Patch, PatchOp, PatchPath, VisitorAsync, WalkControl, WalkNode,
+    Block, CompilationDigest, ExecutionMessage, ExecutionMode, ExecutionStatus, ExecutionTag,
+    Inline, InstructionBlock, InstructionInline, Node, NodeId, NodeProperty, Patch, PatchOp,
+    PatchPath, VisitorAsync, WalkControl, WalkNode,
+};
+
+use crate::{
+    cache::{CachedExecution, ExecutionCache},
+    tools::KernelTools,
 };
 
 type NodeIds = Vec<NodeId>;
 
+mod cache;
 mod prelude;
+mod tools;
 
 mod article;
 mod call_block;
@@ -40,6 +53,8 @@ mod styled_block;
 mod styled_inline;
 mod table;
 
+pub use instruction_block::promote_message;
+
 /// Walk over a root node and compile it and child nodes
 pub async fn compile(
     home: PathBuf,
@@ -160,6 +175,16 @@ pub struct Executor {
 
     /// Options for execution
     options: ExecuteOptions,
+
+    /// The cache of execution outputs
+    ///
+    /// Only `Some` if the `cache` option is enabled and the cache could be opened.
+    cache: Option<ExecutionCache>,
+
+    /// A digest of the code executed so far in [`Phase::Execute`]
+    ///
+    /// Combined with the compilation digest of each node to create its cache key.
+    upstream_digest: u64,
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq, Eq, Args)]
@@ -211,6 +236,31 @@ pub struct ExecuteOptions {
     /// rendering of system prompts without making a potentially slow API request.
     #[arg(long)]
     pub dry_run: bool,
+
+    /// Reuse the outputs of previous executions stored in the execution cache
+    ///
+    /// When enabled, the outputs of code chunks that succeeded are stored on disk,
+    /// keyed by the digest of their code and of the code executed before them.
+    /// Subsequent executions reuse those outputs rather than calling the kernel
+    /// if nothing upstream has changed.
+    #[arg(long)]
+    pub cache: bool,
+
+    /// Skip retrieving passages from the files in the document's project
+    ///
+    /// By default, for assistants that opt in to retrieval, passages from other files in
+    /// the project that are relevant to an instruction are added to the context of the
+    /// instruction. Use this flag to not read the project's files.
+    #[arg(long)]
+    pub skip_passages: bool,
+
+    /// The path of the document being executed
+    ///
+    /// Used to exclude the document itself from passages retrieved from the files
+    /// in its project. Set by the document, rather than on the command line.
+    #[arg(skip)]
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
 }
 
 /// A phase of an [`Executor`]
@@ -233,6 +283,20 @@ impl Executor {
         node_ids: Option<NodeIds>,
         options: Option<ExecuteOptions>,
     ) -> Self {
+        let options = options.unwrap_or_default();
+
+        let cache = if options.cache {
+            match ExecutionCache::open() {
+                Ok(cache) => Some(cache),
+                Err(error) => {
+                    tracing::error!("While opening execution cache: {error}");
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
         Self {
             home,
             kernels,
@@ -244,7 +308,9 @@ impl Executor {
             figure_count: 0,
             equation_count: 0,
             is_last: false,
-            options: options.unwrap_or_default(),
+            options,
+            cache,
+            upstream_digest: 0,
         }
     }
 
@@ -273,6 +339,11 @@ impl Executor {
         // precede them in the document.
         self.context = Context::default();
 
+        // Reset the state used for caching outputs. Code deferred because of cache
+        // hits is not run at the end of the phase, but only when the state of the
+        // kernels is needed (e.g. by code that was not cached in a later execution).
+        self.upstream_digest = 0;
+
         root.walk_async(self).await
     }
 
@@ -294,19 +365,152 @@ impl Executor {
         self.kernels.write().await
     }
 
+    /// Get cached outputs and messages for a node, if any
+    ///
+    /// Returns `None` if the cache is not enabled or there is no entry for the
+    /// node. On a cache hit, the code is deferred so that it is run in the kernels
+    /// before any subsequent code that is not cached.
+    pub(crate) async fn cache_get(
+        &mut self,
+        node_id: &NodeId,
+        code: &str,
+        language: Option<&str>,
+        digest: &Option<CompilationDigest>,
+    ) -> Option<CachedExecution> {
+        let digest = digest.as_ref()?;
+        let cache = self.cache.as_ref()?;
+
+        let key = ExecutionCache::key(digest, self.upstream_digest);
+        let cached = cache.get(key)?;
+
+        self.upstream_digest = key;
+        self.kernels()
+            .await
+            .defer(&node_id.to_string(), code, language);
+
+        Some(cached)
+    }
+
+    /// Store outputs and messages for a node in the cache
+    ///
+    /// Only executions without errors or exceptions are stored. Regardless, updates
+    /// the upstream digest so that the cache keys of subsequent nodes reflect this node.
+    pub(crate) fn cache_set(
+        &mut self,
+        digest: &Option<CompilationDigest>,
+        outputs: &Option<Vec<Node>>,
+        messages: &Option<Vec<ExecutionMessage>>,
+    ) {
+        let Some(key) = self.cache_chain(digest) else {
+            return;
+        };
+
+        let Some(cache) = &self.cache else {
+            return;
+        };
+
+        if matches!(
+            prelude::execution_status(messages),
+            ExecutionStatus::Errors | ExecutionStatus::Exceptions
+        ) {
+            return;
+        }
+
+        let cached = CachedExecution {
+            outputs: outputs.clone(),
+            messages: messages.clone(),
+        };
+        if let Err(error) = cache.set(key, &cached) {
+            tracing::error!("While writing execution cache entry: {error}")
+        }
+    }
+
+    /// Chain the digest of executed code onto the upstream digest
+    ///
+    /// Used for nodes which may change kernel state but whose outputs are not
+    /// cached (e.g. the variable of a `ForBlock`), and for code chunks that are
+    /// skipped (with the digest of when they were last executed). If the node has
+    /// no digest then the upstream digest is randomized so that no subsequent node
+    /// will have a cache hit. Returns the new upstream digest, if any.
+    pub(crate) fn cache_chain(&mut self, digest: &Option<CompilationDigest>) -> Option<u64> {
+        match digest {
+            Some(digest) => {
+                let key = ExecutionCache::key(digest, self.upstream_digest);
+                self.upstream_digest = key;
+                Some(key)
+            }
+            None => {
+                self.upstream_digest = rand::random();
+                None
+            }
+        }
+    }
+
+    /// Calculate a digest of the dependencies of code
+    ///
+    /// Currently only the files declared using `@reads` tags are considered (resolved
+    /// relative to the home directory), so that a change to their content changes the
+    /// compilation digest, and the cache key, of the code. Returns `None` if the code
+    /// has no such dependencies.
+    pub(crate) async fn dependencies_digest(
+        &self,
+        tags: &Option<Vec<ExecutionTag>>,
+    ) -> Option<u64> {
+        let mut digest = None;
+        for tag in tags.iter().flatten() {
+            if tag.name != "reads" || tag.value.is_empty() {
+                continue;
+            }
+
+            // A missing, or unreadable, file is treated as empty
+            let content = fs::read(self.home.join(&tag.value))
+                .await
+                .unwrap_or_default();
+
+            let digest = digest.get_or_insert(0);
+            prelude::add_to_digest(digest, tag.value.as_bytes());
+            prelude::add_to_digest(digest, &content);
+        }
+
+        digest
+    }
+
+    /// Run any code deferred because of cache hits
+    ///
+    /// Called before executing code that was not cached (or calling tools) so that the
+    /// kernels have the state they would have if all preceding code had been executed.
+    pub(crate) async fn cache_replay(&mut self) {
+        self.kernels().await.execute_deferred().await;
+    }
+
     /// Get the document context
     ///
     /// Returns the nodes collected during walking the root node
-    /// and updates it with kernels.
+    /// and updates it with kernels and the home directory.
     ///
     /// Used by [`Executable`] nodes to pass to assistants to be used
     /// in their system prompts.
     pub async fn context(&mut self) -> Context {
+        // Run any code deferred because of cache hits so that the context
+        // includes the kernel state (e.g. variables) resulting from it
+        self.cache_replay().await;
+
         let kernels = self.kernels().await.kernel_contexts().await;
         self.context.kernels = kernels;
+        self.context.home = Some(self.home.clone());
+        self.context.path = self.options.path.clone();
+        self.context.skip_passages = self.options.skip_passages;
         self.context.clone()
     }
 
+    /// Get tools, backed by the kernels and home directory, for assistants to call
+    ///
+    /// Used by [`Executable`] nodes to allow assistants to run code in, and
+    /// inspect variables of, the kernels while performing instructions.
+    pub fn tools(&self) -> Arc<dyn Tools> {
+        Arc::new(KernelTools::new(self.kernels.clone(), self.home.clone()))
+    }
+
     /// Should the executor execute a node
     pub fn should_execute(
         &self,
@@ -416,15 +620,41 @@ impl Executor {
         self.send_patch(node_id, Some(authors), pairs)
     }
 
+    /// Patch paths within a node
+    ///
+    /// Used when the node to be patched is nested within the node and does
+    /// not have a stable id (e.g. a suggestion that is still being generated).
+    pub fn patch_paths<P>(&self, node_id: &NodeId, pairs: P)
+    where
+        P: IntoIterator<Item = (PatchPath, PatchOp)>,
+    {
+        self.send_patch_paths(node_id, None, pairs)
+    }
+
     /// Send a patch reflecting a change in the state of a node during execution
     fn send_patch<P>(&self, node_id: &NodeId, authors: Option<Vec<schema::AuthorRole>>, pairs: P)
     where
         P: IntoIterator<Item = (NodeProperty, PatchOp)>,
     {
-        let ops = pairs
-            .into_iter()
-            .map(|(property, op)| (PatchPath::from(property), op))
-            .collect();
+        self.send_patch_paths(
+            node_id,
+            authors,
+            pairs
+                .into_iter()
+                .map(|(property, op)| (PatchPath::from(property), op)),
+        )
+    }
+
+    /// Send a patch of paths within a node
+    fn send_patch_paths<P>(
+        &self,
+        node_id: &NodeId,
+        authors: Option<Vec<schema::AuthorRole>>,
+        pairs: P,
+    ) where
+        P: IntoIterator<Item = (PatchPath, PatchOp)>,
+    {
+        let ops = pairs.into_iter().collect();
 
         let patch = Patch {
             node_id: Some(node_id.clone()),
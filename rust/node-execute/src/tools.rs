@@ -0,0 +1,240 @@
+use std::{path::PathBuf, sync::Arc};
+
+use codec_text_trait::to_text;
+use common::{
+    async_trait::async_trait,
+    eyre::{bail, eyre, Result},
+    itertools::Itertools,
+    serde_json::{json, Value},
+    tokio::{fs, sync::RwLock},
+    tracing,
+};
+use kernel::KernelInstance;
+use kernels::Kernels;
+use model::{Tool, Tools};
+use schema::ExecutionMessage;
+
+/// The maximum number of characters of a file returned by the `read_file` tool
+const READ_FILE_MAX_CHARS: usize = 10_000;
+
+/// Tools, backed by the kernels and home directory of a document, that models
+/// can call while performing instructions
+///
+/// Allows models to inspect the variables in, and run code in forks of, the document's kernels
+/// (e.g. to check the names of the columns of a dataframe, or to test code) before
+/// proposing a suggestion.
+#[derive(Debug)]
+pub(crate) struct KernelTools {
+    /// The kernels of the document
+    kernels: Arc<RwLock<Kernels>>,
+
+    /// The home directory of the document, outside of which files can not be read
+    home: PathBuf,
+}
+
+impl KernelTools {
+    /// Create a new set of tools for a document
+    pub fn new(kernels: Arc<RwLock<Kernels>>, home: PathBuf) -> Self {
+        Self { kernels, home }
+    }
+
+    /// Execute code in a fork of the kernels
+    ///
+    /// A fork is used so that the code can not change the state of the document's kernels
+    /// (e.g. by reassigning variables). This tool is only offered to models when explicitly
+    /// opted into using the `tools` generate option.
+    async fn execute_code(&self, arguments: &Value) -> Result<String> {
+        let code = string_arg(arguments, "code")?;
+        let language = arguments.get("language").and_then(|value| value.as_str());
+
+        let mut fork = self.kernels.write().await.fork(language).await?;
+        let result = fork.execute(code).await;
+        if let Err(error) = fork.stop().await {
+            tracing::debug!("While stopping kernel fork: {error}");
+        }
+        let (outputs, messages) = result?;
+
+        let outputs = outputs.iter().map(to_text).join("\n\n");
+        let messages = messages
+            .iter()
+            .map(|message| {
+                let error_type = message
+                    .error_type
+                    .as_ref()
+                    .map(|error_type| [error_type, ": "].concat())
+                    .unwrap_or_default();
+                format!("{}: {error_type}{}", message.level, message.message)
+            })
+            .join("\n");
+
+        Ok(match (outputs.is_empty(), messages.is_empty()) {
+            (true, true) => "Code executed without outputs or messages".to_string(),
+            (false, true) => outputs,
+            (true, false) => messages,
+            (false, false) => [outputs, messages].join("\n\n"),
+        })
+    }
+
+    /// Get the value of a variable in the kernels
+    async fn get_variable(&self, arguments: &Value) -> Result<String> {
+        let name = string_arg(arguments, "name")?;
+
+        match self.kernels.write().await.get(name).await? {
+            Some(value) => Ok(to_text(&value)),
+            None => bail!("No variable named `{name}`"),
+        }
+    }
+
+    /// List the variables in the kernels
+    async fn list_variables(&self) -> Result<String> {
+        let variables = self.kernels.read().await.variables().await;
+        if variables.is_empty() {
+            return Ok("There are no variables".to_string());
+        }
+
+        Ok(variables
+            .into_iter()
+            .map(|variable| {
+                let mut line = format!("- {}", variable.name);
+                if let Some(lang) = variable.programming_language {
+                    line += &format!(" ({lang})");
+                }
+                if let Some(native_type) = variable.native_type {
+                    line += &format!(": {native_type}");
+                }
+                if let Some(hint) = variable.native_hint {
+                    line += &format!("\n  {}", hint.replace('\n', "\n  "));
+                }
+                line
+            })
+            .join("\n"))
+    }
+
+    /// Read a file within the home directory of the document
+    async fn read_file(&self, arguments: &Value) -> Result<String> {
+        let path = string_arg(arguments, "path")?;
+
+        let home = self.home.canonicalize()?;
+        let path = home
+            .join(path)
+            .canonicalize()
+            .map_err(|error| eyre!("Unable to read file `{path}`: {error}"))?;
+        if !path.starts_with(&home) {
+            bail!("Files outside of the document's directory can not be read");
+        }
+
+        let content = fs::read_to_string(&path).await?;
+        Ok(match content.char_indices().nth(READ_FILE_MAX_CHARS) {
+            Some((index, ..)) => format!("{}\n\n[truncated]", &content[..index]),
+            None => content,
+        })
+    }
+}
+
+#[async_trait]
+impl Tools for KernelTools {
+    fn list(&self) -> Vec<Tool> {
+        vec![
+            Tool {
+                name: "execute_code".to_string(),
+                description: "Execute code in a fork of the document's kernels and get any outputs and messages. Note that any changes to variables do not persist.".to_string(),
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "code": {
+                            "type": "string",
+                            "description": "The code to execute"
+                        },
+                        "language": {
+                            "type": "string",
+                            "description": "The programming language of the code e.g. python, r"
+                        }
+                    },
+                    "required": ["code"]
+                }),
+            },
+            Tool {
+                name: "get_variable".to_string(),
+                description: "Get the value of a variable in the document's kernels".to_string(),
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "name": {
+                            "type": "string",
+                            "description": "The name of the variable"
+                        }
+                    },
+                    "required": ["name"]
+                }),
+            },
+            Tool {
+                name: "list_variables".to_string(),
+                description: "List the variables in the document's kernels, including their types and a summary of their values".to_string(),
+                parameters: json!({
+                    "type": "object",
+                    "properties": {}
+                }),
+            },
+            Tool {
+                name: "read_file".to_string(),
+                description: "Read a file in the document's directory".to_string(),
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "The path of the file, relative to the document's directory"
+                        }
+                    },
+                    "required": ["path"]
+                }),
+            },
+        ]
+    }
+
+    async fn call(&self, name: &str, arguments: &Value) -> Result<String> {
+        match name {
+            "execute_code" => self.execute_code(arguments).await,
+            "get_variable" => self.get_variable(arguments).await,
+            "list_variables" => self.list_variables().await,
+            "read_file" => self.read_file(arguments).await,
+            _ => bail!("Unknown tool `{name}`"),
+        }
+    }
+
+    async fn check_code(&self, code: &[(String, Option<String>)]) -> Result<Vec<ExecutionMessage>> {
+        // Execute the code in forks of the kernels, one for each language, so that
+        // the state of the document's kernels is not changed
+        let mut forks: Vec<(Option<String>, Box<dyn KernelInstance>)> = Vec::new();
+        let mut messages = Vec::new();
+        for (code, language) in code {
+            let index = match forks.iter().position(|(lang, ..)| lang == language) {
+                Some(index) => index,
+                None => {
+                    let fork = self.kernels.write().await.fork(language.as_deref()).await?;
+                    forks.push((language.clone(), fork));
+                    forks.len() - 1
+                }
+            };
+
+            let (.., mut msgs) = forks[index].1.execute(code).await?;
+            messages.append(&mut msgs);
+        }
+
+        for (.., mut fork) in forks {
+            if let Err(error) = fork.stop().await {
+                tracing::debug!("While stopping kernel fork: {error}");
+            }
+        }
+
+        Ok(messages)
+    }
+}
+
+/// Get a required string argument of a tool call
+fn string_arg<'a>(arguments: &'a Value, name: &str) -> Result<&'a str> {
+    arguments
+        .get(name)
+        .and_then(|value| value.as_str())
+        .ok_or_else(|| eyre!("Missing required string argument `{name}`"))
+}
@@ -47,12 +47,14 @@ pub enum NodeProperty {
     Comments,
     CompilationDigest,
     CompilationMessages,
+    CompletionTokens,
     ContactPoints,
     Contains,
     Content,
     ContentSize,
     ContentUrl,
     Contributors,
+    Cost,
     CostWeight,
     Css,
     DateAccepted,
@@ -186,6 +188,7 @@ pub enum NodeProperty {
     PostalCode,
     ProductId,
     ProgrammingLanguage,
+    PromptTokens,
     PropertyId,
     Provenance,
     ProvenanceCategory,
@@ -2,7 +2,11 @@ use std::hash::{Hash, Hasher};
 
 use common::{once_cell::sync::Lazy, regex::Regex, seahash::SeaHasher};
 use format::Format;
-use schema::{CompilationDigest, ExecutionTag};
+use schema::{
+    CodeLocation, CompilationDigest, ExecutionDependant, ExecutionDependantNode,
+    ExecutionDependantRelation, ExecutionDependency, ExecutionDependencyNode,
+    ExecutionDependencyRelation, ExecutionTag, Variable,
+};
 
 // Re-exports for the convenience of internal crates implementing
 // the `Parser` trait
@@ -54,9 +58,18 @@ pub trait Parser: Sync + Send {
         let tags = REGEX
             .captures_iter(code)
             .fold(Vec::new(), |mut tags, captures| {
-                for value in captures[2].split_whitespace() {
+                let name = &captures[1];
+                let values: Vec<&str> = captures[2].split_whitespace().collect();
+                if values.is_empty() {
+                    // Tags such as `@pure` and `@impure` do not have values
                     tags.push(ExecutionTag {
-                        name: captures[1].to_string(),
+                        name: name.to_string(),
+                        ..Default::default()
+                    });
+                }
+                for value in values {
+                    tags.push(ExecutionTag {
+                        name: name.to_string(),
                         value: value.to_string(),
                         ..Default::default()
                     });
@@ -71,10 +84,194 @@ pub trait Parser: Sync + Send {
         }
     }
 
+    /// Extract execution dependencies from some code
+    ///
+    /// Not called by `parse` (i.e. not run when documents are compiled) because the
+    /// analysis is approximate. Used on demand, e.g. by the language server.
+    ///
+    /// This default implementation uses `@uses`, `@reads` and `@imports` tags. If the code is
+    /// an expression (i.e. a single line), then identifiers in it, other than those for function
+    /// calls, assignments, attributes and keywords, are also treated as variables that it uses.
+    fn execution_dependencies(
+        &self,
+        code: &str,
+        tags: &Option<Vec<ExecutionTag>>,
+    ) -> Option<Vec<ExecutionDependency>> {
+        let mut dependencies: Vec<ExecutionDependency> = Vec::new();
+        let mut push = |relation: ExecutionDependencyRelation,
+                        name: &str,
+                        line: usize,
+                        column: usize| {
+            if dependencies.iter().any(|dependency| {
+                matches!(&dependency.dependency_node, ExecutionDependencyNode::Variable(var) if var.name == name)
+            }) {
+                return;
+            }
+            dependencies.push(ExecutionDependency {
+                code_location: Some(code_location(line, column, name)),
+                ..ExecutionDependency::new(
+                    relation,
+                    ExecutionDependencyNode::Variable(Variable::new(name.to_string())),
+                )
+            });
+        };
+
+        for tag in tags.iter().flatten().filter(|tag| !tag.value.is_empty()) {
+            let relation = match tag.name.as_str() {
+                "uses" => ExecutionDependencyRelation::Uses,
+                "reads" => ExecutionDependencyRelation::Reads,
+                "imports" => ExecutionDependencyRelation::Imports,
+                _ => continue,
+            };
+            if let Some((line, column)) = location_of(code, &tag.value) {
+                push(relation, &tag.value, line, column);
+            }
+        }
+
+        if !code.trim().contains('\n') {
+            static REGEX: Lazy<Regex> = Lazy::new(|| {
+                Regex::new(
+                    r#"("[^"]*"|'[^']*')|([A-Za-z_][A-Za-z0-9_]*)(?:\.[A-Za-z_][A-Za-z0-9_]*)*(\s*(?:\(|<-|=(?:[^=]|$)))?"#,
+                )
+                .expect("Invalid regex")
+            });
+
+            for captures in REGEX.captures_iter(code) {
+                let (Some(name), None) = (captures.get(2), captures.get(3)) else {
+                    // A string literal, a function call, or an assignment
+                    continue;
+                };
+                let before = &code[..name.start()];
+                if KEYWORDS.contains(&name.as_str())
+                    || before.ends_with(|c: char| c.is_ascii_digit())
+                    || before.trim_end().ends_with(['.', '$', '@'])
+                {
+                    continue;
+                }
+
+                let column = code[..name.start()].chars().count();
+                push(ExecutionDependencyRelation::Uses, name.as_str(), 0, column);
+            }
+        }
+
+        (!dependencies.is_empty()).then_some(dependencies)
+    }
+
+    /// Extract execution dependants from some code
+    ///
+    /// As for `execution_dependencies`, only used on demand.
+    ///
+    /// This default implementation uses `@assigns`, `@changes` and `@writes` tags, and
+    /// detects top-level (i.e. unindented) assignments and function definitions using
+    /// patterns common to many languages (e.g. `x = ...`, `x <- ...`, `let x = ...`,
+    /// `def f(...)`, `function f(...)`).
+    fn execution_dependants(
+        &self,
+        code: &str,
+        tags: &Option<Vec<ExecutionTag>>,
+    ) -> Option<Vec<ExecutionDependant>> {
+        let mut dependants: Vec<ExecutionDependant> = Vec::new();
+        let mut push =
+            |relation: ExecutionDependantRelation, name: &str, line: usize, column: usize| {
+                dependants.push(ExecutionDependant {
+                    code_location: Some(code_location(line, column, name)),
+                    ..ExecutionDependant::new(
+                        relation,
+                        ExecutionDependantNode::Variable(Variable::new(name.to_string())),
+                    )
+                });
+            };
+
+        for tag in tags.iter().flatten().filter(|tag| !tag.value.is_empty()) {
+            let relation = match tag.name.as_str() {
+                "assigns" => ExecutionDependantRelation::Assigns,
+                "changes" => ExecutionDependantRelation::Alters,
+                "writes" => ExecutionDependantRelation::Writes,
+                _ => continue,
+            };
+            if let Some((line, column)) = location_of(code, &tag.value) {
+                push(relation, &tag.value, line, column);
+            }
+        }
+
+        static REGEX: Lazy<Regex> = Lazy::new(|| {
+            Regex::new(
+                r"^(?:(?:let|const|var)\s+([A-Za-z_$][\w$]*)|(?:def|function|fn)\s+([A-Za-z_$][\w$]*)|([A-Za-z_][\w.]*)\s*(?:<<?-|=(?:[^=]|$)))",
+            )
+            .expect("Invalid regex")
+        });
+
+        for (line, text) in code.lines().enumerate() {
+            let Some(captures) = REGEX.captures(text) else {
+                continue;
+            };
+            let (relation, name) = if let Some(name) = captures.get(1) {
+                (ExecutionDependantRelation::Declares, name)
+            } else if let Some(name) = captures.get(2) {
+                (ExecutionDependantRelation::Declares, name)
+            } else if let Some(name) = captures.get(3) {
+                (ExecutionDependantRelation::Assigns, name)
+            } else {
+                continue;
+            };
+            let column = text[..name.start()].chars().count();
+            push(relation, name.as_str(), line, column);
+        }
+
+        (!dependants.is_empty()).then_some(dependants)
+    }
+
     /// Parse code in a language
     fn parse(&self, code: &str, format: &Format) -> ParseInfo;
 }
 
+/// Keywords and literals, in various languages, that are not variables
+const KEYWORDS: &[&str] = &[
+    "and",
+    "else",
+    "false",
+    "False",
+    "FALSE",
+    "for",
+    "if",
+    "in",
+    "Inf",
+    "is",
+    "lambda",
+    "NA",
+    "NaN",
+    "new",
+    "None",
+    "not",
+    "null",
+    "NULL",
+    "or",
+    "true",
+    "True",
+    "TRUE",
+    "typeof",
+    "undefined",
+];
+
+/// Get the zero-based line and column of the first occurrence of a name in some code
+fn location_of(code: &str, name: &str) -> Option<(usize, usize)> {
+    code.lines().enumerate().find_map(|(line, text)| {
+        text.find(name)
+            .map(|index| (line, text[..index].chars().count()))
+    })
+}
+
+/// Create a [`CodeLocation`] for a name at a line and column
+fn code_location(line: usize, column: usize, name: &str) -> CodeLocation {
+    CodeLocation {
+        start_line: Some(line as u64),
+        start_column: Some(column as u64),
+        end_line: Some(line as u64),
+        end_column: Some((column + name.chars().count()) as u64),
+        ..Default::default()
+    }
+}
+
 /// A default parser
 ///
 /// Calculates language independent `ParseInfo` properties such
@@ -88,12 +285,95 @@ impl Parser for DefaultParser {
     }
 
     fn parse(&self, code: &str, format: &Format) -> ParseInfo {
+        let execution_tags = self.execution_tags(code);
+
         ParseInfo {
             compilation_digest: CompilationDigest {
                 state_digest: self.state_digest(code, format),
                 ..Default::default()
             },
-            execution_tags: self.execution_tags(code),
+            execution_tags,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(code: &str) -> (Vec<String>, Vec<String>) {
+        let parser = DefaultParser::default();
+        let tags = parser.execution_tags(code);
+
+        let dependencies = parser
+            .execution_dependencies(code, &tags)
+            .iter()
+            .flatten()
+            .filter_map(|dependency| match &dependency.dependency_node {
+                ExecutionDependencyNode::Variable(var) => Some(var.name.clone()),
+                _ => None,
+            })
+            .collect();
+        let dependants = parser
+            .execution_dependants(code, &tags)
+            .iter()
+            .flatten()
+            .filter_map(|dependant| match &dependant.dependant_node {
+                ExecutionDependantNode::Variable(var) => Some(var.name.clone()),
+                _ => None,
+            })
+            .collect();
+        (dependencies, dependants)
+    }
+
+    #[test]
+    fn execution_tags() {
+        let parser = DefaultParser::default();
+
+        let tags = parser
+            .execution_tags("# @impure\n# @reads a.csv b.csv\nx = 1")
+            .unwrap();
+        let tags: Vec<(&str, &str)> = tags
+            .iter()
+            .map(|tag| (tag.name.as_str(), tag.value.as_str()))
+            .collect();
+        assert_eq!(
+            tags,
+            vec![("impure", ""), ("reads", "a.csv"), ("reads", "b.csv")]
+        );
+
+        assert!(parser.execution_tags("x = 1").is_none());
+    }
+
+    #[test]
+    fn dependencies_and_dependants() {
+        let parser = DefaultParser::default();
+
+        assert_eq!(
+            names("a + mean(b$c, na.rm = TRUE)"),
+            (vec!["a".into(), "b".into()], vec![])
+        );
+
+        assert_eq!(names("'x' + y.z"), (vec!["y".into()], vec![]));
+
+        let code =
+            "# @uses data\nx = 1\ny <- 2\n  z = 3\nif x == y: pass\ndef f(): pass\nlet w = 4";
+        assert_eq!(
+            names(code),
+            (
+                vec!["data".into()],
+                vec!["x".into(), "y".into(), "f".into(), "w".into()]
+            )
+        );
+
+        let location = parser
+            .execution_dependants(code, &parser.execution_tags(code))
+            .unwrap()[1]
+            .code_location
+            .clone()
+            .unwrap();
+        assert_eq!(location.start_line, Some(2));
+        assert_eq!(location.start_column, Some(0));
+        assert_eq!(location.end_column, Some(1));
+    }
+}
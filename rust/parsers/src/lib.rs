@@ -1,17 +1,46 @@
-use parser::{common::once_cell::sync::Lazy, format::Format, DefaultParser};
+use parser::{
+    common::once_cell::sync::Lazy,
+    format::Format,
+    schema::{ExecutionDependant, ExecutionDependency},
+    DefaultParser,
+};
 
 pub use parser::{ParseInfo, Parser};
 
+/// Get the parser for a language
+fn parser(format: &Format) -> &'static dyn Parser {
+    static PARSERS: Lazy<Vec<Box<dyn Parser>>> = Lazy::new(Vec::new);
+    static DEFAULT: DefaultParser = DefaultParser {};
+
+    PARSERS
+        .iter()
+        .find(|parser| parser.supports_language(format))
+        .map_or(&DEFAULT as &dyn Parser, |parser| parser.as_ref())
+}
+
 /// Parse some code in a language
 pub fn parse(code: &str, language: &str) -> ParseInfo {
-    static PARSERS: Lazy<Vec<Box<dyn Parser>>> = Lazy::new(Vec::new);
+    let format = Format::from_name(language);
+    parser(&format).parse(code, &format)
+}
 
+/// Get the execution dependencies and dependants of some code in a language
+///
+/// This analysis is not done when code is parsed for compilation and should
+/// only be used where needed (e.g. for go to definition).
+pub fn dependencies(
+    code: &str,
+    language: &str,
+) -> (
+    Option<Vec<ExecutionDependency>>,
+    Option<Vec<ExecutionDependant>>,
+) {
     let format = Format::from_name(language);
-    for parser in PARSERS.iter() {
-        if parser.supports_language(&format) {
-            return parser.parse(code, &format);
-        }
-    }
+    let parser = parser(&format);
 
-    DefaultParser::default().parse(code, &format)
+    let tags = parser.execution_tags(code);
+    (
+        parser.execution_dependencies(code, &tags),
+        parser.execution_dependants(code, &tags),
+    )
 }
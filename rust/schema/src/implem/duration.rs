@@ -39,6 +39,26 @@ impl Duration {
         )
     }
 
+    /// Get the duration in milliseconds
+    pub fn to_millis(&self) -> u64 {
+        let factor = match self.time_unit {
+            TimeUnit::Year => 365.25 * 86_400_000.,
+            TimeUnit::Month => 30.5 * 86_400_000.,
+            TimeUnit::Week => 7. * 86_400_000.,
+            TimeUnit::Day => 86_400_000.,
+            TimeUnit::Hour => 3_600_000.,
+            TimeUnit::Minute => 60_000.,
+            TimeUnit::Second => 1_000.,
+            TimeUnit::Millisecond => 1.,
+            TimeUnit::Microsecond => 1e-3,
+            TimeUnit::Nanosecond => 1e-6,
+            TimeUnit::Picosecond => 1e-9,
+            TimeUnit::Femtosecond => 1e-12,
+            TimeUnit::Attosecond => 1e-15,
+        };
+        (self.value as f64 * factor) as u64
+    }
+
     /// Encode a duration as a DOM HTML attribute
     ///
     /// This is lossy with respect to the `timeUnit` of the duration but produces
@@ -1,15 +1,23 @@
 use codec_info::{lost_exec_options, lost_options};
 
 use crate::{
-    authorship, prelude::*, AuthorRole, AuthorRoleName, InstructionBlock, SuggestionStatus,
+    authorship, prelude::*, AuthorRole, AuthorRoleName, InstructionBlock, InstructionMessage,
+    InstructionType, MessagePart, MessageRole, SuggestionStatus,
 };
 
 impl InstructionBlock {
+    /// Get the latest message from the user (if any)
+    ///
+    /// For chats, this is the message that is encoded in source formats such as Markdown.
+    pub fn latest_user_message(&self) -> Option<&InstructionMessage> {
+        latest_user_message(&self.messages)
+    }
+
     pub fn apply_patch_op(
         &mut self,
         path: &mut PatchPath,
         op: &PatchOp,
-        _context: &mut PatchContext,
+        context: &mut PatchContext,
     ) -> Result<bool> {
         if path.is_empty() {
             if let PatchOp::Choose(suggestion_id) = op {
@@ -46,12 +54,100 @@ impl InstructionBlock {
             if self.hide_suggestions == Some(true) {
                 return Ok(true);
             }
+        } else if matches!(
+            path.front(),
+            Some(PatchSlot::Property(NodeProperty::Messages))
+        ) && matches!(self.instruction_type, InstructionType::Chat)
+            && context.format.is_some()
+        {
+            // Only the latest user message of a chat is encoded in source formats so,
+            // rather than replacing the conversation with it, use it to update the conversation.
+            // Operations are applied to a copy of the messages which is kept in the context
+            // so that subsequent operations in the patch are applied to the same copy.
+            let node_id = self.node_id();
+            let (original, mut messages) = match context.chat_messages.take() {
+                Some((id, original, messages)) if id == node_id => (original, messages),
+                _ => (self.messages.clone(), self.messages.clone()),
+            };
+
+            path.pop_front();
+            messages.apply(path, op.clone(), context)?;
+
+            self.messages = original.clone();
+            if let Some(message) = latest_user_message(&messages) {
+                update_chat_messages(&mut self.messages, message);
+            }
+
+            context.chat_messages = Some((node_id, original, messages));
+
+            return Ok(true);
         }
 
         Ok(false)
     }
 }
 
+/// Update the messages of a chat with the latest user message from a source format
+///
+/// The message is matched to the existing messages by id: messages that were patched
+/// in place keep their id, whereas new messages have a new id. If the matched message
+/// is unchanged then the conversation is left as is. Otherwise, if the latest user
+/// message has not yet been replied to, it is edited in place. Otherwise, the message
+/// is a follow up and is appended to the conversation (even if it has the same text
+/// as a previous message).
+fn update_chat_messages(messages: &mut Vec<InstructionMessage>, message: &InstructionMessage) {
+    let message_id = message.node_id();
+    let matched = messages
+        .iter()
+        .find(|existing| existing.node_id() == message_id);
+    if matched.is_some_and(|existing| {
+        matches!(existing.role, None | Some(MessageRole::User))
+            && message_text(existing) == message_text(message)
+    }) {
+        return;
+    }
+
+    let latest = messages
+        .iter()
+        .rposition(|message| matches!(message.role, None | Some(MessageRole::User)));
+    if let Some(index) = latest.filter(|index| index + 1 == messages.len()) {
+        messages[index].parts = message.parts.clone();
+        return;
+    }
+
+    if matched.is_some() {
+        // Use a new message, rather than a clone, so that ids are not duplicated
+        messages.push(InstructionMessage {
+            role: message.role.clone(),
+            parts: message.parts.clone(),
+            authors: message.authors.clone(),
+            ..Default::default()
+        });
+    } else {
+        messages.push(message.clone());
+    }
+}
+
+/// Get the latest message from the user in a list of messages
+fn latest_user_message(messages: &[InstructionMessage]) -> Option<&InstructionMessage> {
+    messages
+        .iter()
+        .rev()
+        .find(|message| matches!(message.role, None | Some(MessageRole::User)))
+}
+
+/// Get the text of a message
+fn message_text(message: &InstructionMessage) -> String {
+    message
+        .parts
+        .iter()
+        .filter_map(|part| match part {
+            MessagePart::Text(text) => Some(text.to_value_string()),
+            _ => None,
+        })
+        .join("")
+}
+
 impl MarkdownCodec for InstructionBlock {
     fn to_markdown(&self, context: &mut MarkdownEncodeContext) {
         if context.render {
@@ -69,17 +165,27 @@ impl MarkdownCodec for InstructionBlock {
 
         let instruction_type = self.instruction_type.to_string().to_lowercase();
 
+        // For chats, encode the latest message from the user, rather than
+        // the latest message which will usually be a reply. The rest of the conversation
+        // is kept while the document is open (see `apply_patch_op`) but is lost if the
+        // document is saved to, and reopened from, Markdown, so record that loss.
+        let message = match self.instruction_type {
+            InstructionType::Chat => {
+                if self.messages.len() > 1 {
+                    context.add_loss("InstructionBlock.messages");
+                }
+                self.latest_user_message()
+            }
+            _ => self.messages.last(),
+        };
+
         if matches!(context.format, Format::Myst) {
             context
                 .myst_directive(
                     ':',
                     &instruction_type,
                     |context| {
-                        if let Some(part) = self
-                            .messages
-                            .last()
-                            .and_then(|message| message.parts.first())
-                        {
+                        if let Some(part) = message.and_then(|message| message.parts.first()) {
                             context
                                 .push_str(" ")
                                 .push_prop_fn(NodeProperty::Messages, |context| {
@@ -178,11 +284,7 @@ impl MarkdownCodec for InstructionBlock {
                     .push_str(" ");
             }
 
-            if let Some(part) = self
-                .messages
-                .last()
-                .and_then(|message| message.parts.first())
-            {
+            if let Some(part) = message.and_then(|message| message.parts.first()) {
                 context
                     .push_prop_fn(NodeProperty::Messages, |context| part.to_markdown(context))
                     .newline();
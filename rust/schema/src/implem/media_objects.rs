@@ -128,9 +128,17 @@ impl MarkdownCodec for AudioObject {
 
 impl ImageObject {
     pub fn to_html_special(&self, _context: &mut HtmlEncodeContext) -> String {
-        use codec_html_trait::encode::elem;
+        use codec_html_trait::encode::{attr, elem};
+
+        let mut attrs = html_attrs!(self);
+
+        // Keep any embed URL (e.g. the specification of a Vega-Lite chart)
+        // so that the image can be rendered interactively
+        if let Some(embed_url) = &self.options.embed_url {
+            attrs.push(attr("data-embed-url", embed_url));
+        }
 
-        elem("img", &html_attrs!(self), &[])
+        elem("img", &attrs, &[])
     }
 
     pub fn to_jats_special(&self) -> (String, Losses) {
@@ -145,8 +153,13 @@ impl ImageObject {
 
 impl DomCodec for ImageObject {
     fn to_dom(&self, context: &mut DomEncodeContext) {
+        context.enter_node(self.node_type(), self.node_id());
+
+        if let Some(embed_url) = &self.options.embed_url {
+            context.push_attr("embed-url", embed_url);
+        }
+
         context
-            .enter_node(self.node_type(), self.node_id())
             .enter_elem("img")
             .push_attr("src", &self.content_url)
             .exit_elem();
@@ -2,6 +2,8 @@ use codec_info::lost_options;
 
 use crate::{prelude::*, SuggestionBlock};
 
+use super::utils::suggestion_usage;
+
 impl SuggestionBlock {
     pub fn to_jats_special(&self) -> (String, Losses) {
         let (content, mut losses) = self.content.to_jats();
@@ -16,7 +18,14 @@ impl MarkdownCodec for SuggestionBlock {
     fn to_markdown(&self, context: &mut MarkdownEncodeContext) {
         context
             .enter_node(self.node_type(), self.node_id())
-            .merge_losses(lost_options!(self, id));
+            .merge_losses(lost_options!(self, id, messages));
+
+        let usage = suggestion_usage(
+            &self.prompt_tokens,
+            &self.completion_tokens,
+            &self.cost,
+            &self.execution_duration,
+        );
 
         if matches!(context.format, Format::Myst) {
             context.myst_directive(
@@ -29,7 +38,15 @@ impl MarkdownCodec for SuggestionBlock {
                             .push_prop_str(NodeProperty::Feedback, feedback);
                     }
                 },
-                |_| {},
+                |context| {
+                    for (property, name, value) in &usage {
+                        context.myst_directive_option(
+                            *property,
+                            Some(&name.replace('_', "-")),
+                            value,
+                        );
+                    }
+                },
                 |context| {
                     context.push_prop_fn(NodeProperty::Content, |context| {
                         self.content.to_markdown(context)
@@ -39,6 +56,20 @@ impl MarkdownCodec for SuggestionBlock {
         } else {
             context.push_semis().push_str(" suggest");
 
+            if !usage.is_empty() {
+                context.push_str(" {");
+                for (index, (property, name, value)) in usage.iter().enumerate() {
+                    if index > 0 {
+                        context.push_str(" ");
+                    }
+                    context
+                        .push_str(name)
+                        .push_str("=")
+                        .push_prop_str(*property, value);
+                }
+                context.push_str("}");
+            }
+
             if let Some(feedback) = &self.feedback {
                 context
                     .push_str(" ")
@@ -2,12 +2,36 @@ use codec_info::lost_options;
 
 use crate::{prelude::*, SuggestionInline};
 
+use super::utils::suggestion_usage;
+
 impl MarkdownCodec for SuggestionInline {
     fn to_markdown(&self, context: &mut MarkdownEncodeContext) {
         context
             .enter_node(self.node_type(), self.node_id())
-            .merge_losses(lost_options!(self, id))
-            .push_str("[[suggest ")
+            .merge_losses(lost_options!(self, id, messages))
+            .push_str("[[suggest ");
+
+        let usage = suggestion_usage(
+            &self.prompt_tokens,
+            &self.completion_tokens,
+            &self.cost,
+            &self.execution_duration,
+        );
+        if !usage.is_empty() {
+            context.push_str("{");
+            for (index, (property, name, value)) in usage.iter().enumerate() {
+                if index > 0 {
+                    context.push_str(" ");
+                }
+                context
+                    .push_str(name)
+                    .push_str("=")
+                    .push_prop_str(*property, value);
+            }
+            context.push_str("} ");
+        }
+
+        context
             .push_prop_fn(NodeProperty::Content, |context| {
                 self.content.to_markdown(context)
             })
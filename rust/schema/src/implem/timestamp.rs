@@ -61,14 +61,19 @@ impl Timestamp {
         };
 
         let human_time = HumanTime::from(date_time);
-        human_time.to_text_en(
-            if precise {
-                Accuracy::Precise
-            } else {
-                Accuracy::Rough
-            },
-            Tense::Past,
-        )
+        let accuracy = if precise {
+            Accuracy::Precise
+        } else {
+            Accuracy::Rough
+        };
+
+        // Avoid the "now ago" that results from using `Tense::Past` for zero durations
+        let text = human_time.to_text_en(accuracy, Tense::Present);
+        if text == "now" {
+            "just now".to_string()
+        } else {
+            human_time.to_text_en(accuracy, Tense::Past)
+        }
     }
 
     /// Encode a timestamp as a DOM HTML attribute
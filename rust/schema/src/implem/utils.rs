@@ -1,6 +1,7 @@
 use codec_dom_trait::{DomCodec, DomEncodeContext};
+use node_type::NodeProperty;
 
-use crate::Block;
+use crate::{Block, Duration, Number, UnsignedInteger};
 
 /// Encode the `caption` of a `Figure`, `Table` of `CodeChunk` to DOM HTML
 ///
@@ -40,3 +41,41 @@ pub(super) fn caption_to_dom(
         }
     }
 }
+
+/// Get the usage properties of a generated suggestion as property, name and value tuples
+///
+/// Used to encode the tokens, cost and duration (in milliseconds) of suggestions to formats,
+/// such as Markdown, which otherwise would not store them.
+pub(super) fn suggestion_usage(
+    prompt_tokens: &Option<UnsignedInteger>,
+    completion_tokens: &Option<UnsignedInteger>,
+    cost: &Option<Number>,
+    duration: &Option<Duration>,
+) -> Vec<(NodeProperty, &'static str, String)> {
+    let mut usage = Vec::new();
+    if let Some(tokens) = prompt_tokens {
+        usage.push((
+            NodeProperty::PromptTokens,
+            "prompt_tokens",
+            tokens.to_string(),
+        ));
+    }
+    if let Some(tokens) = completion_tokens {
+        usage.push((
+            NodeProperty::CompletionTokens,
+            "completion_tokens",
+            tokens.to_string(),
+        ));
+    }
+    if let Some(cost) = cost {
+        usage.push((NodeProperty::Cost, "cost", cost.to_string()));
+    }
+    if let Some(duration) = duration {
+        usage.push((
+            NodeProperty::ExecutionDuration,
+            "duration",
+            duration.to_millis().to_string(),
+        ));
+    }
+    usage
+}
@@ -17,7 +17,7 @@ use node_type::NodeProperty;
 
 use crate::{
     prelude::AuthorType, replicate, Author, AuthorRole, AuthorRoleAuthor, Block, CordOp, Inline,
-    Node, ProvenanceCount, Timestamp,
+    InstructionMessage, Node, ProvenanceCount, Timestamp,
 };
 
 /// Assign authorship to a node
@@ -116,6 +116,10 @@ pub struct PatchContext {
     /// The author (0-based index) which should be using when making changes to the
     /// authorship of `Cord` nodes during a call to `patch`.
     author_index: Option<u8>,
+
+    /// The id of a chat, its original messages, and the messages with operations from a
+    /// source format applied, while those operations are being applied during a call to `patch`.
+    pub(crate) chat_messages: Option<(NodeId, Vec<InstructionMessage>, Vec<InstructionMessage>)>,
 }
 
 impl PatchContext {
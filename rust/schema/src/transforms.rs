@@ -7,7 +7,10 @@
 
 use node_type::NodeType;
 
-use crate::{Block, CodeBlock, CodeChunk, CodeExpression, CodeInline, Inline, Node};
+use crate::{
+    Block, CodeBlock, CodeChunk, CodeExpression, CodeInline, Inline, List, ListItem, ListOrder,
+    Node, Paragraph, Text,
+};
 
 /// Transform a vector of [`Block`]s into a vector of [`Inline`]s
 pub fn blocks_to_inlines(blocks: Vec<Block>) -> Vec<Inline> {
@@ -80,6 +83,47 @@ pub fn transform_block(block: Block, node_type: NodeType) -> Block {
             }),
             _ => Block::CodeChunk(inner),
         },
+        Block::Paragraph(inner) => match node_type {
+            NodeType::List => {
+                let items = paragraph_lines(inner.content)
+                    .into_iter()
+                    .map(|line| ListItem::new(vec![Block::Paragraph(Paragraph::new(line))]))
+                    .collect();
+                Block::List(List::new(items, ListOrder::Unordered))
+            }
+            _ => Block::Paragraph(inner),
+        },
         _ => block,
     }
 }
+
+/// Split the content of a paragraph into lines at the newlines within its [`Text`] nodes
+///
+/// Used when transforming a paragraph into a list so that each line becomes a list item.
+fn paragraph_lines(content: Vec<Inline>) -> Vec<Vec<Inline>> {
+    let mut lines = vec![Vec::new()];
+    for inline in content {
+        match inline {
+            Inline::Text(text) if text.value.contains('\n') => {
+                for (index, part) in text.value.split('\n').enumerate() {
+                    if index > 0 {
+                        lines.push(Vec::new());
+                    }
+                    if !part.is_empty() {
+                        if let Some(line) = lines.last_mut() {
+                            line.push(Inline::Text(Text::new(part.into())));
+                        }
+                    }
+                }
+            }
+            _ => {
+                if let Some(line) = lines.last_mut() {
+                    line.push(inline)
+                }
+            }
+        }
+    }
+
+    lines.retain(|line| !line.is_empty());
+    lines
+}
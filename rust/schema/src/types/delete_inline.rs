@@ -5,10 +5,13 @@ use crate::prelude::*;
 use super::author::Author;
 use super::duration::Duration;
 use super::inline::Inline;
+use super::instruction_message::InstructionMessage;
+use super::number::Number;
 use super::provenance_count::ProvenanceCount;
 use super::string::String;
 use super::suggestion_status::SuggestionStatus;
 use super::timestamp::Timestamp;
+use super::unsigned_integer::UnsignedInteger;
 
 /// A suggestion to delete some inline content.
 #[skip_serializing_none]
@@ -73,6 +76,31 @@ pub struct DeleteInline {
     #[cfg_attr(feature = "proptest", proptest(value = "None"))]
     pub feedback: Option<String>,
 
+    /// The number of tokens in the prompts used to generate the suggestion.
+    #[serde(alias = "prompt-tokens", alias = "prompt_tokens")]
+    #[strip(execution)]
+    #[cfg_attr(feature = "proptest", proptest(value = "None"))]
+    pub prompt_tokens: Option<UnsignedInteger>,
+
+    /// The number of tokens generated for the suggestion.
+    #[serde(alias = "completion-tokens", alias = "completion_tokens")]
+    #[strip(execution)]
+    #[cfg_attr(feature = "proptest", proptest(value = "None"))]
+    pub completion_tokens: Option<UnsignedInteger>,
+
+    /// The estimated cost, in US dollars, of generating the suggestion.
+    #[strip(execution)]
+    #[cfg_attr(feature = "proptest", proptest(value = "None"))]
+    pub cost: Option<Number>,
+
+    /// The messages, other than those of the instruction, exchanged with the model while generating the suggestion.
+    #[serde(alias = "message")]
+    #[serde(default, deserialize_with = "option_one_or_many")]
+    #[strip(execution)]
+    #[cfg_attr(feature = "proptest", proptest(value = "None"))]
+    #[dom(elem = "div")]
+    pub messages: Option<Vec<InstructionMessage>>,
+
     /// The content that is suggested to be inserted, modified, replaced, or deleted.
     #[serde(deserialize_with = "one_or_many")]
     #[walk]
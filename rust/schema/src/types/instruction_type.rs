@@ -14,4 +14,6 @@ pub enum InstructionType {
     Edit,
 
     Update,
+
+    Chat,
 }
@@ -17,9 +17,9 @@ use schema::{
     authorship, diff, merge, patch,
     shortcuts::{art, p, sec, t},
     Article, Author, AuthorRole, AuthorRoleName, Block, CodeChunk, Cord, CordAuthorship, CordOp,
-    Figure, Inline, Node, NodeProperty, Paragraph, Patch, PatchNode, PatchOp, PatchPath, PatchSlot,
-    PatchValue, Person, Primitive, ProvenanceCategory, ProvenanceCount, SoftwareApplication,
-    Strong, Text, TimeUnit,
+    Figure, Inline, InstructionBlock, InstructionMessage, InstructionType, MessagePart, Node,
+    NodeProperty, Paragraph, Patch, PatchNode, PatchOp, PatchPath, PatchSlot, PatchValue, Person,
+    Primitive, ProvenanceCategory, ProvenanceCount, SoftwareApplication, Strong, Text, TimeUnit,
 };
 
 /// An individual fixture
@@ -792,3 +792,105 @@ fn authorship_on_nodes() -> Result<()> {
 
     Ok(())
 }
+
+/// Test that merging a chat from a source format appends any follow up
+/// message from the user, or edits the latest message if it has not been
+/// replied to, rather than replacing the conversation
+#[test]
+fn chat_messages() -> Result<()> {
+    let chat = |messages: Vec<InstructionMessage>| InstructionBlock {
+        instruction_type: InstructionType::Chat,
+        messages,
+        ..Default::default()
+    };
+    let texts = |chat: &InstructionBlock| -> Vec<String> {
+        chat.messages
+            .iter()
+            .flat_map(|message| message.parts.iter())
+            .filter_map(|part| match part {
+                MessagePart::Text(text) => Some(text.to_value_string()),
+                _ => None,
+            })
+            .collect()
+    };
+
+    let mut old = chat(vec![
+        InstructionMessage::user("load data.csv"),
+        InstructionMessage::assistant("data = read.csv('data.csv')"),
+    ]);
+
+    // Unchanged latest user message
+    merge(
+        &mut old,
+        &chat(vec![InstructionMessage::from("load data.csv")]),
+        Some(Format::Markdown),
+        None,
+    )?;
+    assert_eq!(
+        texts(&old),
+        vec!["load data.csv", "data = read.csv('data.csv')"]
+    );
+
+    // Follow up message
+    merge(
+        &mut old,
+        &chat(vec![InstructionMessage::from("now plot it")]),
+        Some(Format::Markdown),
+        None,
+    )?;
+    assert_eq!(
+        texts(&old),
+        vec![
+            "load data.csv",
+            "data = read.csv('data.csv')",
+            "now plot it"
+        ]
+    );
+
+    // Unchanged follow up message after a reply
+    old.messages
+        .push(InstructionMessage::assistant("plot(data)"));
+    merge(
+        &mut old,
+        &chat(vec![InstructionMessage::from("now plot it")]),
+        Some(Format::Markdown),
+        None,
+    )?;
+    assert_eq!(old.messages.len(), 4);
+
+    // Follow up message with the same text as the previous message
+    patch(
+        &mut old,
+        Patch {
+            ops: vec![(
+                PatchPath::from(NodeProperty::Messages),
+                PatchOp::Push(InstructionMessage::from("now plot it").to_value()?),
+            )],
+            format: Some(Format::Markdown),
+            ..Default::default()
+        },
+    )?;
+    assert_eq!(old.messages.len(), 5);
+    assert_eq!(texts(&old)[4], "now plot it");
+
+    // Editing the latest user message, before it has been replied to, replaces it
+    merge(
+        &mut old,
+        &chat(vec![InstructionMessage::from("now plot it as bars")]),
+        Some(Format::Markdown),
+        None,
+    )?;
+    assert_eq!(old.messages.len(), 5);
+    assert_eq!(texts(&old)[4], "now plot it as bars");
+
+    // Not from a source format so messages are replaced
+    merge(
+        &mut old,
+        &chat(vec![InstructionMessage::from("start again")]),
+        None,
+        None,
+    )?;
+    assert_eq!(texts(&old), vec!["start again"]);
+
+    Ok(())
+}
@@ -0,0 +1,14 @@
+use schema::{TimeUnit, Timestamp};
+
+#[test]
+fn humanize() {
+    let now = Timestamp::now();
+    assert_eq!(now.humanize(false), "just now");
+
+    let earlier = Timestamp {
+        value: now.value - 3 * 3_600_000,
+        time_unit: TimeUnit::Millisecond,
+        ..Default::default()
+    };
+    assert_eq!(earlier.humanize(false), "3 hours ago");
+}
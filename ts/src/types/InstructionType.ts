@@ -5,6 +5,7 @@
 export type InstructionType =
   'New' |
   'Edit' |
-  'Update';
+  'Update' |
+  'Chat';
 
 
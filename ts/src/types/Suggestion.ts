@@ -3,9 +3,11 @@
 import { Author } from "./Author.js";
 import { Duration } from "./Duration.js";
 import { Entity } from "./Entity.js";
+import { InstructionMessage } from "./InstructionMessage.js";
 import { ProvenanceCount } from "./ProvenanceCount.js";
 import { SuggestionStatus } from "./SuggestionStatus.js";
 import { Timestamp } from "./Timestamp.js";
+import { UnsignedInteger } from "./UnsignedInteger.js";
 
 /**
  * Abstract base type for nodes that indicate a suggested change to content.
@@ -44,6 +46,26 @@ export class Suggestion extends Entity {
    */
   feedback?: string;
 
+  /**
+   * The number of tokens in the prompts used to generate the suggestion.
+   */
+  promptTokens?: UnsignedInteger;
+
+  /**
+   * The number of tokens generated for the suggestion.
+   */
+  completionTokens?: UnsignedInteger;
+
+  /**
+   * The estimated cost, in US dollars, of generating the suggestion.
+   */
+  cost?: number;
+
+  /**
+   * The messages, other than those of the instruction, exchanged with the model while generating the suggestion.
+   */
+  messages?: InstructionMessage[];
+
   constructor(options?: Partial<Suggestion>) {
     super();
     this.type = "Suggestion";
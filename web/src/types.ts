@@ -61,6 +61,7 @@ export type AvailableLanguages =
   | 'js'
   | 'javascript'
   | 'latex'
+  | 'lua'
   | 'mathml'
   | 'node'
   | 'py'
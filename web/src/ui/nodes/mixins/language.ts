@@ -46,6 +46,10 @@ export const AvailableLanguagesMixin = <
         displayName: 'LaTeX',
         icon: ['latex', 'stencila'],
       },
+      lua: {
+        displayName: 'Lua',
+        icon: ['bx-code', 'boxicons'],
+      },
       mathml: {
         displayName: 'MathML',
         icon: ['code-block', 'stencila'],
@@ -249,6 +249,8 @@ export class UINodeCode extends LitElement {
         return 'JavaScript'
       case 'latex':
         return 'LaTeX'
+      case 'lua':
+        return 'Lua'
       case 'mathml':
         return 'MathML'
       case 'py':